@@ -1,8 +1,19 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, SystemTime};
+use crate::agent_approval::Approval;
+use crate::agent_intent::Intent;
+use crate::agent_learning::{self, CommandHistory};
+use crate::agent_schedule::TaskSchedule;
+use crate::agent_store::AgentStore;
+use crate::failure::{FailureClass, TaskFailure};
 use crate::logger::Logger;
+use crate::policy::{Caller, Decision, Policy};
+use crate::runner::{self, StreamingRunner};
+use crate::snapshot::{RestoreOutcome, Snapshot, SnapshotManager};
+use crate::system_config::SystemConfig;
 
 /// AI Agent Planning and Execution System
 /// 
@@ -20,6 +31,23 @@ pub enum TaskType {
     PluginExecution,
 }
 
+impl TaskType {
+    /// Guess the task type from keywords in a free-form description
+    pub fn from_description(description: &str) -> Self {
+        if description.contains("install") || description.contains("remove") {
+            TaskType::PackageManagement
+        } else if description.contains("security") || description.contains("audit") {
+            TaskType::SecurityAudit
+        } else if description.contains("config") {
+            TaskType::SystemConfiguration
+        } else if description.contains("monitor") {
+            TaskType::Monitoring
+        } else {
+            TaskType::PackageManagement
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Ord, Eq)]
 pub enum Priority {
     Low,
@@ -28,7 +56,7 @@ pub enum Priority {
     Critical,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SafetyLevel {
     Safe,      // No system changes, read-only operations
     Moderate,  // System changes with backup/rollback capability
@@ -50,6 +78,16 @@ pub struct Task {
     pub max_retries: u32,
     pub created_at: SystemTime,
     pub metadata: HashMap<String, String>,
+    /// Run through the matching subsystem instead of `command`, which then
+    /// only describes the task to the safety policy
+    #[serde(default)]
+    pub intent: Option<Intent>,
+    /// Makes the task recurring; a copy is queued each time it comes due
+    #[serde(default)]
+    pub schedule: Option<TaskSchedule>,
+    /// Sign-off required before a task at or above the approval threshold runs
+    #[serde(default)]
+    pub approval: Option<Approval>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,20 +100,105 @@ pub struct ExecutionResult {
     pub safety_checks_passed: bool,
     pub rollback_available: bool,
     pub executed_at: SystemTime,
+    /// Snapshot taken before the task ran, for `--rollback`
+    #[serde(default)]
+    pub snapshot: Option<Snapshot>,
+    /// Why the task failed, when it did
+    #[serde(default)]
+    pub failure: Option<FailureClass>,
+    /// Who approved or rejected the task, when it needed approval
+    #[serde(default)]
+    pub approval: Option<Approval>,
+}
+
+impl ExecutionResult {
+    /// Result for a task that could not be run at all
+    fn failed(task_id: &str, error: &anyhow::Error) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            success: false,
+            output: String::new(),
+            error: Some(error.to_string()),
+            duration: Duration::from_secs(0),
+            safety_checks_passed: false,
+            rollback_available: false,
+            executed_at: SystemTime::now(),
+            snapshot: None,
+            failure: Some(TaskFailure::classify(error)),
+            approval: None,
+        }
+    }
+
+    /// Result for a task that was never started because of its dependencies
+    fn skipped(task_id: &str, reason: &str) -> Self {
+        Self {
+            failure: None,
+            ..Self::failed(task_id, &anyhow::anyhow!("Skipped: {}", reason))
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentState {
     pub current_tasks: Vec<Task>,
+    /// Recurring tasks, each queued again whenever its schedule comes due
+    #[serde(default)]
+    pub scheduled_tasks: Vec<Task>,
+    /// Tasks claimed by a running agent loop, tagged with its pid
+    #[serde(default)]
+    pub in_progress: Vec<Task>,
+    /// While set, no agent claims new tasks
+    #[serde(default)]
+    pub paused: bool,
     pub completed_tasks: Vec<ExecutionResult>,
     pub failed_tasks: Vec<ExecutionResult>,
-    pub learning_data: HashMap<String, f64>,
+    /// Tasks that never ran because a dependency failed or was cancelled
+    #[serde(default)]
+    pub skipped_tasks: Vec<ExecutionResult>,
+    /// Run statistics per command signature, learned across runs
+    #[serde(default)]
+    pub command_history: CommandHistory,
     pub safety_violations: u32,
+    /// Attempts that failed transiently and were queued again, by cause
+    #[serde(default)]
+    pub retries: HashMap<FailureClass, u32>,
     pub last_update: SystemTime,
 }
 
+impl Default for AgentState {
+    fn default() -> Self {
+        Self {
+            current_tasks: Vec::new(),
+            scheduled_tasks: Vec::new(),
+            in_progress: Vec::new(),
+            paused: false,
+            completed_tasks: Vec::new(),
+            failed_tasks: Vec::new(),
+            skipped_tasks: Vec::new(),
+            command_history: CommandHistory::new(),
+            safety_violations: 0,
+            retries: HashMap::new(),
+            last_update: SystemTime::now(),
+        }
+    }
+}
+
+/// Metadata key recording which process claimed an in-progress task
+const CLAIMED_BY: &str = "claimed_by_pid";
+
+/// Metadata key naming the scheduled task a queued task was copied from
+pub const SCHEDULED_FROM: &str = "scheduled_from";
+
+/// Metadata key holding the unix time before which a retried task must not run
+pub const RETRY_AFTER: &str = "retry_after";
+
+/// Backoff before the first retry of a transient failure, doubling each attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
 pub struct IntelligentAgent {
     state: AgentState,
+    store: AgentStore,
     logger: Logger,
     safety_enforcer: SafetyEnforcer,
     task_planner: TaskPlanner,
@@ -84,14 +207,48 @@ pub struct IntelligentAgent {
 
 /// Safety Enforcement System
 pub struct SafetyEnforcer {
-    dangerous_patterns: Vec<String>,
-    system_critical_paths: Vec<String>,
+    policy: Policy,
+    caller: Caller,
+    snapshots: SnapshotManager,
+    /// Tasks at or above this level wait for approval before running
+    approval_threshold: SafetyLevel,
+    separate_approver: bool,
 }
 
 /// Task Planning System
 pub struct TaskPlanner {
     optimization_strategy: OptimizationStrategy,
     dependency_resolver: DependencyResolver,
+    max_concurrent: usize,
+    /// Use the adaptive strategy whenever the system isn't under load
+    adaptive: bool,
+}
+
+/// How a dependency outside the batch being planned stands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependencyStatus {
+    Succeeded,
+    Failed,
+    Running,
+}
+
+/// The queue split by what can happen to each task now
+#[derive(Debug, Default)]
+pub struct ExecutionPlan {
+    /// Tasks to run, every task after the tasks it depends on
+    pub tasks: Vec<Task>,
+    /// Tasks that can never run, with the reason
+    pub skipped: Vec<(Task, String)>,
+    /// Tasks waiting on a dependency that is still running elsewhere
+    pub waiting: Vec<Task>,
+}
+
+/// How a task ended within one pass, for deciding what its dependents do
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TaskOutcome {
+    Succeeded,
+    Failed,
+    Requeued,
 }
 
 /// Execution Engine
@@ -109,78 +266,316 @@ pub enum OptimizationStrategy {
 pub struct DependencyResolver {}
 
 impl IntelligentAgent {
-    pub fn new(verbose: bool, quiet: bool) -> Self {
-        Self {
-            state: AgentState {
-                current_tasks: Vec::new(),
-                completed_tasks: Vec::new(),
-                failed_tasks: Vec::new(),
-                learning_data: HashMap::new(),
-                safety_violations: 0,
-                last_update: SystemTime::now(),
-            },
+    /// Create an agent backed by the default persistent queue
+    pub fn new(verbose: bool, quiet: bool) -> Result<Self> {
+        Self::with_store(AgentStore::open_default()?, verbose, quiet)
+    }
+
+    pub fn with_store(store: AgentStore, verbose: bool, quiet: bool) -> Result<Self> {
+        let config = SystemConfig::load()?;
+        let policy = Policy::load(&config.agent)?;
+        let max_concurrent = config.security.max_concurrent_operations;
+        Ok(Self {
+            state: store.load()?,
+            store,
             logger: Logger::new(verbose, quiet),
-            safety_enforcer: SafetyEnforcer::new(),
-            task_planner: TaskPlanner::new(),
+            safety_enforcer: SafetyEnforcer::with_policy(policy)
+                .approval(config.agent.approval_threshold.clone(), config.agent.separate_approver),
+            task_planner: TaskPlanner::new(max_concurrent as usize).adaptive(config.agent.adaptive_planning),
             execution_engine: ExecutionEngine::new(),
-        }
+        })
+    }
+
+    /// Judge, submit and decide on behalf of another user, such as a client
+    /// of the daemon, instead of the user running this process
+    pub fn acting_as(mut self, caller: Caller) -> Self {
+        self.safety_enforcer.caller = caller;
+        self
+    }
+
+    /// Whether the acting user is listed in `agent.allowed_users` or `allowed_groups`
+    pub fn caller_allowed(&self) -> bool {
+        self.safety_enforcer.policy.is_allowed_caller(&self.safety_enforcer.caller)
+    }
+
+    /// Hold a task for approval whatever its safety level
+    pub fn hold_for_approval(&self, task: &mut Task) {
+        self.safety_enforcer.hold(task);
     }
 
     /// Main agent loop - processes tasks intelligently
     pub async fn run_agent_loop(&mut self) -> Result<()> {
         self.logger.info("🤖 Starting Intelligent Agent Loop");
+        self.requeue_abandoned()?;
         
         loop {
-            // 1. Analyze current system state
-            self.analyze_system_state().await?;
+            let results = self.run_once().await?;
             
-            // 2. Plan optimal task execution
-            let execution_plan = self.task_planner.create_execution_plan(&self.state.current_tasks)?;
-            
-            // 3. Execute tasks with safety checks
-            for task in execution_plan {
-                match self.execute_task_safely(&task).await {
-                    Ok(result) => {
-                        self.state.completed_tasks.push(result.clone());
-                        self.learn_from_execution(&task, &result);
-                    }
-                    Err(e) => {
-                        self.logger.error(format!("Task execution failed: {}", e));
-                        self.handle_task_failure(&task, &e);
+            // Check if we should continue, sleeping through the backoff of
+            // tasks waiting to be retried
+            if results.is_empty() || self.should_stop_loop() {
+                match self.next_retry() {
+                    Some(delay) => {
+                        self.logger.info(format!("⏳ Waiting {}s to retry failed task(s)", delay.as_secs().max(1)));
+                        tokio::time::sleep(delay).await;
+                        self.update_agent_state().await?;
+                        continue;
                     }
+                    None => break,
                 }
             }
             
-            // 4. Update agent state and learning
-            self.update_agent_state().await?;
-            
-            // 5. Check if we should continue
-            if self.should_stop_loop() {
-                break;
-            }
-            
-            // 6. Brief pause before next iteration
+            // Brief pause before next iteration
             tokio::time::sleep(Duration::from_millis(100)).await;
         }
+
+        let awaiting = self.state.current_tasks.iter()
+            .filter(|task| task.approval.as_ref().is_some_and(Approval::is_pending))
+            .count();
+        if awaiting > 0 {
+            self.logger.warn(format!("✋ {} task(s) are waiting for approval (agent --approve <id>)", awaiting));
+        }
+        if self.state.current_tasks.len() > awaiting {
+            self.logger.warn(format!("⏸️  {} task(s) are waiting on unmet dependencies", self.state.current_tasks.len() - awaiting));
+        }
         
         self.logger.success("🎯 Agent loop completed successfully");
         Ok(())
     }
 
+    /// Put tasks claimed by agent processes that are no longer running back on the queue
+    pub fn requeue_abandoned(&mut self) -> Result<()> {
+        let requeued = self.store.update(requeue_abandoned_tasks)?;
+        if requeued > 0 {
+            self.logger.warn(format!("🔄 Requeued {} task(s) left behind by a stopped agent", requeued));
+        }
+        Ok(())
+    }
+
+    /// Run one planning pass over the persisted queue and return the result
+    /// of every task it executed or skipped
+    pub async fn run_once(&mut self) -> Result<Vec<ExecutionResult>> {
+        // 1. Analyze current system state
+        self.analyze_system_state().await?;
+        
+        // 2. Plan optimal task execution and claim the planned tasks so
+        //    other agent processes don't run them too
+        let planner = &self.task_planner;
+        let (plan, released) = self.store.update(|state| -> Result<(ExecutionPlan, Vec<(String, bool)>)> {
+            if state.paused {
+                return Ok((ExecutionPlan::default(), Vec::new()));
+            }
+            let released = release_scheduled(state, SystemTime::now())?;
+            let plan = planner.schedule(&state.current_tasks, &dependency_statuses(state), &state.command_history)?;
+            state.current_tasks.retain(|task| plan.waiting.iter().any(|waiting| waiting.id == task.id));
+            state.skipped_tasks.extend(plan.skipped.iter().map(|(task, reason)| ExecutionResult::skipped(&task.id, reason)));
+            state.in_progress.extend(plan.tasks.iter().cloned().map(|mut task| {
+                task.metadata.insert(CLAIMED_BY.to_string(), std::process::id().to_string());
+                task
+            }));
+            Ok((plan, released))
+        })??;
+
+        for (description, queued) in released {
+            if queued {
+                self.logger.info(format!("⏰ Scheduled task due: {}", description));
+            } else {
+                self.logger.warn(format!("⏰ Skipping missed or overlapping run of {}", description));
+            }
+        }
+
+        let mut results: Vec<ExecutionResult> = plan.skipped.iter()
+            .map(|(task, reason)| {
+                self.logger.warn(format!("⏭️  Skipping task {}: {}", task.description, reason));
+                ExecutionResult::skipped(&task.id, reason)
+            })
+            .collect();
+        
+        // 3. Execute tasks with safety checks, running independent tasks
+        //    side by side and recording each result as it finishes
+        let limit = self.task_planner.concurrency_limit();
+        let quiet = limit > 1 && plan.tasks.len() > 1;
+        let pass_ids: HashSet<String> = plan.tasks.iter().map(|task| task.id.clone()).collect();
+        let mut pending = plan.tasks;
+        let mut outcomes: HashMap<String, TaskOutcome> = HashMap::new();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            let mut i = 0;
+            while i < pending.len() && running.len() < limit {
+                // Dependencies outside this pass have already succeeded
+                let mut unfinished = false;
+                let mut blocking = None;
+                for dep in &pending[i].dependencies {
+                    match outcomes.get(dep) {
+                        Some(TaskOutcome::Succeeded) => {}
+                        Some(TaskOutcome::Failed) => blocking = Some((dep.clone(), TaskOutcome::Failed)),
+                        Some(outcome) => {
+                            blocking.get_or_insert((dep.clone(), *outcome));
+                        }
+                        None if pass_ids.contains(dep) => unfinished = true,
+                        None => {}
+                    }
+                }
+                match blocking {
+                    Some((dep, TaskOutcome::Failed)) => {
+                        let task = pending.remove(i);
+                        let reason = format!("dependency {} did not succeed", dep);
+                        self.logger.warn(format!("⏭️  Skipping task {}: {}", task.description, reason));
+                        let result = ExecutionResult::skipped(&task.id, &reason);
+                        self.store.update(|state| {
+                            state.in_progress.retain(|claimed| claimed.id != task.id);
+                            state.skipped_tasks.push(result.clone());
+                        })?;
+                        outcomes.insert(task.id.clone(), TaskOutcome::Failed);
+                        results.push(result);
+                    }
+                    Some(_) => {
+                        // The dependency will be retried, so this task waits for it
+                        let task = pending.remove(i);
+                        self.store.update(|state| {
+                            state.in_progress.retain(|claimed| claimed.id != task.id);
+                            let mut task = task.clone();
+                            task.metadata.remove(CLAIMED_BY);
+                            state.current_tasks.push(task);
+                        })?;
+                        outcomes.insert(task.id.clone(), TaskOutcome::Requeued);
+                    }
+                    None if unfinished => i += 1,
+                    None => {
+                        let task = pending.remove(i);
+                        let agent = &*self;
+                        running.push(async move {
+                            let outcome = agent.execute_task_safely(&task, quiet).await;
+                            (task, outcome)
+                        });
+                    }
+                }
+            }
+
+            let Some((task, outcome)) = running.next().await else {
+                break;
+            };
+            let result = match &outcome {
+                Ok(result) => result.clone(),
+                Err(e) => {
+                    self.logger.error(format!("Task execution failed: {}", e));
+                    ExecutionResult { approval: task.approval.clone(), ..ExecutionResult::failed(&task.id, e) }
+                }
+            };
+            let logger = &self.logger;
+            let task_outcome = self.store.update(|state| {
+                state.in_progress.retain(|claimed| claimed.id != task.id);
+                if outcome.is_ok() {
+                    Self::learn_from_execution(state, &task, &result);
+                }
+                if result.success {
+                    state.completed_tasks.push(result.clone());
+                    TaskOutcome::Succeeded
+                } else if Self::handle_task_failure(state, logger, &task, result.clone()) {
+                    TaskOutcome::Requeued
+                } else {
+                    TaskOutcome::Failed
+                }
+            })?;
+            outcomes.insert(task.id.clone(), task_outcome);
+            results.push(result);
+        }
+        drop(running);
+        
+        // 4. Update agent state and learning
+        self.update_agent_state().await?;
+        Ok(results)
+    }
+
     /// Add a new task to the agent's queue
-    pub fn add_task(&mut self, task: Task) -> Result<()> {
+    pub fn add_task(&mut self, mut task: Task) -> Result<()> {
         // Validate task safety
-        if !self.safety_enforcer.validate_task(&task)? {
-            return Err(anyhow::anyhow!("Task failed safety validation: {}", task.description));
+        if let Some(reason) = self.safety_enforcer.rejection_reason(&task) {
+            return Err(anyhow::anyhow!("Task failed safety validation: {} - {}", task.description, reason));
+        }
+        
+        if task.schedule.is_some() && !task.dependencies.is_empty() {
+            anyhow::bail!("Scheduled task {} can't have dependencies", task.description);
+        }
+        
+        self.safety_enforcer.request_approval(&mut task);
+        if task.approval.is_some() {
+            self.logger.warn(format!("✋ {} is {}; it will wait for `agent --approve {}`", task.description, task.safety_level, task.id));
         }
         
         self.logger.info(format!("📋 Adding task: {} (Priority: {:?})", task.description, task.priority));
-        self.state.current_tasks.push(task);
+        self.store.update(|state| {
+            let task = estimate_duration(&state.command_history, task);
+            if task.schedule.is_some() {
+                state.scheduled_tasks.push(task);
+            } else {
+                state.current_tasks.push(task);
+            }
+        })?;
+        self.state = self.store.load()?;
         Ok(())
     }
 
+    /// Check a batch of tasks against the safety rules, reporting every
+    /// task that fails rather than stopping at the first
+    pub fn validate_tasks(&self, tasks: &[Task]) -> Result<()> {
+        let mut problems = Vec::new();
+        for task in tasks {
+            if let Some(reason) = self.safety_enforcer.rejection_reason(task) {
+                problems.push(format!("{}: {}", task.description, reason));
+                continue;
+            }
+            let check = self.safety_enforcer.pre_execution_check(task)?;
+            if !check.safe_to_execute {
+                problems.push(format!("{}: {}", task.description, check.reason));
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!("{} task(s) rejected:\n  {}", problems.len(), problems.join("\n  "));
+        }
+        Ok(())
+    }
+
+    /// Queue a batch of tasks together, or none of them if any is unsafe or
+    /// its dependencies can't be resolved
+    pub fn add_tasks(&mut self, mut tasks: Vec<Task>) -> Result<()> {
+        self.validate_tasks(&tasks)?;
+        for task in &mut tasks {
+            self.safety_enforcer.request_approval(task);
+        }
+        let awaiting = tasks.iter().filter(|task| task.approval.is_some()).count();
+        if awaiting > 0 {
+            self.logger.warn(format!("✋ {} task(s) will wait for `agent --approve <id>`", awaiting));
+        }
+
+        self.logger.info(format!("📋 Adding {} task(s)", tasks.len()));
+        let (scheduled, tasks): (Vec<Task>, Vec<Task>) = tasks.into_iter().partition(|task| task.schedule.is_some());
+        if let Some(task) = scheduled.iter().find(|task| !task.dependencies.is_empty()) {
+            anyhow::bail!("Scheduled task {} can't have dependencies", task.description);
+        }
+        let planner = &self.task_planner;
+        self.store.update(|state| -> Result<()> {
+            let mut queue = state.current_tasks.clone();
+            queue.extend(tasks.iter().cloned());
+            planner.schedule(&queue, &dependency_statuses(state), &state.command_history)?;
+            let tasks: Vec<Task> = tasks.into_iter().map(|task| estimate_duration(&state.command_history, task)).collect();
+            state.current_tasks.extend(tasks);
+            state.scheduled_tasks.extend(scheduled);
+            Ok(())
+        })??;
+        self.state = self.store.load()?;
+        Ok(())
+    }
+
+    /// The order the planner would run these tasks in, without queueing them
+    pub fn preview_plan(&self, tasks: &[Task]) -> Result<Vec<Task>> {
+        self.task_planner.create_execution_plan(tasks, &self.state.command_history)
+    }
+
     /// Execute a single task with comprehensive safety checks
-    async fn execute_task_safely(&mut self, task: &Task) -> Result<ExecutionResult> {
+    async fn execute_task_safely(&self, task: &Task, quiet: bool) -> Result<ExecutionResult> {
         let start_time = SystemTime::now();
         
         self.logger.info(format!("⚡ Executing task: {}", task.description));
@@ -188,18 +583,42 @@ impl IntelligentAgent {
         // Pre-execution safety checks
         let safety_check = self.safety_enforcer.pre_execution_check(task)?;
         if !safety_check.safe_to_execute {
-            return Err(anyhow::anyhow!("Safety check failed: {}", safety_check.reason));
+            return Err(TaskFailure::new(FailureClass::PolicyDenied, format!("Safety check failed: {}", safety_check.reason)).into());
+        }
+        if self.safety_enforcer.needs_approval(task) && !task.approval.as_ref().is_some_and(Approval::is_approved) {
+            return Err(TaskFailure::new(FailureClass::PolicyDenied, format!("{} task has not been approved", task.safety_level)).into());
+        }
+        
+        if let Some(stats) = agent_learning::lookup(&self.state.command_history, &task.command)
+            && stats.is_unreliable()
+        {
+            self.logger.warn(format!("⚠️  {} has succeeded in only {} of {} runs{}", task.description, stats.successes, stats.runs,
+                stats.last_failure.as_ref().map(|failure| format!(" (last failure: {})", failure)).unwrap_or_default()));
         }
         
-        // Create backup if needed
-        let backup_id = if task.safety_level != SafetyLevel::Safe {
-            Some(self.safety_enforcer.create_backup(&task.command)?)
+        // Snapshot the system first if the task changes it
+        let snapshot = if task.safety_level != SafetyLevel::Safe {
+            match self.safety_enforcer.create_backup(task) {
+                Ok(snapshot) => {
+                    self.logger.verbose(format!("📸 Took {} snapshot {}", snapshot.provider, snapshot.id));
+                    Some(snapshot)
+                }
+                // Only tasks that can't do real damage run without a way back
+                Err(e) if task.safety_level >= SafetyLevel::Risky => {
+                    return Err(TaskFailure::new(FailureClass::PolicyDenied,
+                        format!("Refusing to run {} task without a snapshot: {}", task.safety_level, e)).into());
+                }
+                Err(e) => {
+                    self.logger.warn(format!("⚠️  Could not snapshot the system before {}: {}", task.description, e));
+                    None
+                }
+            }
         } else {
             None
         };
         
         // Execute the task
-        let result = self.execution_engine.execute(task).await;
+        let result = self.execution_engine.execute(task, quiet).await;
         
         let duration = start_time.elapsed().unwrap_or(Duration::from_secs(0));
         
@@ -214,17 +633,30 @@ impl IntelligentAgent {
                     error: None,
                     duration,
                     safety_checks_passed: true,
-                    rollback_available: backup_id.is_some(),
+                    rollback_available: snapshot.is_some(),
                     executed_at: SystemTime::now(),
+                    snapshot,
+                    failure: None,
+                    approval: task.approval.clone(),
                 }
             }
             Err(e) => {
                 self.logger.error(format!("❌ Task failed: {} - {}", task.description, e));
                 
-                // Attempt rollback if backup exists
-                if let Some(ref backup) = backup_id {
-                    self.safety_enforcer.rollback(backup)?;
-                    self.logger.info("🔄 System rolled back to previous state");
+                // Roll back straight away when that doesn't need a reboot
+                let mut rolled_back = false;
+                if let Some(snapshot) = &snapshot {
+                    if self.safety_enforcer.restores_live(snapshot) {
+                        match self.safety_enforcer.rollback(snapshot) {
+                            Ok(_) => {
+                                rolled_back = true;
+                                self.logger.info("🔄 System rolled back to previous state");
+                            }
+                            Err(rollback_error) => self.logger.error(format!("Rollback failed: {}", rollback_error)),
+                        }
+                    } else {
+                        self.logger.info(format!("🔄 Run `linux-distro-agent agent --rollback {}` to restore the {} snapshot taken before this task", task.id, snapshot.provider));
+                    }
                 }
                 
                 ExecutionResult {
@@ -234,8 +666,11 @@ impl IntelligentAgent {
                     error: Some(e.to_string()),
                     duration,
                     safety_checks_passed: true,
-                    rollback_available: backup_id.is_some(),
+                    rollback_available: snapshot.is_some() && !rolled_back,
                     executed_at: SystemTime::now(),
+                    snapshot,
+                    failure: Some(TaskFailure::classify(&e)),
+                    approval: task.approval.clone(),
                 }
             }
         };
@@ -254,6 +689,8 @@ impl IntelligentAgent {
         if cpu_usage > 80.0 || memory_usage > 90.0 {
             self.task_planner.optimization_strategy = OptimizationStrategy::Sequential;
             self.logger.warn("🚦 High system load detected, switching to sequential execution");
+        } else if self.task_planner.adaptive {
+            self.task_planner.optimization_strategy = OptimizationStrategy::Adaptive;
         } else {
             self.task_planner.optimization_strategy = OptimizationStrategy::Parallel;
         }
@@ -262,72 +699,54 @@ impl IntelligentAgent {
     }
 
     /// Learn from task execution to improve future decisions
-    fn learn_from_execution(&mut self, task: &Task, result: &ExecutionResult) {
-        let task_key = format!("{}:{}", task.task_type, task.safety_level);
-        
-        // Update success rate
-        let current_rate = self.state.learning_data.get(&task_key).unwrap_or(&0.5);
-        let new_rate = if result.success {
-            (current_rate + 0.1).min(1.0)
-        } else {
-            (current_rate - 0.1).max(0.0)
-        };
-        
-        self.state.learning_data.insert(task_key, new_rate);
-        
-        // Learn execution time patterns
-        let duration_key = format!("{}_duration", task.task_type);
-        let avg_duration = self.state.learning_data.get(&duration_key).unwrap_or(&30.0);
-        let new_avg = (avg_duration + result.duration.as_secs() as f64) / 2.0;
-        self.state.learning_data.insert(duration_key, new_avg);
+    fn learn_from_execution(state: &mut AgentState, task: &Task, result: &ExecutionResult) {
+        agent_learning::record_run(&mut state.command_history, &task.command, result.success, result.duration, result.error.as_deref());
     }
 
-    /// Handle task failures intelligently
-    fn handle_task_failure(&mut self, task: &Task, error: &anyhow::Error) {
-        // Check if task should be retried
-        if task.retry_count < task.max_retries {
+    /// Handle task failures intelligently: transient failures go back on the
+    /// queue with exponential backoff until the task runs out of retries, and
+    /// anything else fails straight away. Returns true when the task was
+    /// queued again for another attempt.
+    fn handle_task_failure(state: &mut AgentState, logger: &Logger, task: &Task, result: ExecutionResult) -> bool {
+        let class = result.failure.unwrap_or(FailureClass::Unknown);
+        if class.is_transient() && task.retry_count < task.max_retries {
+            let delay = retry_delay(task.retry_count);
             let mut retry_task = task.clone();
             retry_task.retry_count += 1;
             
-            // Adjust retry delay based on failure type
-            let delay = match task.retry_count {
-                1 => Duration::from_secs(1),
-                2 => Duration::from_secs(5),
-                _ => Duration::from_secs(30),
-            };
-            
-            self.logger.warn(format!("🔄 Retrying task {} in {:?}", task.description, delay));
+            logger.warn(format!("🔄 Task {} failed ({}), retrying in {}s (attempt {}/{})",
+                task.description, class, delay.as_secs(), retry_task.retry_count + 1, task.max_retries + 1));
             
-            // Add back to queue with delay (simplified - in real implementation, use a scheduler)
-            self.state.current_tasks.push(retry_task);
+            let retry_at = SystemTime::now() + delay;
+            let retry_at = retry_at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs();
+            retry_task.metadata.insert(RETRY_AFTER.to_string(), retry_at.to_string());
+            retry_task.metadata.remove(CLAIMED_BY);
+            state.current_tasks.push(retry_task);
+            *state.retries.entry(class).or_default() += 1;
+            true
         } else {
-            self.logger.error(format!("💀 Task permanently failed: {}", task.description));
-            
-            let result = ExecutionResult {
-                task_id: task.id.clone(),
-                success: false,
-                output: String::new(),
-                error: Some(error.to_string()),
-                duration: Duration::from_secs(0),
-                safety_checks_passed: false,
-                rollback_available: false,
-                executed_at: SystemTime::now(),
-            };
-            
-            self.state.failed_tasks.push(result);
+            logger.error(format!("💀 Task permanently failed: {} ({})", task.description, class));
+            state.failed_tasks.push(result);
+            false
         }
     }
 
-    /// Update agent state and persist learning data
+    /// How long until the earliest queued retry is due, if any task is
+    /// backing off and the queue isn't paused
+    fn next_retry(&self) -> Option<Duration> {
+        if self.state.paused {
+            return None;
+        }
+        let now = SystemTime::now();
+        self.state.current_tasks.iter()
+            .filter_map(retry_after)
+            .map(|at| at.duration_since(now).unwrap_or_default())
+            .min()
+    }
+
+    /// Reload the persisted state, picking up tasks added by other invocations
     async fn update_agent_state(&mut self) -> Result<()> {
-        self.state.last_update = SystemTime::now();
-        
-        // Remove completed tasks from current queue
-        self.state.current_tasks.retain(|task| {
-            !self.state.completed_tasks.iter().any(|result| result.task_id == task.id)
-        });
-        
-        // Persist state (simplified - in real implementation, save to file)
+        self.state = self.store.load()?;
         self.logger.verbose("💾 Agent state updated".to_string());
         
         Ok(())
@@ -493,11 +912,11 @@ impl IntelligentAgent {
         args: &[String],
         task_type: TaskType,
     ) -> Task {
-        let id = format!("task_{}", uuid::Uuid::new_v4().to_string()[..8].to_string());
+        let id = new_task_id();
         let description = format!("{} {}", command, args.join(" "));
         
-        // Determine safety level based on command
-        let safety_level = self.assess_command_safety(command, args);
+        // Determine safety level from the policy
+        let safety_level = self.safety_enforcer.evaluate(&format!("{} {}", command, args.join(" "))).level();
         
         // Determine priority based on task type
         let priority = match task_type {
@@ -520,105 +939,295 @@ impl IntelligentAgent {
             max_retries: 3,
             created_at: SystemTime::now(),
             metadata: HashMap::new(),
+            intent: None,
+            schedule: None,
+            approval: None,
         }
     }
 
-    /// Assess the safety level of a command
-    fn assess_command_safety(&self, command: &str, args: &[String]) -> SafetyLevel {
-        let dangerous_commands = ["rm", "dd", "mkfs", "fdisk", "parted"];
-        let risky_commands = ["sudo", "su", "chmod", "chown"];
-        let moderate_commands = ["install", "update", "upgrade"];
-        
-        if dangerous_commands.contains(&command) {
-            SafetyLevel::Dangerous
-        } else if risky_commands.contains(&command) || args.iter().any(|arg| arg.contains("sudo")) {
-            SafetyLevel::Risky
-        } else if moderate_commands.contains(&command) {
-            SafetyLevel::Moderate
-        } else {
-            SafetyLevel::Safe
-        }
+    /// Create a task that carries out a parsed `--add-task` intent
+    pub fn create_task_from_intent(&self, description: &str, intent: Intent) -> Result<Task> {
+        let command = intent.command_line()?;
+        let mut task = self.create_task_from_command(&command, &[], intent.task_type());
+        task.command = command;
+        task.description = description.trim().to_string();
+        task.intent = Some(intent);
+        Ok(task)
     }
-    
+
     /// Get the current agent state (for status/stats display)
     pub fn get_state(&self) -> &AgentState {
         &self.state
     }
     
-    /// Clear all tasks from the agent queue
-    pub fn clear_all_tasks(&mut self) {
-        self.state.current_tasks.clear();
+    /// Remove a pending task from the queue. Returns false when no task has
+    /// that id; tasks that are already running cannot be cancelled. The task
+    /// is recorded as skipped so tasks depending on it are skipped too.
+    pub fn cancel_task(&mut self, task_id: &str) -> Result<bool> {
+        let removed = self.store.update(|state| -> Result<bool> {
+            if state.in_progress.iter().any(|task| task.id == task_id) {
+                anyhow::bail!("Task {} is already running", task_id);
+            }
+            let before = state.scheduled_tasks.len();
+            state.scheduled_tasks.retain(|task| task.id != task_id);
+            if state.scheduled_tasks.len() < before {
+                return Ok(true);
+            }
+            let before = state.current_tasks.len();
+            state.current_tasks.retain(|task| task.id != task_id);
+            let removed = state.current_tasks.len() < before;
+            if removed {
+                state.skipped_tasks.push(ExecutionResult::skipped(task_id, "cancelled"));
+            }
+            Ok(removed)
+        })??;
+        self.state = self.store.load()?;
+        Ok(removed)
+    }
+
+    /// Approve or reject a task waiting for approval, as the acting user.
+    /// A rejected task leaves the queue and is recorded as skipped, so tasks
+    /// depending on it are skipped too. Returns the task's description.
+    pub fn decide_approval(&mut self, task_id: &str, approve: bool, reason: Option<String>) -> Result<String> {
+        let enforcer = &self.safety_enforcer;
+        let description = self.store.update(|state| -> Result<String> {
+            let task = state.current_tasks.iter_mut()
+                .chain(state.scheduled_tasks.iter_mut())
+                .find(|task| task.id == task_id)
+                .ok_or_else(|| anyhow::anyhow!("No queued task with id {}", task_id))?;
+            let approval = task.approval.as_mut()
+                .ok_or_else(|| anyhow::anyhow!("Task {} doesn't need approval", task_id))?;
+            approval.decide(&enforcer.caller.user, approve, reason, enforcer.separate_approver)?;
+            let description = task.description.clone();
+            if !approve {
+                let approval = task.approval.clone();
+                state.scheduled_tasks.retain(|task| task.id != task_id);
+                let before = state.current_tasks.len();
+                state.current_tasks.retain(|task| task.id != task_id);
+                if state.current_tasks.len() < before {
+                    state.skipped_tasks.push(ExecutionResult { approval, ..ExecutionResult::skipped(task_id, "rejected") });
+                }
+            }
+            Ok(description)
+        })??;
+        self.state = self.store.load()?;
+        Ok(description)
+    }
+
+    /// Restore the snapshot taken before a finished task ran
+    pub fn rollback_task(&mut self, task_id: &str) -> Result<RestoreOutcome> {
+        let result = self.state.completed_tasks.iter()
+            .chain(&self.state.failed_tasks)
+            .filter(|result| result.task_id == task_id)
+            .max_by_key(|result| result.executed_at)
+            .ok_or_else(|| anyhow::anyhow!("No finished task with id {}", task_id))?;
+        let snapshot = match &result.snapshot {
+            Some(snapshot) if result.rollback_available => snapshot.clone(),
+            Some(_) => anyhow::bail!("Task {} has already been rolled back", task_id),
+            None => anyhow::bail!("No snapshot was taken before task {}", task_id),
+        };
+
+        self.logger.info(format!("🔄 Restoring {} snapshot {} taken before {}", snapshot.provider, snapshot.id, task_id));
+        let outcome = self.safety_enforcer.rollback(&snapshot)?;
+        self.store.update(|state| {
+            for result in state.completed_tasks.iter_mut().chain(state.failed_tasks.iter_mut()) {
+                if result.task_id == task_id {
+                    result.rollback_available = false;
+                }
+            }
+        })?;
+        self.state = self.store.load()?;
+        Ok(outcome)
+    }
+
+    /// Stop or resume claiming new tasks in every agent process
+    pub fn set_paused(&mut self, paused: bool) -> Result<()> {
+        self.store.update(|state| state.paused = paused)?;
+        self.state = self.store.load()?;
+        Ok(())
+    }
+
+    /// Clear all pending tasks from the agent queue. Tasks already claimed
+    /// by a running agent are left to finish.
+    pub fn clear_all_tasks(&mut self) -> Result<()> {
+        self.store.update(|state| {
+            state.current_tasks.clear();
+            state.scheduled_tasks.clear();
+        })?;
+        self.state = self.store.load()?;
         self.logger.info("All tasks cleared from agent queue");
+        Ok(())
     }
 }
 
+fn new_task_id() -> String {
+    format!("task_{}", &uuid::Uuid::new_v4().to_string()[..8])
+}
+
+/// Queue a copy of every scheduled task that has come due, unless the copy
+/// queued last time is still waiting or running. Returns each due task's
+/// description and whether a copy was queued.
+fn release_scheduled(state: &mut AgentState, now: SystemTime) -> Result<Vec<(String, bool)>> {
+    let busy: HashSet<String> = state.current_tasks.iter()
+        .chain(&state.in_progress)
+        .map(|task| task.id.clone())
+        .collect();
+    let mut released = Vec::new();
+    let mut queued = Vec::new();
+    for template in &mut state.scheduled_tasks {
+        let mut copy = template.clone();
+        let Some(schedule) = template.schedule.as_mut() else {
+            continue;
+        };
+        let Some(run) = schedule.advance(now)? else {
+            continue;
+        };
+        // A schedule that hasn't been approved yet misses its runs
+        let run = run && !schedule.last_task.as_ref().is_some_and(|id| busy.contains(id))
+            && !copy.approval.as_ref().is_some_and(Approval::is_pending);
+        if run {
+            copy.id = new_task_id();
+            copy.schedule = None;
+            copy.created_at = now;
+            copy.metadata.insert(SCHEDULED_FROM.to_string(), template.id.clone());
+            schedule.last_run = Some(now);
+            schedule.last_task = Some(copy.id.clone());
+            queued.push(estimate_duration(&state.command_history, copy));
+        }
+        released.push((template.description.clone(), run));
+    }
+    state.current_tasks.extend(queued);
+    Ok(released)
+}
+
+/// Put tasks claimed by agent processes that are no longer running back on
+/// the queue. Returns how many tasks were requeued.
+fn requeue_abandoned_tasks(state: &mut AgentState) -> usize {
+    let (alive, abandoned): (Vec<Task>, Vec<Task>) = std::mem::take(&mut state.in_progress)
+        .into_iter()
+        .partition(|task| {
+            task.metadata.get(CLAIMED_BY)
+                .is_some_and(|pid| std::path::Path::new("/proc").join(pid).exists())
+        });
+    state.in_progress = alive;
+
+    let requeued = abandoned.len();
+    state.current_tasks.extend(abandoned.into_iter().map(|mut task| {
+        task.metadata.remove(CLAIMED_BY);
+        task
+    }));
+    requeued
+}
+
 impl SafetyEnforcer {
-    pub fn new() -> Self {
+    pub fn with_policy(policy: Policy) -> Self {
         Self {
-            dangerous_patterns: vec![
-                "rm -rf /".to_string(),
-                "dd if=".to_string(),
-                "mkfs".to_string(),
-                "format".to_string(),
-            ],
-            system_critical_paths: vec![
-                "/boot".to_string(),
-                "/etc".to_string(),
-                "/usr".to_string(),
-                "/var".to_string(),
-            ],
-        }
-    }
-
-    pub fn validate_task(&self, task: &Task) -> Result<bool> {
-        // Check for dangerous patterns
-        for pattern in &self.dangerous_patterns {
-            if task.command.contains(pattern) {
-                return Ok(false);
-            }
+            policy,
+            caller: Caller::current(),
+            snapshots: SnapshotManager::new(SnapshotManager::default_archive_dir()),
+            approval_threshold: SafetyLevel::Risky,
+            separate_approver: false,
         }
-        
-        // Additional safety checks based on task type
-        match task.task_type {
-            TaskType::SystemConfiguration => {
-                if task.safety_level == SafetyLevel::Dangerous {
-                    return Ok(false);
-                }
-            }
-            _ => {}
+    }
+
+    pub fn approval(mut self, threshold: SafetyLevel, separate_approver: bool) -> Self {
+        self.approval_threshold = threshold;
+        self.separate_approver = separate_approver;
+        self
+    }
+
+    pub fn needs_approval(&self, task: &Task) -> bool {
+        task.safety_level >= self.approval_threshold
+    }
+
+    /// Put a task that needs approval into the pending-approval state,
+    /// recording the acting user as its submitter
+    pub fn request_approval(&self, task: &mut Task) {
+        if self.needs_approval(task) {
+            self.hold(task);
         }
-        
-        Ok(true)
     }
 
-    pub fn pre_execution_check(&self, task: &Task) -> Result<SafetyCheck> {
-        let mut safe_to_execute = true;
-        let mut reason = String::new();
-        
-        // Check if command affects critical system paths
-        for path in &self.system_critical_paths {
-            if task.command.contains(path) && task.safety_level == SafetyLevel::Dangerous {
-                safe_to_execute = false;
-                reason = format!("Command affects critical system path: {}", path);
-                break;
-            }
+    /// Put a task into the pending-approval state unless it already is
+    pub fn hold(&self, task: &mut Task) {
+        if task.approval.is_none() {
+            task.approval = Some(Approval::request(&self.caller.user, self.approval_plan(task)));
         }
-        
+    }
+
+    /// What an approver is shown: the command, the policy's verdict and the
+    /// critical files it touches
+    pub fn approval_plan(&self, task: &Task) -> String {
+        let mut plan = vec![
+            format!("Command: {}", task.command),
+            format!("Safety: {} ({})", task.safety_level, self.evaluate(&task.command).summary()),
+        ];
+        let paths = self.touched_paths(&task.command);
+        if !paths.is_empty() {
+            let paths: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+            plan.push(format!("Touches: {}", paths.join(", ")));
+        }
+        if let Some(schedule) = &task.schedule {
+            plan.push(format!("Schedule: {}", schedule.spec));
+        }
+        plan.join("\n")
+    }
+
+    /// Judge a command line against the policy for the acting user
+    pub fn evaluate(&self, command: &str) -> Decision {
+        self.policy.evaluate(command, &self.caller)
+    }
+
+    /// Why a task may not be queued, if it may not
+    pub fn rejection_reason(&self, task: &Task) -> Option<String> {
+        let decision = self.evaluate(&task.command);
+        if !decision.allowed() {
+            return Some(decision.summary());
+        }
+        // Dangerous configuration changes are never automated
+        if matches!(task.task_type, TaskType::SystemConfiguration) && task.safety_level == SafetyLevel::Dangerous {
+            return Some("dangerous system configuration tasks are not allowed".to_string());
+        }
+        None
+    }
+
+    /// Re-check the policy right before running, in case it changed since
+    /// the task was queued
+    pub fn pre_execution_check(&self, task: &Task) -> Result<SafetyCheck> {
+        let decision = self.evaluate(&task.command);
         Ok(SafetyCheck {
-            safe_to_execute,
-            reason,
+            safe_to_execute: decision.allowed(),
+            reason: if decision.allowed() { String::new() } else { decision.summary() },
         })
     }
 
-    pub fn create_backup(&self, _command: &str) -> Result<String> {
-        // Simplified backup creation
-        let backup_id = format!("backup_{}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs());
-        Ok(backup_id)
+    /// Files under the critical system paths that a command names, including
+    /// ones it may create
+    pub fn touched_paths(&self, command: &str) -> Vec<std::path::PathBuf> {
+        let mut paths: Vec<std::path::PathBuf> = Vec::new();
+        for word in command.split_whitespace() {
+            let word = word.trim_start_matches(['>', '<']).trim_matches(['\'', '"', ';', ',']);
+            let path = std::path::Path::new(word);
+            let critical = self.policy.critical_paths.iter().any(|root| path.starts_with(root));
+            if critical && !paths.iter().any(|known| known == path) {
+                paths.push(path.to_path_buf());
+            }
+        }
+        paths
     }
 
-    pub fn rollback(&self, _backup_id: &str) -> Result<()> {
-        // Simplified rollback implementation
-        Ok(())
+    /// Snapshot the system with the best provider available before a task runs
+    pub fn create_backup(&self, task: &Task) -> Result<Snapshot> {
+        self.snapshots.create(&task.id, &self.touched_paths(&task.command))
+    }
+
+    /// Whether `rollback` takes effect without a reboot
+    pub fn restores_live(&self, snapshot: &Snapshot) -> bool {
+        self.snapshots.restores_live(snapshot)
+    }
+
+    pub fn rollback(&self, snapshot: &Snapshot) -> Result<RestoreOutcome> {
+        self.snapshots.restore(snapshot)
     }
 }
 
@@ -629,37 +1238,78 @@ pub struct SafetyCheck {
 }
 
 impl TaskPlanner {
-    pub fn new() -> Self {
+    pub fn new(max_concurrent: usize) -> Self {
         Self {
             optimization_strategy: OptimizationStrategy::Adaptive,
             dependency_resolver: DependencyResolver::new(),
+            max_concurrent: max_concurrent.max(1),
+            adaptive: false,
         }
     }
 
-    pub fn create_execution_plan(&self, tasks: &[Task]) -> Result<Vec<Task>> {
-        let mut execution_plan = Vec::new();
-        
-        // Sort tasks by priority and dependencies
-        let mut sorted_tasks = tasks.to_vec();
-        sorted_tasks.sort_by(|a, b| {
-            // First sort by priority
-            match a.priority.cmp(&b.priority) {
-                std::cmp::Ordering::Equal => {
-                    // Then by creation time
-                    a.created_at.cmp(&b.created_at)
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// How many tasks may run at once under the current strategy
+    pub fn concurrency_limit(&self) -> usize {
+        match self.optimization_strategy {
+            OptimizationStrategy::Sequential => 1,
+            OptimizationStrategy::Parallel => self.max_concurrent,
+            OptimizationStrategy::Adaptive => (self.max_concurrent / 2).max(1),
+        }
+    }
+
+    /// Order a self-contained batch of tasks so every task comes after the
+    /// tasks it depends on
+    pub fn create_execution_plan(&self, tasks: &[Task], history: &CommandHistory) -> Result<Vec<Task>> {
+        Ok(self.schedule(tasks, &HashMap::new(), history)?.tasks)
+    }
+
+    /// Plan the queue. Dependencies may name tasks in `tasks` or tasks whose
+    /// status is in `external`; anything else, and any cycle, is an error.
+    /// The adaptive strategy also orders tasks by their command history.
+    pub fn schedule(&self, tasks: &[Task], external: &HashMap<String, DependencyStatus>, history: &CommandHistory) -> Result<ExecutionPlan> {
+        for task in tasks {
+            for dep in &task.dependencies {
+                if !external.contains_key(dep) && !tasks.iter().any(|other| other.id == *dep) {
+                    anyhow::bail!("Task {} depends on unknown task {}", task.description, dep);
                 }
-                other => other,
             }
-        });
-        
-        // Resolve dependencies
-        for task in sorted_tasks {
-            if self.dependency_resolver.dependencies_satisfied(&task, &execution_plan) {
-                execution_plan.push(task);
+        }
+        let history = matches!(self.optimization_strategy, OptimizationStrategy::Adaptive).then_some(history);
+        let ordered = self.dependency_resolver.topological_order(tasks, history)?;
+
+        let now = SystemTime::now();
+        let mut plan = ExecutionPlan::default();
+        for task in ordered {
+            let mut skip_reason = None;
+            let mut waits = retry_after(&task).is_some_and(|at| at > now)
+                || task.approval.as_ref().is_some_and(Approval::is_pending);
+            for dep in &task.dependencies {
+                if let Some(status) = external.get(dep) {
+                    match status {
+                        DependencyStatus::Succeeded => {}
+                        DependencyStatus::Failed => skip_reason = Some(format!("dependency {} did not succeed", dep)),
+                        DependencyStatus::Running => waits = true,
+                    }
+                } else if plan.skipped.iter().any(|(skipped, _)| skipped.id == *dep) {
+                    skip_reason = Some(format!("dependency {} was skipped", dep));
+                } else if plan.waiting.iter().any(|waiting| waiting.id == *dep) {
+                    waits = true;
+                }
+            }
+
+            if let Some(reason) = skip_reason {
+                plan.skipped.push((task, reason));
+            } else if waits {
+                plan.waiting.push(task);
+            } else {
+                plan.tasks.push(task);
             }
         }
-        
-        Ok(execution_plan)
+        Ok(plan)
     }
 }
 
@@ -668,14 +1318,94 @@ impl DependencyResolver {
         Self {}
     }
 
-    pub fn dependencies_satisfied(&self, task: &Task, completed_tasks: &[Task]) -> bool {
-        // Check if all dependencies are in completed tasks
-        task.dependencies.iter().all(|dep| {
-            completed_tasks.iter().any(|completed| completed.id == *dep)
-        })
+    /// Whether every dependency that is part of `batch` is in `completed_tasks`;
+    /// dependencies outside the batch are resolved by the caller
+    pub fn dependencies_satisfied(&self, task: &Task, completed_tasks: &[Task], batch: &[Task]) -> bool {
+        task.dependencies.iter()
+            .filter(|dep| batch.iter().any(|other| other.id == **dep))
+            .all(|dep| completed_tasks.iter().any(|completed| completed.id == *dep))
+    }
+
+    /// Order tasks so dependencies come first, picking the highest priority
+    /// (then, given a history, the most reliable and quickest, then oldest)
+    /// task whenever several are ready
+    pub fn topological_order(&self, tasks: &[Task], history: Option<&CommandHistory>) -> Result<Vec<Task>> {
+        let mut remaining: Vec<Task> = tasks.to_vec();
+        let mut ordered: Vec<Task> = Vec::with_capacity(tasks.len());
+
+        while !remaining.is_empty() {
+            let next = remaining.iter()
+                .enumerate()
+                .filter(|(_, task)| self.dependencies_satisfied(task, &ordered, tasks))
+                .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority)
+                    .then_with(|| history.map_or(std::cmp::Ordering::Equal, |history| agent_learning::compare_commands(history, &b.command, &a.command)))
+                    .then(b.created_at.cmp(&a.created_at)))
+                .map(|(i, _)| i);
+
+            match next {
+                Some(i) => ordered.push(remaining.remove(i)),
+                None => anyhow::bail!("Dependency cycle: {}", Self::describe_cycle(&remaining)),
+            }
+        }
+        Ok(ordered)
+    }
+
+    /// Follow unmet dependencies from the first stuck task until one repeats
+    fn describe_cycle(stuck: &[Task]) -> String {
+        let mut path: Vec<&Task> = vec![&stuck[0]];
+        loop {
+            let current = path[path.len() - 1];
+            let Some(next) = current.dependencies.iter()
+                .find_map(|dep| stuck.iter().find(|task| task.id == *dep))
+            else {
+                return path.iter().map(|task| task.description.as_str()).collect::<Vec<_>>().join(" -> ");
+            };
+            if let Some(start) = path.iter().position(|task| task.id == next.id) {
+                return path[start..].iter().chain(std::iter::once(&next))
+                    .map(|task| task.description.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+            }
+            path.push(next);
+        }
     }
 }
 
+/// Set a task's expected run time from the history of its command, when it has one
+fn estimate_duration(history: &CommandHistory, mut task: Task) -> Task {
+    if let Some(stats) = agent_learning::lookup(history, &task.command) {
+        task.estimated_duration = stats.avg_duration;
+    }
+    task
+}
+
+/// Exponential backoff before retry number `retry_count + 1`
+fn retry_delay(retry_count: u32) -> Duration {
+    RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(retry_count)).min(RETRY_MAX_DELAY)
+}
+
+/// When a task backing off after a transient failure may run again
+fn retry_after(task: &Task) -> Option<SystemTime> {
+    let secs = task.metadata.get(RETRY_AFTER)?.parse().ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// The status of every task that isn't waiting in the queue, for resolving
+/// dependencies on them
+fn dependency_statuses(state: &AgentState) -> HashMap<String, DependencyStatus> {
+    let mut statuses = HashMap::new();
+    for result in &state.completed_tasks {
+        statuses.insert(result.task_id.clone(), DependencyStatus::Succeeded);
+    }
+    for result in state.failed_tasks.iter().chain(&state.skipped_tasks) {
+        statuses.insert(result.task_id.clone(), DependencyStatus::Failed);
+    }
+    for task in &state.in_progress {
+        statuses.insert(task.id.clone(), DependencyStatus::Running);
+    }
+    statuses
+}
+
 impl ExecutionEngine {
     pub fn new() -> Self {
         Self {
@@ -683,25 +1413,49 @@ impl ExecutionEngine {
         }
     }
 
-    pub async fn execute(&self, task: &Task) -> Result<String> {
+    pub async fn execute(&self, task: &Task, quiet: bool) -> Result<String> {
         if self.dry_run_mode {
             return Ok(format!("[DRY RUN] Would execute: {}", task.command));
         }
         
-        // Execute the actual command
-        let output = tokio::process::Command::new("sh")
-            .arg("-c")
-            .arg(&task.command)
-            .output()
+        let Some(intent) = &task.intent else {
+            // Execute the actual command, streaming its output as it runs
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c").arg(&task.command);
+            return self.run(task, command, quiet).await;
+        };
+
+        match intent.steps()? {
+            Some(steps) => {
+                let mut stdout = String::new();
+                for step in steps {
+                    stdout.push_str(&self.run(task, step.to_command(), quiet).await?);
+                }
+                Ok(stdout)
+            }
+            None => intent.run_in_process().await,
+        }
+    }
+
+    /// Stream one program's output and turn a failed run into a classified error
+    async fn run(&self, task: &Task, command: std::process::Command, quiet: bool) -> Result<String> {
+        let timeout = task.metadata.get("timeout_secs")
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs);
+        let output = StreamingRunner::new()
+            .label(&task.description)
+            .timeout(timeout)
+            .log_file(runner::default_log_path())
+            .quiet(quiet)
+            .run_async(command)
             .await?;
-            
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+
+        if output.success {
+            Ok(output.stdout)
         } else {
-            Err(anyhow::anyhow!(
-                "Command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ))
+            // Package managers don't agree on which stream errors go to
+            let class = FailureClass::from_output(&format!("{}\n{}", output.stderr, output.stdout), output.timed_out);
+            Err(TaskFailure::new(class, format!("Command failed: {}", output.error_summary())).into())
         }
     }
 }
@@ -734,3 +1488,214 @@ impl std::fmt::Display for SafetyLevel {
 }
 
 // Add UUID dependency to Cargo.toml
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, priority: Priority, dependencies: &[&str]) -> Task {
+        Task {
+            id: id.to_string(),
+            task_type: TaskType::Monitoring,
+            command: "true".to_string(),
+            description: id.to_string(),
+            priority,
+            safety_level: SafetyLevel::Safe,
+            dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+            estimated_duration: Duration::from_secs(1),
+            retry_count: 0,
+            max_retries: 0,
+            created_at: SystemTime::now(),
+            metadata: HashMap::new(),
+            intent: None,
+            schedule: None,
+            approval: None,
+        }
+    }
+
+    fn ids(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|task| task.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_orders_dependencies_before_priority() {
+        let planner = TaskPlanner::new(4);
+        let tasks = vec![
+            task("deploy", Priority::Critical, &["build"]),
+            task("build", Priority::Low, &[]),
+            task("audit", Priority::High, &[]),
+        ];
+        let plan = planner.create_execution_plan(&tasks, &CommandHistory::new()).unwrap();
+        assert_eq!(ids(&plan), ["audit", "build", "deploy"]);
+    }
+
+    #[test]
+    fn test_plan_rejects_cycles_and_unknown_dependencies() {
+        let planner = TaskPlanner::new(4);
+        let cycle = vec![
+            task("a", Priority::Low, &["c"]),
+            task("b", Priority::Low, &["a"]),
+            task("c", Priority::Low, &["b"]),
+        ];
+        let error = planner.create_execution_plan(&cycle, &CommandHistory::new()).unwrap_err().to_string();
+        assert!(error.contains("Dependency cycle: a -> c -> b -> a"), "{error}");
+
+        let missing = vec![task("a", Priority::Low, &["ghost"])];
+        let error = planner.create_execution_plan(&missing, &CommandHistory::new()).unwrap_err().to_string();
+        assert!(error.contains("unknown task ghost"), "{error}");
+    }
+
+    #[test]
+    fn test_schedule_uses_external_status() {
+        let planner = TaskPlanner::new(4);
+        let tasks = vec![
+            task("after_ok", Priority::Low, &["done"]),
+            task("after_failed", Priority::Low, &["broken"]),
+            task("chained", Priority::Low, &["after_failed"]),
+            task("after_running", Priority::Low, &["busy"]),
+        ];
+        let external = HashMap::from([
+            ("done".to_string(), DependencyStatus::Succeeded),
+            ("broken".to_string(), DependencyStatus::Failed),
+            ("busy".to_string(), DependencyStatus::Running),
+        ]);
+        let plan = planner.schedule(&tasks, &external, &CommandHistory::new()).unwrap();
+        assert_eq!(ids(&plan.tasks), ["after_ok"]);
+        assert_eq!(plan.skipped.iter().map(|(task, _)| task.id.as_str()).collect::<Vec<_>>(), ["after_failed", "chained"]);
+        assert_eq!(ids(&plan.waiting), ["after_running"]);
+    }
+
+    #[test]
+    fn test_adaptive_plan_runs_reliable_quick_commands_first() {
+        let planner = TaskPlanner::new(4).adaptive(true);
+        let mut flaky = task("flaky", Priority::Low, &[]);
+        flaky.command = "make deploy".to_string();
+        let mut slow = task("slow", Priority::Low, &[]);
+        slow.command = "make build".to_string();
+        let mut quick = task("quick", Priority::Low, &[]);
+        quick.command = "make test".to_string();
+        let mut history = CommandHistory::new();
+        agent_learning::record_run(&mut history, "make deploy", false, Duration::from_secs(1), Some("boom"));
+        agent_learning::record_run(&mut history, "make build", true, Duration::from_secs(60), None);
+        agent_learning::record_run(&mut history, "make test", true, Duration::from_secs(5), None);
+
+        let tasks = vec![flaky, slow, quick, task("urgent", Priority::High, &[])];
+        let plan = planner.create_execution_plan(&tasks, &history).unwrap();
+        assert_eq!(ids(&plan), ["urgent", "quick", "slow", "flaky"]);
+    }
+
+    #[test]
+    fn test_touched_paths() {
+        let enforcer = SafetyEnforcer::with_policy(Policy::default());
+        let paths = enforcer.touched_paths("cp /etc/passwd /tmp/x && echo hi >>/etc/passwd; cat '/etcetera' /usr/missing-file");
+        assert_eq!(paths, vec![std::path::PathBuf::from("/etc/passwd"), std::path::PathBuf::from("/usr/missing-file")]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_task_skips_dependents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut agent = IntelligentAgent::with_store(AgentStore::open(dir.path()).unwrap(), false, true).unwrap();
+        let mut failing = task("fail", Priority::High, &[]);
+        failing.command = "false".to_string();
+        agent.add_tasks(vec![
+            failing,
+            task("next", Priority::Low, &["fail"]),
+            task("last", Priority::Low, &["next"]),
+            task("other", Priority::Low, &[]),
+        ]).unwrap();
+
+        agent.run_once().await.unwrap();
+        let state = agent.get_state();
+        assert_eq!(state.failed_tasks.len(), 1);
+        assert_eq!(state.completed_tasks.len(), 1);
+        let mut skipped: Vec<&str> = state.skipped_tasks.iter().map(|result| result.task_id.as_str()).collect();
+        skipped.sort();
+        assert_eq!(skipped, ["last", "next"]);
+        assert!(state.current_tasks.is_empty() && state.in_progress.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transient_failures_back_off_and_permanent_ones_fail() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut agent = IntelligentAgent::with_store(AgentStore::open(dir.path()).unwrap(), false, true).unwrap();
+        let mut locked = task("locked", Priority::High, &[]);
+        locked.command = "echo 'E: Could not get lock /var/lib/dpkg/lock-frontend' >&2; false".to_string();
+        locked.max_retries = 2;
+        let mut missing = task("missing", Priority::High, &[]);
+        missing.command = "echo 'E: Unable to locate package nosuchpkg' >&2; false".to_string();
+        missing.max_retries = 2;
+        agent.add_tasks(vec![locked, missing, task("after", Priority::Low, &["locked"])]).unwrap();
+
+        agent.run_once().await.unwrap();
+        let state = agent.get_state();
+        assert_eq!(state.failed_tasks.len(), 1);
+        assert_eq!(state.failed_tasks[0].task_id, "missing");
+        assert_eq!(state.failed_tasks[0].failure, Some(FailureClass::PackageNotFound));
+        assert_eq!(state.retries[&FailureClass::PackageLock], 1);
+        assert_eq!(ids(&state.current_tasks).len(), 2);
+        let retry = state.current_tasks.iter().find(|task| task.id == "locked").unwrap();
+        assert_eq!(retry.retry_count, 1);
+        assert!(retry_after(retry).unwrap() > SystemTime::now());
+
+        // Neither the backing-off task nor its dependent runs before the retry is due
+        assert!(agent.run_once().await.unwrap().is_empty());
+        assert!(agent.next_retry().is_some());
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(1), RETRY_BASE_DELAY * 2);
+        assert_eq!(retry_delay(10), RETRY_MAX_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_risky_tasks_wait_for_approval() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut agent = IntelligentAgent::with_store(AgentStore::open(dir.path()).unwrap(), false, true).unwrap();
+        let mut risky = task("risky", Priority::High, &[]);
+        risky.safety_level = SafetyLevel::Risky;
+        let mut rejected = task("rejected", Priority::High, &[]);
+        rejected.safety_level = SafetyLevel::Dangerous;
+        agent.add_tasks(vec![risky, rejected, task("after", Priority::Low, &["rejected"])]).unwrap();
+
+        assert!(agent.run_once().await.unwrap().is_empty());
+        assert_eq!(agent.get_state().current_tasks.len(), 3);
+        assert!(agent.decide_approval("after", true, None).is_err());
+
+        agent.decide_approval("risky", true, Some("planned maintenance".to_string())).unwrap();
+        agent.decide_approval("rejected", false, Some("not on this host".to_string())).unwrap();
+        assert!(agent.decide_approval("risky", false, None).is_err());
+        agent.run_once().await.unwrap();
+
+        let state = agent.get_state();
+        assert_eq!(state.completed_tasks.len(), 1);
+        let approval = state.completed_tasks[0].approval.as_ref().unwrap();
+        assert!(approval.is_approved());
+        assert_eq!(approval.reason.as_deref(), Some("planned maintenance"));
+        let mut skipped: Vec<&str> = state.skipped_tasks.iter().map(|result| result.task_id.as_str()).collect();
+        skipped.sort();
+        assert_eq!(skipped, ["after", "rejected"]);
+        assert!(state.current_tasks.is_empty());
+    }
+
+    #[test]
+    fn test_due_schedules_queue_one_copy_at_a_time() {
+        let now = SystemTime::now();
+        let mut template = task("audit", Priority::High, &[]);
+        let mut schedule = TaskSchedule::new("@every 1h", Default::default()).unwrap();
+        schedule.next_run = now;
+        template.schedule = Some(schedule);
+        let mut state = AgentState { scheduled_tasks: vec![template], ..AgentState::default() };
+
+        assert_eq!(release_scheduled(&mut state, now).unwrap(), [("audit".to_string(), true)]);
+        let copy = state.current_tasks[0].clone();
+        assert!(copy.schedule.is_none());
+        assert_eq!(copy.metadata[SCHEDULED_FROM], "audit");
+        let schedule = state.scheduled_tasks[0].schedule.clone().unwrap();
+        assert_eq!(schedule.last_task.as_deref(), Some(copy.id.as_str()));
+        assert_eq!(schedule.next_run, now + Duration::from_secs(3600));
+        assert!(release_scheduled(&mut state, now).unwrap().is_empty());
+
+        // The next run is skipped while the previous copy is still queued
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(release_scheduled(&mut state, later).unwrap(), [("audit".to_string(), false)]);
+        assert_eq!(state.current_tasks.len(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl fmt::Display for ApprovalStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Sign-off on a task at or above the approval threshold. Travels with the
+/// task and is copied onto its result, so the record of who allowed what
+/// outlives the queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    pub status: ApprovalStatus,
+    pub submitted_by: String,
+    pub requested_at: SystemTime,
+    /// What the approver signs off on: the command, how the policy judged
+    /// it and which critical files it touches
+    pub plan: String,
+    pub decided_by: Option<String>,
+    pub decided_at: Option<SystemTime>,
+    pub reason: Option<String>,
+}
+
+impl Approval {
+    pub fn request(submitted_by: &str, plan: String) -> Self {
+        Self {
+            status: ApprovalStatus::Pending,
+            submitted_by: submitted_by.to_string(),
+            requested_at: SystemTime::now(),
+            plan,
+            decided_by: None,
+            decided_at: None,
+            reason: None,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.status == ApprovalStatus::Pending
+    }
+
+    pub fn is_approved(&self) -> bool {
+        self.status == ApprovalStatus::Approved
+    }
+
+    /// Record `approver`'s decision. With `separate_approver` set, nobody may
+    /// approve their own task, though they may still reject it.
+    pub fn decide(&mut self, approver: &str, approve: bool, reason: Option<String>, separate_approver: bool) -> Result<()> {
+        if !self.is_pending() {
+            anyhow::bail!("Task was already {} by {}", self.status, self.decided_by.as_deref().unwrap_or("unknown"));
+        }
+        if approve && separate_approver && approver == self.submitted_by {
+            anyhow::bail!("{} submitted this task and can't also approve it (agent.separate_approver is set)", approver);
+        }
+        self.status = if approve { ApprovalStatus::Approved } else { ApprovalStatus::Rejected };
+        self.decided_by = Some(approver.to_string());
+        self.decided_at = Some(SystemTime::now());
+        self.reason = reason;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decide_once_and_separate_approver() {
+        let mut approval = Approval::request("alice", "Command: rm -rf /srv/cache".to_string());
+        assert!(approval.is_pending());
+        assert!(approval.decide("alice", true, None, true).is_err());
+        assert!(approval.is_pending());
+
+        approval.decide("bob", true, Some("cache is rebuilt nightly".to_string()), true).unwrap();
+        assert!(approval.is_approved());
+        assert_eq!(approval.decided_by.as_deref(), Some("bob"));
+        assert!(approval.decided_at.is_some());
+        assert!(approval.decide("bob", false, None, true).is_err());
+
+        // Submitters may always withdraw their own task
+        let mut approval = Approval::request("alice", String::new());
+        approval.decide("alice", false, Some("wrong host".to_string()), true).unwrap();
+        assert_eq!(approval.status, ApprovalStatus::Rejected);
+    }
+}
@@ -0,0 +1,536 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{broadcast, Notify};
+use crate::agent::{ExecutionResult, IntelligentAgent, Priority, TaskType};
+use crate::agent_schedule::{MissedRuns, TaskSchedule};
+use crate::agent_store::AgentStore;
+use crate::logger::Logger;
+use crate::policy::Caller;
+
+/// How often the worker looks for tasks added directly to the store
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Completed and failed results returned by `list`
+const LIST_RESULT_LIMIT: usize = 20;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+const NOT_PERMITTED: i64 = -32001;
+
+/// Default control socket: `/run/linux-distro-agent` for root, otherwise
+/// under the user's runtime directory. There is no fallback to a shared
+/// temporary directory, where another user could claim the path first.
+pub fn default_socket_path() -> Result<PathBuf> {
+    let dir = if euid() == 0 {
+        PathBuf::from("/run")
+    } else {
+        dirs::runtime_dir().context("XDG_RUNTIME_DIR is not set; pass --socket with a path in a directory only you can write to")?
+    };
+    Ok(dir.join("linux-distro-agent").join("agent.sock"))
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitParams {
+    command: String,
+    description: Option<String>,
+    priority: Option<Priority>,
+    task_type: Option<TaskType>,
+    timeout_secs: Option<u64>,
+    /// Make the task recurring
+    schedule: Option<String>,
+    missed: Option<MissedRuns>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelParams {
+    task_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecisionParams {
+    task_id: String,
+    reason: Option<String>,
+}
+
+/// State shared between the worker and every client connection
+struct Shared {
+    store_dir: PathBuf,
+    /// The user the daemon runs as
+    owner: Caller,
+    wake: Notify,
+    results: broadcast::Sender<ExecutionResult>,
+    verbose: bool,
+    quiet: bool,
+}
+
+impl Shared {
+    /// An agent acting for this caller
+    fn agent(&self, caller: &Caller) -> Result<IntelligentAgent> {
+        Ok(IntelligentAgent::with_store(AgentStore::open(&self.store_dir)?, self.verbose, self.quiet)?.acting_as(caller.clone()))
+    }
+
+    /// Whether the agent's caller may manage the queue: the daemon's own
+    /// user, or one listed in `agent.allowed_users` or `allowed_groups`
+    fn trusted(&self, agent: &IntelligentAgent, caller: &Caller) -> bool {
+        caller.user == self.owner.user || agent.caller_allowed()
+    }
+}
+
+/// Long-running agent that executes queued tasks and accepts newline
+/// delimited JSON-RPC 2.0 requests on a Unix socket.
+///
+/// Methods: `submit`, `list`, `cancel`, `pause`, `resume`, `approve`,
+/// `reject` and `stream`. Pausing is stored with the queue, so it also holds
+/// `lda agent --start`. `stream` keeps the connection open and sends a
+/// `result` notification for every task the daemon finishes.
+///
+/// Requests act as the user on the other end of the socket. Run as root, the
+/// daemon serves the system-wide store to every user: anyone may submit and
+/// list, but tasks from users outside `agent.allowed_users` and
+/// `allowed_groups` wait for approval, and only the daemon's own user and
+/// the allowed users may cancel, pause, resume, approve or reject.
+pub struct AgentDaemon {
+    socket_path: PathBuf,
+    /// Open the socket to every local user
+    system: bool,
+    shared: Arc<Shared>,
+    logger: Logger,
+}
+
+impl AgentDaemon {
+    pub fn new(socket_path: PathBuf, store_dir: PathBuf, verbose: bool, quiet: bool) -> Self {
+        let (results, _) = broadcast::channel(64);
+        Self {
+            socket_path,
+            system: euid() == 0,
+            shared: Arc::new(Shared {
+                store_dir,
+                owner: Caller::current(),
+                wake: Notify::new(),
+                results,
+                verbose,
+                quiet,
+            }),
+            logger: Logger::new(verbose, quiet),
+        }
+    }
+
+    /// Serve until interrupted
+    pub async fn run(&self) -> Result<()> {
+        let listener = self.bind().await?;
+        self.logger.info(format!("🤖 Agent daemon listening on {}", self.socket_path.display()));
+
+        let result = tokio::select! {
+            result = self.accept_loop(&listener) => result,
+            result = worker_loop(self.shared.clone()) => result,
+            _ = tokio::signal::ctrl_c() => {
+                self.logger.info("Shutting down agent daemon");
+                Ok(())
+            }
+        };
+
+        let _ = std::fs::remove_file(&self.socket_path);
+        result
+    }
+
+    async fn bind(&self) -> Result<UnixListener> {
+        let parent = match self.socket_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(if self.system { 0o755 } else { 0o700 })
+            .create(parent)
+            .with_context(|| format!("Failed to create socket directory: {parent:?}"))?;
+        check_socket_dir(parent)?;
+        if self.socket_path.exists() {
+            if UnixStream::connect(&self.socket_path).await.is_ok() {
+                anyhow::bail!("An agent daemon is already listening on {}", self.socket_path.display());
+            }
+            // Left behind by a daemon that didn't shut down cleanly
+            std::fs::remove_file(&self.socket_path)?;
+        }
+
+        // Submitting a task runs a command as this user, so the socket must be
+        // private from the moment it exists. Nothing else is running yet, so
+        // changing the process umask here can't affect other files.
+        // SAFETY: umask has no preconditions and cannot fail
+        let previous = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(&self.socket_path);
+        // SAFETY: as above
+        unsafe { libc::umask(previous) };
+        let listener = listener.with_context(|| format!("Failed to bind {}", self.socket_path.display()))?;
+        if self.system {
+            // Every request acts as its peer, so other users may connect
+            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(0o666))?;
+        }
+        Ok(listener)
+    }
+
+    async fn accept_loop(&self, listener: &UnixListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let shared = self.shared.clone();
+            tokio::spawn(async move {
+                let logger = Logger::new(shared.verbose, shared.quiet);
+                if let Err(e) = handle_connection(stream, shared).await {
+                    logger.verbose(format!("Agent client disconnected: {}", e));
+                }
+            });
+        }
+    }
+}
+
+/// Refuse a socket directory that someone else could swap the socket out of
+fn check_socket_dir(dir: &Path) -> Result<()> {
+    let metadata = std::fs::metadata(dir).with_context(|| format!("Failed to inspect socket directory: {dir:?}"))?;
+    if metadata.uid() != euid() {
+        anyhow::bail!("Socket directory {} is owned by uid {}, not by this user", dir.display(), metadata.uid());
+    }
+    if metadata.mode() & 0o022 != 0 {
+        anyhow::bail!("Socket directory {} is writable by other users", dir.display());
+    }
+    Ok(())
+}
+
+fn euid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+/// Execute queued tasks; a paused queue makes `run_once` claim nothing
+async fn worker_loop(shared: Arc<Shared>) -> Result<()> {
+    let mut agent = shared.agent(&shared.owner)?;
+    agent.requeue_abandoned()?;
+
+    loop {
+        let results = agent.run_once().await?;
+        let made_progress = !results.is_empty();
+        for result in results {
+            // Nobody listening is fine
+            let _ = shared.results.send(result);
+        }
+        // Finishing tasks can unblock their dependents straight away
+        if made_progress {
+            continue;
+        }
+
+        tokio::select! {
+            _ = shared.wake.notified() => {}
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+        }
+    }
+}
+
+async fn handle_connection(stream: UnixStream, shared: Arc<Shared>) -> Result<()> {
+    // Who is asking comes from the kernel, not from the request
+    let uid = stream.peer_cred()?.uid();
+    let caller = tokio::task::spawn_blocking(move || Caller::for_uid(uid)).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: Request = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = RpcError::new(PARSE_ERROR, format!("Invalid request: {e}"));
+                write_line(&mut writer, &response(Value::Null, Err(error))).await?;
+                continue;
+            }
+        };
+
+        if request.method == "stream" {
+            let mut results = shared.results.subscribe();
+            write_line(&mut writer, &response(request.id, Ok(json!({ "streaming": true })))).await?;
+            loop {
+                match results.recv().await {
+                    Ok(result) => {
+                        let notification = json!({ "jsonrpc": "2.0", "method": "result", "params": result });
+                        write_line(&mut writer, &notification).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+
+        // Loading the agent reads config, runs `id` and takes the store lock,
+        // so keep it off the runtime threads
+        let (task_shared, task_caller) = (shared.clone(), caller.clone());
+        let outcome = tokio::task::spawn_blocking(move || dispatch(&task_shared, &task_caller, &request.method, request.params))
+            .await
+            .unwrap_or_else(|e| Err(RpcError::new(SERVER_ERROR, e.to_string())));
+        write_line(&mut writer, &response(request.id, outcome)).await?;
+    }
+
+    Ok(())
+}
+
+fn dispatch(shared: &Shared, caller: &Caller, method: &str, params: Value) -> Result<Value, RpcError> {
+    let server_error = |e: anyhow::Error| RpcError::new(SERVER_ERROR, e.to_string());
+    let trusted_agent = || {
+        let agent = shared.agent(caller).map_err(server_error)?;
+        match shared.trusted(&agent, caller) {
+            true => Ok(agent),
+            false => Err(RpcError::new(NOT_PERMITTED, format!("{} may not {} tasks", caller.user, method))),
+        }
+    };
+
+    match method {
+        "submit" => {
+            let params: SubmitParams = serde_json::from_value(params)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+            let mut agent = shared.agent(caller).map_err(server_error)?;
+
+            let description = params.description.unwrap_or_else(|| params.command.clone());
+            let task_type = params.task_type.unwrap_or_else(|| TaskType::from_description(&description));
+            let (program, args) = params.command.split_once(' ').unwrap_or((params.command.as_str(), ""));
+            let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+
+            let mut task = agent.create_task_from_command(program, &args, task_type);
+            task.command = params.command.clone();
+            task.description = description;
+            if let Some(priority) = params.priority {
+                task.priority = priority;
+            }
+            if let Some(timeout) = params.timeout_secs {
+                task.metadata.insert("timeout_secs".to_string(), timeout.to_string());
+            }
+            if let Some(spec) = &params.schedule {
+                let schedule = TaskSchedule::new(spec, params.missed.unwrap_or_default())
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+                task.schedule = Some(schedule);
+            }
+
+            if !shared.trusted(&agent, caller) {
+                agent.hold_for_approval(&mut task);
+            }
+
+            let task_id = task.id.clone();
+            agent.add_task(task).map_err(server_error)?;
+            shared.wake.notify_one();
+            Ok(json!({ "task_id": task_id }))
+        }
+        "list" => {
+            let agent = shared.agent(caller).map_err(server_error)?;
+            let state = agent.get_state();
+            let recent = |results: &[ExecutionResult]| results[results.len().saturating_sub(LIST_RESULT_LIMIT)..].to_vec();
+            Ok(json!({
+                "paused": state.paused,
+                "pending": state.current_tasks,
+                "scheduled": state.scheduled_tasks,
+                "running": state.in_progress,
+                "completed": recent(&state.completed_tasks),
+                "failed": recent(&state.failed_tasks),
+                "skipped": recent(&state.skipped_tasks),
+            }))
+        }
+        "cancel" => {
+            let params: CancelParams = serde_json::from_value(params)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+            let mut agent = trusted_agent()?;
+            match agent.cancel_task(&params.task_id).map_err(server_error)? {
+                true => Ok(json!({ "cancelled": params.task_id })),
+                false => Err(RpcError::new(INVALID_PARAMS, format!("No pending task {}", params.task_id))),
+            }
+        }
+        "pause" | "resume" => {
+            let paused = method == "pause";
+            trusted_agent()?.set_paused(paused).map_err(server_error)?;
+            shared.wake.notify_one();
+            Ok(json!({ "paused": paused }))
+        }
+        "approve" | "reject" => {
+            let params: DecisionParams = serde_json::from_value(params)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+            let approve = method == "approve";
+            trusted_agent()?.decide_approval(&params.task_id, approve, params.reason).map_err(server_error)?;
+            shared.wake.notify_one();
+            Ok(json!({ "task_id": params.task_id, "approved": approve }))
+        }
+        other => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {other}"))),
+    }
+}
+
+fn response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
+
+async fn write_line(writer: &mut tokio::net::unix::OwnedWriteHalf, value: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
+/// Render a systemd unit that runs the daemon. System units run as root and
+/// start at boot; user units run in the user's session.
+pub fn systemd_unit(executable: &Path, user_unit: bool) -> String {
+    let wanted_by = if user_unit { "default.target" } else { "multi-user.target" };
+    format!(
+        "[Unit]
+Description=Linux Distribution Agent task daemon
+After=network-online.target
+
+[Service]
+Type=simple
+RuntimeDirectory=linux-distro-agent
+ExecStart={} agent --daemon --socket %t/linux-distro-agent/agent.sock
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy={}
+",
+        executable.display(),
+        wanted_by
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caller(user: &str, groups: &[&str]) -> Caller {
+        Caller { user: user.to_string(), groups: groups.iter().map(|group| group.to_string()).collect() }
+    }
+
+    fn shared(dir: &Path) -> Shared {
+        let (results, _) = broadcast::channel(4);
+        Shared {
+            store_dir: dir.to_path_buf(),
+            owner: caller("owner", &[]),
+            wake: Notify::new(),
+            results,
+            verbose: false,
+            quiet: true,
+        }
+    }
+
+    #[test]
+    fn test_submit_list_cancel() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = shared(dir.path());
+
+        let submitted = dispatch(&shared, &shared.owner, "submit", json!({ "command": "echo hi", "priority": "High" })).unwrap();
+        let task_id = submitted["task_id"].as_str().unwrap().to_string();
+
+        let listed = dispatch(&shared, &shared.owner, "list", Value::Null).unwrap();
+        assert_eq!(listed["pending"][0]["id"], task_id.as_str());
+        assert_eq!(listed["pending"][0]["command"], "echo hi");
+        assert_eq!(listed["pending"][0]["priority"], "High");
+
+        dispatch(&shared, &shared.owner, "cancel", json!({ "task_id": task_id })).unwrap();
+        let listed = dispatch(&shared, &shared.owner, "list", Value::Null).unwrap();
+        assert!(listed["pending"].as_array().unwrap().is_empty());
+
+        let error = dispatch(&shared, &shared.owner, "cancel", json!({ "task_id": task_id })).unwrap_err();
+        assert_eq!(error.code, INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_pause_and_unknown_method() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = shared(dir.path());
+
+        dispatch(&shared, &shared.owner, "pause", Value::Null).unwrap();
+        assert_eq!(dispatch(&shared, &shared.owner, "list", Value::Null).unwrap()["paused"], true);
+        dispatch(&shared, &shared.owner, "resume", Value::Null).unwrap();
+        assert_eq!(dispatch(&shared, &shared.owner, "list", Value::Null).unwrap()["paused"], false);
+
+        assert_eq!(dispatch(&shared, &shared.owner, "reboot", Value::Null).unwrap_err().code, METHOD_NOT_FOUND);
+        assert_eq!(dispatch(&shared, &shared.owner, "submit", json!({})).unwrap_err().code, INVALID_PARAMS);
+    }
+
+    #[test]
+    fn test_one_user_submits_another_approves() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = shared(dir.path());
+        let alice = caller("alice", &["users"]);
+        let bob = caller("bob", &["wheel"]);
+
+        let submitted = dispatch(&shared, &alice, "submit", json!({ "command": "echo hi" })).unwrap();
+        let task_id = submitted["task_id"].as_str().unwrap().to_string();
+        let approval = &dispatch(&shared, &bob, "list", Value::Null).unwrap()["pending"][0]["approval"];
+        assert_eq!(approval["status"], "pending");
+        assert_eq!(approval["submitted_by"], "alice");
+
+        // Alice isn't an allowed user, so she can't wave her own task through
+        let decision = json!({ "task_id": task_id, "reason": "routine" });
+        assert_eq!(dispatch(&shared, &alice, "approve", decision.clone()).unwrap_err().code, NOT_PERMITTED);
+        assert_eq!(dispatch(&shared, &alice, "pause", Value::Null).unwrap_err().code, NOT_PERMITTED);
+
+        dispatch(&shared, &bob, "approve", decision).unwrap();
+        let approval = &dispatch(&shared, &alice, "list", Value::Null).unwrap()["pending"][0]["approval"];
+        assert_eq!(approval["status"], "approved");
+        assert_eq!(approval["decided_by"], "bob");
+        assert_eq!(approval["reason"], "routine");
+    }
+
+    #[tokio::test]
+    async fn test_bind_keeps_socket_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("run").join("agent.sock");
+        let mut daemon = AgentDaemon::new(socket.clone(), dir.path().join("store"), false, true);
+        daemon.system = false;
+        let _listener = daemon.bind().await.unwrap();
+        assert_eq!(std::fs::metadata(socket.parent().unwrap()).unwrap().mode() & 0o777, 0o700);
+        assert_eq!(std::fs::metadata(&socket).unwrap().mode() & 0o777, 0o600);
+
+        let shared_dir = dir.path().join("shared");
+        std::fs::create_dir(&shared_dir).unwrap();
+        std::fs::set_permissions(&shared_dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        let mut daemon = AgentDaemon::new(shared_dir.join("agent.sock"), dir.path().join("store"), false, true);
+        daemon.system = false;
+        assert!(daemon.bind().await.is_err());
+    }
+
+    #[test]
+    fn test_systemd_unit() {
+        let unit = systemd_unit(Path::new("/usr/bin/linux-distro-agent"), false);
+        assert!(unit.contains("ExecStart=/usr/bin/linux-distro-agent agent --daemon --socket %t/linux-distro-agent/agent.sock"));
+        assert!(unit.contains("WantedBy=multi-user.target"));
+        assert!(systemd_unit(Path::new("/usr/bin/lda"), true).contains("WantedBy=default.target"));
+    }
+}
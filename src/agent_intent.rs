@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use crate::agent::TaskType;
+use crate::distro::DistroInfo;
+use crate::distro_builder::{DistroBuilder, DistroConfig};
+use crate::executor::{self, CommandStep};
+use crate::monitoring::{HealthStatus, SystemMonitor};
+use crate::package_manager::PackageManagerRegistry;
+use crate::security::SecurityAuditor;
+use crate::service_manager::ServiceManager;
+
+/// The phrases `agent --add-task` understands, shown when nothing matches
+const SUPPORTED: &[&str] = &[
+    "install <package>...",
+    "remove <package>...",
+    "update system",
+    "audit security",
+    "check disk",
+    "restart service <name>",
+    "build distro from <config.toml>",
+];
+
+/// A task described in words, carried out by the matching subsystem
+/// instead of a shell command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Intent {
+    Install { packages: Vec<String> },
+    Remove { packages: Vec<String> },
+    UpdateSystem,
+    AuditSecurity,
+    CheckDisk,
+    RestartService { service: String },
+    BuildDistro { config: PathBuf },
+}
+
+impl Intent {
+    /// Match a description against the fixed intent grammar. Keywords are
+    /// case-insensitive; package, service and file names are kept as written.
+    pub fn parse(text: &str) -> Result<Self> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let keywords: Vec<String> = words.iter().map(|word| word.to_lowercase()).collect();
+        let keywords: Vec<&str> = keywords.iter().map(String::as_str).collect();
+
+        let intent = match keywords.as_slice() {
+            ["install", rest @ ..] if !rest.is_empty() => Self::Install { packages: names(&words[1..], "package")? },
+            ["remove" | "uninstall", rest @ ..] if !rest.is_empty() => Self::Remove { packages: names(&words[1..], "package")? },
+            ["update" | "upgrade"] | ["update" | "upgrade", "system"] => Self::UpdateSystem,
+            ["audit", "security"] | ["security", "audit"] => Self::AuditSecurity,
+            ["check", "disk" | "disks"] | ["check", "disk", "space"] => Self::CheckDisk,
+            ["restart", "service", _] => Self::RestartService { service: names(&words[2..], "service")?.remove(0) },
+            // `restart service` names no service and falls through to the usage text
+            ["restart", name] if *name != "service" => Self::RestartService { service: names(&words[1..], "service")?.remove(0) },
+            ["build", "distro", "from", _] | ["build", "distro", _] => {
+                let path = words[words.len() - 1];
+                let config = std::fs::canonicalize(path)
+                    .with_context(|| format!("Distro config not found: {}", path))?;
+                Self::BuildDistro { config }
+            }
+            _ => anyhow::bail!("Don't know how to \"{}\". Supported tasks:\n  {}", text.trim(), SUPPORTED.join("\n  ")),
+        };
+        Ok(intent)
+    }
+
+    pub fn task_type(&self) -> TaskType {
+        match self {
+            Self::Install { .. } | Self::Remove { .. } | Self::UpdateSystem => TaskType::PackageManagement,
+            Self::AuditSecurity => TaskType::SecurityAudit,
+            Self::CheckDisk => TaskType::Monitoring,
+            Self::RestartService { .. } => TaskType::SystemConfiguration,
+            Self::BuildDistro { .. } => TaskType::DistroBuilding,
+        }
+    }
+
+    /// The programs this intent runs, or `None` when it runs in-process
+    pub fn steps(&self) -> Result<Option<Vec<CommandStep>>> {
+        let steps = match self {
+            Self::Install { packages } | Self::Remove { packages } => {
+                let distro = DistroInfo::detect()?;
+                let registry = PackageManagerRegistry::new();
+                let backend = distro.backend(&registry)
+                    .context("No supported package manager found for this distribution")?;
+                let packages: Vec<String> = packages.iter().map(|package| distro.resolve_package_name(package)).collect();
+                if matches!(self, Self::Install { .. }) {
+                    backend.install_command(&packages)
+                } else {
+                    backend.remove_command(&packages)
+                }
+            }
+            Self::UpdateSystem => DistroInfo::detect()?.get_system_update_command()
+                .context("No supported package manager found for this distribution")?,
+            Self::RestartService { service } => {
+                let command = ServiceManager::new().get_service_command("restart", service)
+                    .context("Service manager cannot restart services")?;
+                let mut words = command.command.split_whitespace();
+                let program = words.next().context("Empty service command")?;
+                let step = CommandStep::new(program, words);
+                vec![if command.requires_root { step.with_root() } else { step }]
+            }
+            Self::AuditSecurity | Self::CheckDisk | Self::BuildDistro { .. } => return Ok(None),
+        };
+        Ok(Some(steps))
+    }
+
+    /// The equivalent command line, which the safety policy judges the task by
+    pub fn command_line(&self) -> Result<String> {
+        Ok(match self {
+            Self::AuditSecurity => "linux-distro-agent security --audit".to_string(),
+            Self::CheckDisk => "linux-distro-agent monitor --health".to_string(),
+            Self::BuildDistro { config } => format!("linux-distro-agent build-distro --config {}", config.display()),
+            _ => executor::render_steps(&self.steps()?.unwrap_or_default()),
+        })
+    }
+
+    /// Carry out an intent that has no steps and describe what happened
+    pub async fn run_in_process(&self) -> Result<String> {
+        match self {
+            Self::AuditSecurity => {
+                let report = tokio::task::spawn_blocking(|| SecurityAuditor::new().run_full_audit()).await??;
+                let summary = report.summary;
+                Ok(format!("Security audit: {} finding(s) ({} critical, {} high, {} medium, {} low)",
+                    summary.total_findings, summary.critical_count, summary.high_count, summary.medium_count, summary.low_count))
+            }
+            Self::CheckDisk => {
+                let checks = tokio::task::spawn_blocking(|| SystemMonitor::new().run_health_checks()).await?;
+                let disks: Vec<_> = checks.into_iter().filter(|check| check.name.starts_with("disk_usage")).collect();
+                let report = disks.iter()
+                    .map(|check| {
+                        let mount = check.details.as_ref().and_then(|details| details.get("mount_point")).map_or("?", String::as_str);
+                        format!("{}: {}", mount, check.message)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if disks.iter().any(|check| matches!(check.status, HealthStatus::Critical)) {
+                    anyhow::bail!("Disk space is critically low\n{}", report);
+                }
+                Ok(report)
+            }
+            Self::BuildDistro { config: path } => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read distro config: {path:?}"))?;
+                let config: DistroConfig = toml::from_str(&content)
+                    .with_context(|| format!("Invalid distro config: {path:?}"))?;
+                let base = path.parent().map(PathBuf::from).unwrap_or_default();
+                let outputs = DistroBuilder::new(config, base.join("work_dir"), base.join("output"))
+                    .resume(true)
+                    .build()
+                    .await?;
+                let outputs: Vec<String> = outputs.iter().map(|path| path.display().to_string()).collect();
+                Ok(format!("Created {}", outputs.join(", ")))
+            }
+            _ => anyhow::bail!("{} runs programs, not in-process", self),
+        }
+    }
+}
+
+impl fmt::Display for Intent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Install { packages } => write!(f, "install {}", packages.join(" ")),
+            Self::Remove { packages } => write!(f, "remove {}", packages.join(" ")),
+            Self::UpdateSystem => write!(f, "update system"),
+            Self::AuditSecurity => write!(f, "audit security"),
+            Self::CheckDisk => write!(f, "check disk"),
+            Self::RestartService { service } => write!(f, "restart service {}", service),
+            Self::BuildDistro { config } => write!(f, "build distro from {}", config.display()),
+        }
+    }
+}
+
+/// Package or service names, refusing anything a package manager or
+/// systemctl could mistake for an option or that isn't a plain name
+fn names(words: &[&str], kind: &str) -> Result<Vec<String>> {
+    words.iter()
+        .map(|word| {
+            let plain = word.chars().all(|c| c.is_ascii_alphanumeric() || "._+-:@".contains(c));
+            if !plain || word.starts_with('-') {
+                anyhow::bail!("Invalid {} name: {}", kind, word);
+            }
+            Ok(word.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::SafetyLevel;
+    use crate::policy::{Caller, Policy};
+
+    #[test]
+    fn test_parse_intents() {
+        assert_eq!(Intent::parse("install firefox vim").unwrap(),
+            Intent::Install { packages: vec!["firefox".to_string(), "vim".to_string()] });
+        assert_eq!(Intent::parse("Uninstall libreoffice-core").unwrap(),
+            Intent::Remove { packages: vec!["libreoffice-core".to_string()] });
+        assert_eq!(Intent::parse("update system").unwrap(), Intent::UpdateSystem);
+        assert_eq!(Intent::parse("Audit Security").unwrap(), Intent::AuditSecurity);
+        assert_eq!(Intent::parse("check disk space").unwrap(), Intent::CheckDisk);
+        assert_eq!(Intent::parse("restart service nginx").unwrap(), Intent::RestartService { service: "nginx".to_string() });
+        assert_eq!(Intent::parse("restart sshd").unwrap(), Intent::RestartService { service: "sshd".to_string() });
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("distro.toml");
+        std::fs::write(&config, "").unwrap();
+        let intent = Intent::parse(&format!("build distro from {}", config.display())).unwrap();
+        assert_eq!(intent, Intent::BuildDistro { config: std::fs::canonicalize(&config).unwrap() });
+        assert!(matches!(intent.task_type(), TaskType::DistroBuilding));
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_and_unsafe_input() {
+        let error = Intent::parse("make me a sandwich").unwrap_err().to_string();
+        assert!(error.contains("Supported tasks") && error.contains("install <package>..."), "{error}");
+        assert!(Intent::parse("install").is_err());
+        let error = Intent::parse("restart service").unwrap_err().to_string();
+        assert!(error.contains("restart service <name>"), "{error}");
+        assert!(Intent::parse("install vim;rm").is_err());
+        assert!(Intent::parse("remove --purge vim").is_err());
+        assert!(Intent::parse("build distro from /nonexistent/distro.toml").is_err());
+    }
+
+    #[test]
+    fn test_in_process_intents_need_approval() {
+        let policy = Policy::default();
+        let caller = Caller { user: "alice".to_string(), groups: vec!["wheel".to_string()] };
+        for intent in [Intent::AuditSecurity, Intent::BuildDistro { config: PathBuf::from("/srv/distro.toml") }] {
+            let decision = policy.evaluate(&intent.command_line().unwrap(), &caller);
+            assert!(decision.allowed());
+            assert_eq!(decision.level(), SafetyLevel::Risky, "{intent}");
+        }
+        assert_eq!(policy.evaluate(&Intent::CheckDisk.command_line().unwrap(), &caller).level(), SafetyLevel::Safe);
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use crate::policy::parse_command_line;
+
+/// Weight of the newest run in the moving-average duration
+const DURATION_WEIGHT: f64 = 0.3;
+
+/// Runs needed before a track record is trusted enough to warn about
+const MIN_RUNS_FOR_WARNING: u32 = 3;
+
+/// Success ratio below which a command is called unreliable
+const UNRELIABLE_RATIO: f64 = 0.5;
+
+/// What the agent has learned about one command signature across runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandStats {
+    pub runs: u32,
+    pub successes: u32,
+    /// Moving average of the run time, weighted towards recent runs
+    pub avg_duration: Duration,
+    pub last_failure: Option<String>,
+    pub last_run: SystemTime,
+}
+
+impl CommandStats {
+    pub fn success_ratio(&self) -> f64 {
+        if self.runs == 0 {
+            return 1.0;
+        }
+        self.successes as f64 / self.runs as f64
+    }
+
+    /// Whether the command has failed often enough to warn before running it
+    pub fn is_unreliable(&self) -> bool {
+        self.runs >= MIN_RUNS_FOR_WARNING && self.success_ratio() < UNRELIABLE_RATIO
+    }
+
+    pub fn record(&mut self, success: bool, duration: Duration, failure: Option<String>) {
+        self.avg_duration = if self.runs == 0 {
+            duration
+        } else {
+            self.avg_duration.mul_f64(1.0 - DURATION_WEIGHT) + duration.mul_f64(DURATION_WEIGHT)
+        };
+        self.runs += 1;
+        if success {
+            self.successes += 1;
+        } else if failure.is_some() {
+            self.last_failure = failure;
+        }
+        self.last_run = SystemTime::now();
+    }
+}
+
+/// Learned statistics keyed by command signature
+pub type CommandHistory = HashMap<String, CommandStats>;
+
+/// Record one run of `command` in the history
+pub fn record_run(history: &mut CommandHistory, command: &str, success: bool, duration: Duration, error: Option<&str>) {
+    // The last line of the error output usually says what went wrong
+    let failure = error.and_then(|error| error.lines().rev().find(|line| !line.trim().is_empty()))
+        .map(|line| line.trim().chars().take(200).collect());
+    history.entry(command_signature(command))
+        .or_insert_with(|| CommandStats {
+            runs: 0,
+            successes: 0,
+            avg_duration: Duration::ZERO,
+            last_failure: None,
+            last_run: SystemTime::now(),
+        })
+        .record(success, duration, failure);
+}
+
+/// What the history knows about `command`, if it has run before
+pub fn lookup<'a>(history: &'a CommandHistory, command: &str) -> Option<&'a CommandStats> {
+    history.get(&command_signature(command))
+}
+
+/// Group commands that do the same kind of work: each program's name plus
+/// its subcommand, so `sudo apt-get -y install vim` and `apt-get install
+/// curl` share `apt-get install`
+pub fn command_signature(command: &str) -> String {
+    let parts: Vec<String> = parse_command_line(command).iter()
+        .map(|invocation| {
+            let program = invocation.program.rsplit('/').next().unwrap_or(&invocation.program);
+            let verb = invocation.args.iter()
+                .find(|arg| !arg.starts_with('-'))
+                .filter(|arg| arg.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && arg.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+            match verb {
+                Some(verb) => format!("{} {}", program, verb),
+                None => program.to_string(),
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        command.trim().to_string()
+    } else {
+        parts.join(" | ")
+    }
+}
+
+/// Adaptive ordering between two otherwise equal commands: reliable ones
+/// before ones that tend to fail, then quick ones before slow ones
+pub fn compare_commands(history: &CommandHistory, a: &str, b: &str) -> Ordering {
+    let stats = |command| lookup(history, command)
+        .map(|stats| (stats.success_ratio(), stats.avg_duration))
+        .unwrap_or((1.0, Duration::MAX));
+    let (a_ratio, a_duration) = stats(a);
+    let (b_ratio, b_duration) = stats(b);
+    b_ratio.total_cmp(&a_ratio).then(a_duration.cmp(&b_duration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_signature() {
+        assert_eq!(command_signature("sudo apt-get -y install vim"), "apt-get install");
+        assert_eq!(command_signature("/usr/bin/apt-get install curl"), "apt-get install");
+        assert_eq!(command_signature("sleep 5 && systemctl restart nginx"), "sleep | systemctl restart");
+        assert_eq!(command_signature("sh -c 'systemctl restart nginx'"), "systemctl restart");
+        assert_eq!(command_signature("sh ./setup.sh"), "sh");
+    }
+
+    #[test]
+    fn test_history_tracks_duration_and_failures() {
+        let mut history = CommandHistory::new();
+        record_run(&mut history, "apt-get install vim", true, Duration::from_secs(10), None);
+        record_run(&mut history, "apt-get install curl", false, Duration::from_secs(20), Some("Command failed: E: Unable to locate package curl\n"));
+        let stats = lookup(&history, "sudo apt-get install htop").unwrap();
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.avg_duration, Duration::from_secs(13));
+        assert_eq!(stats.last_failure.as_deref(), Some("Command failed: E: Unable to locate package curl"));
+        assert!(!stats.is_unreliable());
+
+        record_run(&mut history, "apt-get install x", false, Duration::from_secs(1), Some("boom"));
+        assert!(lookup(&history, "apt-get install y").unwrap().is_unreliable());
+
+        record_run(&mut history, "true", true, Duration::from_secs(1), None);
+        assert_eq!(compare_commands(&history, "true", "apt-get install z"), Ordering::Less);
+        assert_eq!(compare_commands(&history, "true", "never-run"), Ordering::Less);
+    }
+}
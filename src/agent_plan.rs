@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+use crate::agent::{IntelligentAgent, Priority, Task, TaskType};
+use crate::agent_schedule::{MissedRuns, TaskSchedule};
+
+/// Metadata key recording the plan task name a queued task came from
+pub const PLAN_TASK: &str = "plan_task";
+
+/// A file of named agent tasks, loaded with `lda agent --plan plan.toml`
+/// so routine maintenance can live in version control.
+///
+/// ```toml
+/// [[task]]
+/// name = "refresh"
+/// command = "apt-get update"
+/// task_type = "PackageManagement"
+/// priority = "High"
+///
+/// [[task]]
+/// name = "upgrade"
+/// command = "apt-get -y upgrade"
+/// dependencies = ["refresh"]
+/// max_retries = 1
+/// metadata = { timeout_secs = 1800 }
+///
+/// [[task]]
+/// name = "audit"
+/// command = "lynis audit system"
+/// schedule = "0 2 * * *"
+/// missed = "catch-up"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskPlan {
+    #[serde(rename = "task", default)]
+    pub tasks: Vec<PlanTask>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanTask {
+    /// Name other tasks use in `dependencies`
+    pub name: String,
+    pub command: String,
+    /// Defaults to the task name
+    pub description: Option<String>,
+    /// Guessed from the description when omitted
+    pub task_type: Option<TaskType>,
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    pub max_retries: Option<u32>,
+    /// Cron expression, `@daily`-style macro or `@every <interval>`
+    pub schedule: Option<String>,
+    /// Missed-run policy for scheduled tasks
+    pub missed: Option<MissedRuns>,
+    /// Free-form task metadata; non-string values are stored as written
+    #[serde(default)]
+    pub metadata: HashMap<String, toml::Value>,
+}
+
+impl TaskPlan {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read task plan: {path:?}"))?;
+        Self::parse(&content).with_context(|| format!("Invalid task plan: {path:?}"))
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let plan: TaskPlan = toml::from_str(content)?;
+        plan.check_names()?;
+        Ok(plan)
+    }
+
+    /// Every name must be unique and every dependency must name a task in the plan
+    fn check_names(&self) -> Result<()> {
+        if self.tasks.is_empty() {
+            anyhow::bail!("Plan contains no [[task]] entries");
+        }
+
+        let mut names = HashSet::new();
+        for task in &self.tasks {
+            if task.name.trim().is_empty() {
+                anyhow::bail!("Every task needs a name");
+            }
+            if !names.insert(task.name.as_str()) {
+                anyhow::bail!("Duplicate task name: {}", task.name);
+            }
+        }
+
+        let scheduled: HashSet<&str> = self.tasks.iter()
+            .filter(|task| task.schedule.is_some())
+            .map(|task| task.name.as_str())
+            .collect();
+        for task in &self.tasks {
+            if task.missed.is_some() && task.schedule.is_none() {
+                anyhow::bail!("Task {} sets missed without a schedule", task.name);
+            }
+            if scheduled.contains(task.name.as_str()) && !task.dependencies.is_empty() {
+                anyhow::bail!("Scheduled task {} can't have dependencies", task.name);
+            }
+            for dependency in &task.dependencies {
+                if scheduled.contains(dependency.as_str()) {
+                    anyhow::bail!("Task {} depends on scheduled task {}", task.name, dependency);
+                }
+                if dependency == &task.name {
+                    anyhow::bail!("Task {} depends on itself", task.name);
+                }
+                if !names.contains(dependency.as_str()) {
+                    anyhow::bail!("Task {} depends on unknown task {}", task.name, dependency);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Turn the plan into queue tasks with fresh ids, keeping file order
+    /// between tasks of equal priority
+    pub fn into_tasks(self, agent: &IntelligentAgent) -> Result<Vec<Task>> {
+        let mut tasks: Vec<Task> = self.tasks.iter()
+            .map(|entry| -> Result<Task> {
+                let description = entry.description.clone().unwrap_or_else(|| entry.name.clone());
+                let task_type = entry.task_type.clone()
+                    .unwrap_or_else(|| TaskType::from_description(&description));
+                let (program, args) = entry.command.split_once(' ').unwrap_or((entry.command.as_str(), ""));
+                let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+
+                let mut task = agent.create_task_from_command(program, &args, task_type);
+                task.command = entry.command.clone();
+                task.description = description;
+                if let Some(priority) = &entry.priority {
+                    task.priority = priority.clone();
+                }
+                if let Some(max_retries) = entry.max_retries {
+                    task.max_retries = max_retries;
+                }
+                for (key, value) in &entry.metadata {
+                    let value = match value {
+                        toml::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    task.metadata.insert(key.clone(), value);
+                }
+                task.metadata.insert(PLAN_TASK.to_string(), entry.name.clone());
+                if let Some(spec) = &entry.schedule {
+                    task.schedule = Some(TaskSchedule::new(spec, entry.missed.unwrap_or_default())
+                        .with_context(|| format!("Task {}", entry.name))?);
+                }
+                Ok(task)
+            })
+            .collect::<Result<_>>()?;
+
+        let ids: HashMap<&str, String> = self.tasks.iter()
+            .zip(&tasks)
+            .map(|(entry, task)| (entry.name.as_str(), task.id.clone()))
+            .collect();
+        let base = tasks.first().map(|task| task.created_at);
+        for (i, (entry, task)) in self.tasks.iter().zip(tasks.iter_mut()).enumerate() {
+            task.dependencies = entry.dependencies.iter().map(|name| ids[name.as_str()].clone()).collect();
+            if let Some(base) = base {
+                task.created_at = base + Duration::from_micros(i as u64);
+            }
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent_store::AgentStore;
+
+    const PLAN: &str = r#"
+        [[task]]
+        name = "refresh"
+        command = "apt-get update"
+        priority = "High"
+
+        [[task]]
+        name = "upgrade"
+        command = "apt-get -y upgrade"
+        task_type = "PackageManagement"
+        dependencies = ["refresh"]
+        max_retries = 1
+        metadata = { timeout_secs = 1800, window = "night" }
+    "#;
+
+    fn agent(dir: &Path) -> IntelligentAgent {
+        IntelligentAgent::with_store(AgentStore::open(dir).unwrap(), false, true).unwrap()
+    }
+
+    #[test]
+    fn test_plan_becomes_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let tasks = TaskPlan::parse(PLAN).unwrap().into_tasks(&agent(dir.path())).unwrap();
+
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].priority, Priority::High);
+        assert_eq!(tasks[1].command, "apt-get -y upgrade");
+        assert_eq!(tasks[1].dependencies, vec![tasks[0].id.clone()]);
+        assert_eq!(tasks[1].max_retries, 1);
+        assert_eq!(tasks[1].metadata["timeout_secs"], "1800");
+        assert_eq!(tasks[1].metadata["window"], "night");
+        assert_eq!(tasks[1].metadata[PLAN_TASK], "upgrade");
+        assert!(tasks[0].created_at < tasks[1].created_at);
+    }
+
+    #[test]
+    fn test_plan_rejects_bad_references() {
+        let unknown = "[[task]]\nname = \"a\"\ncommand = \"true\"\ndependencies = [\"b\"]\n";
+        assert!(TaskPlan::parse(unknown).unwrap_err().to_string().contains("unknown task b"));
+
+        let duplicate = "[[task]]\nname = \"a\"\ncommand = \"true\"\n[[task]]\nname = \"a\"\ncommand = \"false\"\n";
+        assert!(TaskPlan::parse(duplicate).unwrap_err().to_string().contains("Duplicate"));
+
+        assert!(TaskPlan::parse("[[task]]\nname = \"a\"\ncommand = \"true\"\nretries = 2\n").is_err());
+
+        let scheduled = "[[task]]\nname = \"a\"\ncommand = \"true\"\nschedule = \"@daily\"\n[[task]]\nname = \"b\"\ncommand = \"true\"\ndependencies = [\"a\"]\n";
+        assert!(TaskPlan::parse(scheduled).unwrap_err().to_string().contains("scheduled task a"));
+    }
+
+    #[test]
+    fn test_unsafe_plan_is_not_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut agent = agent(dir.path());
+        let plan = "[[task]]\nname = \"ok\"\ncommand = \"true\"\n[[task]]\nname = \"wipe\"\ncommand = \"rm -rf /\"\n";
+        let tasks = TaskPlan::parse(plan).unwrap().into_tasks(&agent).unwrap();
+
+        let error = agent.add_tasks(tasks).unwrap_err().to_string();
+        assert!(error.contains("wipe"));
+        assert!(agent.get_state().current_tasks.is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// How late a run may start before it counts as missed
+pub const MISSED_GRACE: Duration = Duration::from_secs(60);
+
+/// What to do about runs that came due while no agent was running
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MissedRuns {
+    /// Wait for the next scheduled time
+    #[default]
+    Skip,
+    /// Run once as soon as possible, however many runs were missed
+    CatchUp,
+}
+
+impl FromStr for MissedRuns {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "catch-up" => Ok(Self::CatchUp),
+            other => anyhow::bail!("Unknown missed-run policy: {} (expected skip or catch-up)", other),
+        }
+    }
+}
+
+/// When a recurring task runs. Kept on the task it repeats, which is queued
+/// afresh every time it comes due.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskSchedule {
+    /// Cron expression (`0 2 * * *`), macro (`@daily`) or interval (`@every 6h`)
+    pub spec: String,
+    #[serde(default)]
+    pub missed: MissedRuns,
+    pub next_run: SystemTime,
+    pub last_run: Option<SystemTime>,
+    /// The task most recently queued from this schedule
+    pub last_task: Option<String>,
+}
+
+impl TaskSchedule {
+    pub fn new(spec: &str, missed: MissedRuns) -> Result<Self> {
+        let recurrence = Recurrence::parse(spec)?;
+        let next_run = recurrence.next_after(SystemTime::now())
+            .with_context(|| format!("Schedule {} never runs", spec))?;
+        Ok(Self { spec: spec.trim().to_string(), missed, next_run, last_run: None, last_task: None })
+    }
+
+    /// What to do now: `Some(true)` to queue a run, `Some(false)` to skip a
+    /// missed run, `None` when nothing is due yet. Either way a due schedule
+    /// moves on to its next run after `now`; the caller records the run.
+    pub fn advance(&mut self, now: SystemTime) -> Result<Option<bool>> {
+        if self.next_run > now {
+            return Ok(None);
+        }
+        let late = now.duration_since(self.next_run).unwrap_or_default();
+        let run = late <= MISSED_GRACE || self.missed == MissedRuns::CatchUp;
+        self.next_run = Recurrence::parse(&self.spec)?.next_after(now)
+            .with_context(|| format!("Schedule {} never runs again", self.spec))?;
+        Ok(Some(run))
+    }
+}
+
+/// A parsed schedule spec
+#[derive(Debug, Clone, PartialEq)]
+pub enum Recurrence {
+    Every(Duration),
+    Cron(CronExpr),
+}
+
+impl Recurrence {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        if let Some(interval) = spec.strip_prefix("@every") {
+            return Ok(Self::Every(parse_interval(interval.trim())?));
+        }
+        let expr = match spec {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        Ok(Self::Cron(CronExpr::parse(expr).with_context(|| format!("Invalid schedule: {}", spec))?))
+    }
+
+    /// The first run strictly after `after`, in local time for cron expressions
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        match self {
+            Self::Every(interval) => after.checked_add(*interval),
+            Self::Cron(expr) => {
+                let after = chrono::DateTime::<Local>::from(after).naive_local();
+                let next = expr.next_after(after)?;
+                // Skip times that don't exist because of a DST change
+                let mut candidate = next;
+                loop {
+                    if let Some(local) = Local.from_local_datetime(&candidate).earliest() {
+                        return Some(local.into());
+                    }
+                    candidate = expr.next_after(candidate)?;
+                }
+            }
+        }
+    }
+}
+
+/// `90s`, `30m`, `6h`, `1d`, `2w` or combinations such as `1h30m`
+fn parse_interval(text: &str) -> Result<Duration> {
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => anyhow::bail!("Invalid interval: {}", text),
+        };
+        let value: u64 = digits.parse().with_context(|| format!("Invalid interval: {}", text))?;
+        total = value.checked_mul(unit)
+            .and_then(|seconds| total.checked_add(seconds))
+            .with_context(|| format!("Interval is too long: {}", text))?;
+        digits.clear();
+    }
+    if !digits.is_empty() || total == 0 {
+        anyhow::bail!("Invalid interval: {} (use e.g. 30m, 6h or 1d)", text);
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// A five-field cron expression: minute, hour, day of month, month, day of week
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Cron runs on either a matching day of month or day of week when both are restricted
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronExpr {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            anyhow::bail!("Expected 5 fields (minute hour day month weekday), found {}", fields.len());
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &["sun", "mon", "tue", "wed", "thu", "fri", "sat"])?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[])?,
+            hours: parse_field(hour, 0, 23, &[])?,
+            days: parse_field(day, 1, 31, &[])?,
+            months: parse_field(month, 1, 12, &["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"])?,
+            weekdays,
+            days_restricted: *day != "*",
+            weekdays_restricted: *weekday != "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first matching minute strictly after `after`, looking up to five years ahead
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = after + ChronoDuration::days(5 * 366);
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        while t <= limit {
+            if self.months & (1 << t.month()) == 0 {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(t.date()) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += ChronoDuration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+/// One cron field as a bit set: `*`, `5`, `1-5`, `*/15`, `1-30/2`, lists of
+/// those, and names where the field has them
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |text: &str| -> Result<u32> {
+        let lower = text.to_lowercase();
+        if let Some(i) = names.iter().position(|name| *name == lower) {
+            return Ok(min + i as u32);
+        }
+        let n: u32 = text.parse().with_context(|| format!("Invalid value {} in {}", text, field))?;
+        if n < min || n > max {
+            anyhow::bail!("{} is out of range {}-{} in {}", n, min, max, field);
+        }
+        Ok(n)
+    };
+
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)
+                .with_context(|| format!("Invalid step in {}", field))?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let n = value(range)?;
+                    (n, n)
+                }
+            },
+        };
+        if start > end {
+            anyhow::bail!("Invalid range {} in {}", range, field);
+        }
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        CronExpr::parse(expr).unwrap().next_after(at(after)).unwrap().format("%Y-%m-%d %H:%M").to_string()
+    }
+
+    #[test]
+    fn test_cron_next_run() {
+        assert_eq!(next("0 2 * * *", "2026-03-10 01:59"), "2026-03-10 02:00");
+        assert_eq!(next("0 2 * * *", "2026-03-10 02:00"), "2026-03-11 02:00");
+        assert_eq!(next("*/15 * * * *", "2026-03-10 10:07"), "2026-03-10 10:15");
+        assert_eq!(next("30 4 * * sun", "2026-03-10 00:00"), "2026-03-15 04:30");
+        assert_eq!(next("0 0 1 jan *", "2026-03-10 00:00"), "2027-01-01 00:00");
+        assert_eq!(next("0 0 29 2 *", "2026-03-10 00:00"), "2028-02-29 00:00");
+        // Day of month or day of week when both are given
+        assert_eq!(next("0 12 1 * 1", "2026-03-10 00:00"), "2026-03-16 12:00");
+        assert_eq!(next("0 0 * * 7", "2026-03-10 00:00"), "2026-03-15 00:00");
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        assert_eq!(Recurrence::parse("@every 1h30m").unwrap(), Recurrence::Every(Duration::from_secs(5400)));
+        assert_eq!(Recurrence::parse("@daily").unwrap(), Recurrence::parse("0 0 * * *").unwrap());
+        assert!(Recurrence::parse("@every 0m").is_err());
+        assert!(Recurrence::parse("@every soon").is_err());
+        assert!(Recurrence::parse("@every 99999999999999w").is_err());
+        assert!(Recurrence::parse("@every 18446744073709551615s1s").is_err());
+        assert!(Recurrence::parse("61 * * * *").is_err());
+        assert!(Recurrence::parse("* * *").is_err());
+        assert!(Recurrence::parse("0 0 31 2 *").unwrap().next_after(SystemTime::now()).is_none());
+    }
+
+    #[test]
+    fn test_missed_runs() {
+        let now = SystemTime::now();
+        let mut schedule = TaskSchedule::new("@every 1h", MissedRuns::Skip).unwrap();
+        assert_eq!(schedule.advance(now).unwrap(), None);
+
+        schedule.next_run = now - Duration::from_secs(10);
+        assert_eq!(schedule.advance(now).unwrap(), Some(true));
+        assert_eq!(schedule.next_run, now + Duration::from_secs(3600));
+
+        schedule.next_run = now - Duration::from_secs(7200);
+        assert_eq!(schedule.advance(now).unwrap(), Some(false));
+        schedule.missed = MissedRuns::CatchUp;
+        schedule.next_run = now - Duration::from_secs(7200);
+        assert_eq!(schedule.advance(now).unwrap(), Some(true));
+    }
+}
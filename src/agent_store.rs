@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use crate::agent::AgentState;
+
+/// On-disk agent state shared by every `lda agent` invocation.
+///
+/// The state lives in a JSON file next to a lock file. Readers take a shared
+/// lock and writers an exclusive one, so an `--add-task` never loses a task
+/// that a running `--start` is about to record a result next to.
+pub struct AgentStore {
+    state_path: PathBuf,
+    lock_path: PathBuf,
+}
+
+impl AgentStore {
+    /// Store in the default directory
+    pub fn open_default() -> Result<Self> {
+        Self::open(&Self::default_dir()?)
+    }
+
+    /// The system-wide store for root, which the system daemon serves to
+    /// every user, otherwise the user's own
+    pub fn default_dir() -> Result<PathBuf> {
+        // SAFETY: geteuid has no preconditions and cannot fail
+        if unsafe { libc::geteuid() } == 0 {
+            return Ok(PathBuf::from("/var/lib/linux-distro-agent/agent"));
+        }
+        Ok(dirs::data_local_dir()
+            .context("Unable to determine data directory")?
+            .join("linux-distro-agent")
+            .join("agent"))
+    }
+
+    /// Store in an explicit directory
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create agent state directory: {dir:?}"))?;
+        Ok(Self {
+            state_path: dir.join("state.json"),
+            lock_path: dir.join("state.lock"),
+        })
+    }
+
+    /// Read the current state
+    pub fn load(&self) -> Result<AgentState> {
+        let lock = self.lock_file()?;
+        lock.lock_shared().context("Failed to lock agent state")?;
+        self.read()
+    }
+
+    /// Load, modify and save the state while holding the exclusive lock
+    pub fn update<T>(&self, f: impl FnOnce(&mut AgentState) -> T) -> Result<T> {
+        let lock = self.lock_file()?;
+        lock.lock().context("Failed to lock agent state")?;
+
+        let mut state = self.read()?;
+        let value = f(&mut state);
+        state.last_update = std::time::SystemTime::now();
+        self.write(&state)?;
+        Ok(value)
+    }
+
+    fn lock_file(&self) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.lock_path)
+            .with_context(|| format!("Failed to open agent lock file: {:?}", self.lock_path))
+    }
+
+    fn read(&self) -> Result<AgentState> {
+        if !self.state_path.exists() {
+            return Ok(AgentState::default());
+        }
+        let content = fs::read_to_string(&self.state_path)
+            .with_context(|| format!("Failed to read agent state file: {:?}", self.state_path))?;
+        serde_json::from_str(&content).with_context(|| "Failed to parse agent state file")
+    }
+
+    /// Write through a temporary file so a crash never leaves half a state file
+    fn write(&self, state: &AgentState) -> Result<()> {
+        let content = serde_json::to_string_pretty(state)
+            .with_context(|| "Failed to serialize agent state")?;
+        let temp_path = self.state_path.with_extension("json.tmp");
+        fs::write(&temp_path, content)
+            .with_context(|| format!("Failed to write agent state file: {temp_path:?}"))?;
+        fs::rename(&temp_path, &self.state_path)
+            .with_context(|| format!("Failed to replace agent state file: {:?}", self.state_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Priority, SafetyLevel, Task, TaskType};
+    use std::collections::HashMap;
+    use std::time::{Duration, SystemTime};
+
+    fn task(id: &str) -> Task {
+        Task {
+            id: id.to_string(),
+            task_type: TaskType::Monitoring,
+            command: "true".to_string(),
+            description: id.to_string(),
+            priority: Priority::Low,
+            safety_level: SafetyLevel::Safe,
+            dependencies: Vec::new(),
+            estimated_duration: Duration::from_secs(1),
+            retry_count: 0,
+            max_retries: 0,
+            created_at: SystemTime::now(),
+            metadata: HashMap::new(),
+            intent: None,
+            schedule: None,
+            approval: None,
+        }
+    }
+
+    #[test]
+    fn test_state_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        AgentStore::open(dir.path()).unwrap()
+            .update(|state| state.current_tasks.push(task("a")))
+            .unwrap();
+
+        let state = AgentStore::open(dir.path()).unwrap().load().unwrap();
+        assert_eq!(state.current_tasks.len(), 1);
+        assert_eq!(state.current_tasks[0].id, "a");
+    }
+
+    #[test]
+    fn test_concurrent_updates_are_not_lost() {
+        let dir = tempfile::tempdir().unwrap();
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let path = dir.path().to_path_buf();
+                std::thread::spawn(move || {
+                    let store = AgentStore::open(&path).unwrap();
+                    store.update(|state| state.current_tasks.push(task(&format!("t{i}")))).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let state = AgentStore::open(dir.path()).unwrap().load().unwrap();
+        assert_eq!(state.current_tasks.len(), 8);
+    }
+}
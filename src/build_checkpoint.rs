@@ -0,0 +1,224 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The stages of a distro build, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildStep {
+    Setup,
+    Rootfs,
+    Kernel,
+    Packages,
+    Configure,
+    Branding,
+    Bootloader,
+    /// Formerly only the ISO
+    #[serde(alias = "iso")]
+    Images,
+}
+
+impl BuildStep {
+    pub const ALL: [BuildStep; 8] = [
+        Self::Setup,
+        Self::Rootfs,
+        Self::Kernel,
+        Self::Packages,
+        Self::Configure,
+        Self::Branding,
+        Self::Bootloader,
+        Self::Images,
+    ];
+
+    /// Position in the build, counting from 1
+    pub fn number(self) -> usize {
+        Self::ALL.iter().position(|step| *step == self).unwrap_or_default() + 1
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Setup => "setup",
+            Self::Rootfs => "rootfs",
+            Self::Kernel => "kernel",
+            Self::Packages => "packages",
+            Self::Configure => "configure",
+            Self::Branding => "branding",
+            Self::Bootloader => "bootloader",
+            Self::Images => "images",
+        }
+    }
+
+    /// What the step does, for progress output
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Setup => "Setting up build directories",
+            Self::Rootfs => "Building root filesystem",
+            Self::Kernel => "Installing kernel",
+            Self::Packages => "Installing packages",
+            Self::Configure => "Configuring system",
+            Self::Branding => "Applying branding",
+            Self::Bootloader => "Configuring bootloader",
+            Self::Images => "Creating images",
+        }
+    }
+
+    /// The builder function and error type reported when the step fails
+    pub fn error_kind(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Setup => ("setup_directories", "filesystem"),
+            Self::Rootfs => ("build_rootfs", "bootstrap"),
+            Self::Kernel => ("install_kernel", "package_installation"),
+            Self::Packages => ("install_packages", "package_installation"),
+            Self::Configure => ("configure_system", "configuration"),
+            Self::Branding => ("apply_branding", "branding"),
+            Self::Bootloader => ("configure_bootloader", "bootloader"),
+            Self::Images => ("create_images", "image_creation"),
+        }
+    }
+}
+
+impl fmt::Display for BuildStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for BuildStep {
+    type Err = anyhow::Error;
+
+    /// A step name (`packages`) or number (`4`)
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim().to_lowercase();
+        if s == "iso" {
+            return Ok(Self::Images);
+        }
+        Self::ALL.iter()
+            .find(|step| step.name() == s || step.number().to_string() == s)
+            .copied()
+            .with_context(|| format!("Unknown build step: {} (expected 1-8 or one of {})", s,
+                Self::ALL.map(|step| step.name()).join(", ")))
+    }
+}
+
+/// A finished build step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub step: BuildStep,
+    /// Hash of the configuration the step read, chained with the steps before it
+    pub input_hash: String,
+    /// What the step produced; the step is redone if any of these is gone
+    pub outputs: Vec<PathBuf>,
+    pub completed_at: DateTime<Utc>,
+}
+
+/// The steps a build in a work directory has finished, so `--resume` can
+/// pick up after the last step whose inputs are unchanged
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildCheckpoint {
+    /// Hash of the whole configuration of the latest build
+    pub config_hash: String,
+    pub steps: Vec<StepRecord>,
+}
+
+impl BuildCheckpoint {
+    pub const FILE: &'static str = "checkpoint.json";
+
+    /// The checkpoint in `work_dir`, or an empty one if there is none or it
+    /// can't be read
+    pub fn load(work_dir: &Path) -> Self {
+        fs::read_to_string(work_dir.join(Self::FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, work_dir: &Path) -> Result<()> {
+        let path = work_dir.join(Self::FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write build checkpoint: {}", tmp.display()))?;
+        fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to write build checkpoint: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn get(&self, step: BuildStep) -> Option<&StepRecord> {
+        self.steps.iter().find(|record| record.step == step)
+    }
+
+    /// The record of `step` if it finished with the same inputs and its outputs still exist
+    pub fn reusable(&self, step: BuildStep, input_hash: &str) -> Option<&StepRecord> {
+        self.get(step)
+            .filter(|record| record.input_hash == input_hash && record.outputs.iter().all(|output| output.exists()))
+    }
+
+    /// Forget `step` and everything after it, which a rerun of `step` invalidates
+    pub fn invalidate_from(&mut self, step: BuildStep) {
+        self.steps.retain(|record| record.step < step);
+    }
+
+    pub fn record(&mut self, step: BuildStep, input_hash: String, outputs: Vec<PathBuf>) {
+        self.invalidate_from(step);
+        self.steps.push(StepRecord { step, input_hash, outputs, completed_at: Utc::now() });
+    }
+}
+
+/// Hash a step's inputs together with the hash of the step before it, so a
+/// change early in the build invalidates every later step
+pub fn input_hash(previous: &str, inputs: &serde_json::Value) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(previous.as_bytes());
+    hasher.update(inputs.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hash of a file's contents, for inputs that name files
+pub fn file_hash(path: &Path) -> Option<String> {
+    let content = fs::read(path).ok()?;
+    Some(format!("{:x}", Sha256::digest(&content)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_steps() {
+        assert_eq!("packages".parse::<BuildStep>().unwrap(), BuildStep::Packages);
+        assert_eq!("8".parse::<BuildStep>().unwrap(), BuildStep::Images);
+        assert_eq!("iso".parse::<BuildStep>().unwrap(), BuildStep::Images);
+        assert_eq!(BuildStep::Rootfs.number(), 2);
+        assert!("9".parse::<BuildStep>().is_err());
+        assert!("xorriso".parse::<BuildStep>().is_err());
+    }
+
+    #[test]
+    fn test_checkpoint_reuse() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path().join("rootfs");
+        fs::create_dir(&rootfs).unwrap();
+
+        let mut checkpoint = BuildCheckpoint::default();
+        checkpoint.record(BuildStep::Rootfs, "a".to_string(), vec![rootfs.clone()]);
+        checkpoint.record(BuildStep::Kernel, "b".to_string(), vec![rootfs.clone()]);
+        checkpoint.save(dir.path()).unwrap();
+
+        let mut checkpoint = BuildCheckpoint::load(dir.path());
+        assert!(checkpoint.reusable(BuildStep::Rootfs, "a").is_some());
+        assert!(checkpoint.reusable(BuildStep::Rootfs, "changed").is_none());
+        assert!(checkpoint.reusable(BuildStep::Packages, "c").is_none());
+
+        // Redoing a step forgets the steps after it
+        checkpoint.record(BuildStep::Rootfs, "a2".to_string(), vec![rootfs.clone()]);
+        assert!(checkpoint.get(BuildStep::Kernel).is_none());
+
+        fs::remove_dir(&rootfs).unwrap();
+        assert!(checkpoint.reusable(BuildStep::Rootfs, "a2").is_none());
+        assert_ne!(input_hash("", &serde_json::json!(1)), input_hash("x", &serde_json::json!(1)));
+    }
+}
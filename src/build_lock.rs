@@ -0,0 +1,161 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Days, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::distro_builder::{BaseSystem, DistroConfig};
+use crate::executor::CommandStep;
+
+/// An installed package as the lock records it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// The package manager's checksum, or a digest of the package's file list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+/// The point in a repository archive a build installed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositorySnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub url: String,
+}
+
+/// What a build resolved, written to `<name>.lock` so later builds of the
+/// same config install the same packages and produce the same images
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildLock {
+    pub base_system: BaseSystem,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_release: Option<String>,
+    pub architecture: String,
+    /// Every timestamp in the images is clamped to this
+    pub source_date_epoch: i64,
+    /// `None` when the base system has no snapshot archive, so only the
+    /// versions are recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<RepositorySnapshot>,
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl BuildLock {
+    /// The lock at `path`, or `None` if there is none yet
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lockfile: {}", path.display()))?;
+        let lock = toml::from_str(&content)
+            .with_context(|| format!("Invalid lockfile: {}", path.display()))?;
+        Ok(Some(lock))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = format!("# Generated by `lda build-distro`; keep it with the config to rebuild the same image\n\n{}",
+            toml::to_string(self)?);
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write lockfile: {}", path.display()))
+    }
+
+    /// Whether the lock was resolved for this base system and architecture
+    pub fn matches(&self, config: &DistroConfig) -> bool {
+        self.base_system == config.base_system
+            && self.base_release == config.base_release
+            && self.architecture == config.architecture
+    }
+
+    /// How `installed` differs from the locked packages, one line per package
+    pub fn changes(&self, installed: &[LockedPackage]) -> Vec<String> {
+        let find = |packages: &[LockedPackage], name: &str| packages.iter().find(|package| package.name == name).cloned();
+        let mut changes = Vec::new();
+        for locked in &self.packages {
+            match find(installed, &locked.name) {
+                None => changes.push(format!("- {} {}", locked.name, locked.version)),
+                Some(package) if package.version != locked.version =>
+                    changes.push(format!("~ {} {} -> {}", locked.name, locked.version, package.version)),
+                Some(package) if package.checksum.is_some() && locked.checksum.is_some() && package.checksum != locked.checksum =>
+                    changes.push(format!("~ {} {} (checksum changed)", locked.name, locked.version)),
+                Some(_) => {}
+            }
+        }
+        for package in installed {
+            if find(&self.packages, &package.name).is_none() {
+                changes.push(format!("+ {} {}", package.name, package.version));
+            }
+        }
+        changes
+    }
+}
+
+/// Parse `name version [checksum]` lines, sorted by name. Tools print
+/// `(none)` for a missing checksum.
+pub fn parse_installed(output: &str) -> Vec<LockedPackage> {
+    let mut packages: Vec<LockedPackage> = output.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?.to_string();
+            let version = fields.next()?.to_string();
+            let checksum = fields.next().filter(|checksum| *checksum != "(none)").map(str::to_string);
+            Some(LockedPackage { name, version, checksum })
+        })
+        .collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages.dedup_by(|a, b| a.name == b.name);
+    packages
+}
+
+/// The snapshot a build without a lock installs from: the start of the
+/// previous day, which every archive has finished publishing
+pub fn default_snapshot(now: DateTime<Utc>) -> DateTime<Utc> {
+    let midnight = now.date_naive().and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    midnight.checked_sub_days(Days::new(1)).unwrap_or(midnight)
+}
+
+/// Set every modification time in `dir` later than `epoch` back to it,
+/// symlinks included, so image tools that copy mtimes produce the same bytes
+pub fn clamp_mtimes_step(dir: &Path, epoch: i64) -> CommandStep {
+    let date = format!("@{}", epoch);
+    CommandStep::new("find", [
+        dir.display().to_string(),
+        "-newermt".to_string(), date.clone(),
+        "-exec".to_string(), "touch".to_string(), "--no-dereference".to_string(), format!("--date={}", date), "{}".to_string(), "+".to_string(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_round_trip_and_changes() {
+        let installed = parse_installed("zlib 1:1.3.1-2 (none)\nbash 5.2.037-1 sha256:ab\n\nbash 5.2.037-1 sha256:ab\n");
+        assert_eq!(installed.iter().map(|package| package.name.as_str()).collect::<Vec<_>>(), ["bash", "zlib"]);
+        assert_eq!(installed[1].checksum, None);
+
+        let snapshot = default_snapshot("2024-10-16T13:45:00Z".parse().unwrap());
+        assert_eq!(snapshot.to_rfc3339(), "2024-10-15T00:00:00+00:00");
+        let lock = BuildLock {
+            base_system: BaseSystem::Arch,
+            base_release: None,
+            architecture: "x86_64".to_string(),
+            source_date_epoch: snapshot.timestamp(),
+            snapshot: Some(RepositorySnapshot { timestamp: snapshot, url: "https://archive.archlinux.org/repos/2024/10/15/".to_string() }),
+            packages: installed.clone(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mylinux.lock");
+        lock.save(&path).unwrap();
+        let loaded = BuildLock::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.packages, installed);
+        assert_eq!(loaded.snapshot, lock.snapshot);
+        assert!(BuildLock::load(&dir.path().join("missing.lock")).unwrap().is_none());
+
+        let rebuilt = parse_installed("bash 5.2.037-2 sha256:cd\nvim 9.1-1\n");
+        assert_eq!(loaded.changes(&rebuilt), ["~ bash 5.2.037-1 -> 5.2.037-2", "- zlib 1:1.3.1-2", "+ vim 9.1-1"]);
+        assert!(loaded.changes(&installed).is_empty());
+    }
+}
@@ -0,0 +1,788 @@
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use crate::distro::DistroInfo;
+use crate::distro_builder::{BaseSystem, DesktopEnvironment, KernelType};
+use crate::executor::CommandStep;
+
+/// How the distro builder bootstraps and changes a root filesystem for one
+/// base system. Methods return the steps to run on the build host; the
+/// builder runs them and reports failures.
+pub trait BuilderBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Programs the build host needs
+    fn required_tools(&self) -> Vec<&'static str>;
+
+    /// Whether the backend can bootstrap `arch` (in `uname -m` spelling)
+    fn supports_architecture(&self, arch: &str) -> bool;
+
+    /// Steps that install a minimal base system into `root`
+    fn bootstrap(&self, root: &Path, arch: &str) -> Vec<CommandStep>;
+
+    /// Host files copied into the new root after bootstrapping, when present
+    fn host_files(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Packages the bootstrap already installs, left out of later installs
+    fn base_packages(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Refresh the package database in `root` before installing, if needed
+    fn refresh(&self, _root: &Path) -> Option<CommandStep> {
+        None
+    }
+
+    fn install(&self, root: &Path, arch: &str, packages: &[String]) -> CommandStep;
+
+    /// A step printing every installed package as `name version [checksum]`
+    fn list_installed(&self, root: &Path) -> CommandStep;
+
+    /// A file whose digest stands in for a package's checksum when
+    /// `list_installed` prints none
+    fn package_manifest(&self, _root: &Path, _name: &str, _version: &str) -> Option<PathBuf> {
+        None
+    }
+
+    /// The repository snapshot installs are pinned to, or `None` when the
+    /// base system has no snapshot archive and installs what its mirrors serve
+    fn snapshot_url(&self, _arch: &str) -> Option<String> {
+        None
+    }
+
+    /// Files pointing the package manager at the snapshot. Paths inside
+    /// `root` are written after bootstrapping, the rest before it.
+    fn repository_files(&self, _root: &Path, _arch: &str) -> Vec<(PathBuf, String)> {
+        Vec::new()
+    }
+
+    /// The package providing a kernel flavour, or `None` when the base
+    /// system doesn't ship it
+    fn kernel_package(&self, kernel: &KernelType, arch: &str) -> Option<String> {
+        match kernel {
+            KernelType::Custom(package) => Some(package.clone()),
+            kernel => self.kernel_name(kernel, arch),
+        }
+    }
+
+    fn kernel_name(&self, kernel: &KernelType, arch: &str) -> Option<String>;
+
+    /// The packages for a desktop, or `None` when the base system doesn't ship it
+    fn desktop_packages(&self, desktop: &DesktopEnvironment) -> Option<Vec<String>> {
+        match desktop {
+            DesktopEnvironment::Custom(package) => Some(vec![package.clone()]),
+            DesktopEnvironment::None => Some(Vec::new()),
+            desktop => self.desktop_names(desktop).map(|names| names.iter().map(|name| name.to_string()).collect()),
+        }
+    }
+
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]>;
+
+    /// Services every built system has enabled
+    fn default_services(&self) -> &'static [&'static str];
+
+    fn ssh_service(&self) -> &'static str {
+        "sshd.service"
+    }
+
+    fn enable_service(&self, root: &Path, service: &str) -> CommandStep {
+        systemctl(root, "enable", service)
+    }
+
+    fn disable_service(&self, root: &Path, service: &str) -> CommandStep {
+        systemctl(root, "disable", service)
+    }
+
+    /// Why this build host can't run the backend, if it can't
+    fn host_problem(&self) -> Option<String> {
+        None
+    }
+
+    /// The initramfs hook that mounts the live squashfs, or `None` when the
+    /// base system has none
+    fn live_boot(&self) -> Option<LiveBoot>;
+
+    /// Packages providing the live-boot hook
+    fn live_boot_packages(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Run a command inside `root`
+    fn chroot(&self, root: &Path, args: &[&str]) -> CommandStep {
+        in_root("chroot", root, args, &[])
+    }
+}
+
+/// Where `LiveBoot::disk_initramfs` puts the disk image initramfs, relative to the rootfs
+pub const DISK_INITRD: &str = "boot/initramfs-disk.img";
+
+/// An initramfs hook that finds the ISO by its volume label and mounts the
+/// squashfs on it as the root filesystem
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LiveBoot {
+    /// mkinitcpio's archiso hooks
+    Archiso,
+    /// Debian's live-boot for initramfs-tools
+    DebianLive,
+    /// dracut's dmsquash-live module
+    Dracut,
+}
+
+impl LiveBoot {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Archiso => "archiso",
+            Self::DebianLive => "live-boot",
+            Self::Dracut => "dracut dmsquash-live",
+        }
+    }
+
+    /// Where the hook looks for the squashfs, relative to the ISO root
+    pub fn squashfs_path(self, arch: &str) -> String {
+        match self {
+            Self::Archiso => format!("live/{}/airootfs.sfs", arch),
+            Self::DebianLive => "live/filesystem.squashfs".to_string(),
+            Self::Dracut => "LiveOS/squashfs.img".to_string(),
+        }
+    }
+
+    pub fn cmdline(self, label: &str) -> String {
+        match self {
+            Self::Archiso => format!("archisobasedir=live archisolabel={}", label),
+            Self::DebianLive => format!("boot=live live-media=/dev/disk/by-label/{} components", label),
+            Self::Dracut => format!("root=live:CDLABEL={} rd.live.image", label),
+        }
+    }
+
+    /// Drop-in configuration written into the rootfs, so the hook is also
+    /// kept when a later package install rebuilds the initramfs
+    pub fn config_files(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Archiso => &[(
+                "etc/mkinitcpio.conf.d/live.conf",
+                "HOOKS=(base udev microcode modconf kms archiso archiso_loop_mnt block filesystems keyboard)\n",
+            )],
+            Self::DebianLive => &[],
+            // The build host isn't the machine the image boots on
+            Self::Dracut => &[(
+                "etc/dracut.conf.d/live.conf",
+                "hostonly=\"no\"\nadd_dracutmodules+=\" dmsquash-live \"\n",
+            )],
+        }
+    }
+
+    /// The command, run inside the rootfs, that builds an initramfs at
+    /// `DISK_INITRD` without the hook, for disk images. Only archiso needs
+    /// one: it takes over every boot, while the others wait for their
+    /// command-line options.
+    pub fn disk_initramfs(self, kernel: &str) -> Option<Vec<String>> {
+        match self {
+            Self::Archiso => Some(vec![
+                "mkinitcpio".to_string(),
+                "-k".to_string(), format!("/boot/{}", kernel),
+                "-S".to_string(), "archiso,archiso_loop_mnt".to_string(),
+                "-g".to_string(), format!("/{}", DISK_INITRD),
+            ]),
+            Self::DebianLive | Self::Dracut => None,
+        }
+    }
+
+    /// The command, run inside the rootfs, that rebuilds every installed kernel's initramfs
+    pub fn regenerate_initramfs(self) -> &'static [&'static str] {
+        match self {
+            Self::Archiso => &["mkinitcpio", "-P"],
+            Self::DebianLive => &["update-initramfs", "-u", "-k", "all"],
+            Self::Dracut => &["dracut", "--force", "--regenerate-all"],
+        }
+    }
+}
+
+/// The backend for a base system, installing from the repository archive
+/// as of `snapshot` where the base system has one; `Scratch` has none
+pub fn for_base_system(base: &BaseSystem, release: Option<&str>, snapshot: Option<DateTime<Utc>>) -> Option<Box<dyn BuilderBackend>> {
+    let release = |default: &str| release.unwrap_or(default).to_string();
+    Some(match base {
+        BaseSystem::Arch => Box::new(Pacstrap { snapshot }),
+        BaseSystem::Debian => Box::new(Debootstrap {
+            suite: release("stable"),
+            mirror: "http://deb.debian.org/debian/",
+            ports_mirror: None,
+            components: "main",
+            ubuntu: false,
+            snapshot,
+        }),
+        BaseSystem::Ubuntu => Box::new(Debootstrap {
+            suite: release("jammy"),
+            mirror: "http://archive.ubuntu.com/ubuntu/",
+            ports_mirror: Some("http://ports.ubuntu.com/ubuntu-ports/"),
+            components: "main,universe",
+            ubuntu: true,
+            snapshot,
+        }),
+        BaseSystem::Fedora => Box::new(Dnf { release: release("41"), centos: false }),
+        BaseSystem::CentOS => Box::new(Dnf { release: release("9"), centos: true }),
+        BaseSystem::OpenSUSE => Box::new(Zypper { release: release("tumbleweed") }),
+        BaseSystem::Alpine => Box::new(Apk { release: release("latest-stable") }),
+        BaseSystem::Scratch => return None,
+    })
+}
+
+fn systemctl(root: &Path, action: &str, service: &str) -> CommandStep {
+    CommandStep::new("systemctl", [format!("--root={}", root.display()), action.to_string(), service.to_string()])
+}
+
+/// `program root args... packages...`, for tools that take the root as an argument
+fn in_root(program: &str, root: &Path, args: &[&str], packages: &[String]) -> CommandStep {
+    let mut words = vec![root.display().to_string()];
+    words.extend(args.iter().map(|arg| arg.to_string()));
+    words.extend(packages.iter().cloned());
+    CommandStep::new(program, words)
+}
+
+/// `rpm -qa` on `root`, with the header digest as the checksum
+fn rpm_installed(root: &Path) -> CommandStep {
+    CommandStep::new("rpm", [
+        "--root".to_string(), root.display().to_string(),
+        "-qa".to_string(), "--queryformat".to_string(), "%{NAME} %{EVR} %{SHA256HEADER}\\n".to_string(),
+    ])
+}
+
+/// Arch Linux through pacstrap, with pacman run inside arch-chroot. Pinned
+/// builds install from the Arch Linux Archive.
+pub struct Pacstrap {
+    snapshot: Option<DateTime<Utc>>,
+}
+
+impl Pacstrap {
+    /// The pacman.conf pacstrap uses for a pinned build, next to the root
+    fn pacman_conf(root: &Path) -> PathBuf {
+        root.with_file_name("pacman.conf")
+    }
+
+    fn server(&self) -> Option<String> {
+        self.snapshot_url("x86_64").map(|url| format!("Server = {}$repo/os/$arch\n", url))
+    }
+}
+
+impl BuilderBackend for Pacstrap {
+    fn name(&self) -> &'static str { "pacstrap" }
+    fn required_tools(&self) -> Vec<&'static str> { vec!["pacstrap", "arch-chroot"] }
+    fn supports_architecture(&self, arch: &str) -> bool { arch == "x86_64" }
+    fn bootstrap(&self, root: &Path, _arch: &str) -> Vec<CommandStep> {
+        let mut args = Vec::new();
+        if self.snapshot.is_some() {
+            args.extend(["-C".to_string(), Self::pacman_conf(root).display().to_string()]);
+        }
+        args.extend(["-c".to_string(), root.display().to_string()]);
+        args.extend(self.base_packages().iter().map(|package| package.to_string()));
+        vec![CommandStep::new("pacstrap", args)]
+    }
+    // Use the host's mirrors for installs inside the chroot, unless pinned
+    fn host_files(&self) -> &'static [&'static str] { &["/etc/pacman.d/mirrorlist"] }
+    fn base_packages(&self) -> &'static [&'static str] { &["base", "linux", "linux-firmware"] }
+    fn refresh(&self, root: &Path) -> Option<CommandStep> { Some(in_root("arch-chroot", root, &["pacman", "-Sy", "--noconfirm"], &[])) }
+    fn install(&self, root: &Path, _arch: &str, packages: &[String]) -> CommandStep {
+        in_root("arch-chroot", root, &["pacman", "-S", "--noconfirm", "--needed", "--noprogressbar"], packages)
+    }
+    fn list_installed(&self, root: &Path) -> CommandStep { self.chroot(root, &["pacman", "-Q"]) }
+    // The local database keeps no package checksum, but the mtree of installed files has one per file
+    fn package_manifest(&self, root: &Path, name: &str, version: &str) -> Option<PathBuf> {
+        Some(root.join("var/lib/pacman/local").join(format!("{}-{}", name, version)).join("mtree"))
+    }
+    fn snapshot_url(&self, _arch: &str) -> Option<String> {
+        self.snapshot.map(|snapshot| format!("https://archive.archlinux.org/repos/{}/", snapshot.format("%Y/%m/%d")))
+    }
+    fn repository_files(&self, root: &Path, _arch: &str) -> Vec<(PathBuf, String)> {
+        let Some(server) = self.server() else { return Vec::new() };
+        let pacman_conf = format!(
+            "[options]\nArchitecture = auto\nSigLevel = Required DatabaseOptional\nLocalFileSigLevel = Optional\n\n[core]\n{server}\n[extra]\n{server}");
+        vec![
+            (Self::pacman_conf(root), pacman_conf),
+            (root.join("etc/pacman.d/mirrorlist"), server),
+        ]
+    }
+    fn kernel_name(&self, kernel: &KernelType, _arch: &str) -> Option<String> {
+        Some(match kernel {
+            KernelType::Lts => "linux-lts",
+            KernelType::Hardened => "linux-hardened",
+            KernelType::Rt => "linux-rt",
+            _ => "linux",
+        }.to_string())
+    }
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]> {
+        Some(match desktop {
+            DesktopEnvironment::Gnome => &["gnome"],
+            DesktopEnvironment::Kde => &["plasma", "kde-applications"],
+            DesktopEnvironment::Xfce => &["xfce4", "xfce4-goodies"],
+            DesktopEnvironment::Lxde => &["lxde"],
+            DesktopEnvironment::Mate => &["mate"],
+            DesktopEnvironment::Cinnamon => &["cinnamon"],
+            DesktopEnvironment::Sway => &["sway"],
+            DesktopEnvironment::I3 => &["i3"],
+            _ => &[],
+        })
+    }
+    fn default_services(&self) -> &'static [&'static str] {
+        &["NetworkManager.service", "systemd-resolved.service", "systemd-timesyncd.service"]
+    }
+    fn live_boot(&self) -> Option<LiveBoot> { Some(LiveBoot::Archiso) }
+    fn live_boot_packages(&self) -> &'static [&'static str] { &["mkinitcpio-archiso"] }
+    fn chroot(&self, root: &Path, args: &[&str]) -> CommandStep { in_root("arch-chroot", root, args, &[]) }
+}
+
+/// Debian and Ubuntu through debootstrap, with apt-get run inside chroot
+pub struct Debootstrap {
+    suite: String,
+    mirror: &'static str,
+    /// Mirror for architectures the main archive doesn't carry
+    ports_mirror: Option<&'static str>,
+    components: &'static str,
+    ubuntu: bool,
+    /// Install from snapshot.debian.org or snapshot.ubuntu.com as of this time
+    snapshot: Option<DateTime<Utc>>,
+}
+
+impl Debootstrap {
+    fn ports(debian_arch: &str) -> bool {
+        !matches!(debian_arch, "amd64" | "i386")
+    }
+
+    fn mirror(&self, debian_arch: &str) -> String {
+        if let Some(url) = self.snapshot_url(debian_arch) {
+            return url;
+        }
+        match self.ports_mirror {
+            Some(ports) if Self::ports(debian_arch) => ports.to_string(),
+            _ => self.mirror.to_string(),
+        }
+    }
+
+    /// Debian's name for a `uname -m` architecture
+    fn debian_arch(arch: &str) -> Option<&'static str> {
+        Some(match arch {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "i686" | "i386" => "i386",
+            "armv7l" | "armv7h" => "armhf",
+            "ppc64le" => "ppc64el",
+            "riscv64" => "riscv64",
+            "s390x" => "s390x",
+            _ => return None,
+        })
+    }
+}
+
+impl BuilderBackend for Debootstrap {
+    fn name(&self) -> &'static str { "debootstrap" }
+    fn required_tools(&self) -> Vec<&'static str> { vec!["debootstrap", "chroot", "systemctl"] }
+    fn supports_architecture(&self, arch: &str) -> bool {
+        match Self::debian_arch(arch) {
+            Some("amd64" | "i386") => true,
+            Some(_) => !self.ubuntu || self.ports_mirror.is_some(),
+            None => false,
+        }
+    }
+    fn bootstrap(&self, root: &Path, arch: &str) -> Vec<CommandStep> {
+        let debian_arch = Self::debian_arch(arch).unwrap_or(arch);
+        vec![CommandStep::new("debootstrap", [
+            format!("--arch={}", debian_arch),
+            format!("--components={}", self.components),
+            "--include=systemd-sysv".to_string(),
+            self.suite.clone(),
+            root.display().to_string(),
+            self.mirror(debian_arch),
+        ])]
+    }
+    // Name resolution for apt inside the chroot
+    fn host_files(&self) -> &'static [&'static str] { &["/etc/resolv.conf"] }
+    fn refresh(&self, root: &Path) -> Option<CommandStep> { Some(in_root("chroot", root, &["apt-get", "update"], &[])) }
+    fn install(&self, root: &Path, _arch: &str, packages: &[String]) -> CommandStep {
+        in_root("chroot", root, &["apt-get", "install", "-y", "--no-install-recommends"], packages)
+            .with_env("DEBIAN_FRONTEND", "noninteractive")
+    }
+    fn list_installed(&self, root: &Path) -> CommandStep {
+        self.chroot(root, &["dpkg-query", "--show", "--showformat=${Package} ${Version}\\n"])
+    }
+    // dpkg keeps no package checksum, but has the md5sums of its files
+    fn package_manifest(&self, root: &Path, name: &str, _version: &str) -> Option<PathBuf> {
+        let info = root.join("var/lib/dpkg/info");
+        let plain = info.join(format!("{}.md5sums", name));
+        if plain.exists() {
+            return Some(plain);
+        }
+        // Multi-arch packages are listed as `name:arch`
+        let prefix = format!("{}:", name);
+        std::fs::read_dir(&info).ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .find(|path| path.file_name().and_then(|file| file.to_str())
+                .is_some_and(|file| file.starts_with(&prefix) && file.ends_with(".md5sums")))
+    }
+    fn snapshot_url(&self, arch: &str) -> Option<String> {
+        let timestamp = self.snapshot?.format("%Y%m%dT%H%M%SZ");
+        let debian_arch = Self::debian_arch(arch).unwrap_or(arch);
+        Some(match (self.ubuntu, self.ports_mirror.is_some() && Self::ports(debian_arch)) {
+            (false, _) => format!("https://snapshot.debian.org/archive/debian/{}/", timestamp),
+            (true, false) => format!("https://snapshot.ubuntu.com/ubuntu/{}/", timestamp),
+            (true, true) => format!("https://snapshot.ubuntu.com/ubuntu-ports/{}/", timestamp),
+        })
+    }
+    // debootstrap writes the snapshot mirror to sources.list; its Release files have long expired
+    fn repository_files(&self, root: &Path, _arch: &str) -> Vec<(PathBuf, String)> {
+        if self.snapshot.is_none() {
+            return Vec::new();
+        }
+        vec![(root.join("etc/apt/apt.conf.d/80snapshot"), "Acquire::Check-Valid-Until \"false\";\n".to_string())]
+    }
+    fn kernel_name(&self, kernel: &KernelType, arch: &str) -> Option<String> {
+        let arch = Self::debian_arch(arch)?;
+        match (kernel, self.ubuntu) {
+            (KernelType::Vanilla | KernelType::Lts, true) => Some("linux-generic".to_string()),
+            (KernelType::Vanilla | KernelType::Lts, false) => Some(format!("linux-image-{}", arch)),
+            (KernelType::Rt, false) => Some(format!("linux-image-rt-{}", arch)),
+            _ => None,
+        }
+    }
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]> {
+        Some(match desktop {
+            DesktopEnvironment::Gnome => &["gnome-core"],
+            DesktopEnvironment::Kde => &["kde-plasma-desktop"],
+            DesktopEnvironment::Xfce => &["xfce4", "xfce4-goodies"],
+            DesktopEnvironment::Lxde => &["lxde"],
+            DesktopEnvironment::Mate => &["mate-desktop-environment"],
+            DesktopEnvironment::Cinnamon => &["cinnamon-desktop-environment"],
+            DesktopEnvironment::Sway => &["sway"],
+            DesktopEnvironment::I3 => &["i3"],
+            _ => &[],
+        })
+    }
+    fn default_services(&self) -> &'static [&'static str] {
+        &["systemd-networkd.service", "systemd-timesyncd.service"]
+    }
+    fn ssh_service(&self) -> &'static str { "ssh.service" }
+    fn live_boot(&self) -> Option<LiveBoot> { Some(LiveBoot::DebianLive) }
+    fn live_boot_packages(&self) -> &'static [&'static str] { &["live-boot", "live-boot-initramfs-tools"] }
+}
+
+/// Fedora and CentOS Stream through `dnf --installroot`
+pub struct Dnf {
+    release: String,
+    centos: bool,
+}
+
+impl Dnf {
+    /// Options every dnf call into the root shares. The root has no
+    /// repository config of its own until `@core` is in, so dnf reads the
+    /// host's: dnf5 (Fedora) through `--use-host-config`, dnf4 (CentOS) by
+    /// pointing `reposdir` at it.
+    fn root_args(&self, root: &Path, arch: &str) -> Vec<String> {
+        vec![
+            format!("--installroot={}", root.display()),
+            if self.centos { "--setopt=reposdir=/etc/yum.repos.d".to_string() } else { "--use-host-config".to_string() },
+            format!("--releasever={}", self.release),
+            format!("--forcearch={}", arch),
+        ]
+    }
+}
+
+impl BuilderBackend for Dnf {
+    fn name(&self) -> &'static str { "dnf" }
+    fn required_tools(&self) -> Vec<&'static str> { vec!["dnf", "rpm", "chroot", "systemctl"] }
+    fn supports_architecture(&self, arch: &str) -> bool { matches!(arch, "x86_64" | "aarch64" | "ppc64le" | "s390x") }
+    fn bootstrap(&self, root: &Path, arch: &str) -> Vec<CommandStep> {
+        let mut args = self.root_args(root, arch);
+        args.extend(["--setopt=install_weak_deps=False", "-y", "install", "@core"].map(str::to_string));
+        vec![CommandStep::new("dnf", args)]
+    }
+    fn base_packages(&self) -> &'static [&'static str] { &["@core"] }
+    fn install(&self, root: &Path, arch: &str, packages: &[String]) -> CommandStep {
+        let mut args = self.root_args(root, arch);
+        args.extend(["-y".to_string(), "install".to_string()]);
+        args.extend(packages.iter().cloned());
+        CommandStep::new("dnf", args)
+    }
+    fn list_installed(&self, root: &Path) -> CommandStep { rpm_installed(root) }
+    fn kernel_name(&self, kernel: &KernelType, _arch: &str) -> Option<String> {
+        match kernel {
+            KernelType::Vanilla => Some("kernel".to_string()),
+            KernelType::Rt if self.centos => Some("kernel-rt".to_string()),
+            _ => None,
+        }
+    }
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]> {
+        match desktop {
+            DesktopEnvironment::Gnome => Some(&["@gnome-desktop"]),
+            // Everything else comes from EPEL on CentOS
+            _ if self.centos => None,
+            DesktopEnvironment::Kde => Some(&["@kde-desktop"]),
+            DesktopEnvironment::Xfce => Some(&["@xfce-desktop"]),
+            DesktopEnvironment::Lxde => Some(&["@lxde-desktop"]),
+            DesktopEnvironment::Mate => Some(&["@mate-desktop"]),
+            DesktopEnvironment::Cinnamon => Some(&["@cinnamon-desktop"]),
+            DesktopEnvironment::Sway => Some(&["sway"]),
+            DesktopEnvironment::I3 => Some(&["i3"]),
+            _ => Some(&[]),
+        }
+    }
+    fn default_services(&self) -> &'static [&'static str] { &["NetworkManager.service", "chronyd.service"] }
+    fn live_boot(&self) -> Option<LiveBoot> { Some(LiveBoot::Dracut) }
+    fn live_boot_packages(&self) -> &'static [&'static str] { &["dracut-live"] }
+    // dnf installs from the host's repository configuration
+    fn host_problem(&self) -> Option<String> {
+        let host = DistroInfo::detect().ok()?;
+        let ids = format!("{} {}", host.id.unwrap_or_default(), host.id_like.unwrap_or_default());
+        let matches = if self.centos {
+            ["centos", "rhel"].iter().any(|id| ids.split_whitespace().any(|word| word == *id))
+        } else {
+            ids.split_whitespace().any(|word| word == "fedora")
+        };
+        (!matches).then(|| format!("dnf installs from the build host's repositories, so {} images must be built on {}",
+            if self.centos { "CentOS" } else { "Fedora" },
+            if self.centos { "CentOS, RHEL or a rebuild" } else { "Fedora" }))
+    }
+}
+
+/// openSUSE through `zypper --root`, with the release's OSS repository
+pub struct Zypper {
+    release: String,
+}
+
+impl Zypper {
+    fn repository(&self) -> String {
+        if self.release == "tumbleweed" {
+            "https://download.opensuse.org/tumbleweed/repo/oss/".to_string()
+        } else {
+            format!("https://download.opensuse.org/distribution/leap/{}/repo/oss/", self.release)
+        }
+    }
+
+    fn zypper(root: &Path, args: &[&str], packages: &[String]) -> CommandStep {
+        let mut words = vec!["--root".to_string(), root.display().to_string(), "--non-interactive".to_string(), "--gpg-auto-import-keys".to_string()];
+        words.extend(args.iter().map(|arg| arg.to_string()));
+        words.extend(packages.iter().cloned());
+        CommandStep::new("zypper", words)
+    }
+}
+
+impl BuilderBackend for Zypper {
+    fn name(&self) -> &'static str { "zypper" }
+    fn required_tools(&self) -> Vec<&'static str> { vec!["zypper", "rpm", "chroot", "systemctl"] }
+    fn supports_architecture(&self, arch: &str) -> bool { arch == std::env::consts::ARCH }
+    fn bootstrap(&self, root: &Path, _arch: &str) -> Vec<CommandStep> {
+        vec![
+            Self::zypper(root, &["addrepo", "--refresh", &self.repository(), "repo-oss"], &[]),
+            Self::zypper(root, &["install", "--no-recommends", "pattern:minimal_base"], &[]),
+        ]
+    }
+    fn base_packages(&self) -> &'static [&'static str] { &["pattern:minimal_base"] }
+    fn refresh(&self, root: &Path) -> Option<CommandStep> { Some(Self::zypper(root, &["refresh"], &[])) }
+    fn install(&self, root: &Path, _arch: &str, packages: &[String]) -> CommandStep {
+        Self::zypper(root, &["install", "--no-recommends"], packages)
+    }
+    fn list_installed(&self, root: &Path) -> CommandStep { rpm_installed(root) }
+    fn kernel_name(&self, kernel: &KernelType, _arch: &str) -> Option<String> {
+        match kernel {
+            KernelType::Vanilla => Some("kernel-default".to_string()),
+            KernelType::Lts if self.release == "tumbleweed" => Some("kernel-longterm".to_string()),
+            KernelType::Rt if self.release != "tumbleweed" => Some("kernel-rt".to_string()),
+            _ => None,
+        }
+    }
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]> {
+        Some(match desktop {
+            DesktopEnvironment::Gnome => &["pattern:gnome_basic"],
+            DesktopEnvironment::Kde => &["pattern:kde_plasma"],
+            DesktopEnvironment::Xfce => &["pattern:xfce"],
+            DesktopEnvironment::Lxde => &["pattern:lxde"],
+            DesktopEnvironment::Mate => &["pattern:mate"],
+            DesktopEnvironment::Cinnamon => &["cinnamon"],
+            DesktopEnvironment::Sway => &["sway"],
+            DesktopEnvironment::I3 => &["i3"],
+            _ => &[],
+        })
+    }
+    fn default_services(&self) -> &'static [&'static str] { &["NetworkManager.service", "chronyd.service"] }
+    fn live_boot(&self) -> Option<LiveBoot> { Some(LiveBoot::Dracut) }
+    fn live_boot_packages(&self) -> &'static [&'static str] { &["dracut"] }
+}
+
+/// Alpine through `apk --root`, which uses OpenRC instead of systemd
+pub struct Apk {
+    release: String,
+}
+
+impl Apk {
+    fn repository(&self, part: &str) -> String {
+        let release = if self.release == "latest-stable" || self.release == "edge" {
+            self.release.clone()
+        } else {
+            format!("v{}", self.release.trim_start_matches('v'))
+        };
+        format!("https://dl-cdn.alpinelinux.org/alpine/{}/{}", release, part)
+    }
+
+    /// Alpine's name for a `uname -m` architecture
+    fn apk_arch(arch: &str) -> Option<&'static str> {
+        Some(match arch {
+            "x86_64" => "x86_64",
+            "i686" | "x86" => "x86",
+            "aarch64" => "aarch64",
+            "armv7l" | "armv7h" | "armv7" => "armv7",
+            "ppc64le" => "ppc64le",
+            "riscv64" => "riscv64",
+            "s390x" => "s390x",
+            _ => return None,
+        })
+    }
+
+    fn rc_update(root: &Path, action: &str, service: &str) -> CommandStep {
+        in_root("chroot", root, &["rc-update", action, service, "default"], &[])
+    }
+}
+
+impl BuilderBackend for Apk {
+    fn name(&self) -> &'static str { "apk" }
+    fn required_tools(&self) -> Vec<&'static str> { vec!["apk", "chroot"] }
+    fn supports_architecture(&self, arch: &str) -> bool { Self::apk_arch(arch).is_some() }
+    // Packages are verified with the host's keys in /etc/apk/keys
+    fn bootstrap(&self, root: &Path, arch: &str) -> Vec<CommandStep> {
+        vec![CommandStep::new("apk", [
+            "--root".to_string(), root.display().to_string(),
+            "--arch".to_string(), Self::apk_arch(arch).unwrap_or(arch).to_string(),
+            "--repository".to_string(), self.repository("main"),
+            "--repository".to_string(), self.repository("community"),
+            "--keys-dir".to_string(), "/etc/apk/keys".to_string(),
+            "--initdb".to_string(), "--update-cache".to_string(),
+            "add".to_string(), "alpine-base".to_string(),
+        ])]
+    }
+    fn host_files(&self) -> &'static [&'static str] { &["/etc/resolv.conf"] }
+    fn base_packages(&self) -> &'static [&'static str] { &["alpine-base"] }
+    fn install(&self, root: &Path, _arch: &str, packages: &[String]) -> CommandStep {
+        let mut args = vec![
+            "--root".to_string(), root.display().to_string(),
+            "--repository".to_string(), self.repository("main"),
+            "--repository".to_string(), self.repository("community"),
+            "--keys-dir".to_string(), "/etc/apk/keys".to_string(),
+            "--update-cache".to_string(), "add".to_string(),
+        ];
+        args.extend(packages.iter().cloned());
+        CommandStep::new("apk", args)
+    }
+    // The installed database has a `C:` checksum line before each package's `P:` and `V:`
+    fn list_installed(&self, root: &Path) -> CommandStep {
+        CommandStep::new("awk", [
+            "-F:".to_string(),
+            "/^C:/ { checksum = substr($0, 3) } /^P:/ { name = $2 } /^V:/ { print name, $2, checksum }".to_string(),
+            root.join("lib/apk/db/installed").display().to_string(),
+        ])
+    }
+    fn kernel_name(&self, kernel: &KernelType, _arch: &str) -> Option<String> {
+        match kernel {
+            KernelType::Vanilla => Some("linux-edge".to_string()),
+            KernelType::Lts => Some("linux-lts".to_string()),
+            _ => None,
+        }
+    }
+    fn desktop_names(&self, desktop: &DesktopEnvironment) -> Option<&'static [&'static str]> {
+        match desktop {
+            DesktopEnvironment::Gnome => Some(&["gnome"]),
+            DesktopEnvironment::Kde => Some(&["plasma"]),
+            DesktopEnvironment::Xfce => Some(&["xfce4"]),
+            DesktopEnvironment::Mate => Some(&["mate-desktop-environment"]),
+            DesktopEnvironment::Sway => Some(&["sway"]),
+            DesktopEnvironment::I3 => Some(&["i3wm"]),
+            DesktopEnvironment::Lxde | DesktopEnvironment::Cinnamon => None,
+            _ => Some(&[]),
+        }
+    }
+    fn default_services(&self) -> &'static [&'static str] { &["networking", "crond"] }
+    fn ssh_service(&self) -> &'static str { "sshd" }
+    fn enable_service(&self, root: &Path, service: &str) -> CommandStep { Self::rc_update(root, "add", service) }
+    fn disable_service(&self, root: &Path, service: &str) -> CommandStep { Self::rc_update(root, "del", service) }
+    // mkinitfs boots Alpine's own media layout (modloop and apkovl), not a squashfs root
+    fn live_boot(&self) -> Option<LiveBoot> { None }
+    fn host_problem(&self) -> Option<String> {
+        (!Path::new("/etc/apk/keys").is_dir())
+            .then(|| "apk needs the Alpine signing keys in /etc/apk/keys (install alpine-keys)".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(step: &CommandStep) -> String {
+        step.to_string()
+    }
+
+    #[test]
+    fn test_backends_for_base_systems() {
+        let root = Path::new("/work/rootfs");
+        let packages = vec!["vim".to_string()];
+        let cases = [
+            (BaseSystem::Arch, "arch-chroot /work/rootfs pacman -S --noconfirm --needed --noprogressbar vim"),
+            (BaseSystem::Debian, "DEBIAN_FRONTEND=noninteractive chroot /work/rootfs apt-get install -y --no-install-recommends vim"),
+            (BaseSystem::Fedora, "dnf --installroot=/work/rootfs --use-host-config --releasever=41 --forcearch=aarch64 -y install vim"),
+            (BaseSystem::OpenSUSE, "zypper --root /work/rootfs --non-interactive --gpg-auto-import-keys install --no-recommends vim"),
+        ];
+        for (base, expected) in cases {
+            let backend = for_base_system(&base, None, None).unwrap();
+            assert_eq!(render(&backend.install(root, "aarch64", &packages)), expected);
+        }
+        assert!(for_base_system(&BaseSystem::Scratch, None, None).is_none());
+
+        let alpine = for_base_system(&BaseSystem::Alpine, Some("3.20"), None).unwrap();
+        assert_eq!(render(&alpine.enable_service(root, "sshd")), "chroot /work/rootfs rc-update add sshd default");
+        assert!(render(&alpine.install(root, "x86_64", &packages)).contains("alpine/v3.20/main"));
+        assert_eq!(render(&for_base_system(&BaseSystem::Arch, None, None).unwrap().enable_service(root, "sshd.service")),
+            "systemctl --root=/work/rootfs enable sshd.service");
+    }
+
+    #[test]
+    fn test_debootstrap_architectures_and_kernels() {
+        let debian = for_base_system(&BaseSystem::Debian, Some("bookworm"), None).unwrap();
+        let bootstrap = render(&debian.bootstrap(Path::new("/r"), "aarch64")[0]);
+        assert_eq!(bootstrap, "debootstrap --arch=arm64 --components=main --include=systemd-sysv bookworm /r http://deb.debian.org/debian/");
+        assert_eq!(debian.kernel_package(&KernelType::Vanilla, "x86_64").as_deref(), Some("linux-image-amd64"));
+        assert_eq!(debian.kernel_package(&KernelType::Hardened, "x86_64"), None);
+
+        let ubuntu = for_base_system(&BaseSystem::Ubuntu, None, None).unwrap();
+        assert!(render(&ubuntu.bootstrap(Path::new("/r"), "aarch64")[0]).ends_with("http://ports.ubuntu.com/ubuntu-ports/"));
+        assert!(!ubuntu.supports_architecture("mips"));
+        assert_eq!(ubuntu.kernel_package(&KernelType::Custom("linux-oem-22.04".to_string()), "x86_64").as_deref(), Some("linux-oem-22.04"));
+
+        let arch = for_base_system(&BaseSystem::Arch, None, None).unwrap();
+        assert_eq!(render(&arch.chroot(Path::new("/r"), LiveBoot::Archiso.regenerate_initramfs())), "arch-chroot /r mkinitcpio -P");
+        assert_eq!(LiveBoot::Dracut.cmdline("MYLINUX"), "root=live:CDLABEL=MYLINUX rd.live.image");
+        assert_eq!(ubuntu.live_boot().unwrap().squashfs_path("x86_64"), "live/filesystem.squashfs");
+
+        let centos = for_base_system(&BaseSystem::CentOS, None, None).unwrap();
+        assert_eq!(render(&centos.bootstrap(Path::new("/r"), "x86_64")[0]),
+            "dnf --installroot=/r --setopt=reposdir=/etc/yum.repos.d --releasever=9 --forcearch=x86_64 --setopt=install_weak_deps=False -y install @core");
+        assert!(centos.desktop_packages(&DesktopEnvironment::Kde).is_none());
+        assert_eq!(centos.desktop_packages(&DesktopEnvironment::None), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_snapshot_pinning() {
+        let snapshot = "2024-10-15T00:00:00Z".parse().ok();
+        let root = Path::new("/work/rootfs");
+        let arch = for_base_system(&BaseSystem::Arch, None, snapshot).unwrap();
+        assert_eq!(render(&arch.bootstrap(root, "x86_64")[0]), "pacstrap -C /work/pacman.conf -c /work/rootfs base linux linux-firmware");
+        let files = arch.repository_files(root, "x86_64");
+        assert_eq!(files[1], (root.join("etc/pacman.d/mirrorlist"), "Server = https://archive.archlinux.org/repos/2024/10/15/$repo/os/$arch\n".to_string()));
+        assert!(files[0].1.contains("[core]\nServer = https://archive.archlinux.org/repos/2024/10/15/"));
+
+        let debian = for_base_system(&BaseSystem::Debian, Some("bookworm"), snapshot).unwrap();
+        assert!(render(&debian.bootstrap(root, "aarch64")[0]).ends_with("bookworm /work/rootfs https://snapshot.debian.org/archive/debian/20241015T000000Z/"));
+        let ubuntu = for_base_system(&BaseSystem::Ubuntu, None, snapshot).unwrap();
+        assert_eq!(ubuntu.snapshot_url("aarch64").as_deref(), Some("https://snapshot.ubuntu.com/ubuntu-ports/20241015T000000Z/"));
+        assert_eq!(render(&debian.list_installed(root)), "chroot /work/rootfs dpkg-query --show '--showformat=${Package} ${Version}\\n'");
+
+        let fedora = for_base_system(&BaseSystem::Fedora, None, snapshot).unwrap();
+        assert!(fedora.snapshot_url("x86_64").is_none());
+        assert!(fedora.repository_files(root, "x86_64").is_empty());
+        assert!(for_base_system(&BaseSystem::Arch, None, None).unwrap().repository_files(root, "x86_64").is_empty());
+    }
+}
@@ -6,6 +6,7 @@ use std::fs;
 use std::path::PathBuf;
 
 use crate::distro::DistroInfo;
+use crate::inventory::InstalledPackage;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheEntry {
@@ -18,6 +19,7 @@ pub enum CacheData {
     DistroInfo(DistroInfo),
     PackageList(Vec<String>),
     PackageInfo { package: String, info: String },
+    InstalledPackages(Vec<InstalledPackage>),
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -129,5 +131,14 @@ impl CacheManager {
         entries.sort_by(|a, b| b.created_at.cmp(&a.created_at));
         Ok(entries)
     }
-    
+
+    /// Store data under a key, replacing any previous entry
+    pub fn store(&mut self, key: &str, data: CacheData) -> Result<()> {
+        self.cache.entries.insert(key.to_string(), CacheEntry {
+            timestamp: Utc::now(),
+            data,
+        });
+        self.cache.save()
+    }
+
 }
@@ -1,4 +1,4 @@
-use dialoguer::{Input, Confirm, Select};
+use dialoguer::{Input, Confirm, MultiSelect, Select};
 use anyhow::Result;
 use crate::distro_builder::{
     DistroConfig, BuildOptions, UserConfig, PackageConfig, KernelConfig,
@@ -7,6 +7,7 @@ use crate::distro_builder::{
     FilesystemType, CompressionType, UserAccount, NetworkConfig, ServicesConfig,
     ColorScheme, ProgressReporting, HostnameStrategy
 };
+use crate::disk_image::OutputFormat;
 use std::path::PathBuf;
 
 pub struct ConfigWizard;
@@ -67,13 +68,21 @@ impl ConfigWizard {
         // Package configuration
         println!();
         println!("📦 Package Configuration");
+        // Package names differ between base systems; scratch builds install none
+        let default_essential = match base_system {
+            BaseSystem::Arch => "base,linux,systemd,bash,coreutils",
+            BaseSystem::Scratch => "",
+            _ => "sudo,bash,coreutils",
+        };
         let essential_packages: String = Input::new()
             .with_prompt("Essential packages (comma-separated)")
-            .default("base,linux,systemd,bash,coreutils".to_string())
+            .default(default_essential.to_string())
+            .allow_empty(true)
             .interact_text()?;
         let essential_packages: Vec<String> = essential_packages
             .split(',')
             .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
             .collect();
 
         let desktop_environments = vec!["None", "GNOME", "KDE", "XFCE", "LXDE", "i3", "Sway", "Cinnamon", "MATE"];
@@ -271,6 +280,23 @@ impl ConfigWizard {
             size_limit: None,
         };
 
+        let formats = [
+            ("Live ISO", OutputFormat::Iso),
+            ("Raw disk image (needs an ext4, btrfs or xfs root)", OutputFormat::Raw),
+            ("qcow2 disk image (needs an ext4, btrfs or xfs root)", OutputFormat::Qcow2),
+            ("Rootfs tarball", OutputFormat::Tarball),
+            ("OCI container image", OutputFormat::Oci),
+        ];
+        let format_indices = MultiSelect::new()
+            .with_prompt("Output formats (space to toggle)")
+            .items(&formats.map(|(label, _)| label))
+            .defaults(&[true, false, false, false, false])
+            .interact()?;
+        let mut output_formats: Vec<OutputFormat> = format_indices.into_iter().map(|i| formats[i].1).collect();
+        if output_formats.is_empty() {
+            output_formats.push(OutputFormat::Iso);
+        }
+
         // Build options
         println!();
         println!("⚙️  Build Options");
@@ -451,11 +477,13 @@ impl ConfigWizard {
             description,
             architecture,
             base_system,
+            base_release: None,
             packages,
             kernel,
             bootloader,
             branding,
             filesystem,
+            output_formats,
             build_options,
             user_config,
             validation,
@@ -473,6 +501,7 @@ impl ConfigWizard {
         println!("Kernel Type: {:?}", config.kernel.kernel_type);
         println!("Bootloader: {:?}", config.bootloader.bootloader);
         println!("Filesystem: {:?}", config.filesystem.root_fs);
+        println!("Output Formats: {:?}", config.output_formats);
         if let Some(user) = &config.user_config.default_user {
             println!("Default User: {}", user.username);
         }
@@ -0,0 +1,351 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
+use crate::distro_builder::{CompressionType, FilesystemType};
+use crate::executor::CommandStep;
+
+const MIB: u64 = 1024 * 1024;
+/// Where the first partition starts, and room for the backup GPT at the end
+const ALIGNMENT_MIB: u64 = 1;
+
+/// What a build produces from the finished rootfs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    /// A hybrid BIOS/UEFI live ISO
+    Iso,
+    /// A GPT disk image: an EFI system partition and the root filesystem
+    Raw,
+    /// The raw disk image converted for QEMU and libvirt
+    Qcow2,
+    /// A tarball of the rootfs
+    Tarball,
+    /// An OCI image layout directory with the rootfs as its only layer
+    Oci,
+}
+
+impl OutputFormat {
+    /// Whether the format is built from the partitioned disk image
+    pub fn is_disk(self) -> bool {
+        matches!(self, Self::Raw | Self::Qcow2)
+    }
+}
+
+pub fn default_output_formats() -> Vec<OutputFormat> {
+    vec![OutputFormat::Iso]
+}
+
+/// The Discoverable Partitions Specification type of a root partition, so
+/// systemd can find it without a `root=`
+pub fn root_partition_type(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" => Some("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709"),
+        "i686" => Some("44479540-F297-41B2-9AF7-D131D5F0458A"),
+        "aarch64" => Some("B921B045-1DF0-41C3-AF44-4C6F280D3FAE"),
+        "armv7h" => Some("69DAD710-2CE4-4E3C-B16C-21A1D49ABED3"),
+        _ => None,
+    }
+}
+
+/// A UUID derived from `seed`, so rebuilding the same config gives the same
+/// partition and disk IDs
+pub fn stable_uuid(seed: &str) -> String {
+    let digest = Sha256::digest(seed.as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_random_bytes(bytes).into_uuid().to_string()
+}
+
+/// A FAT volume ID derived from `seed`, which GRUB's `search --fs-uuid`
+/// sees as `XXXX-XXXX`
+pub fn stable_volume_id(seed: &str) -> u32 {
+    let digest = Sha256::digest(seed.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+pub fn fat_uuid(volume_id: u32) -> String {
+    format!("{:04X}-{:04X}", volume_id >> 16, volume_id & 0xffff)
+}
+
+/// Size in MiB of a root partition holding `content_kib` of files, with a
+/// third of headroom for filesystem metadata and first-boot writes
+pub fn root_size_mib(content_kib: u64) -> u64 {
+    (content_kib * 4 / 3).div_ceil(1024) + 256
+}
+
+/// Create `image` as a sparse file of `size_mib`, replacing any old one
+pub fn create_sparse(image: &Path, size_mib: u64) -> Result<()> {
+    let file = File::create(image)
+        .with_context(|| format!("Failed to create {}", image.display()))?;
+    file.set_len(size_mib * MIB)?;
+    Ok(())
+}
+
+/// The command that formats the root filesystem image `image` with the
+/// contents of `rootfs`, without mounting it
+/// The filesystem UUID, and for ext4 the directory hash seed and the
+/// creation time (`epoch`), are fixed so identical trees give identical images
+pub fn mkfs_step(filesystem: &FilesystemType, rootfs: &Path, image: &Path, label: &str, uuid: &str, epoch: i64) -> Result<CommandStep> {
+    let rootfs = rootfs.display().to_string();
+    let image = image.display().to_string();
+    // Labels are at most 16 characters on ext4 and 12 on XFS
+    let label = |max: usize| label.chars().take(max).collect::<String>();
+    Ok(match filesystem {
+        FilesystemType::Ext4 => CommandStep::new("mkfs.ext4", ["-q", "-F", "-L", &label(16), "-U", uuid, "-E", &format!("hash_seed={}", uuid), "-d", &rootfs, &image])
+            .with_env("E2FSPROGS_FAKE_TIME", &epoch.to_string()),
+        FilesystemType::Btrfs => CommandStep::new("mkfs.btrfs", ["-q", "-f", "-L", &label(16), "-U", uuid, "--rootdir", &rootfs, &image]),
+        FilesystemType::Xfs => CommandStep::new("mkfs.xfs", ["-q", "-f", "-L", &label(12), "-m", &format!("uuid={}", uuid), "-p", &format!("file={}", rootfs), &image]),
+        FilesystemType::SquashFs => anyhow::bail!("Disk images need a writable root filesystem; set filesystem.root_fs to Ext4, Btrfs or Xfs"),
+    })
+}
+
+/// The program `mkfs_step` runs
+pub fn mkfs_tool(filesystem: &FilesystemType) -> Option<&'static str> {
+    match filesystem {
+        FilesystemType::Ext4 => Some("mkfs.ext4"),
+        FilesystemType::Btrfs => Some("mkfs.btrfs"),
+        FilesystemType::Xfs => Some("mkfs.xfs"),
+        FilesystemType::SquashFs => None,
+    }
+}
+
+/// The partitions of a disk image, in MiB
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskLayout {
+    pub esp_mib: u64,
+    pub root_mib: u64,
+}
+
+impl DiskLayout {
+    pub fn esp_offset_mib(&self) -> u64 {
+        ALIGNMENT_MIB
+    }
+
+    pub fn root_offset_mib(&self) -> u64 {
+        ALIGNMENT_MIB + self.esp_mib
+    }
+
+    pub fn disk_mib(&self) -> u64 {
+        self.root_offset_mib() + self.root_mib + ALIGNMENT_MIB
+    }
+
+    /// sgdisk writing the GPT: partition 1 the ESP, partition 2 the root
+    /// with the given partition UUID
+    pub fn partition_step(&self, disk: &Path, root_type: &str, disk_guid: &str, root_guid: &str) -> CommandStep {
+        let esp_start = self.esp_offset_mib() * 2048;
+        let root_start = self.root_offset_mib() * 2048;
+        CommandStep::new("sgdisk", [
+            "--clear".to_string(),
+            format!("--disk-guid={}", disk_guid),
+            format!("--new=1:{}:{}", esp_start, root_start - 1),
+            "--typecode=1:EF00".to_string(),
+            "--change-name=1:ESP".to_string(),
+            format!("--new=2:{}:{}", root_start, root_start + self.root_mib * 2048 - 1),
+            format!("--typecode=2:{}", root_type),
+            format!("--partition-guid=2:{}", root_guid),
+            "--change-name=2:root".to_string(),
+            disk.display().to_string(),
+        ])
+    }
+}
+
+/// Copy a filesystem image into the disk image at `offset_mib`
+pub fn write_partition(disk: &Path, image: &Path, offset_mib: u64) -> Result<()> {
+    let mut src = File::open(image)
+        .with_context(|| format!("Failed to open {}", image.display()))?;
+    let mut dst = OpenOptions::new().write(true).open(disk)
+        .with_context(|| format!("Failed to open {}", disk.display()))?;
+    dst.seek(SeekFrom::Start(offset_mib * MIB))?;
+    io::copy(&mut src, &mut dst)
+        .with_context(|| format!("Failed to write {} into {}", image.display(), disk.display()))?;
+    Ok(())
+}
+
+/// Size of a file rounded up to whole MiB
+pub fn size_mib(path: &Path) -> Result<u64> {
+    Ok(fs::metadata(path)?.len().div_ceil(MIB))
+}
+
+pub fn qcow2_step(raw: &Path, qcow2: &Path) -> CommandStep {
+    CommandStep::new("qemu-img", [
+        "convert".to_string(), "-f".to_string(), "raw".to_string(), "-O".to_string(), "qcow2".to_string(),
+        raw.display().to_string(), qcow2.display().to_string(),
+    ])
+}
+
+/// The file extension and tar option for a tarball compressed with `compression`
+pub fn tarball_compression(compression: &CompressionType) -> (&'static str, Option<&'static str>) {
+    match compression {
+        CompressionType::Gzip => ("tar.gz", Some("--gzip")),
+        CompressionType::Xz => ("tar.xz", Some("--xz")),
+        CompressionType::Zstd => ("tar.zst", Some("--zstd")),
+        CompressionType::Lz4 => ("tar.lz4", Some("--use-compress-program=lz4")),
+        CompressionType::None => ("tar", None),
+    }
+}
+
+/// tar packing `rootfs` into `tarball`, keeping numeric owners, ACLs and
+/// xattrs (file capabilities), leaving out the `excluded` top-level paths
+pub fn tar_step(rootfs: &Path, tarball: &Path, compression: Option<&str>, excluded: &[&str], epoch: i64) -> CommandStep {
+    let mut args = vec!["--create".to_string(), "--numeric-owner".to_string(), "--xattrs".to_string(),
+        "--xattrs-include=*".to_string(), "--acls".to_string()];
+    // Reproducible: sorted entries, no mtime after `epoch`, and no atime, ctime or PID in the headers
+    args.extend(["--format=posix".to_string(), "--sort=name".to_string(), format!("--mtime=@{}", epoch), "--clamp-mtime".to_string(),
+        "--pax-option=exthdr.name=%d/PaxHeaders/%f,delete=atime,delete=ctime".to_string()]);
+    args.extend(compression.map(str::to_string));
+    args.extend(excluded.iter().map(|path| format!("--exclude=./{}", path)));
+    args.extend(["--file".to_string(), tarball.display().to_string(), "-C".to_string(), rootfs.display().to_string(), ".".to_string()]);
+    CommandStep::new("tar", args)
+}
+
+/// The OCI (Go) name of an architecture, and its variant
+pub fn oci_platform(arch: &str) -> Option<(&'static str, Option<&'static str>)> {
+    match arch {
+        "x86_64" => Some(("amd64", None)),
+        "i686" => Some(("386", None)),
+        "aarch64" => Some(("arm64", Some("v8"))),
+        "armv7h" => Some(("arm", Some("v7"))),
+        _ => None,
+    }
+}
+
+/// `sha256:<hex>` of a file, read in chunks since layers can be gigabytes
+pub fn sha256_digest(path: &Path) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("sha256:{:x}", hasher.finalize()))
+}
+
+/// gzip compressing the OCI layer in place, without the name and timestamp
+/// gzip would otherwise record
+pub fn gzip_step(path: &Path) -> CommandStep {
+    CommandStep::new("gzip", ["--no-name".to_string(), "--force".to_string(), path.display().to_string()])
+}
+
+/// What an OCI image is built from
+pub struct OciImage<'a> {
+    /// Name for the `org.opencontainers.image.ref.name` annotation
+    pub reference: &'a str,
+    pub architecture: &'a str,
+    /// RFC 3339 creation time
+    pub created: &'a str,
+    /// Digest of the uncompressed layer tarball
+    pub diff_id: &'a str,
+    /// The gzipped layer tarball, moved into the layout
+    pub layer: &'a Path,
+}
+
+/// Write an OCI image layout to `dir`: the layer as a blob, an image config,
+/// a manifest, and an index pointing at it
+pub fn write_oci_layout(dir: &Path, image: &OciImage) -> Result<()> {
+    let (architecture, variant) = oci_platform(image.architecture)
+        .with_context(|| format!("No OCI platform for {}", image.architecture))?;
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    let blobs = dir.join("blobs").join("sha256");
+    fs::create_dir_all(&blobs)?;
+
+    let layer_digest = sha256_digest(image.layer)?;
+    let layer_size = fs::metadata(image.layer)?.len();
+    fs::rename(image.layer, blobs.join(layer_digest.trim_start_matches("sha256:")))
+        .with_context(|| format!("Failed to move {} into {}", image.layer.display(), dir.display()))?;
+
+    let mut platform = serde_json::json!({ "architecture": architecture, "os": "linux" });
+    if let Some(variant) = variant {
+        platform["variant"] = serde_json::json!(variant);
+    }
+    let mut config = platform.clone();
+    config["created"] = serde_json::json!(image.created);
+    config["config"] = serde_json::json!({ "Cmd": ["/bin/sh"] });
+    config["rootfs"] = serde_json::json!({ "type": "layers", "diff_ids": [image.diff_id] });
+    let config_blob = write_blob(&blobs, &config)?;
+
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.manifest.v1+json",
+        "config": descriptor("application/vnd.oci.image.config.v1+json", &config_blob),
+        "layers": [{
+            "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+            "digest": layer_digest,
+            "size": layer_size,
+        }],
+    });
+    let manifest_blob = write_blob(&blobs, &manifest)?;
+
+    let mut manifest_descriptor = descriptor("application/vnd.oci.image.manifest.v1+json", &manifest_blob);
+    manifest_descriptor["platform"] = platform;
+    manifest_descriptor["annotations"] = serde_json::json!({ "org.opencontainers.image.ref.name": image.reference });
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": "application/vnd.oci.image.index.v1+json",
+        "manifests": [manifest_descriptor],
+    });
+    fs::write(dir.join("index.json"), serde_json::to_string_pretty(&index)?)?;
+    fs::write(dir.join("oci-layout"), "{\"imageLayoutVersion\":\"1.0.0\"}")?;
+    Ok(())
+}
+
+/// Digest and size of a JSON blob written under `blobs`
+fn write_blob(blobs: &Path, value: &serde_json::Value) -> Result<(String, u64)> {
+    let content = serde_json::to_vec(value)?;
+    let digest = format!("{:x}", Sha256::digest(&content));
+    fs::write(blobs.join(&digest), &content)?;
+    Ok((format!("sha256:{}", digest), content.len() as u64))
+}
+
+fn descriptor(media_type: &str, (digest, size): &(String, u64)) -> serde_json::Value {
+    serde_json::json!({ "mediaType": media_type, "digest": digest, "size": size })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_layout() {
+        let layout = DiskLayout { esp_mib: 64, root_mib: root_size_mib(3 * 1024 * 1024) };
+        assert_eq!(layout.root_mib, 4096 + 256);
+        assert_eq!(layout.root_offset_mib(), 65);
+        assert_eq!(layout.disk_mib(), 65 + 4352 + 1);
+
+        let root_guid = stable_uuid("MyLinux-1.0-x86_64-root");
+        assert_eq!(root_guid, stable_uuid("MyLinux-1.0-x86_64-root"));
+        assert_ne!(root_guid, stable_uuid("MyLinux-1.1-x86_64-root"));
+        let step = layout.partition_step(Path::new("disk.img"), root_partition_type("x86_64").unwrap(), "D", &root_guid).to_string();
+        assert!(step.contains("--new=1:2048:133119 --typecode=1:EF00"));
+        assert!(step.contains(&format!("--new=2:133120:9046015 --typecode=2:4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709 --partition-guid=2:{}", root_guid)));
+        assert_eq!(fat_uuid(0x1234abcd), "1234-ABCD");
+
+        assert!(mkfs_step(&FilesystemType::SquashFs, Path::new("rootfs"), Path::new("root.img"), "MYLINUX", "U", 0).is_err());
+        let xfs = mkfs_step(&FilesystemType::Xfs, Path::new("rootfs"), Path::new("root.img"), "MYLINUX_DESKTOP", "U", 0).unwrap();
+        assert_eq!(xfs.to_string(), "mkfs.xfs -q -f -L MYLINUX_DESK -m uuid=U -p file=rootfs root.img");
+        let ext4 = mkfs_step(&FilesystemType::Ext4, Path::new("rootfs"), Path::new("root.img"), "MYLINUX", "U", 1728950400).unwrap();
+        assert_eq!(ext4.to_string(), "E2FSPROGS_FAKE_TIME=1728950400 mkfs.ext4 -q -F -L MYLINUX -U U -E hash_seed=U -d rootfs root.img");
+    }
+
+    #[test]
+    fn test_oci_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer = dir.path().join("layer.tar.gz");
+        fs::write(&layer, "layer").unwrap();
+        let layout = dir.path().join("oci");
+        write_oci_layout(&layout, &OciImage {
+            reference: "1.0", architecture: "aarch64", created: "2024-01-01T00:00:00Z", diff_id: "sha256:00", layer: &layer,
+        }).unwrap();
+
+        let index: serde_json::Value = serde_json::from_str(&fs::read_to_string(layout.join("index.json")).unwrap()).unwrap();
+        let manifest = &index["manifests"][0];
+        assert_eq!(manifest["platform"]["architecture"], "arm64");
+        let blobs = layout.join("blobs/sha256");
+        let manifest_path = blobs.join(manifest["digest"].as_str().unwrap().trim_start_matches("sha256:"));
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(manifest_path).unwrap()).unwrap();
+        let layer_digest = manifest["layers"][0]["digest"].as_str().unwrap();
+        assert_eq!(fs::read_to_string(blobs.join(layer_digest.trim_start_matches("sha256:"))).unwrap(), "layer");
+        assert!(!layer.exists());
+    }
+}
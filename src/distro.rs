@@ -3,7 +3,8 @@ use std::collections::HashMap;
 use std::fs;
 use anyhow::Result;
 use crate::compatibility_layer::CompatibilityLayer;
-use crate::config_manager::Config;
+use crate::executor::CommandStep;
+use crate::package_manager::{PackageBackend, PackageManagerRegistry};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DistroInfo {
@@ -88,143 +89,44 @@ impl DistroInfo {
         }
     }
 
-    pub fn get_package_install_command(&self, package: &str) -> Option<String> {
-        let config = Config::load().unwrap_or_default();
-        let compatibility_layer = CompatibilityLayer::new();
-        
-        let final_package = compatibility_layer.get_package_for_distro(package, self.id.as_deref().unwrap_or(""))
-            .unwrap_or_else(|| package.to_string());
-
-        // Check native package manager
-        let native_cmd = match self.package_manager.as_deref() {
-            Some("pacman") => Some(format!("sudo pacman -S {}", final_package)),
-            Some("apt") => Some(format!("sudo apt install {}", final_package)),
-            Some("dnf") => Some(format!("sudo dnf install {}", final_package)),
-            Some("zypper") => Some(format!("sudo zypper install {}", final_package)),
-            Some("portage") => Some(format!("sudo emerge {}", final_package)),
-            Some("nix") => Some(format!("nix-env -iA nixpkgs.{}", final_package)),
-            Some("apk") => Some(format!("sudo apk add {}", final_package)),
-            Some("xbps") => Some(format!("sudo xbps-install {}", final_package)),
-            Some("paru") => Some(format!("paru -S {}", final_package)),
-            Some("yay") => Some(format!("yay -S {}", final_package)),
-            Some("flatpak") => Some(format!("flatpak install {}", final_package)),
-            Some("snap") => Some(format!("sudo snap install {}", final_package)),
-            _ => None,
-        };
-
-        if native_cmd.is_some() {
-            return native_cmd;
-        }
-
-        // Check AUR, Flatpak, Snap, and AppImage based on config
-        if config.enable_aur {
-            if let Some(cmd) = self.get_aur_install_command(package) {
-                return Some(cmd);
-            }
-        }
-
-        if config.enable_flatpak {
-            if let Some(cmd) = self.get_flatpak_install_command(package) {
-                return Some(cmd);
-            }
-        }
-
-        if config.enable_snap {
-            if let Some(cmd) = self.get_snap_install_command(package) {
-                return Some(cmd);
-            }
-        }
-
-        // Implement logic for AppImage lookup if needed
-        None
-    }
-
-    fn get_aur_install_command(&self, package: &str) -> Option<String> {
-        Some(format!("paru -S {}", package))
-    }
-
-    fn get_flatpak_install_command(&self, package: &str) -> Option<String> {
-        Some(format!("flatpak install {}", package))
+    /// Resolve the package backend for this distribution's package manager
+    pub fn backend<'a>(&self, registry: &'a PackageManagerRegistry) -> Option<&'a dyn PackageBackend> {
+        self.package_manager.as_deref().and_then(|pm| registry.get(pm))
     }
 
-    fn get_snap_install_command(&self, package: &str) -> Option<String> {
-        Some(format!("sudo snap install {}", package))
+    /// Translate a canonical package name into this distribution's package name
+    pub fn resolve_package_name(&self, package: &str) -> String {
+        let compatibility_layer = CompatibilityLayer::new();
+        compatibility_layer.get_package_for_distro(package, self.id.as_deref().unwrap_or(""))
+            .unwrap_or_else(|| package.to_string())
     }
 
-    pub fn get_package_search_command(&self, query: &str) -> Option<String> {
-        match self.package_manager.as_deref() {
-            Some("pacman") => Some(format!("pacman -Ss {query}")),
-            Some("apt") => Some(format!("apt search {query}")),
-            Some("dnf") => Some(format!("dnf search {query}")),
-            Some("zypper") => Some(format!("zypper search {query}")),
-            Some("portage") => Some(format!("emerge --search {query}")),
-            Some("nix") => Some(format!("nix-env -qaP | grep {query}")),
-            Some("apk") => Some(format!("apk search {query}")),
-            _ => None,
-        }
+    pub fn get_package_search_command(&self, query: &str) -> Option<CommandStep> {
+        let registry = PackageManagerRegistry::new();
+        self.backend(&registry).map(|backend| backend.search_command(query))
     }
 
-    pub fn get_system_update_command(&self) -> Option<String> {
-        match self.package_manager.as_deref() {
-            Some("pacman") => Some("sudo pacman -Syu".to_string()),
-            Some("apt") => Some("sudo apt update && sudo apt upgrade".to_string()),
-            Some("dnf") => Some("sudo dnf upgrade".to_string()),
-            Some("zypper") => Some("sudo zypper update".to_string()),
-            Some("portage") => Some("sudo emerge --sync && sudo emerge -uDN @world".to_string()),
-            Some("nix") => Some("sudo nixos-rebuild switch --upgrade".to_string()),
-            Some("apk") => Some("sudo apk update && sudo apk upgrade".to_string()),
-            _ => None,
-        }
+    pub fn get_system_update_command(&self) -> Option<Vec<CommandStep>> {
+        let registry = PackageManagerRegistry::new();
+        self.backend(&registry).map(|backend| backend.update_command())
     }
 
-    pub fn get_package_remove_command(&self, package: &str) -> Option<String> {
-        let compatibility_layer = CompatibilityLayer::new();
-        
-        let final_package = compatibility_layer.get_package_for_distro(package, self.id.as_deref().unwrap_or(""))
-            .unwrap_or_else(|| package.to_string());
-
-        match self.package_manager.as_deref() {
-            Some("pacman") => Some(format!("sudo pacman -R {}", final_package)),
-            Some("apt") => Some(format!("sudo apt remove {}", final_package)),
-            Some("dnf") => Some(format!("sudo dnf remove {}", final_package)),
-            Some("zypper") => Some(format!("sudo zypper remove {}", final_package)),
-            Some("portage") => Some(format!("sudo emerge --unmerge {}", final_package)),
-            Some("nix") => Some(format!("nix-env -e {}", final_package)),
-            Some("apk") => Some(format!("sudo apk del {}", final_package)),
-            _ => None,
-        }
+    pub fn get_package_list_command(&self, detailed: bool) -> Option<CommandStep> {
+        let registry = PackageManagerRegistry::new();
+        self.backend(&registry).map(|backend| backend.list_command(detailed))
     }
 
-    pub fn get_package_list_command(&self, detailed: bool, filter: Option<&str>) -> Option<String> {
-        match self.package_manager.as_deref() {
-            Some("pacman") => Some(format!("pacman -Q{}{}", if detailed { "i" } else { "" }, filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("apt") => Some(format!("dpkg-query -l{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("dnf") => Some(format!("dnf list installed{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("zypper") => Some(format!("zypper se --installed-only{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("portage") => Some(format!("equery list{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("nix") => Some(format!("nix-env -q{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            Some("apk") => Some(format!("apk list --installed{}", filter.map(|f| format!(" | grep {f}")).unwrap_or_default())),
-            _ => None,
-        }
+    pub fn get_package_info_command(&self, package: &str) -> Option<CommandStep> {
+        let registry = PackageManagerRegistry::new();
+        self.backend(&registry).map(|backend| backend.info_command(package))
     }
 
-    pub fn get_package_info_command(&self, package: &str) -> Option<String> {
-        match self.package_manager.as_deref() {
-            Some("pacman") => Some(format!("pacman -Qi {package}")),
-            Some("apt") => Some(format!("apt show {package}")),
-            Some("dnf") => Some(format!("dnf info {package}")),
-            Some("zypper") => Some(format!("zypper info {package}")),
-            Some("portage") => Some(format!("equery list {package}")),
-            Some("nix") => Some(format!("nix-env -qaP | grep {package}")),
-            Some("apk") => Some(format!("apk info {package}")),
-            _ => None,
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::executor::render_steps;
 
     #[test]
     fn test_parse_cachyos() {
@@ -327,20 +229,45 @@ PRETTY_NAME="CustomArch Linux""#;
             package_manager: Some("pacman".to_string()),
         };
 
+        let registry = PackageManagerRegistry::new();
         assert_eq!(
-            distro.get_package_install_command("vim"),
-            Some("sudo pacman -S vim".to_string())
+            distro.backend(&registry).map(|backend| render_steps(&backend.install_command(&[distro.resolve_package_name("vim")]))),
+            Some("sudo pacman -S --noconfirm vim".to_string())
         );
         assert_eq!(
-            distro.get_package_search_command("git"),
+            distro.get_package_search_command("git").map(|step| step.to_string()),
             Some("pacman -Ss git".to_string())
         );
         assert_eq!(
-            distro.get_system_update_command(),
-            Some("sudo pacman -Syu".to_string())
+            distro.get_system_update_command().map(|steps| render_steps(&steps)),
+            Some("sudo pacman -Syu --noconfirm".to_string())
         );
     }
 
+    #[test]
+    fn test_commands_consistent_across_backends() {
+        for pm in ["pacman", "apt", "dnf", "zypper", "portage", "nix", "apk", "xbps"] {
+            let distro = DistroInfo {
+                name: "Test Linux".to_string(),
+                version: None,
+                id: Some("test".to_string()),
+                id_like: None,
+                version_id: None,
+                pretty_name: None,
+                home_url: None,
+                support_url: None,
+                bug_report_url: None,
+                package_manager: Some(pm.to_string()),
+            };
+
+            assert!(distro.backend(&PackageManagerRegistry::new()).is_some(), "{pm} install and remove");
+            assert!(distro.get_package_search_command("vim").is_some(), "{pm} search");
+            assert!(distro.get_system_update_command().is_some(), "{pm} update");
+            assert!(distro.get_package_list_command(false).is_some(), "{pm} list");
+            assert!(distro.get_package_info_command("vim").is_some(), "{pm} info");
+        }
+    }
+
     #[test]
     fn test_empty_os_release() {
         let os_release = "";
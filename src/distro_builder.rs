@@ -3,14 +3,20 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use tokio::process::Command as AsyncCommand;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use tokio::sync::{Semaphore, Mutex};
 use futures::future::try_join_all;
 use std::collections::HashMap;
 use sha2::{Sha256, Digest};
+use crate::build_checkpoint::{self, BuildCheckpoint, BuildStep};
+use crate::build_lock::{self, BuildLock, LockedPackage, RepositorySnapshot};
+use crate::builder_backend::{self, BuilderBackend, LiveBoot};
+use crate::disk_image::{self, DiskLayout, OciImage, OutputFormat};
+use crate::executor::CommandStep;
+use crate::iso_boot::{self, BootEntry, EfiArch, EfiLoader};
+use crate::runner::{RunOutput, StreamingRunner};
 
 // Enhanced logging and progress tracking
 #[derive(Debug, Clone)]
@@ -140,11 +146,18 @@ pub struct DistroConfig {
     pub description: String,
     pub architecture: String,
     pub base_system: BaseSystem,
+    /// Release of the base system to bootstrap (`bookworm`, `41`, `3.20`);
+    /// each base system has a default
+    #[serde(default)]
+    pub base_release: Option<String>,
     pub packages: PackageConfig,
     pub kernel: KernelConfig,
     pub bootloader: BootloaderConfig,
     pub branding: BrandingConfig,
     pub filesystem: FilesystemConfig,
+    /// What to build from the rootfs; an ISO unless set
+    #[serde(default = "disk_image::default_output_formats")]
+    pub output_formats: Vec<OutputFormat>,
     pub build_options: BuildOptions,
     pub user_config: UserConfig,
     pub validation: ValidationConfig,
@@ -234,7 +247,7 @@ pub struct ValidationConfig {
     pub verify_signatures: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum BaseSystem {
     Arch,
     Debian,
@@ -351,6 +364,15 @@ pub struct DistroBuilder {
     output_dir: PathBuf,
     package_cache: Arc<Mutex<HashMap<String, PackageCacheEntry>>>,
     parallel_semaphore: Arc<Semaphore>,
+    resume: bool,
+    from_step: Option<BuildStep>,
+    until_step: Option<BuildStep>,
+    lock_path: PathBuf,
+    /// The lock this build reproduces; `None` resolves a new one
+    lock: Option<BuildLock>,
+    locked: bool,
+    /// The repository snapshot when there's no lock
+    default_snapshot: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -397,6 +419,8 @@ impl DistroBuilder {
     pub fn new(config: DistroConfig, work_dir: PathBuf, output_dir: PathBuf) -> Self {
         let max_parallel = config.build_options.max_parallel_jobs
             .unwrap_or_else(|| num_cpus::get());
+        let lock_path = output_dir.join(format!("{}.lock", config.name));
+        let lock = Self::read_lock(&config, &lock_path);
         
         Self {
             config,
@@ -404,9 +428,36 @@ impl DistroBuilder {
             output_dir,
             package_cache: Arc::new(Mutex::new(HashMap::new())),
             parallel_semaphore: Arc::new(Semaphore::new(max_parallel)),
+            resume: false,
+            from_step: None,
+            until_step: None,
+            lock_path,
+            lock,
+            locked: false,
+            default_snapshot: build_lock::default_snapshot(Utc::now()),
         }
     }
 
+    /// Run a build command, streaming its output and appending it to the
+    /// build log. Parallel and minimal builds only capture the output.
+    async fn run_command(&self, mut command: Command) -> Result<RunOutput> {
+        let options = &self.config.build_options;
+        let quiet = options.parallel_builds || matches!(options.progress_reporting, ProgressReporting::Minimal);
+        command.env("SOURCE_DATE_EPOCH", self.source_date_epoch().to_string());
+
+        StreamingRunner::new()
+            .timeout(options.timeout_minutes.map(|minutes| Duration::from_secs(u64::from(minutes) * 60)))
+            .log_file(options.build_logs.then(|| self.build_log_path()))
+            .quiet(quiet)
+            .run_async(command)
+            .await
+    }
+
+    /// Where `run_command` appends command output when build logs are enabled
+    pub fn build_log_path(&self) -> PathBuf {
+        self.output_dir.join(format!("{}-build.log", self.config.name))
+    }
+
     /// Validates the distribution configuration before building
     pub fn validate_config(&self) -> ValidationResult {
         let mut errors = Vec::new();
@@ -414,6 +465,10 @@ impl DistroBuilder {
 
         // Validate required fields
         self.validate_required_fields(&mut errors);
+
+        // Check the base system can provide what the config asks for
+        self.validate_base_system(&mut errors);
+        self.validate_output_formats(&mut errors);
         
         // Validate network configuration
         self.validate_network_config(&mut errors, &mut warnings);
@@ -552,19 +607,15 @@ impl DistroBuilder {
             }
         }
         
-        // Check for essential packages that might conflict with base system
-        let problematic_packages = ["base", "linux", "linux-firmware"];
-        for pkg in &self.config.packages.essential {
-            if problematic_packages.contains(&pkg.as_str()) {
-                match self.config.base_system {
-                    BaseSystem::Arch => {
-                        warnings.push(ValidationWarning {
-                            field: "packages.essential".to_string(),
-                            message: format!("Package '{}' is typically included in base system", pkg),
-                            suggestion: Some("Consider removing from essential packages list".to_string()),
-                        });
-                    },
-                    _ => {}
+        // Check for essential packages the bootstrap already installs
+        if let Some(backend) = self.backend() {
+            for pkg in &self.config.packages.essential {
+                if backend.base_packages().contains(&pkg.as_str()) {
+                    warnings.push(ValidationWarning {
+                        field: "packages.essential".to_string(),
+                        message: format!("Package '{}' is typically included in base system", pkg),
+                        suggestion: Some("Consider removing from essential packages list".to_string()),
+                    });
                 }
             }
         }
@@ -622,15 +673,87 @@ impl DistroBuilder {
         }
     }
 
+    fn validate_base_system(&self, errors: &mut Vec<ValidationError>) {
+        let config = &self.config;
+        let Some(backend) = self.backend() else {
+            // Scratch builds have no package manager to install anything with
+            let packages = &config.packages;
+            let desktop = packages.desktop_environment.as_ref()
+                .filter(|de| !matches!(de, DesktopEnvironment::None));
+            if !packages.essential.is_empty() || !packages.additional_packages.is_empty() || desktop.is_some() {
+                errors.push(ValidationError {
+                    field: "packages".to_string(),
+                    message: "Scratch builds have no package manager; leave packages and desktop_environment empty".to_string(),
+                    severity: ValidationSeverity::High,
+                });
+            }
+            return;
+        };
+
+        if backend.live_boot().is_none() && self.wants(OutputFormat::Iso) {
+            errors.push(ValidationError {
+                field: "base_system".to_string(),
+                message: format!("{:?} has no initramfs hook that mounts a squashfs root, so it can't boot as a live ISO", config.base_system),
+                severity: ValidationSeverity::High,
+            });
+        }
+
+        if !backend.supports_architecture(&config.architecture) {
+            errors.push(ValidationError {
+                field: "architecture".to_string(),
+                message: format!("{:?} can't be bootstrapped for {} with {}", config.base_system, config.architecture, backend.name()),
+                severity: ValidationSeverity::High,
+            });
+        }
+
+        if backend.kernel_package(&config.kernel.kernel_type, &config.architecture).is_none() {
+            errors.push(ValidationError {
+                field: "kernel.kernel_type".to_string(),
+                message: format!("{:?} doesn't provide a {:?} kernel", config.base_system, config.kernel.kernel_type),
+                severity: ValidationSeverity::High,
+            });
+        }
+
+        if let Some(ref de) = config.packages.desktop_environment
+            && backend.desktop_packages(de).is_none() {
+                errors.push(ValidationError {
+                    field: "packages.desktop_environment".to_string(),
+                    message: format!("{:?} doesn't provide the {:?} desktop", config.base_system, de),
+                    severity: ValidationSeverity::High,
+                });
+            }
+    }
+
+    fn validate_output_formats(&self, errors: &mut Vec<ValidationError>) {
+        let config = &self.config;
+        let mut error = |message: String| errors.push(ValidationError {
+            field: "output_formats".to_string(),
+            message,
+            severity: ValidationSeverity::High,
+        });
+
+        if config.output_formats.is_empty() {
+            error("No output formats; list at least one of Iso, Raw, Qcow2, Tarball and Oci".to_string());
+        }
+        if self.wants_disk() {
+            if disk_image::mkfs_tool(&config.filesystem.root_fs).is_none() {
+                error("Disk images need a writable root filesystem; set filesystem.root_fs to Ext4, Btrfs or Xfs".to_string());
+            }
+            if disk_image::root_partition_type(&config.architecture).is_none() || EfiArch::for_architecture(&config.architecture).is_none() {
+                error(format!("Disk images aren't supported for {}", config.architecture));
+            }
+        }
+        if self.wants(OutputFormat::Oci) && disk_image::oci_platform(&config.architecture).is_none() {
+            error(format!("OCI images aren't supported for {}", config.architecture));
+        }
+    }
+
     fn validate_dependencies(&self, errors: &mut Vec<ValidationError>, warnings: &mut Vec<ValidationWarning>) {
         // Check if required tools are available on the build system
-        let required_tools = match self.config.base_system {
-            BaseSystem::Arch => vec!["pacstrap", "arch-chroot"],
-            BaseSystem::Debian | BaseSystem::Ubuntu => vec!["debootstrap"],
-            BaseSystem::Fedora | BaseSystem::CentOS => vec!["dnf", "rpm"],
-            BaseSystem::OpenSUSE => vec!["zypper", "rpm"],
-            BaseSystem::Alpine => vec!["apk"],
-            BaseSystem::Scratch => vec!["gcc", "make"],
+        let backend = self.backend();
+        let required_tools = match backend {
+            Some(ref backend) => backend.required_tools(),
+            None => vec!["gcc", "make"],
         };
         
         for tool in required_tools {
@@ -642,40 +765,80 @@ impl DistroBuilder {
                 });
             }
         }
+
+        if let Some(problem) = backend.and_then(|backend| backend.host_problem()) {
+            errors.push(ValidationError {
+                field: "base_system".to_string(),
+                message: problem,
+                severity: ValidationSeverity::Critical,
+            });
+        }
         
-        // Check for ISO creation tools
-        let iso_tools = ["mksquashfs", "xorriso"];
-        for tool in iso_tools {
+        // Check for the tools each output format is made with
+        let mut image_tools = Vec::new();
+        for format in &self.config.output_formats {
+            match format {
+                OutputFormat::Iso => image_tools.extend(["mksquashfs", "xorriso"]),
+                OutputFormat::Raw => {}
+                OutputFormat::Qcow2 => image_tools.push("qemu-img"),
+                OutputFormat::Tarball => image_tools.push("tar"),
+                OutputFormat::Oci => image_tools.extend(["tar", "gzip"]),
+            }
+        }
+        if self.wants_disk() {
+            image_tools.push("sgdisk");
+            image_tools.extend(disk_image::mkfs_tool(&self.config.filesystem.root_fs));
+        }
+        image_tools.sort();
+        image_tools.dedup();
+        for tool in image_tools {
             if !self.check_command_exists(tool) {
                 errors.push(ValidationError {
                     field: "build_dependencies".to_string(),
-                    message: format!("Required ISO creation tool '{}' is not available", tool),
+                    message: format!("Required image creation tool '{}' is not available", tool),
                     severity: ValidationSeverity::Critical,
                 });
             }
         }
         
-        // Check bootloader dependencies
-        match self.config.bootloader.bootloader {
-            Bootloader::Syslinux => {
-                if !Path::new("/usr/lib/syslinux/bios/isolinux.bin").exists() {
-                    warnings.push(ValidationWarning {
-                        field: "bootloader.bootloader".to_string(),
-                        message: "Syslinux BIOS files not found in expected location".to_string(),
-                        suggestion: Some("Install syslinux package: pacman -S syslinux".to_string()),
-                    });
-                }
-            },
-            Bootloader::Grub => {
-                if !self.check_command_exists("grub-mkrescue") {
-                    warnings.push(ValidationWarning {
+        // Check bootloader dependencies: isolinux for BIOS ISOs, and the UEFI
+        // loader and FAT tools for the EFI system partition
+        if !self.wants(OutputFormat::Iso) && !self.wants_disk() {
+            return;
+        }
+        if iso_boot::has_bios(&self.config.architecture) && self.wants(OutputFormat::Iso) {
+            for file in ["isolinux.bin", "isohdpfx.bin"] {
+                if iso_boot::syslinux_file(file).is_none() {
+                    errors.push(ValidationError {
                         field: "bootloader.bootloader".to_string(),
-                        message: "GRUB tools not found".to_string(),
-                        suggestion: Some("Install grub package".to_string()),
+                        message: format!("Syslinux BIOS file {} not found; install the syslinux package", file),
+                        severity: ValidationSeverity::Critical,
                     });
                 }
-            },
-            _ => {}
+            }
+        }
+
+        let loader = EfiLoader::for_bootloader(&self.config.bootloader.bootloader);
+        for tool in loader.required_tools() {
+            if !self.check_command_exists(tool) {
+                errors.push(ValidationError {
+                    field: "build_dependencies".to_string(),
+                    message: format!("Required UEFI boot tool '{}' is not available", tool),
+                    severity: ValidationSeverity::Critical,
+                });
+            }
+        }
+        if let Some(binary) = EfiArch::for_architecture(&self.config.architecture)
+            .and_then(|efi| loader.binary(&efi))
+            .filter(|binary| !binary.exists()) {
+            warnings.push(ValidationWarning {
+                field: "bootloader.bootloader".to_string(),
+                message: format!("UEFI loader {} not found", binary.display()),
+                suggestion: Some(match loader {
+                    EfiLoader::Refind => "Install the refind package".to_string(),
+                    _ => "Install systemd-boot (part of systemd, or systemd-boot-efi on Debian)".to_string(),
+                }),
+            });
         }
     }
 
@@ -895,7 +1058,165 @@ impl DistroBuilder {
         println!();
     }
 
-    pub async fn build(&self) -> Result<PathBuf> {
+    /// Resume from the checkpoint in the work directory, redoing only the
+    /// steps whose inputs changed since they last finished
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Run only the steps from `from` through `until`, reusing whatever the
+    /// work directory holds for the earlier ones
+    pub fn steps(mut self, from: Option<BuildStep>, until: Option<BuildStep>) -> Self {
+        self.from_step = from;
+        self.until_step = until;
+        self
+    }
+
+    /// Read and write the lock at `path` instead of `<output>/<name>.lock`
+    pub fn lock_file(mut self, path: PathBuf) -> Self {
+        self.lock = Self::read_lock(&self.config, &path);
+        self.lock_path = path;
+        self
+    }
+
+    /// Ignore the lock and resolve packages from a new snapshot
+    pub fn update_lock(mut self, update: bool) -> Self {
+        if update {
+            self.lock = None;
+        }
+        self
+    }
+
+    /// Fail unless the build installs exactly the locked packages
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    /// The lock at `path`, unless it's missing, unreadable or for another
+    /// base system, which only warns
+    fn read_lock(config: &DistroConfig, path: &Path) -> Option<BuildLock> {
+        match BuildLock::load(path) {
+            Ok(Some(lock)) if lock.matches(config) => Some(lock),
+            Ok(Some(_)) => {
+                println!("⚠️  Ignoring {}: it was resolved for another base system or architecture", path.display());
+                None
+            }
+            Ok(None) => None,
+            Err(e) => {
+                println!("⚠️  Ignoring {}: {:#}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// When the repository snapshot is from; `None` if the lock has none
+    fn snapshot(&self) -> Option<DateTime<Utc>> {
+        match &self.lock {
+            Some(lock) => lock.snapshot.as_ref().map(|snapshot| snapshot.timestamp),
+            None => Some(self.default_snapshot),
+        }
+    }
+
+    /// The time every timestamp in the images is clamped to: the lock's, or
+    /// `SOURCE_DATE_EPOCH` from the environment, or the snapshot's
+    fn source_date_epoch(&self) -> i64 {
+        if let Some(lock) = &self.lock {
+            return lock.source_date_epoch;
+        }
+        std::env::var("SOURCE_DATE_EPOCH").ok()
+            .and_then(|epoch| epoch.trim().parse().ok())
+            .unwrap_or_else(|| self.default_snapshot.timestamp())
+    }
+
+    /// Packages installed in `rootfs_dir`, with a checksum where the package
+    /// manager keeps one or a digest of the package's file list
+    async fn installed_packages(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path) -> Result<Vec<LockedPackage>> {
+        let step = backend.list_installed(rootfs_dir);
+        let output = StreamingRunner::new().quiet(true).run_async(step.to_command()).await
+            .with_context(|| format!("Failed to run {}", step.program))?;
+        if !output.success {
+            anyhow::bail!("{} failed: {}", step.program, output.error_summary());
+        }
+        let mut packages = build_lock::parse_installed(&output.stdout);
+        for package in packages.iter_mut().filter(|package| package.checksum.is_none()) {
+            package.checksum = backend.package_manifest(rootfs_dir, &package.name, &package.version)
+                .and_then(|manifest| build_checkpoint::file_hash(&manifest))
+                .map(|hash| format!("sha256:{}", hash));
+        }
+        Ok(packages)
+    }
+
+    fn write_lock(&self, packages: Vec<LockedPackage>) -> Result<()> {
+        let snapshot = self.backend().zip(self.snapshot()).and_then(|(backend, timestamp)| {
+            let url = backend.snapshot_url(&self.config.architecture)?;
+            Some(RepositorySnapshot { timestamp, url })
+        });
+        BuildLock {
+            base_system: self.config.base_system.clone(),
+            base_release: self.config.base_release.clone(),
+            architecture: self.config.architecture.clone(),
+            source_date_epoch: self.source_date_epoch(),
+            snapshot,
+            packages,
+        }.save(&self.lock_path)
+    }
+
+    /// Record the installed packages in the lockfile, after reporting how
+    /// they differ from the lock; with `--locked` any difference fails
+    async fn lock_packages(&self) -> Result<()> {
+        let packages = match self.backend() {
+            Some(backend) => self.installed_packages(backend.as_ref(), &self.work_dir.join("rootfs")).await?,
+            None => Vec::new(),
+        };
+        if let Some(lock) = self.lock.as_ref().filter(|lock| !lock.packages.is_empty()) {
+            let changes = lock.changes(&packages);
+            if !changes.is_empty() {
+                println!("📋 Installed packages differ from {}:", self.lock_path.display());
+                for change in &changes {
+                    println!("   {}", change);
+                }
+                if self.locked {
+                    anyhow::bail!("{} packages differ from the lockfile; rebuild with --update-lock to accept them", changes.len());
+                }
+            }
+        }
+        let count = packages.len();
+        self.write_lock(packages)?;
+        println!("🔒 Locked {} packages in {}", count, self.lock_path.display());
+        Ok(())
+    }
+
+    /// Hash of each step's inputs: the parts of the configuration it reads,
+    /// chained with the steps before it
+    fn step_input_hashes(&self) -> Vec<(BuildStep, String)> {
+        let config = &self.config;
+        let file = |path: &Option<PathBuf>| path.as_deref().and_then(build_checkpoint::file_hash);
+        let mut previous = String::new();
+        BuildStep::ALL.iter()
+            .map(|&step| {
+                let inputs = match step {
+                    BuildStep::Setup => serde_json::Value::Null,
+                    BuildStep::Rootfs => serde_json::json!([config.base_system, config.base_release, config.architecture,
+                        self.backend().and_then(|backend| backend.snapshot_url(&config.architecture))]),
+                    BuildStep::Kernel => serde_json::json!([config.kernel, file(&config.kernel.custom_config)]),
+                    BuildStep::Packages => serde_json::json!(config.packages),
+                    BuildStep::Configure => serde_json::json!([config.name, config.user_config]),
+                    BuildStep::Branding => serde_json::json!([config.branding, file(&config.branding.logo), file(&config.branding.wallpaper)]),
+                    BuildStep::Bootloader => serde_json::json!([config.name, config.version, config.architecture, config.bootloader, config.output_formats]),
+                    BuildStep::Images => serde_json::json!([config.name, config.version, config.architecture, config.filesystem, config.output_formats,
+                        self.source_date_epoch()]),
+                };
+                previous = build_checkpoint::input_hash(&previous, &inputs);
+                (step, previous.clone())
+            })
+            .collect()
+    }
+
+    /// Build every output format, returning their paths, or the work
+    /// directory if the build stops early
+    pub async fn build(&self) -> Result<Vec<PathBuf>> {
         // Validate configuration before building
         let validation_result = self.validate_config();
         if !validation_result.is_valid {
@@ -913,7 +1234,7 @@ impl DistroBuilder {
                               self.config.name, 
                               chrono::Utc::now().format("%Y%m%d_%H%M%S"));
         
-        let mut progress = BuildProgress::new(8, build_id.clone());
+        let mut progress = BuildProgress::new(BuildStep::ALL.len(), build_id.clone());
         
         println!("🚀 Starting Linux distribution build: {} (ID: {})", 
                 self.config.name, build_id);
@@ -923,171 +1244,112 @@ impl DistroBuilder {
                 self.config.base_system, self.config.packages.desktop_environment);
         println!("💾 Filesystem: {:?} with {:?} compression", 
                 self.config.filesystem.root_fs, self.config.filesystem.compression);
-        
-        let build_start = std::time::Instant::now();
-        let mut errors = Vec::new();
-        
-        // Step 1: Setup directories
-        progress.start_step("Setting up build directories", 1);
-        match self.setup_directories().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Created work directories successfully");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "setup_directories", "filesystem", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
-        }
-        
-        // Step 2: Build root filesystem
-        progress.start_step("Building root filesystem", 2);
-        match self.build_rootfs().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Root filesystem created successfully");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "build_rootfs", "bootstrap", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
-        }
-        
-        // Step 3: Install kernel
-        progress.start_step("Installing kernel", 3);
-        match self.install_kernel().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Kernel installation completed");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "install_kernel", "package_installation", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
-        }
-        
-        // Step 4: Install packages
-        progress.start_step("Installing packages", 4);
-        match self.install_packages().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Package installation completed");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "install_packages", "package_installation", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
+        match &self.lock {
+            Some(lock) => println!("🔒 Reproducing {} ({} packages{})", self.lock_path.display(), lock.packages.len(),
+                lock.snapshot.as_ref().map(|snapshot| format!(" from {}", snapshot.url)).unwrap_or_default()),
+            None if self.locked => anyhow::bail!("--locked needs a lockfile, but {} doesn't exist; build once without it to create one",
+                self.lock_path.display()),
+            None => println!("🔓 Resolving a new lock: {}", self.lock_path.display()),
+        }
+        
+        // Work out where to start: the requested step, the first step whose
+        // inputs changed when resuming, or the beginning
+        let hashes = self.step_input_hashes();
+        let mut checkpoint = if self.resume || self.from_step.is_some() {
+            BuildCheckpoint::load(&self.work_dir)
+        } else {
+            BuildCheckpoint::default()
+        };
+        let until = self.until_step.unwrap_or(BuildStep::Images);
+        let start = match self.from_step {
+            Some(step) => {
+                for (earlier, hash) in hashes.iter().filter(|(earlier, _)| *earlier > BuildStep::Setup && *earlier < step) {
+                    if checkpoint.reusable(*earlier, hash).is_none() {
+                        progress.log_warning(&format!("Step {} has no up-to-date checkpoint; later steps may fail or use stale output", earlier));
+                    }
+                }
+                Some(step)
             }
-        }
-        
-        // Step 5: Configure system
-        progress.start_step("Configuring system", 5);
-        match self.configure_system().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("System configuration completed");
+            None if self.resume => hashes.iter()
+                .find(|(step, hash)| *step > BuildStep::Setup && checkpoint.reusable(*step, hash).is_none())
+                .map(|(step, _)| *step),
+            None => Some(BuildStep::Setup),
+        };
+        if start.is_some_and(|start| start > until) {
+            anyhow::bail!("Nothing to do: the build would start at step {} but stop after step {}", start.unwrap_or(until), until);
+        }
+        checkpoint.config_hash = build_checkpoint::input_hash("", &serde_json::json!(self.config));
+        
+        let mut last_outputs = vec![self.work_dir.clone()];
+        for (step, hash) in hashes {
+            if step > until {
+                break;
+            }
+            // Directories are always set up, but only emptied when the root
+            // filesystem is rebuilt from scratch
+            let runs = step == BuildStep::Setup || start.is_some_and(|start| step >= start);
+            if !runs {
+                let record = checkpoint.get(step).cloned();
+                if let Some(record) = record.as_ref().filter(|record| !record.outputs.is_empty()) {
+                    last_outputs = record.outputs.clone();
+                }
+                let when = record.map(|record| record.completed_at.format(" on %Y-%m-%d %H:%M UTC").to_string()).unwrap_or_default();
+                println!("\n⏭️  [{}/{}] {} | unchanged since last build{}, skipping", step.number(), BuildStep::ALL.len(), step.title(), when);
+                continue;
             }
-            Err(e) => {
-                let error = BuildError::new(
-                    "configure_system", "configuration", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
+            
+            progress.start_step(step.title(), step.number());
+            let clean = start.is_some_and(|start| start <= BuildStep::Rootfs);
+            if step == BuildStep::Setup && clean {
+                checkpoint.steps.clear();
+            } else if step != BuildStep::Setup {
+                checkpoint.invalidate_from(step);
+                checkpoint.save(&self.work_dir)?;
+            }
+            match self.run_step(step, clean).await {
+                Ok(outputs) => {
+                    progress.complete_step(true);
+                    if let Some(output) = outputs.last() {
+                        progress.log_substep(&format!("{} done: {}", step.title(), output.display()));
+                        last_outputs = outputs.clone();
+                    }
+                    // Setup runs every time, so only the steps after it are checkpointed
+                    if step != BuildStep::Setup {
+                        checkpoint.record(step, hash, outputs);
+                    }
+                    checkpoint.save(&self.work_dir)?;
+                }
+                Err(e) => {
+                    let (function, error_type) = step.error_kind();
+                    let error = BuildError::new(
+                        function, error_type, &e.to_string(), &build_id,
+                        None, None, None
+                    );
+                    error.log_detailed_error();
+                    progress.complete_step(false);
+                    println!("💡 Fix the problem and run again with --resume to continue from step {}", step);
+                    return Err(e);
+                }
             }
         }
         
-        // Step 6: Apply branding
-        progress.start_step("Applying branding", 6);
-        match self.apply_branding().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Branding applied successfully");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "apply_branding", "branding", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
+        if until < BuildStep::Images {
+            println!("\n⏸️  Build stopped after step {} ({})", until.number(), until);
+            println!("📊 {}", progress.get_build_summary());
+            return Ok(vec![self.work_dir.clone()]);
         }
-        
-        // Step 7: Configure bootloader
-        progress.start_step("Configuring bootloader", 7);
-        match self.configure_bootloader().await {
-            Ok(_) => {
-                progress.complete_step(true);
-                progress.log_substep("Bootloader configuration completed");
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "configure_bootloader", "bootloader", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
-        }
-        
-        // Step 8: Create ISO
-        progress.start_step("Creating ISO image", 8);
-        let iso_path = match self.create_iso().await {
-            Ok(path) => {
-                progress.complete_step(true);
-                progress.log_substep(&format!("ISO created: {}", path.display()));
-                path
-            }
-            Err(e) => {
-                let error = BuildError::new(
-                    "create_iso", "iso_creation", &e.to_string(), &build_id,
-                    None, None, None
-                );
-                error.log_detailed_error();
-                errors.push(error);
-                progress.complete_step(false);
-                return Err(e);
-            }
-        };
+        let outputs = last_outputs;
         
         // Final summary
-        let _total_duration = build_start.elapsed();
         println!("\n🎉 BUILD COMPLETED SUCCESSFULLY!");
         println!("📊 {}", progress.get_build_summary());
-        println!("💿 ISO Path: {}", iso_path.display());
+        for output in &outputs {
+            println!("💿 Output: {}", output.display());
+        }
         
         // Check ISO file size
-        if let Ok(metadata) = std::fs::metadata(&iso_path) {
+        let iso_path = outputs.iter().find(|output| output.extension().is_some_and(|ext| ext == "iso"));
+        if let Some(metadata) = iso_path.and_then(|iso_path| std::fs::metadata(iso_path).ok()) {
             let size_mb = metadata.len() as f64 / 1024.0 / 1024.0;
             println!("📏 ISO Size: {:.1} MB", size_mb);
             
@@ -1101,18 +1363,56 @@ impl DistroBuilder {
             }
         }
         
-        println!("🔗 You can now test the ISO with: qemu-system-x86_64 -m 2G -cdrom {}", 
-                iso_path.display());
+        if let Some(iso_path) = iso_path {
+            println!("🔗 You can now test the ISO with: lda test-iso {}", iso_path.display());
+        }
         
-        Ok(iso_path)
+        Ok(outputs)
     }
 
+    /// Run one build step, returning what it produced
+    async fn run_step(&self, step: BuildStep, clean: bool) -> Result<Vec<PathBuf>> {
+        let rootfs_dir = self.work_dir.join("rootfs");
+        Ok(match step {
+            BuildStep::Setup => {
+                self.setup_directories(clean).await?;
+                vec![rootfs_dir]
+            }
+            BuildStep::Rootfs => {
+                self.build_rootfs().await?;
+                // Pin the snapshot now, so a resumed build installs from it
+                if self.lock.is_none() {
+                    self.write_lock(Vec::new())?;
+                }
+                vec![rootfs_dir]
+            }
+            BuildStep::Kernel => {
+                self.install_kernel().await?;
+                vec![rootfs_dir.join("boot")]
+            }
+            BuildStep::Packages => {
+                self.install_packages().await?;
+                self.lock_packages().await?;
+                vec![rootfs_dir, self.lock_path.clone()]
+            }
+            BuildStep::Configure => {
+                self.configure_system().await?;
+                vec![rootfs_dir.join("etc/hostname")]
+            }
+            BuildStep::Branding => {
+                self.apply_branding().await?;
+                vec![rootfs_dir]
+            }
+            BuildStep::Bootloader => self.configure_bootloader().await?,
+            BuildStep::Images => self.create_images().await?,
+        })
+    }
 
-    async fn setup_directories(&self) -> Result<()> {
+    async fn setup_directories(&self, clean: bool) -> Result<()> {
         println!("📁 Setting up build directories...");
         
-        // Clean up any existing directories first
-        if self.work_dir.exists() {
+        // Clean up any existing directories first, unless resuming
+        if clean && self.work_dir.exists() {
             println!("Cleaning up existing work directory...");
             fs::remove_dir_all(&self.work_dir)
                 .with_context(|| format!("Failed to remove existing work directory: {}", self.work_dir.display()))?;
@@ -1122,7 +1422,6 @@ impl DistroBuilder {
             &self.work_dir,
             &self.output_dir,
             &self.work_dir.join("rootfs"),
-            &self.work_dir.join("boot"),
             &self.work_dir.join("iso"),
         ];
 
@@ -1135,111 +1434,74 @@ impl DistroBuilder {
         Ok(())
     }
 
-    async fn build_rootfs(&self) -> Result<()> {
-        println!("🔧 Building root filesystem...");
-        
-        let rootfs_dir = self.work_dir.join("rootfs");
-        
-        match self.config.base_system {
-            BaseSystem::Arch => self.build_arch_rootfs(&rootfs_dir).await?,
-            BaseSystem::Debian => self.build_debian_rootfs(&rootfs_dir).await?,
-            BaseSystem::Ubuntu => self.build_ubuntu_rootfs(&rootfs_dir).await?,
-            BaseSystem::Fedora => self.build_fedora_rootfs(&rootfs_dir).await?,
-            BaseSystem::CentOS => self.build_centos_rootfs(&rootfs_dir).await?,
-            BaseSystem::OpenSUSE => self.build_opensuse_rootfs(&rootfs_dir).await?,
-            BaseSystem::Alpine => self.build_alpine_rootfs(&rootfs_dir).await?,
-            BaseSystem::Scratch => self.build_scratch_rootfs(&rootfs_dir).await?,
-        }
-
-        Ok(())
+    /// The bootstrap backend for the configured base system; `None` for scratch builds
+    fn backend(&self) -> Option<Box<dyn BuilderBackend>> {
+        builder_backend::for_base_system(&self.config.base_system, self.config.base_release.as_deref(), self.snapshot())
     }
 
-    async fn build_arch_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building Arch Linux base system...");
-        
-        // Ensure the directory exists and has proper permissions
-        fs::create_dir_all(rootfs_dir)?;
-        
-        // Use pacstrap to bootstrap base system (don't skip copying mirrorlist)
-        let mut cmd = AsyncCommand::new("pacstrap");
-        cmd.arg("-c")  // Use package cache
-           .arg(rootfs_dir)
-           .arg("base")
-           .arg("linux")
-           .arg("linux-firmware");
-
-        println!("Running: pacstrap -c {} base linux linux-firmware", rootfs_dir.display());
-        
-        let output = cmd.output().await
-            .context("Failed to run pacstrap")?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("STDOUT: {stdout}");
-            println!("STDERR: {stderr}");
-            anyhow::bail!("pacstrap failed: {}", stderr);
+    /// Run a backend command, failing with its error output if it fails
+    async fn run_backend_step(&self, step: &CommandStep) -> Result<()> {
+        let output = self.run_command(step.to_command()).await
+            .with_context(|| format!("Failed to run {}", step.program))?;
+        if !output.success {
+            anyhow::bail!("{} failed: {}", step.program, output.error_summary());
         }
-
-        // Copy mirrorlist to ensure package installations work
-        let host_mirrorlist = Path::new("/etc/pacman.d/mirrorlist");
-        let chroot_mirrorlist = rootfs_dir.join("etc/pacman.d/mirrorlist");
-        if host_mirrorlist.exists() {
-            fs::create_dir_all(chroot_mirrorlist.parent().unwrap())?;
-            fs::copy(host_mirrorlist, chroot_mirrorlist)?;
-            println!("✅ Copied mirrorlist to chroot");
-        }
-
-        println!("✅ Arch Linux base system created successfully");
         Ok(())
     }
 
-    async fn build_debian_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building Debian base system...");
-        
-        // Install debootstrap if not available
-        if Command::new("which").arg("debootstrap").output()?.status.success() {
-            let mut cmd = AsyncCommand::new("debootstrap");
-            cmd.arg("--arch").arg(&self.config.architecture)
-               .arg("stable")
-               .arg(rootfs_dir)
-               .arg("http://deb.debian.org/debian/");
-
-            let output = cmd.output().await
-                .context("Failed to run debootstrap")?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("debootstrap failed: {}", stderr);
+    /// Refresh the package database in the chroot; a failure only warns,
+    /// since the bootstrap's database is usually still usable
+    async fn refresh_packages(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path) {
+        if let Some(step) = backend.refresh(rootfs_dir)
+            && let Err(e) = self.run_backend_step(&step).await {
+                println!("⚠️  Warning: Failed to update package database: {}", e);
             }
-        } else {
-            anyhow::bail!("debootstrap not found. Please install it first.");
-        }
-
-        Ok(())
     }
 
-    async fn build_ubuntu_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building Ubuntu base system...");
+    async fn build_rootfs(&self) -> Result<()> {
+        println!("🔧 Building root filesystem...");
         
-        if Command::new("which").arg("debootstrap").output()?.status.success() {
-            let mut cmd = AsyncCommand::new("debootstrap");
-            cmd.arg("--arch").arg(&self.config.architecture)
-               .arg("jammy") // Ubuntu 22.04 LTS
-               .arg(rootfs_dir)
-               .arg("http://archive.ubuntu.com/ubuntu/");
+        let rootfs_dir = self.work_dir.join("rootfs");
+        let Some(backend) = self.backend() else {
+            return self.build_scratch_rootfs(&rootfs_dir).await;
+        };
+
+        println!("🏗️  Building {:?} base system with {}...", self.config.base_system, backend.name());
+        fs::create_dir_all(&rootfs_dir)?;
 
-            let output = cmd.output().await
-                .context("Failed to run debootstrap")?;
+        let architecture = &self.config.architecture;
+        match backend.snapshot_url(architecture) {
+            Some(url) => println!("📅 Installing from {}", url),
+            None => println!("⚠️  {} has no snapshot archive; the lock records package versions, but rebuilds may install newer ones", backend.name()),
+        }
+        let (root_files, build_files): (Vec<_>, Vec<_>) = backend.repository_files(&rootfs_dir, architecture)
+            .into_iter()
+            .partition(|(path, _)| path.starts_with(&rootfs_dir));
+        write_files(&build_files)?;
+
+        for step in backend.bootstrap(&rootfs_dir, &self.config.architecture) {
+            println!("Running: {}", step);
+            self.run_backend_step(&step).await?;
+        }
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("debootstrap failed: {}", stderr);
+        // Host configuration the chroot needs for later package installs
+        for host_file in backend.host_files() {
+            let host_file = Path::new(host_file);
+            if host_file.exists() {
+                let target = rootfs_dir.join(host_file.strip_prefix("/")?);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                // resolv.conf may be a dangling symlink into /run
+                let _ = fs::remove_file(&target);
+                fs::copy(host_file, &target)
+                    .with_context(|| format!("Failed to copy {} into the chroot", host_file.display()))?;
+                println!("✅ Copied {} to chroot", host_file.display());
             }
-        } else {
-            anyhow::bail!("debootstrap not found. Please install it first.");
         }
+        write_files(&root_files)?;
 
+        println!("✅ {:?} base system created successfully", self.config.base_system);
         Ok(())
     }
 
@@ -1264,165 +1526,47 @@ impl DistroBuilder {
         Ok(())
     }
 
-    async fn build_fedora_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building Fedora base system...");
-        
-        if Command::new("which").arg("dnf").output()?.status.success() {
-            // Use dnf to create a chroot environment
-            let mut cmd = AsyncCommand::new("dnf");
-            cmd.arg("--installroot=").arg(rootfs_dir)
-               .arg("install")
-               .arg("@core")
-               .arg("--releasever").arg("latest")
-               .arg("-y");
-
-            let output = cmd.output().await.context("Failed to run dnf")?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("dnf install failed: {}", stderr);
-            }
-        } else {
-            anyhow::bail!("dnf not found. Please install it first.");
-        }
-
-        Ok(())
-    }
-
-    async fn build_centos_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building CentOS base system...");
-        
-        if Command::new("which").arg("yum").output()?.status.success() {
-            // Use yum to create a yum shell and install base
-            // For simplicity, use yum groupinstall
-            let mut cmd = AsyncCommand::new("yum");
-            cmd.arg("--installroot=").arg(rootfs_dir)
-               .arg("groupinstall")
-               .arg("Core")
-               .arg("-y");
-
-            let output = cmd.output().await.context("Failed to run yum")?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("yum groupinstall failed: {}", stderr);
-            }
-        } else {
-            anyhow::bail!("yum not found. Please install it first.");
-        }
-
-        Ok(())
-    }
-
-    async fn build_opensuse_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building openSUSE base system...");
+    async fn install_kernel(&self) -> Result<()> {
+        println!("🐧 Installing kernel...");
         
-        if Command::new("which").arg("zypper").output()?.status.success() {
-            // Use zypper to create base
-            let mut cmd = AsyncCommand::new("zypper");
-            cmd.arg("--root").arg(rootfs_dir)
-               .arg("install")
-               .arg("-t").arg("pattern")
-               .arg("minimal_base")
-               .arg("-y");
+        let rootfs_dir = self.work_dir.join("rootfs");
+        let Some(backend) = self.backend() else {
+            println!("⚠️  Scratch builds have no package manager; put a kernel in the rootfs yourself");
+            return Ok(());
+        };
 
-            let output = cmd.output().await.context("Failed to run zypper")?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("zypper install failed: {}", stderr);
-            }
-        } else {
-            anyhow::bail!("zypper not found. Please install it first.");
-        }
+        let kernel_package = backend.kernel_package(&self.config.kernel.kernel_type, &self.config.architecture)
+            .with_context(|| format!("{:?} doesn't provide a {:?} kernel", self.config.base_system, self.config.kernel.kernel_type))?;
+        println!("Installing kernel package: {kernel_package}");
 
-        Ok(())
-    }
+        // The live-boot hook goes in with the kernel so its initramfs can mount the squashfs
+        let mut packages = vec![kernel_package];
+        packages.extend(backend.live_boot_packages().iter().map(|package| package.to_string()));
 
-    async fn build_alpine_rootfs(&self, rootfs_dir: &Path) -> Result<()> {
-        println!("🏗️  Building Alpine base system...");
-        
-        if Command::new("which").arg("apk").output()?.status.success() {
-            let mut cmd = AsyncCommand::new("apk");
-            cmd.arg("--root").arg(rootfs_dir)
-               .arg("--initdb")
-               .arg("add")
-               .arg("alpine-base");
+        self.refresh_packages(backend.as_ref(), &rootfs_dir).await;
+        self.run_backend_step(&backend.install(&rootfs_dir, &self.config.architecture, &packages)).await
+            .context("Kernel installation failed")?;
 
-            let output = cmd.output().await.context("Failed to run apk")?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                anyhow::bail!("apk add failed: {}", stderr);
-            }
-        } else {
-            anyhow::bail!("apk not found. Please install it first.");
+        if let Some(live_boot) = backend.live_boot() {
+            self.configure_live_boot(backend.as_ref(), live_boot, &rootfs_dir).await?;
         }
 
+        println!("✅ Kernel installation completed");
         Ok(())
     }
 
-    async fn install_kernel(&self) -> Result<()> {
-        println!("🐧 Installing kernel...");
-        
-        let rootfs_dir = self.work_dir.join("rootfs");
-        
-        match self.config.kernel.kernel_type {
-            KernelType::Vanilla => {
-                // Install vanilla kernel
-                self.install_arch_kernel(&rootfs_dir, "linux").await?;
-            }
-            KernelType::Lts => {
-                self.install_arch_kernel(&rootfs_dir, "linux-lts").await?;
-            }
-            KernelType::Hardened => {
-                self.install_arch_kernel(&rootfs_dir, "linux-hardened").await?;
-            }
-            KernelType::Rt => {
-                self.install_arch_kernel(&rootfs_dir, "linux-rt").await?;
-            }
-            KernelType::Custom(ref kernel) => {
-                self.install_arch_kernel(&rootfs_dir, kernel).await?;
-            }
+    /// Enable the live-boot hook and rebuild the initramfs with it
+    async fn configure_live_boot(&self, backend: &dyn BuilderBackend, live_boot: LiveBoot, rootfs_dir: &Path) -> Result<()> {
+        println!("Configuring {} initramfs...", live_boot.name());
+        for (path, content) in live_boot.config_files() {
+            let path = rootfs_dir.join(path);
+            fs::create_dir_all(path.parent().context("Invalid live-boot config path")?)?;
+            fs::write(&path, content)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
         }
 
-        Ok(())
-    }
-
-    async fn install_arch_kernel(&self, rootfs_dir: &Path, kernel_package: &str) -> Result<()> {
-        println!("Installing kernel package: {kernel_package}");
-        
-        // First, update the package database
-        let mut update_cmd = AsyncCommand::new("arch-chroot");
-        update_cmd.arg(rootfs_dir)
-                  .arg("pacman")
-                  .arg("-Sy")
-                  .arg("--noconfirm");
-        
-        let update_output = update_cmd.output().await?;
-        if !update_output.status.success() {
-            println!("Warning: Failed to update package database");
-        }
-        
-        // Install the kernel (it might already be installed from base)
-        let mut cmd = AsyncCommand::new("arch-chroot");
-        cmd.arg(rootfs_dir)
-           .arg("pacman")
-           .arg("-S")
-           .arg("--noconfirm")
-           .arg("--needed")  // Only install if not already present
-           .arg(kernel_package);
-
-        let output = cmd.output().await?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("STDOUT: {stdout}");
-            println!("STDERR: {stderr}");
-            // Don't fail if kernel is already installed
-            if !stderr.contains("is up to date") && !stderr.contains("target not found") {
-                anyhow::bail!("Kernel installation failed: {}", stderr);
-            }
-        }
-
-        println!("✅ Kernel installation completed");
+        self.run_backend_step(&backend.chroot(rootfs_dir, live_boot.regenerate_initramfs())).await
+            .context("Failed to regenerate the initramfs")?;
         Ok(())
     }
 
@@ -1430,96 +1574,64 @@ impl DistroBuilder {
         println!("📦 Installing packages...");
         
         let rootfs_dir = self.work_dir.join("rootfs");
+        let Some(backend) = self.backend() else {
+            println!("✅ Scratch build; no packages to install");
+            return Ok(());
+        };
+        let backend = backend.as_ref();
         
         // Filter out packages that are already included in base system
-        let base_packages = vec!["base", "linux", "linux-firmware"];
         let additional_essential: Vec<String> = self.config.packages.essential
             .iter()
-            .filter(|pkg| !base_packages.contains(&pkg.as_str()))
+            .filter(|pkg| !backend.base_packages().contains(&pkg.as_str()))
             .cloned()
             .collect();
         
         // Use parallel installation if enabled
         if self.config.build_options.parallel_builds {
-            self.install_packages_parallel(&rootfs_dir, &additional_essential).await?
+            self.install_packages_parallel(backend, &rootfs_dir, &additional_essential).await?
         } else {
             // Sequential installation for better reliability
             if !additional_essential.is_empty() {
                 println!("Installing additional essential packages: {additional_essential:?}");
-                self.install_package_list(&rootfs_dir, &additional_essential).await?;
+                self.install_package_list(backend, &rootfs_dir, &additional_essential).await?;
             } else {
                 println!("✅ Skipping essential packages (already installed in base system)");
             }
 
             // Install desktop environment
-            if let Some(ref de) = self.config.packages.desktop_environment {
-                self.install_desktop_environment(&rootfs_dir, de).await?;
+            let desktop_packages = self.desktop_packages(backend)?;
+            if !desktop_packages.is_empty() {
+                self.install_package_list(backend, &rootfs_dir, &desktop_packages).await?;
             }
 
             // Install additional packages
             if !self.config.packages.additional_packages.is_empty() {
-                self.install_package_list(&rootfs_dir, &self.config.packages.additional_packages).await?;
+                self.install_package_list(backend, &rootfs_dir, &self.config.packages.additional_packages).await?;
             }
         }
 
         Ok(())
     }
 
-    async fn install_package_list(&self, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
+    async fn install_package_list(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
         println!("Installing packages: {packages:?}");
         
-        // First update the package database
-        let mut update_cmd = AsyncCommand::new("arch-chroot");
-        update_cmd.arg(rootfs_dir)
-                  .arg("pacman")
-                  .arg("-Sy")
-                  .arg("--noconfirm");
-        
-        let update_output = update_cmd.output().await?;
-        if !update_output.status.success() {
-            println!("Warning: Failed to update package database in chroot");
-        }
-        
-        let mut cmd = AsyncCommand::new("arch-chroot");
-        cmd.arg(rootfs_dir)
-           .arg("pacman")
-           .arg("-S")
-           .arg("--noconfirm")
-           .arg("--needed");  // Only install if not already present
-        
-        for package in packages {
-            cmd.arg(package);
-        }
-
-        let output = cmd.output().await?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("STDOUT: {stdout}");
-            println!("STDERR: {stderr}");
-            anyhow::bail!("Package installation failed: {}", stderr);
-        }
+        self.refresh_packages(backend, rootfs_dir).await;
+        self.run_backend_step(&backend.install(rootfs_dir, &self.config.architecture, packages)).await
+            .context("Package installation failed")?;
 
         println!("✅ Successfully installed packages");
         Ok(())
     }
 
-    async fn install_desktop_environment(&self, rootfs_dir: &Path, de: &DesktopEnvironment) -> Result<()> {
-        let packages = match de {
-            DesktopEnvironment::Gnome => vec!["gnome".to_string()],
-            DesktopEnvironment::Kde => vec!["plasma".to_string(), "kde-applications".to_string()],
-            DesktopEnvironment::Xfce => vec!["xfce4".to_string(), "xfce4-goodies".to_string()],
-            DesktopEnvironment::Lxde => vec!["lxde".to_string()],
-            DesktopEnvironment::Mate => vec!["mate".to_string()],
-            DesktopEnvironment::Cinnamon => vec!["cinnamon".to_string()],
-            DesktopEnvironment::Sway => vec!["sway".to_string()],
-            DesktopEnvironment::I3 => vec!["i3".to_string()],
-            DesktopEnvironment::Custom(package) => vec![package.clone()],
-            DesktopEnvironment::None => return Ok(()),
+    /// The configured desktop's packages on this base system
+    fn desktop_packages(&self, backend: &dyn BuilderBackend) -> Result<Vec<String>> {
+        let Some(ref de) = self.config.packages.desktop_environment else {
+            return Ok(Vec::new());
         };
-
-        self.install_package_list(rootfs_dir, &packages).await
+        backend.desktop_packages(de)
+            .with_context(|| format!("{:?} doesn't provide the {:?} desktop", self.config.base_system, de))
     }
 
     async fn configure_system(&self) -> Result<()> {
@@ -1537,27 +1649,41 @@ impl DistroBuilder {
         );
         fs::write(rootfs_dir.join("etc/hosts"), hosts_content)?;
         
-        // Enable systemd services
-        self.configure_systemd(&rootfs_dir).await?;
+        // Enable and disable services
+        self.configure_services(&rootfs_dir).await?;
         
         Ok(())
     }
 
-    async fn configure_systemd(&self, rootfs_dir: &Path) -> Result<()> {
-        let services = [
-            "NetworkManager.service",
-            "systemd-resolved.service",
-            "systemd-timesyncd.service",
-        ];
+    async fn configure_services(&self, rootfs_dir: &Path) -> Result<()> {
+        let Some(backend) = self.backend() else {
+            return Ok(());
+        };
+        let services = &self.config.user_config.services;
+
+        // The defaults may not be installed, so they only warn
+        for service in backend.default_services() {
+            if services.disabled_services.iter().any(|disabled| disabled == service) {
+                continue;
+            }
+            if let Err(e) = self.run_backend_step(&backend.enable_service(rootfs_dir, service)).await {
+                println!("⚠️  Could not enable {}: {}", service, e);
+            }
+        }
 
-        for service in services {
-            let mut cmd = AsyncCommand::new("arch-chroot");
-            cmd.arg(rootfs_dir)
-               .arg("systemctl")
-               .arg("enable")
-               .arg(service);
+        let mut requested: Vec<&str> = services.custom_services.iter().map(String::as_str).collect();
+        if services.enable_ssh {
+            requested.push(backend.ssh_service());
+        }
+        for service in requested {
+            self.run_backend_step(&backend.enable_service(rootfs_dir, service)).await
+                .with_context(|| format!("Failed to enable service {}", service))?;
+        }
 
-            let _ = cmd.output().await; // Don't fail if service doesn't exist
+        for service in &services.disabled_services {
+            if let Err(e) = self.run_backend_step(&backend.disable_service(rootfs_dir, service)).await {
+                println!("⚠️  Could not disable {}: {}", service, e);
+            }
         }
 
         Ok(())
@@ -1572,84 +1698,390 @@ impl DistroBuilder {
         Ok(())
     }
 
-    async fn configure_bootloader(&self) -> Result<()> {
+    /// The live-boot hook the initramfs uses; scratch builds have none
+    fn live_boot(&self) -> Option<LiveBoot> {
+        self.backend().and_then(|backend| backend.live_boot())
+    }
+
+    /// Kernel command line for the live system: where the live-boot hook
+    /// finds the ISO, by its volume label, and a serial console
+    fn kernel_cmdline(&self) -> String {
+        let label = iso_boot::volume_label(&self.config.name);
+        let root = match self.live_boot() {
+            Some(live_boot) => live_boot.cmdline(&label),
+            None => format!("root=/dev/disk/by-label/{} ro", label),
+        };
+        format!("{} {}", root, self.console_args())
+    }
+
+    /// The last console is /dev/console, which gets the login prompt
+    fn console_args(&self) -> String {
+        format!("console=tty0 console={}", iso_boot::serial_console(&self.config.architecture))
+    }
+
+    fn wants(&self, format: OutputFormat) -> bool {
+        self.config.output_formats.contains(&format)
+    }
+
+    /// Whether a partitioned disk image is built, for Raw or Qcow2
+    fn wants_disk(&self) -> bool {
+        self.config.output_formats.iter().any(|format| format.is_disk())
+    }
+
+    /// `<name>-<version>-<arch>`, the file name of every output
+    fn output_stem(&self) -> String {
+        format!("{}-{}-{}", self.config.name, self.config.version, self.config.architecture)
+    }
+
+    /// An ID for part of an output that stays the same across rebuilds
+    fn stable_id_seed(&self, part: &str) -> String {
+        format!("{}-{}", self.output_stem(), part)
+    }
+
+    /// Where the squashfs goes on the ISO, relative to its root
+    fn squashfs_path(&self) -> String {
+        match self.live_boot() {
+            Some(live_boot) => live_boot.squashfs_path(&self.config.architecture),
+            None => "live/filesystem.squashfs".to_string(),
+        }
+    }
+
+    /// Stage what each output boots from, returning the staged paths: the
+    /// kernel, isolinux and an EFI system partition image under `iso/boot`
+    /// for the ISO, and the disk images' EFI system partition
+    async fn configure_bootloader(&self) -> Result<Vec<PathBuf>> {
         println!("🥾 Configuring bootloader...");
-        
-        let boot_dir = self.work_dir.join("boot");
+
         let rootfs_dir = self.work_dir.join("rootfs");
-        
-        // Copy kernel and initramfs to boot directory
-        let kernel_files = [
-            "vmlinuz-linux",
-            "initramfs-linux.img",
-            "initramfs-linux-fallback.img",
-        ];
+        let (kernel, initrd) = iso_boot::find_boot_files(&rootfs_dir.join("boot"))?;
+        if initrd.is_none() {
+            println!("⚠️  No initramfs found for {}; booting without one", kernel.display());
+        }
 
-        for file in kernel_files {
-            let src = rootfs_dir.join("boot").join(file);
-            let dst = boot_dir.join(file);
-            if src.exists() {
-                fs::copy(src, dst)?;
-            }
+        let mut staged = Vec::new();
+        if self.wants(OutputFormat::Iso) {
+            staged.push(self.configure_iso_boot(&kernel, initrd.as_deref()).await?);
+        }
+        if self.wants_disk() {
+            staged.push(self.configure_disk_boot(&kernel, initrd.as_deref()).await?);
+        }
+        Ok(staged)
+    }
+
+    async fn configure_iso_boot(&self, kernel: &Path, initrd: Option<&Path>) -> Result<PathBuf> {
+        let iso_dir = self.work_dir.join("iso");
+        let boot_dir = iso_dir.join("boot");
+
+        // A previous build may have used another loader
+        if boot_dir.exists() {
+            fs::remove_dir_all(&boot_dir)?;
+        }
+        fs::create_dir_all(&boot_dir)?;
+        copy_boot_files(kernel, initrd, &iso_dir)?;
+
+        let entries = iso_boot::boot_entries(
+            &self.config.bootloader.default_entry, &self.config.name, &self.kernel_cmdline(), initrd.is_some());
+
+        if iso_boot::has_bios(&self.config.architecture) {
+            self.configure_syslinux(&boot_dir, &entries)?;
         }
+        let esp_dir = self.work_dir.join("esp");
+        if esp_dir.exists() {
+            fs::remove_dir_all(&esp_dir)?;
+        }
+        let image = iso_dir.join(iso_boot::EFI_IMAGE);
+        self.configure_uefi(&esp_dir, &image, &entries, EspTarget::Iso(&iso_dir)).await?;
+
+        Ok(boot_dir)
+    }
 
-        match self.config.bootloader.bootloader {
-            Bootloader::Syslinux => self.configure_syslinux(&boot_dir).await?,
-            Bootloader::Grub => self.configure_grub(&boot_dir).await?,
-            _ => println!("⚠️  Bootloader configuration not implemented yet"),
+    /// Build the EFI system partition of the disk images, with the kernel on
+    /// it and the root partition found by its partition UUID
+    async fn configure_disk_boot(&self, kernel: &Path, initrd: Option<&Path>) -> Result<PathBuf> {
+        let rootfs_dir = self.work_dir.join("rootfs");
+        let esp_dir = self.work_dir.join("disk-esp");
+        if esp_dir.exists() {
+            fs::remove_dir_all(&esp_dir)?;
+        }
+
+        // A live hook that takes over every boot needs an initramfs without it
+        let mut disk_initrd = None;
+        if let (Some(backend), Some(live_boot)) = (self.backend(), self.live_boot()) {
+            let kernel_name = kernel.file_name().context("Invalid kernel path")?.to_string_lossy();
+            if let Some(command) = live_boot.disk_initramfs(&kernel_name) {
+                println!("Building an initramfs without {} for disk images...", live_boot.name());
+                let args: Vec<&str> = command.iter().map(String::as_str).collect();
+                self.run_backend_step(&backend.chroot(&rootfs_dir, &args)).await
+                    .context("Failed to build the disk image initramfs")?;
+                disk_initrd = Some(rootfs_dir.join(builder_backend::DISK_INITRD));
+            }
+        }
+        let initrd = disk_initrd.as_deref().or(initrd);
+        copy_boot_files(kernel, initrd, &esp_dir)?;
+
+        let cmdline = format!("root=PARTUUID={} rw {}", self.root_partition_uuid(), self.console_args());
+        let entries = iso_boot::boot_entries(
+            &self.config.bootloader.default_entry, &self.config.name, &cmdline, initrd.is_some());
+        let image = self.work_dir.join("disk").join("esp.img");
+        let volume_id = disk_image::stable_volume_id(&self.stable_id_seed("disk-esp"));
+        self.configure_uefi(&esp_dir, &image, &entries, EspTarget::Disk(volume_id)).await?;
+
+        Ok(image)
+    }
+
+    fn root_partition_uuid(&self) -> String {
+        disk_image::stable_uuid(&self.stable_id_seed("root"))
+    }
+
+    /// isolinux, the BIOS El Torito entry
+    fn configure_syslinux(&self, boot_dir: &Path, entries: &[BootEntry]) -> Result<()> {
+        let isolinux_dir = boot_dir.join("isolinux");
+        fs::create_dir_all(&isolinux_dir)?;
+
+        for file in iso_boot::ISOLINUX_FILES {
+            let src = iso_boot::syslinux_file(file)
+                .with_context(|| format!("Syslinux BIOS file {} not found; install the syslinux package", file))?;
+            fs::copy(&src, isolinux_dir.join(file))?;
         }
 
+        fs::write(isolinux_dir.join("isolinux.cfg"), iso_boot::isolinux_cfg(entries, self.config.bootloader.timeout))?;
+        println!("✅ Configured isolinux for BIOS boot");
         Ok(())
     }
 
-    async fn configure_syslinux(&self, boot_dir: &Path) -> Result<()> {
-        let syslinux_cfg = format!(
-            r#"DEFAULT {default}
-TIMEOUT {timeout}0
-
-LABEL {default}
-    MENU LABEL {name}
-    LINUX /vmlinuz-linux
-    APPEND root=/dev/disk/by-label/{name} rw
-    INITRD /initramfs-linux.img
-
-LABEL {default}fallback
-    MENU LABEL {name} (fallback initramfs)
-    LINUX /vmlinuz-linux
-    APPEND root=/dev/disk/by-label/{name} rw
-    INITRD /initramfs-linux-fallback.img
-"#,
-            default = self.config.bootloader.default_entry,
-            timeout = self.config.bootloader.timeout,
-            name = self.config.name
-        );
+    /// Build an EFI system partition image from the tree staged in `esp_dir`,
+    /// with the loader chosen by `bootloader.bootloader`
+    async fn configure_uefi(&self, esp_dir: &Path, image: &Path, entries: &[BootEntry], target: EspTarget<'_>) -> Result<()> {
+        let arch = &self.config.architecture;
+        let efi = EfiArch::for_architecture(arch)
+            .with_context(|| format!("UEFI boot isn't supported for {}", arch))?;
+        let loader = EfiLoader::for_bootloader(&self.config.bootloader.bootloader);
+        let timeout = self.config.bootloader.timeout;
+
+        let loader_path = esp_dir.join(efi.loader_path());
+        let loader_dir = loader_path.parent().context("Invalid EFI loader path")?;
+        fs::create_dir_all(loader_dir)?;
+
+        if let Some(binary) = loader.binary(&efi) {
+            fs::copy(&binary, &loader_path)
+                .with_context(|| format!("UEFI loader {} not found", binary.display()))?;
+        }
+        if let EspTarget::Iso(iso_dir) = target
+            && loader.needs_kernel_on_esp() {
+                // These loaders can't read ISO 9660, so the kernel goes on the ESP too
+                for path in [iso_boot::KERNEL_PATH, iso_boot::INITRD_PATH] {
+                    let src = iso_dir.join(path.trim_start_matches('/'));
+                    if src.exists() {
+                        let dst = esp_dir.join(path.trim_start_matches('/'));
+                        fs::create_dir_all(dst.parent().context("Invalid boot file path")?)?;
+                        fs::copy(&src, &dst)?;
+                    }
+                }
+            }
 
-        fs::write(boot_dir.join("syslinux.cfg"), syslinux_cfg)?;
+        match loader {
+            EfiLoader::Grub => {
+                // A standalone image that either finds the ISO by label and
+                // loads its grub.cfg, or has the disk's menu built in
+                let embedded = match target {
+                    EspTarget::Iso(iso_dir) => {
+                        let grub_dir = iso_dir.join("boot").join("grub");
+                        fs::create_dir_all(&grub_dir)?;
+                        fs::write(grub_dir.join("grub.cfg"), iso_boot::grub_cfg(entries, timeout))?;
+                        iso_boot::grub_embedded_cfg(&iso_boot::volume_label(&self.config.name))
+                    }
+                    EspTarget::Disk(volume_id) => iso_boot::grub_disk_cfg(entries, timeout, &disk_image::fat_uuid(volume_id)),
+                };
+                let embedded_cfg = esp_dir.with_extension("grub.cfg");
+                fs::write(&embedded_cfg, embedded)?;
+                let mkstandalone = CommandStep::new("grub-mkstandalone", [
+                    "--format".to_string(), efi.grub_target.to_string(),
+                    "--output".to_string(), loader_path.display().to_string(),
+                    "--locales=".to_string(),
+                    "--fonts=".to_string(),
+                    "--modules=part_gpt part_msdos iso9660 fat search search_label search_fs_uuid normal configfile linux all_video gzio".to_string(),
+                    format!("boot/grub/grub.cfg={}", embedded_cfg.display()),
+                ]);
+                self.run_backend_step(&mkstandalone).await?;
+            }
+            EfiLoader::SystemdBoot => {
+                let entries_dir = esp_dir.join("loader").join("entries");
+                fs::create_dir_all(&entries_dir)?;
+                fs::write(esp_dir.join("loader").join("loader.conf"), iso_boot::systemd_boot_loader_conf(entries, timeout))?;
+                for entry in entries {
+                    fs::write(entries_dir.join(format!("{}.conf", entry.id)), iso_boot::systemd_boot_entry(entry))?;
+                }
+            }
+            EfiLoader::Refind => {
+                fs::write(loader_dir.join("refind.conf"), iso_boot::refind_conf(entries, timeout))?;
+            }
+        }
+
+        // mkfs.fat -C refuses to overwrite an existing image
+        if image.exists() {
+            fs::remove_file(image)?;
+        }
+        fs::create_dir_all(image.parent().context("Invalid EFI image path")?)?;
+        let volume_id = match target {
+            EspTarget::Iso(_) => disk_image::stable_volume_id(&self.stable_id_seed("iso-esp")),
+            EspTarget::Disk(volume_id) => volume_id,
+        };
+        self.run_backend_step(&build_lock::clamp_mtimes_step(esp_dir, self.source_date_epoch())).await?;
+        for step in iso_boot::esp_image_steps(esp_dir, image, volume_id)? {
+            self.run_backend_step(&step).await?;
+        }
+
+        println!("✅ Configured {:?} for UEFI boot ({})", loader, efi.loader_path());
         Ok(())
     }
 
-    async fn configure_grub(&self, _boot_dir: &Path) -> Result<()> {
-        // GRUB configuration would go here
+    /// Build each of `output_formats` from the rootfs and the staged boot
+    /// files, returning their paths in the configured order
+    async fn create_images(&self) -> Result<Vec<PathBuf>> {
+        // Qcow2 is converted from the raw image, which is kept only if asked for
+        let disk = if self.wants_disk() {
+            let path = if self.wants(OutputFormat::Raw) {
+                self.output_dir.join(format!("{}.img", self.output_stem()))
+            } else {
+                self.work_dir.join("disk").join("disk.img")
+            };
+            self.create_disk_image(&path).await?;
+            Some(path)
+        } else {
+            None
+        };
+
+        let mut outputs = Vec::new();
+        for format in &self.config.output_formats {
+            let output = match format {
+                OutputFormat::Iso => self.create_iso().await?,
+                OutputFormat::Raw => disk.clone().context("No disk image was built")?,
+                OutputFormat::Qcow2 => self.create_qcow2(disk.as_deref().context("No disk image was built")?).await?,
+                OutputFormat::Tarball => self.create_tarball().await?,
+                OutputFormat::Oci => self.create_oci().await?,
+            };
+            if !outputs.contains(&output) {
+                outputs.push(output);
+            }
+        }
+
+        if let Some(disk) = disk
+            && !self.wants(OutputFormat::Raw) {
+                fs::remove_file(&disk)?;
+            }
+        Ok(outputs)
+    }
+
+    /// A GPT disk image: the EFI system partition staged by the bootloader
+    /// step, and the rootfs on `filesystem.root_fs`, built without mounting
+    async fn create_disk_image(&self, path: &Path) -> Result<()> {
+        println!("💽 Creating disk image...");
+
+        let rootfs_dir = self.work_dir.join("rootfs");
+        let disk_dir = self.work_dir.join("disk");
+        fs::create_dir_all(&disk_dir)?;
+
+        let root_image = disk_dir.join("root.img");
+        let root_mib = disk_image::root_size_mib(iso_boot::tree_size_kib(&rootfs_dir)?);
+        disk_image::create_sparse(&root_image, root_mib)?;
+        let label = iso_boot::volume_label(&self.config.name);
+        let epoch = self.source_date_epoch();
+        let fs_uuid = disk_image::stable_uuid(&self.stable_id_seed("root-fs"));
+        // mkfs copies the rootfs mtimes, which package installs left at build time
+        self.run_backend_step(&build_lock::clamp_mtimes_step(&rootfs_dir, epoch)).await?;
+        self.run_backend_step(&disk_image::mkfs_step(&self.config.filesystem.root_fs, &rootfs_dir, &root_image, &label, &fs_uuid, epoch)?).await?;
+        println!("✅ {:?} root filesystem created ({} MiB)", self.config.filesystem.root_fs, root_mib);
+
+        let esp_image = disk_dir.join("esp.img");
+        let layout = DiskLayout { esp_mib: disk_image::size_mib(&esp_image)?, root_mib };
+        let root_type = disk_image::root_partition_type(&self.config.architecture)
+            .with_context(|| format!("Disk images aren't supported for {}", self.config.architecture))?;
+        disk_image::create_sparse(path, layout.disk_mib())?;
+        let disk_guid = disk_image::stable_uuid(&self.stable_id_seed("disk"));
+        self.run_backend_step(&layout.partition_step(path, root_type, &disk_guid, &self.root_partition_uuid())).await?;
+
+        disk_image::write_partition(path, &esp_image, layout.esp_offset_mib())?;
+        disk_image::write_partition(path, &root_image, layout.root_offset_mib())?;
+        fs::remove_file(&root_image)?;
+
+        println!("✅ Disk image created: {} ({} MiB)", path.display(), layout.disk_mib());
         Ok(())
     }
 
+    async fn create_qcow2(&self, raw: &Path) -> Result<PathBuf> {
+        println!("💽 Converting disk image to qcow2...");
+        let path = self.output_dir.join(format!("{}.qcow2", self.output_stem()));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        self.run_backend_step(&disk_image::qcow2_step(raw, &path)).await?;
+        println!("✅ qcow2 image created: {}", path.display());
+        Ok(path)
+    }
+
+    /// The whole rootfs, compressed with `filesystem.compression`
+    async fn create_tarball(&self) -> Result<PathBuf> {
+        println!("📦 Creating rootfs tarball...");
+        let (extension, compression) = disk_image::tarball_compression(&self.config.filesystem.compression);
+        let path = self.output_dir.join(format!("{}.{}", self.output_stem(), extension));
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        self.run_backend_step(&disk_image::tar_step(&self.work_dir.join("rootfs"), &path, compression, &[], self.source_date_epoch())).await?;
+        println!("✅ Rootfs tarball created: {}", path.display());
+        Ok(path)
+    }
+
+    /// An OCI image layout of the rootfs without the kernel, for use as a
+    /// container base (`skopeo copy oci:<dir> ...`)
+    async fn create_oci(&self) -> Result<PathBuf> {
+        println!("📦 Creating OCI image...");
+        let path = self.output_dir.join(format!("{}-oci", self.output_stem()));
+        let layer = self.work_dir.join("oci-layer.tar");
+        let compressed = layer.with_extension("tar.gz");
+        for stale in [&layer, &compressed] {
+            if stale.exists() {
+                fs::remove_file(stale)?;
+            }
+        }
+
+        self.run_backend_step(&disk_image::tar_step(&self.work_dir.join("rootfs"), &layer, None, &["boot"], self.source_date_epoch())).await?;
+        let diff_id = disk_image::sha256_digest(&layer)?;
+        self.run_backend_step(&disk_image::gzip_step(&layer)).await?;
+        disk_image::write_oci_layout(&path, &OciImage {
+            reference: &self.config.version,
+            architecture: &self.config.architecture,
+            created: &DateTime::from_timestamp(self.source_date_epoch(), 0).unwrap_or_default().to_rfc3339(),
+            diff_id: &diff_id,
+            layer: &compressed,
+        })?;
+        println!("✅ OCI image created: {}", path.display());
+        Ok(path)
+    }
+
     async fn create_iso(&self) -> Result<PathBuf> {
         println!("💿 Creating ISO image...");
         
         let iso_dir = self.work_dir.join("iso");
         let rootfs_dir = self.work_dir.join("rootfs");
-        let _boot_dir = self.work_dir.join("boot");
         
         // Create SquashFS from rootfs
         println!("Creating SquashFS filesystem...");
-        let squashfs_path = iso_dir.join("live").join("filesystem.squashfs");
-        fs::create_dir_all(iso_dir.join("live"))?;
+        let squashfs_path = iso_dir.join(self.squashfs_path());
+        fs::create_dir_all(squashfs_path.parent().context("Invalid squashfs path")?)?;
+        // mksquashfs appends to an existing image, which a resumed build may have left
+        if squashfs_path.exists() {
+            fs::remove_file(&squashfs_path)?;
+        }
         
-        let mut mksquashfs_cmd = AsyncCommand::new("mksquashfs");
+        let mut mksquashfs_cmd = Command::new("mksquashfs");
+        let epoch = self.source_date_epoch().to_string();
         mksquashfs_cmd.arg(&rootfs_dir)
                      .arg(&squashfs_path)
                      .arg("-e")
-                     .arg("boot"); // Exclude boot directory from squashfs
+                     .arg("boot") // Exclude boot directory from squashfs
+                     .arg("-all-time").arg(&epoch) // Same mtimes, so the same bytes
+                     .arg("-mkfs-time").arg(&epoch);
         
         match self.config.filesystem.compression {
             CompressionType::Gzip => { mksquashfs_cmd.arg("-comp").arg("gzip"); }
@@ -1659,65 +2091,52 @@ LABEL {default}fallback
             CompressionType::None => {}
         }
 
-        let output = mksquashfs_cmd.output().await?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("STDOUT: {stdout}");
+        let output = self.run_command(mksquashfs_cmd).await?;
+        if !output.success {
+            let stderr = output.error_summary();
             anyhow::bail!("mksquashfs failed: {stderr}");
         }
         println!("✅ SquashFS created successfully");
 
-        // Copy boot files from rootfs to ISO
-        println!("Copying boot files...");
-        fs::create_dir_all(iso_dir.join("boot"))?;
-        
-        // Copy kernel and initramfs from rootfs/boot
-        let rootfs_boot = rootfs_dir.join("boot");
-        if rootfs_boot.exists() {
-            for entry in fs::read_dir(&rootfs_boot)? {
-                let entry = entry?;
-                if entry.file_name().to_string_lossy().starts_with("vmlinuz") ||
-                   entry.file_name().to_string_lossy().starts_with("initramfs") {
-                    let dst = iso_dir.join("boot").join(entry.file_name());
-                    fs::copy(entry.path(), &dst)?;
-                    let src_path = entry.path().display().to_string();
-                    let dst_path = dst.display().to_string();
-                    println!("Copied: {src_path} -> {dst_path}");
-                }
-            }
-        }
-
-        // Copy syslinux files
-        self.copy_syslinux_files(&iso_dir).await?;
-
-        // Create ISO with xorriso
+        // Create ISO with xorriso, which takes its dates from SOURCE_DATE_EPOCH
+        // but copies file mtimes
         println!("Creating ISO with xorriso...");
+        self.run_backend_step(&build_lock::clamp_mtimes_step(&iso_dir, self.source_date_epoch())).await?;
         let iso_filename = format!("{}-{}-{}.iso", 
                                  self.config.name, 
                                  self.config.version,
                                  self.config.architecture);
         let iso_path = self.output_dir.join(iso_filename);
 
-        let mut xorriso_cmd = AsyncCommand::new("xorriso");
+        let mut xorriso_cmd = Command::new("xorriso");
         xorriso_cmd.arg("-as").arg("mkisofs")
                    .arg("-iso-level").arg("3")
                    .arg("-full-iso9660-filenames")
-                   .arg("-volid").arg(&self.config.name)
-                   .arg("-eltorito-boot").arg("boot/isolinux/isolinux.bin")
-                   .arg("-eltorito-catalog").arg("boot/isolinux/boot.cat")
+                   .arg("-volid").arg(iso_boot::volume_label(&self.config.name));
+
+        // BIOS: isolinux, with an MBR so the image also boots from USB
+        if iso_boot::has_bios(&self.config.architecture) {
+            let isohdpfx = iso_boot::syslinux_file("isohdpfx.bin")
+                .context("Syslinux isohdpfx.bin not found; install the syslinux package")?;
+            xorriso_cmd.arg("-eltorito-boot").arg("boot/isolinux/isolinux.bin")
+                       .arg("-eltorito-catalog").arg("boot/isolinux/boot.cat")
+                       .arg("-no-emul-boot")
+                       .arg("-boot-load-size").arg("4")
+                       .arg("-boot-info-table")
+                       .arg("-isohybrid-mbr").arg(isohdpfx)
+                       .arg("-eltorito-alt-boot");
+        }
+
+        // UEFI: the EFI system partition image, also exposed as a GPT partition
+        xorriso_cmd.arg("-e").arg(iso_boot::EFI_IMAGE)
                    .arg("-no-emul-boot")
-                   .arg("-boot-load-size").arg("4")
-                   .arg("-boot-info-table")
-                   .arg("-isohybrid-mbr").arg("/usr/lib/syslinux/bios/isohdpfx.bin")
+                   .arg("-isohybrid-gpt-basdat")
                    .arg("-output").arg(&iso_path)
                    .arg(&iso_dir);
 
-        let output = xorriso_cmd.output().await?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            println!("STDOUT: {stdout}");
+        let output = self.run_command(xorriso_cmd).await?;
+        if !output.success {
+            let stderr = output.error_summary();
             anyhow::bail!("xorriso failed: {stderr}");
         }
 
@@ -1726,47 +2145,14 @@ LABEL {default}fallback
         Ok(iso_path)
     }
 
-    async fn copy_syslinux_files(&self, iso_dir: &Path) -> Result<()> {
-        let isolinux_dir = iso_dir.join("boot").join("isolinux");
-        fs::create_dir_all(&isolinux_dir)?;
-
-        let syslinux_files = [
-            "/usr/lib/syslinux/bios/isolinux.bin",
-            "/usr/lib/syslinux/bios/ldlinux.c32",
-            "/usr/lib/syslinux/bios/libcom32.c32",
-            "/usr/lib/syslinux/bios/libutil.c32",
-            "/usr/lib/syslinux/bios/menu.c32",
-        ];
-
-        for file_path in syslinux_files {
-            let file_name = Path::new(file_path).file_name().unwrap();
-            let dst = isolinux_dir.join(file_name);
-            if Path::new(file_path).exists() {
-                fs::copy(file_path, dst)?;
-            }
-        }
-
-        // Copy syslinux config as isolinux.cfg
-        let syslinux_cfg = iso_dir.join("boot").join("syslinux.cfg");
-        let isolinux_cfg = isolinux_dir.join("isolinux.cfg");
-        if syslinux_cfg.exists() {
-            fs::copy(syslinux_cfg, isolinux_cfg)?;
-        }
-
-        Ok(())
-    }
-
     // Enhanced parallel installation methods
-    async fn install_packages_parallel(&self, rootfs_dir: &Path, essential_packages: &[String]) -> Result<()> {
+    async fn install_packages_parallel(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path, essential_packages: &[String]) -> Result<()> {
         println!("🚀 Using parallel package installation");
         
         let mut all_packages = essential_packages.to_vec();
         
         // Add desktop environment packages
-        if let Some(ref de) = self.config.packages.desktop_environment {
-            let de_packages = self.get_desktop_environment_packages(de);
-            all_packages.extend(de_packages);
-        }
+        all_packages.extend(self.desktop_packages(backend)?);
         
         // Add additional packages
         all_packages.extend(self.config.packages.additional_packages.clone());
@@ -1793,7 +2179,7 @@ LABEL {default}fallback
             async move {
                 let _permit = semaphore.acquire().await.unwrap();
                 println!("📦 Batch {}: Installing {:?}", i + 1, chunk);
-                self.install_package_batch(&rootfs_dir, &chunk).await
+                self.install_package_batch(backend, &rootfs_dir, &chunk).await
             }
         }).collect();
         
@@ -1804,7 +2190,7 @@ LABEL {default}fallback
         Ok(())
     }
     
-    async fn install_package_batch(&self, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
+    async fn install_package_batch(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
         if packages.is_empty() {
             return Ok(());
         }
@@ -1822,90 +2208,51 @@ LABEL {default}fallback
                         packages.len() - uncached_packages.len(), uncached_packages.len());
             }
             
-            return self.install_package_list_optimized(rootfs_dir, &uncached_packages).await;
+            return self.install_package_list_optimized(backend, rootfs_dir, &uncached_packages).await;
         }
         
-        self.install_package_list_optimized(rootfs_dir, packages).await
+        self.install_package_list_optimized(backend, rootfs_dir, packages).await
     }
     
-    async fn install_package_list_optimized(&self, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
+    async fn install_package_list_optimized(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path, packages: &[String]) -> Result<()> {
         if packages.is_empty() {
             return Ok(());
         }
         
         // Update package database once per batch
-        let mut update_cmd = AsyncCommand::new("arch-chroot");
-        update_cmd.arg(rootfs_dir)
-                  .arg("pacman")
-                  .arg("-Sy")
-                  .arg("--noconfirm");
-        
         let update_start = Instant::now();
-        let update_output = update_cmd.output().await?;
-        if !update_output.status.success() {
-            println!("⚠️  Warning: Failed to update package database in chroot");
-        } else {
-            println!("✅ Updated package database in {:.1}s", update_start.elapsed().as_secs_f64());
+        if let Some(update) = backend.refresh(rootfs_dir) {
+            match self.run_backend_step(&update).await {
+                Ok(()) => println!("✅ Updated package database in {:.1}s", update_start.elapsed().as_secs_f64()),
+                Err(_) => println!("⚠️  Warning: Failed to update package database in chroot"),
+            }
         }
         
-        // Install packages with optimized flags
-        let mut cmd = AsyncCommand::new("arch-chroot");
-        cmd.arg(rootfs_dir)
-           .arg("pacman")
-           .arg("-S")
-           .arg("--noconfirm")
-           .arg("--needed")  // Only install if not already present
-           .arg("--noprogressbar"); // Disable progress bar for cleaner output
+        let mut install = backend.install(rootfs_dir, &self.config.architecture, packages);
         
         // Enable ccache if configured
         if self.config.build_options.enable_ccache {
-            cmd.env("CCACHE_DIR", "/var/cache/ccache");
-        }
-        
-        for package in packages {
-            cmd.arg(package);
+            install = install.with_env("CCACHE_DIR", "/var/cache/ccache");
         }
 
         let install_start = Instant::now();
-        let output = cmd.output().await?;
-        
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            
+        if let Err(e) = self.run_backend_step(&install).await {
             // Log more detailed error information
             println!("❌ Package installation failed after {:.1}s", install_start.elapsed().as_secs_f64());
             println!("📦 Failed packages: {:?}", packages);
-            println!("STDOUT: {}", stdout);
-            println!("STDERR: {}", stderr);
-            anyhow::bail!("Package installation failed: {}", stderr);
+            return Err(e.context("Package installation failed"));
         }
 
         println!("✅ Installed {} packages in {:.1}s", packages.len(), install_start.elapsed().as_secs_f64());
         
         // Cache packages if enabled
         if self.config.build_options.preserve_cache {
-            self.cache_installed_packages(packages).await;
+            self.cache_installed_packages(backend, rootfs_dir, packages).await;
         }
         
         Ok(())
     }
     
-    fn get_desktop_environment_packages(&self, de: &DesktopEnvironment) -> Vec<String> {
-        match de {
-            DesktopEnvironment::Gnome => vec!["gnome".to_string()],
-            DesktopEnvironment::Kde => vec!["plasma".to_string(), "kde-applications".to_string()],
-            DesktopEnvironment::Xfce => vec!["xfce4".to_string(), "xfce4-goodies".to_string()],
-            DesktopEnvironment::Lxde => vec!["lxde".to_string()],
-            DesktopEnvironment::Mate => vec!["mate".to_string()],
-            DesktopEnvironment::Cinnamon => vec!["cinnamon".to_string()],
-            DesktopEnvironment::Sway => vec!["sway".to_string()],
-            DesktopEnvironment::I3 => vec!["i3".to_string()],
-            DesktopEnvironment::Custom(package) => vec![package.clone()],
-            DesktopEnvironment::None => vec![],
-        }
-    }
-    
     async fn filter_cached_packages(&self, packages: &[String]) -> Vec<String> {
         let cache = self.package_cache.lock().await;
         let mut uncached = Vec::new();
@@ -1919,36 +2266,79 @@ LABEL {default}fallback
         uncached
     }
     
-    async fn cache_installed_packages(&self, packages: &[String]) {
+    async fn cache_installed_packages(&self, backend: &dyn BuilderBackend, rootfs_dir: &Path, packages: &[String]) {
+        let installed = match self.installed_packages(backend, rootfs_dir).await {
+            Ok(installed) => installed,
+            Err(e) => {
+                println!("⚠️  Warning: Not caching packages, couldn't list installed versions: {}", e);
+                return;
+            }
+        };
         let mut cache = self.package_cache.lock().await;
         let timestamp = Utc::now();
         
+        // Groups and patterns aren't installed packages, so they aren't cached
+        let mut cached = 0;
         for package in packages {
-            // Create a simple cache entry (in real implementation, you'd want to store version info)
+            let Some(installed) = installed.iter().find(|installed| &installed.name == package) else { continue };
             let entry = PackageCacheEntry {
                 package_name: package.clone(),
-                version: "unknown".to_string(), // Would query actual version
-                hash: self.calculate_package_hash(package),
+                version: installed.version.clone(),
+                hash: installed.checksum.clone().unwrap_or_else(|| self.calculate_package_hash(package, &installed.version)),
                 timestamp,
                 cached_path: self.work_dir.join("cache").join(format!("{}.cached", package)),
             };
             
             cache.insert(package.clone(), entry);
+            cached += 1;
         }
         
-        println!("💾 Cached {} packages for future builds", packages.len());
+        println!("💾 Cached {} packages for future builds", cached);
     }
     
-    fn calculate_package_hash(&self, package: &str) -> String {
+    fn calculate_package_hash(&self, package: &str, version: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(package.as_bytes());
+        hasher.update(version.as_bytes());
         hasher.update(self.config.architecture.as_bytes());
-        hasher.update(Utc::now().format("%Y-%m-%d").to_string().as_bytes()); // Daily hash
         format!("{:x}", hasher.finalize())
     }
     
 }
 
+/// What an EFI system partition boots
+#[derive(Clone, Copy)]
+enum EspTarget<'a> {
+    /// The ISO staged in this directory, found by its volume label
+    Iso(&'a Path),
+    /// A disk image, whose ESP has the kernel on it and this FAT volume ID
+    Disk(u32),
+}
+
+/// Write generated files, creating their directories
+fn write_files(files: &[(PathBuf, String)]) -> Result<()> {
+    for (path, content) in files {
+        fs::create_dir_all(path.parent().context("Invalid file path")?)?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Copy the kernel and initramfs into `dir` under the names the loader configs use
+fn copy_boot_files(kernel: &Path, initrd: Option<&Path>, dir: &Path) -> Result<()> {
+    let boot_files = [(Some(kernel), iso_boot::KERNEL_PATH), (initrd, iso_boot::INITRD_PATH)];
+    for (src, path) in boot_files {
+        let Some(src) = src else { continue };
+        let dst = dir.join(path.trim_start_matches('/'));
+        fs::create_dir_all(dst.parent().context("Invalid boot file path")?)?;
+        fs::copy(src, &dst)
+            .with_context(|| format!("Failed to copy {}", src.display()))?;
+        println!("Copied: {} -> {}", src.display(), path);
+    }
+    Ok(())
+}
+
 impl Default for DistroConfig {
     fn default() -> Self {
         Self {
@@ -1957,6 +2347,7 @@ impl Default for DistroConfig {
             description: "A custom Linux distribution".to_string(),
             architecture: "x86_64".to_string(),
             base_system: BaseSystem::Arch,
+            base_release: None,
             packages: PackageConfig {
                 essential: vec![
                     "base".to_string(),
@@ -1998,6 +2389,7 @@ impl Default for DistroConfig {
                 compression: CompressionType::Xz,
                 size_limit: Some(4096), // 4GB
             },
+            output_formats: disk_image::default_output_formats(),
             build_options: BuildOptions {
                 parallel_builds: false,
                 max_parallel_jobs: None,
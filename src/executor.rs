@@ -1,60 +1,162 @@
-use std::process::Command;
+use std::fmt;
+use std::process::{Command, Output};
+use std::time::Duration;
 use std::io::{self, Write};
 use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use crate::runner::{self, Input, RunOutput, StreamingRunner};
+
+/// A single program invocation. Arguments are passed to the OS as an argv
+/// vector, so nothing in a step is ever interpreted by a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandStep {
+    pub program: String,
+    pub args: Vec<String>,
+    pub needs_root: bool,
+    pub env: Vec<(String, String)>,
+}
+
+impl CommandStep {
+    pub fn new<I, S>(program: &str, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program: program.to_string(),
+            args: args.into_iter().map(Into::into).collect(),
+            needs_root: false,
+            env: Vec::new(),
+        }
+    }
+
+    /// Mark the step as requiring root, so it is run through sudo
+    pub fn with_root(mut self) -> Self {
+        self.needs_root = true;
+        self
+    }
+
+    /// Set an environment variable for the step
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Build the process for this step. Root steps go through `sudo env` so
+    /// the environment survives sudo's reset.
+    pub fn to_command(&self) -> Command {
+        if self.needs_root {
+            let mut command = Command::new("sudo");
+            if !self.env.is_empty() {
+                command.arg("env");
+                command.args(self.env.iter().map(|(key, value)| format!("{key}={value}")));
+            }
+            command.arg(&self.program).args(&self.args);
+            command
+        } else {
+            let mut command = Command::new(&self.program);
+            command.args(&self.args).envs(self.env.iter().map(|(key, value)| (key, value)));
+            command
+        }
+    }
+}
+
+impl fmt::Display for CommandStep {
+    /// Render the step as a shell-quoted command line, for display only
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words: Vec<String> = Vec::new();
+        if self.needs_root {
+            words.push("sudo".to_string());
+        }
+        words.extend(self.env.iter().map(|(key, value)| format!("{}={}", key, shell_quote(value))));
+        words.push(shell_quote(&self.program));
+        words.extend(self.args.iter().map(|arg| shell_quote(arg)));
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+/// Render a sequence of steps the way a user would type them
+pub fn render_steps(steps: &[CommandStep]) -> String {
+    steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(" && ")
+}
+
+fn shell_quote(word: &str) -> String {
+    let is_plain = !word.is_empty()
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,%".contains(c));
+    if is_plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
 
 pub struct CommandExecutor;
 
 impl CommandExecutor {
-    pub fn execute_command(command: &str, requires_confirmation: bool) -> Result<bool> {
+    /// Run steps in order, stopping at the first failure
+    pub fn execute_steps(steps: &[CommandStep], requires_confirmation: bool) -> Result<bool> {
+        let rendered = render_steps(steps);
         if requires_confirmation
-            && !Self::confirm_execution(command)? {
+            && !Self::confirm_execution(&rendered)? {
                 println!("Command execution cancelled by user.");
                 return Ok(false);
             }
 
-        println!("Executing: {command}");
-        
-        let output = if command.contains("&&") {
-            // Handle compound commands with shell
-            Command::new("sh")
-                .arg("-c")
-                .arg(command)
-                .output()
-                .with_context(|| format!("Failed to execute command: {command}"))?
+        let output = Self::run_steps(steps)?;
+        if output.success {
+            println!("Command executed successfully.");
+        } else if output.timed_out {
+            println!("Command {}", output.error_summary());
         } else {
-            // Handle simple commands
-            let parts: Vec<&str> = command.split_whitespace().collect();
-            if parts.is_empty() {
-                return Err(anyhow::anyhow!("Empty command"));
-            }
-
-            let mut cmd = Command::new(parts[0]);
-            for arg in &parts[1..] {
-                cmd.arg(arg);
-            }
+            let exit_code = output.exit_code.unwrap_or(-1);
+            println!("Command failed with exit code: {exit_code}");
+        }
+        Ok(output.success)
+    }
 
-            cmd.output()
-                .with_context(|| format!("Failed to execute command: {command}"))?
+    /// Stream steps in order, stopping at the first failure, and return
+    /// their combined output
+    pub fn run_steps(steps: &[CommandStep]) -> Result<RunOutput> {
+        let mut combined = RunOutput {
+            success: true,
+            exit_code: Some(0),
+            timed_out: false,
+            stdout: String::new(),
+            stderr: String::new(),
+            duration: Duration::ZERO,
         };
 
-        // Print stdout
-        if !output.stdout.is_empty() {
-            print!("{}", String::from_utf8_lossy(&output.stdout));
-        }
+        for step in steps {
+            println!("Executing: {step}");
 
-        // Print stderr
-        if !output.stderr.is_empty() {
-            eprint!("{}", String::from_utf8_lossy(&output.stderr));
-        }
+            // Privileged steps run after the user confirmed them, and sudo may
+            // need to ask for a password
+            let input = if step.needs_root { Input::Terminal } else { Input::Closed };
+            let output = StreamingRunner::new()
+                .label(step.to_string())
+                .log_file(runner::default_log_path())
+                .input(input)
+                .run(step.to_command())?;
 
-        if output.status.success() {
-            println!("Command executed successfully.");
-            Ok(true)
-        } else {
-            let exit_code = output.status.code().unwrap_or(-1);
-            println!("Command failed with exit code: {exit_code}");
-            Ok(false)
+            combined.stdout.push_str(&output.stdout);
+            combined.stderr.push_str(&output.stderr);
+            combined.duration += output.duration;
+            combined.exit_code = output.exit_code;
+            combined.timed_out = output.timed_out;
+            combined.success = output.success;
+            if !output.success {
+                break;
+            }
         }
+
+        Ok(combined)
+    }
+
+    /// Run a step without echoing it and return its captured output
+    pub fn capture_output(step: &CommandStep) -> Result<Output> {
+        step.to_command()
+            .output()
+            .with_context(|| format!("Failed to execute command: {step}"))
     }
 
     fn confirm_execution(command: &str) -> Result<bool> {
@@ -63,25 +165,30 @@ impl CommandExecutor {
 
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
-        
+
         let input = input.trim().to_lowercase();
         Ok(matches!(input.as_str(), "y" | "yes"))
     }
 
-    pub fn is_safe_to_execute(command: &str) -> bool {
-        // Define patterns that are generally safe to execute
+    pub fn is_safe_to_execute(steps: &[CommandStep]) -> bool {
+        // Define invocations that are generally safe to execute
         let safe_patterns = [
-            "pacman -Ss",    // search packages
-            "apt search",    // search packages
-            "dnf search",    // search packages
-            "zypper search", // search packages
-            "emerge --search", // search packages
-            "nix-env -qaP | grep", // search packages
-            "apk search",    // search packages
+            ("pacman", "-Ss"),      // search packages
+            ("apt", "search"),      // search packages
+            ("dnf", "search"),      // search packages
+            ("zypper", "search"),   // search packages
+            ("emerge", "--search"), // search packages
+            ("nix-env", "-qaP"),    // search packages
+            ("apk", "search"),      // search packages
         ];
 
-        // Check if command starts with any safe pattern
-        safe_patterns.iter().any(|pattern| command.starts_with(pattern))
+        // Every step must be unprivileged and match a safe pattern
+        !steps.is_empty() && steps.iter().all(|step| {
+            !step.needs_root
+                && safe_patterns.iter().any(|(program, first_arg)| {
+                    step.program == *program && step.args.first().is_some_and(|arg| arg == first_arg)
+                })
+        })
     }
 }
 
@@ -91,9 +198,20 @@ mod tests {
 
     #[test]
     fn test_is_safe_to_execute() {
-        assert!(CommandExecutor::is_safe_to_execute("pacman -Ss vim"));
-        assert!(CommandExecutor::is_safe_to_execute("apt search git"));
-        assert!(!CommandExecutor::is_safe_to_execute("sudo rm -rf /"));
-        assert!(!CommandExecutor::is_safe_to_execute("sudo pacman -S vim"));
+        assert!(CommandExecutor::is_safe_to_execute(&[CommandStep::new("pacman", ["-Ss", "vim"])]));
+        assert!(CommandExecutor::is_safe_to_execute(&[CommandStep::new("apt", ["search", "git"])]));
+        assert!(!CommandExecutor::is_safe_to_execute(&[CommandStep::new("rm", ["-rf", "/"]).with_root()]));
+        assert!(!CommandExecutor::is_safe_to_execute(&[CommandStep::new("pacman", ["-S", "vim"]).with_root()]));
+    }
+
+    #[test]
+    fn test_display_quotes_untrusted_arguments() {
+        let step = CommandStep::new("apt", ["install", "foo;rm -rf ~"]).with_root();
+        assert_eq!(step.to_string(), "sudo apt install 'foo;rm -rf ~'");
+
+        // The argument reaches the program as a single argv entry
+        let command = step.to_command();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["apt", "install", "foo;rm -rf ~"]);
     }
 }
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a command failed, worked out from its output. Transient failures are
+/// worth retrying after a pause; the rest will fail the same way again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureClass {
+    /// Another package manager process holds the lock
+    PackageLock,
+    /// DNS, connection or transfer failure
+    Network,
+    /// A mirror returned an error or a broken file
+    Mirror,
+    /// The command ran past its time limit
+    Timeout,
+    PackageNotFound,
+    PermissionDenied,
+    /// The safety policy refused to run the command
+    PolicyDenied,
+    Unknown,
+}
+
+impl FailureClass {
+    pub fn is_transient(self) -> bool {
+        matches!(self, Self::PackageLock | Self::Network | Self::Mirror | Self::Timeout)
+    }
+
+    /// Classify a command failure from its error output
+    pub fn from_output(output: &str, timed_out: bool) -> Self {
+        if timed_out {
+            return Self::Timeout;
+        }
+        let output = output.to_lowercase();
+        PATTERNS.iter()
+            .find(|(_, patterns)| patterns.iter().any(|pattern| output.contains(pattern)))
+            .map(|(class, _)| *class)
+            .unwrap_or(Self::Unknown)
+    }
+}
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::PackageLock => "package_lock",
+            Self::Network => "network",
+            Self::Mirror => "mirror",
+            Self::Timeout => "timeout",
+            Self::PackageNotFound => "package_not_found",
+            Self::PermissionDenied => "permission_denied",
+            Self::PolicyDenied => "policy_denied",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Lower-case messages each package manager prints for a failure cause,
+/// checked in order so the more specific causes win
+const PATTERNS: &[(FailureClass, &[&str])] = &[
+    (FailureClass::PackageLock, &[
+        "could not get lock",                       // apt
+        "unable to acquire the dpkg frontend lock", // apt
+        "unable to lock database",                  // pacman, apk
+        "db.lck",                                   // pacman
+        "waiting for process with pid",             // dnf
+        "another app is currently holding the yum lock",
+        "system management is locked",              // zypper
+    ]),
+    (FailureClass::PackageNotFound, &[
+        "unable to locate package",                 // apt
+        "has no installation candidate",            // apt
+        "target not found",                         // pacman
+        "no match for argument",                    // dnf
+        "unable to find a match",                   // dnf
+        "not found in package names",               // zypper
+        "no provider of",                           // zypper
+        "unable to select packages",                // apk
+        "no such package",                          // apk, xbps
+        "there are no ebuilds to satisfy",          // portage
+    ]),
+    (FailureClass::PermissionDenied, &[
+        "permission denied",
+        "operation not permitted",
+        "are you root?",                            // apt
+        "you cannot perform this operation unless you are root", // pacman
+        "this command has to be run with superuser privileges",  // dnf
+        "root privileges are required",             // zypper
+        "must be run as root",
+    ]),
+    (FailureClass::Mirror, &[
+        "404  not found",                           // apt
+        "404 not found",
+        "error 404",
+        "status code: 404",                         // dnf
+        "failed retrieving file",                   // pacman
+        "hash sum mismatch",                        // apt
+        "checksum doesn't match",
+        "invalid or corrupted package",             // pacman
+        "cannot download repomd.xml",               // dnf
+    ]),
+    (FailureClass::Network, &[
+        "temporary failure resolving",              // apt
+        "could not resolve host",                   // curl-based managers
+        "failed to connect",
+        "connection timed out",
+        "connection refused",
+        "network is unreachable",
+        "operation too slow",                       // pacman
+        "timeout was reached",                      // dnf
+        "failed to fetch",                          // apt
+        "curl error",
+    ]),
+];
+
+/// A failed task attempt that already knows its cause
+#[derive(Debug)]
+pub struct TaskFailure {
+    pub class: FailureClass,
+    pub message: String,
+}
+
+impl TaskFailure {
+    pub fn new(class: FailureClass, message: impl Into<String>) -> Self {
+        Self { class, message: message.into() }
+    }
+
+    /// The cause of any error, classifying its message when it wasn't a `TaskFailure`
+    pub fn classify(error: &anyhow::Error) -> FailureClass {
+        error.downcast_ref::<TaskFailure>()
+            .map(|failure| failure.class)
+            .unwrap_or_else(|| FailureClass::from_output(&error.to_string(), false))
+    }
+}
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TaskFailure {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_backend_output() {
+        let cases = [
+            ("E: Could not get lock /var/lib/dpkg/lock-frontend. It is held by process 1234 (apt)", FailureClass::PackageLock),
+            ("error: failed to init transaction (unable to lock database)", FailureClass::PackageLock),
+            ("E: Unable to locate package nosuchpkg", FailureClass::PackageNotFound),
+            ("error: target not found: nosuchpkg", FailureClass::PackageNotFound),
+            ("No match for argument: nosuchpkg\nError: Unable to find a match: nosuchpkg", FailureClass::PackageNotFound),
+            ("E: Could not open lock file /var/lib/dpkg/lock-frontend - open (13: Permission denied)", FailureClass::PermissionDenied),
+            ("error: you cannot perform this operation unless you are root.", FailureClass::PermissionDenied),
+            ("Err:1 http://deb.debian.org/debian bookworm/main vim 404  Not Found [IP: 1.2.3.4 80]", FailureClass::Mirror),
+            ("error: failed retrieving file 'vim-9.1.pkg.tar.zst' from mirror.example.org", FailureClass::Mirror),
+            ("W: Failed to fetch http://deb.debian.org/dists/bookworm/InRelease  Temporary failure resolving 'deb.debian.org'", FailureClass::Network),
+            ("Curl error (28): Timeout was reached for https://mirrors.fedoraproject.org", FailureClass::Network),
+            ("exit code 1", FailureClass::Unknown),
+        ];
+        for (output, expected) in cases {
+            assert_eq!(FailureClass::from_output(output, false), expected, "{output}");
+        }
+        assert_eq!(FailureClass::from_output("", true), FailureClass::Timeout);
+        assert!(FailureClass::Network.is_transient());
+        assert!(!FailureClass::PackageNotFound.is_transient());
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        let typed = anyhow::Error::new(TaskFailure::new(FailureClass::PolicyDenied, "denied"));
+        assert_eq!(TaskFailure::classify(&typed), FailureClass::PolicyDenied);
+        let untyped = anyhow::anyhow!("Command failed: E: Unable to locate package foo");
+        assert_eq!(TaskFailure::classify(&untyped), FailureClass::PackageNotFound);
+    }
+}
@@ -60,7 +60,6 @@ impl History {
         self.entries.push(entry);
     }
 
-    #[allow(dead_code)]
     pub fn add_command(&mut self, command: &str, operation: &str, package: Option<String>, success: bool, output: Option<String>, distro: &str) {
         let entry = HistoryEntry {
             timestamp: Utc::now(),
@@ -126,13 +125,11 @@ impl HistoryManager {
         self.save()
     }
 
-    #[allow(dead_code)]
     pub fn track_command(&mut self, command: &str, operation: &str, package: Option<String>, success: bool, output: Option<String>, distro: &str) -> Result<()> {
         self.history.add_command(command, operation, package, success, output, distro);
         self.save()
     }
 
-    #[allow(dead_code)]
     fn save(&self) -> Result<()> {
         self.history.save()
     }
@@ -0,0 +1,514 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use crate::distro::DistroInfo;
+use crate::package_manager::PackageManagerRegistry;
+
+/// Why a package is present on the system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallReason {
+    Explicit,
+    Dependency,
+    Unknown,
+}
+
+impl std::fmt::Display for InstallReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            InstallReason::Explicit => "explicit",
+            InstallReason::Dependency => "dependency",
+            InstallReason::Unknown => "unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A package installed on the system, as recorded by the package manager database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    pub arch: Option<String>,
+    /// Where the package came from ("native", "foreign", vendor name, ...)
+    pub source: Option<String>,
+    pub install_reason: InstallReason,
+    /// Installed size in bytes
+    pub size: Option<u64>,
+}
+
+impl InstalledPackage {
+    fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            arch: None,
+            source: None,
+            install_reason: InstallReason::Unknown,
+            size: None,
+        }
+    }
+}
+
+/// Read the pacman local database (`/var/lib/pacman/local/*/desc`)
+pub fn read_pacman_db(db_path: &Path) -> Result<Vec<InstalledPackage>> {
+    let foreign = foreign_pacman_packages();
+    let mut packages = Vec::new();
+
+    let entries = fs::read_dir(db_path)
+        .with_context(|| format!("Failed to read pacman database: {}", db_path.display()))?;
+    for entry in entries.flatten() {
+        let desc_path = entry.path().join("desc");
+        let Ok(content) = fs::read_to_string(&desc_path) else {
+            continue;
+        };
+        if let Some(mut package) = parse_pacman_desc(&content) {
+            package.source = Some(if foreign.contains(&package.name) { "foreign" } else { "native" }.to_string());
+            packages.push(package);
+        }
+    }
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packages)
+}
+
+/// Packages not found in any sync database (usually installed from the AUR)
+fn foreign_pacman_packages() -> HashSet<String> {
+    Command::new("pacman")
+        .arg("-Qmq")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Parse a single pacman `desc` file made of `%FIELD%` headers followed by values
+pub fn parse_pacman_desc(content: &str) -> Option<InstalledPackage> {
+    let mut name = None;
+    let mut version = None;
+    let mut arch = None;
+    let mut size = None;
+    // pacman only writes %REASON% for dependencies; absence means explicit
+    let mut reason = InstallReason::Explicit;
+
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let value = || lines.clone().next().map(str::to_string);
+        match line {
+            "%NAME%" => name = value(),
+            "%VERSION%" => version = value(),
+            "%ARCH%" => arch = value(),
+            "%SIZE%" => size = value().and_then(|v| v.parse().ok()),
+            "%REASON%" if value().as_deref() == Some("1") => reason = InstallReason::Dependency,
+            _ => {}
+        }
+    }
+
+    let mut package = InstalledPackage::new(&name?, &version?);
+    package.arch = arch;
+    package.size = size;
+    package.install_reason = reason;
+    Some(package)
+}
+
+/// Read the dpkg status file, using apt's extended states for install reasons
+pub fn read_dpkg_status(status_path: &Path, extended_states_path: &Path) -> Result<Vec<InstalledPackage>> {
+    let status = fs::read_to_string(status_path)
+        .with_context(|| format!("Failed to read dpkg status file: {}", status_path.display()))?;
+    let auto_installed = fs::read_to_string(extended_states_path)
+        .map(|content| parse_apt_auto_installed(&content))
+        .ok();
+
+    Ok(parse_dpkg_status(&status, auto_installed.as_ref()))
+}
+
+/// Parse `/var/lib/apt/extended_states` into the set of automatically installed packages
+pub fn parse_apt_auto_installed(content: &str) -> HashSet<String> {
+    let mut auto = HashSet::new();
+
+    for stanza in content.split("\n\n") {
+        let mut name = None;
+        let mut is_auto = false;
+        for line in stanza.lines() {
+            if let Some(value) = line.strip_prefix("Package:") {
+                name = Some(value.trim().to_string());
+            } else if let Some(value) = line.strip_prefix("Auto-Installed:") {
+                is_auto = value.trim() == "1";
+            }
+        }
+        if let (Some(name), true) = (name, is_auto) {
+            auto.insert(name);
+        }
+    }
+
+    auto
+}
+
+/// Parse the dpkg status database, keeping only fully installed packages
+pub fn parse_dpkg_status(content: &str, auto_installed: Option<&HashSet<String>>) -> Vec<InstalledPackage> {
+    let mut packages = Vec::new();
+
+    for stanza in content.split("\n\n") {
+        let mut name = None;
+        let mut version = None;
+        let mut arch = None;
+        let mut size = None;
+        let mut installed = false;
+
+        for line in stanza.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "Package" => name = Some(value.to_string()),
+                "Version" => version = Some(value.to_string()),
+                "Architecture" => arch = Some(value.to_string()),
+                // dpkg records Installed-Size in KiB
+                "Installed-Size" => size = value.parse::<u64>().ok().map(|kib| kib * 1024),
+                "Status" => installed = value.ends_with(" installed"),
+                _ => {}
+            }
+        }
+
+        let (Some(name), Some(version), true) = (name, version, installed) else {
+            continue;
+        };
+
+        let mut package = InstalledPackage::new(&name, &version);
+        package.arch = arch;
+        package.size = size;
+        package.source = Some("native".to_string());
+        package.install_reason = match auto_installed {
+            Some(auto) if auto.contains(&name) => InstallReason::Dependency,
+            Some(_) => InstallReason::Explicit,
+            None => InstallReason::Unknown,
+        };
+        packages.push(package);
+    }
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+/// Query format used for `rpm -qa`, tab separated
+pub const RPM_QUERY_FORMAT: &str = "%{NAME}\\t%{EPOCHNUM}:%{VERSION}-%{RELEASE}\\t%{ARCH}\\t%{SIZE}\\t%{VENDOR}\\n";
+
+/// Query the rpm database on dnf and zypper systems
+pub fn query_rpm() -> Result<Vec<InstalledPackage>> {
+    let output = Command::new("rpm")
+        .args(["-qa", "--queryformat", RPM_QUERY_FORMAT])
+        .output()
+        .context("Failed to query rpm database")?;
+
+    if !output.status.success() {
+        anyhow::bail!("rpm query failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    Ok(parse_rpm_query(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse output produced with [`RPM_QUERY_FORMAT`]
+pub fn parse_rpm_query(output: &str) -> Vec<InstalledPackage> {
+    let mut packages: Vec<InstalledPackage> = output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 2 || fields[0] == "gpg-pubkey" {
+                return None;
+            }
+
+            // Drop the zero epoch that rpm reports for most packages
+            let version = fields[1].strip_prefix("0:").unwrap_or(fields[1]);
+            let mut package = InstalledPackage::new(fields[0], version);
+            package.arch = fields.get(2).filter(|a| **a != "(none)").map(|a| a.to_string());
+            package.size = fields.get(3).and_then(|s| s.parse().ok());
+            package.source = fields.get(4).filter(|v| **v != "(none)").map(|v| v.to_string());
+            Some(package)
+        })
+        .collect();
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+/// Read the apk installed database, using the world file for install reasons
+pub fn read_apk_db(installed_path: &Path, world_path: &Path) -> Result<Vec<InstalledPackage>> {
+    let installed = fs::read_to_string(installed_path)
+        .with_context(|| format!("Failed to read apk database: {}", installed_path.display()))?;
+    let world = fs::read_to_string(world_path).ok().map(|content| parse_world_file(&content));
+
+    Ok(parse_apk_installed(&installed, world.as_ref()))
+}
+
+/// Parse a world file (apk or portage) into the set of explicitly requested packages
+pub fn parse_world_file(content: &str) -> HashSet<String> {
+    content
+        .split_whitespace()
+        .map(|entry| {
+            // Strip version constraints and repository pins such as "vim>=9" or "foo@edge"
+            let end = entry.find(['<', '>', '=', '~', '@', ':']).unwrap_or(entry.len());
+            entry[..end].to_string()
+        })
+        .collect()
+}
+
+/// Parse `/lib/apk/db/installed`, a list of `X:value` records separated by blank lines
+pub fn parse_apk_installed(content: &str, world: Option<&HashSet<String>>) -> Vec<InstalledPackage> {
+    let mut packages = Vec::new();
+
+    for record in content.split("\n\n") {
+        let mut name = None;
+        let mut version = None;
+        let mut arch = None;
+        let mut size = None;
+
+        for line in record.lines() {
+            match line.split_once(':') {
+                Some(("P", value)) => name = Some(value.to_string()),
+                Some(("V", value)) => version = Some(value.to_string()),
+                Some(("A", value)) => arch = Some(value.to_string()),
+                Some(("I", value)) => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        let (Some(name), Some(version)) = (name, version) else {
+            continue;
+        };
+
+        let mut package = InstalledPackage::new(&name, &version);
+        package.arch = arch;
+        package.size = size;
+        package.source = Some("native".to_string());
+        package.install_reason = match world {
+            Some(world) if world.contains(&name) => InstallReason::Explicit,
+            Some(_) => InstallReason::Dependency,
+            None => InstallReason::Unknown,
+        };
+        packages.push(package);
+    }
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+}
+
+/// Read the portage package database (`/var/db/pkg/<category>/<name>-<version>`)
+pub fn read_portage_db(db_path: &Path, world_path: &Path) -> Result<Vec<InstalledPackage>> {
+    let world = fs::read_to_string(world_path).ok().map(|content| parse_world_file(&content));
+    let mut packages = Vec::new();
+
+    let categories = fs::read_dir(db_path)
+        .with_context(|| format!("Failed to read portage database: {}", db_path.display()))?;
+    for category in categories.flatten() {
+        let category_name = category.file_name().to_string_lossy().to_string();
+        let Ok(entries) = fs::read_dir(category.path()) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let read_field = |field: &str| fs::read_to_string(path.join(field)).ok().map(|v| v.trim().to_string());
+
+            let name = read_field("PF").unwrap_or_else(|| entry.file_name().to_string_lossy().to_string());
+            let Some((name, version)) = split_portage_pf(&name) else {
+                continue;
+            };
+
+            let atom = format!("{category_name}/{name}");
+            let mut package = InstalledPackage::new(&atom, &version);
+            package.arch = read_field("CHOST").and_then(|chost| chost.split('-').next().map(str::to_string));
+            package.size = read_field("SIZE").and_then(|s| s.parse().ok());
+            package.source = read_field("repository");
+            package.install_reason = match &world {
+                Some(world) if world.contains(&atom) => InstallReason::Explicit,
+                Some(_) => InstallReason::Dependency,
+                None => InstallReason::Unknown,
+            };
+            packages.push(package);
+        }
+    }
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packages)
+}
+
+/// Split a portage `PF` such as `vim-9.0.2167-r1` into name and version
+fn split_portage_pf(pf: &str) -> Option<(String, String)> {
+    let mut search_from = 0;
+    while let Some(offset) = pf[search_from..].find('-') {
+        let index = search_from + offset;
+        if pf[index + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+            return Some((pf[..index].to_string(), pf[index + 1..].to_string()));
+        }
+        search_from = index + 1;
+    }
+    None
+}
+
+/// Query the xbps package database
+pub fn query_xbps() -> Result<Vec<InstalledPackage>> {
+    let output = Command::new("xbps-query")
+        .arg("-l")
+        .output()
+        .context("Failed to query xbps database")?;
+
+    Ok(parse_xbps_list(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse `xbps-query -l` output (`ii vim-9.0_1  description`)
+pub fn parse_xbps_list(output: &str) -> Vec<InstalledPackage> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let state = fields.next()?;
+            let (name, version) = fields.next()?.rsplit_once('-')?;
+            if state != "ii" {
+                return None;
+            }
+
+            let mut package = InstalledPackage::new(name, version);
+            package.source = Some("native".to_string());
+            Some(package)
+        })
+        .collect()
+}
+
+/// Read the inventory of the running system using its detected package manager
+pub fn detect_installed_packages() -> Result<Vec<InstalledPackage>> {
+    let distro = DistroInfo::detect()?;
+    let registry = PackageManagerRegistry::new();
+    let backend = distro
+        .backend(&registry)
+        .ok_or_else(|| anyhow::anyhow!("Unable to determine package manager for {}", distro.name))?;
+    backend.installed_packages()
+}
+
+/// Render an inventory as an aligned plain-text table
+pub fn render_table(packages: &[InstalledPackage], detailed: bool) -> String {
+    let name_width = packages.iter().map(|p| p.name.len()).max().unwrap_or(0).max(4);
+    let version_width = packages.iter().map(|p| p.version.len()).max().unwrap_or(0).max(7);
+
+    if !detailed {
+        return packages
+            .iter()
+            .map(|p| format!("{:<name_width$}  {}", p.name, p.version))
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let mut output = format!(
+        "{:<name_width$}  {:<version_width$}  {:<8}  {:<10}  {:>10}  SOURCE\n",
+        "NAME", "VERSION", "ARCH", "REASON", "SIZE"
+    );
+    for package in packages {
+        output.push_str(&format!(
+            "{:<name_width$}  {:<version_width$}  {:<8}  {:<10}  {:>10}  {}\n",
+            package.name,
+            package.version,
+            package.arch.as_deref().unwrap_or("-"),
+            package.install_reason,
+            package.size.map(format_size).unwrap_or_else(|| "-".to_string()),
+            package.source.as_deref().unwrap_or("-"),
+        ));
+    }
+    output.trim_end().to_string()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Render an inventory as CSV with a header row
+pub fn to_csv(packages: &[InstalledPackage]) -> String {
+    let escape = |value: &str| {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+
+    let mut output = String::from("name,version,arch,source,install_reason,size\n");
+    for package in packages {
+        output.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            escape(&package.name),
+            escape(&package.version),
+            escape(package.arch.as_deref().unwrap_or("")),
+            escape(package.source.as_deref().unwrap_or("")),
+            package.install_reason,
+            package.size.map(|s| s.to_string()).unwrap_or_default(),
+        ));
+    }
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pacman_desc() {
+        let desc = "%NAME%\nvim\n\n%VERSION%\n9.1.0016-1\n\n%ARCH%\nx86_64\n\n%SIZE%\n4194304\n\n%REASON%\n1\n";
+        let package = parse_pacman_desc(desc).unwrap();
+        assert_eq!(package.name, "vim");
+        assert_eq!(package.version, "9.1.0016-1");
+        assert_eq!(package.arch.as_deref(), Some("x86_64"));
+        assert_eq!(package.size, Some(4194304));
+        assert_eq!(package.install_reason, InstallReason::Dependency);
+
+        let explicit = parse_pacman_desc("%NAME%\ngit\n\n%VERSION%\n2.43.0-1\n").unwrap();
+        assert_eq!(explicit.install_reason, InstallReason::Explicit);
+    }
+
+    #[test]
+    fn test_parse_dpkg_status() {
+        let status = "Package: vim\nStatus: install ok installed\nInstalled-Size: 10\nArchitecture: amd64\nVersion: 2:8.2.3995-1ubuntu2\n\nPackage: removed\nStatus: deinstall ok config-files\nVersion: 1.0\n\nPackage: libfoo\nStatus: install ok installed\nArchitecture: amd64\nVersion: 1.2\n";
+        let auto = parse_apt_auto_installed("Package: libfoo\nArchitecture: amd64\nAuto-Installed: 1\n");
+        let packages = parse_dpkg_status(status, Some(&auto));
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "libfoo");
+        assert_eq!(packages[0].install_reason, InstallReason::Dependency);
+        assert_eq!(packages[1].name, "vim");
+        assert_eq!(packages[1].size, Some(10240));
+        assert_eq!(packages[1].install_reason, InstallReason::Explicit);
+    }
+
+    #[test]
+    fn test_parse_rpm_and_apk() {
+        let rpm = "bash\t0:5.2.26-3.fc40\tx86_64\t8388608\tFedora Project\ngpg-pubkey\t0:abc-def\t(none)\t0\t(none)\n";
+        let packages = parse_rpm_query(rpm);
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].version, "5.2.26-3.fc40");
+        assert_eq!(packages[0].source.as_deref(), Some("Fedora Project"));
+
+        let installed = "C:Q1abc=\nP:musl\nV:1.2.4-r2\nA:x86_64\nI:622592\n\nP:vim\nV:9.0.2073-r0\nA:x86_64\nI:3000000\n";
+        let world = parse_world_file("vim>=9\n");
+        let packages = parse_apk_installed(installed, Some(&world));
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].install_reason, InstallReason::Dependency);
+        assert_eq!(packages[1].install_reason, InstallReason::Explicit);
+        assert_eq!(packages[1].size, Some(3000000));
+    }
+
+    #[test]
+    fn test_split_portage_pf_and_csv() {
+        assert_eq!(split_portage_pf("vim-9.0.2167-r1"), Some(("vim".to_string(), "9.0.2167-r1".to_string())));
+        assert_eq!(split_portage_pf("xdg-utils-1.2.1"), Some(("xdg-utils".to_string(), "1.2.1".to_string())));
+
+        let mut package = InstalledPackage::new("foo", "1,0");
+        package.install_reason = InstallReason::Explicit;
+        assert_eq!(to_csv(&[package]), "name,version,arch,source,install_reason,size\nfoo,\"1,0\",,,explicit,");
+    }
+}
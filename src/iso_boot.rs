@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::distro_builder::Bootloader;
+use crate::executor::CommandStep;
+
+/// Where the kernel and initramfs live on the ISO, and on the EFI system
+/// partition for loaders that can't read ISO 9660 and for disk images
+pub const KERNEL_PATH: &str = "/boot/vmlinuz";
+pub const INITRD_PATH: &str = "/boot/initrd.img";
+/// The FAT image used as the UEFI El Torito entry, relative to the ISO root
+pub const EFI_IMAGE: &str = "boot/efiboot.img";
+
+/// The UEFI loader put on the EFI system partition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EfiLoader {
+    /// A standalone GRUB image that finds the ISO by label and reads
+    /// `/boot/grub/grub.cfg` from it
+    Grub,
+    /// systemd-boot, with the kernel and loader entries on the ESP
+    SystemdBoot,
+    /// rEFInd, with the kernel and `refind.conf` on the ESP
+    Refind,
+}
+
+impl EfiLoader {
+    /// Syslinux has no UEFI ISO support, so Syslinux builds boot UEFI with GRUB
+    pub fn for_bootloader(bootloader: &Bootloader) -> Self {
+        match bootloader {
+            Bootloader::Systemd => Self::SystemdBoot,
+            Bootloader::Refind => Self::Refind,
+            Bootloader::Grub | Bootloader::Syslinux => Self::Grub,
+        }
+    }
+
+    /// Whether the kernel and initramfs must be copied onto the ESP
+    pub fn needs_kernel_on_esp(self) -> bool {
+        self != Self::Grub
+    }
+
+    /// The prebuilt loader binary, for loaders that aren't built during the build
+    pub fn binary(self, efi: &EfiArch) -> Option<PathBuf> {
+        match self {
+            Self::Grub => None,
+            Self::SystemdBoot => Some(PathBuf::from(format!("/usr/lib/systemd/boot/efi/systemd-boot{}.efi", efi.suffix))),
+            Self::Refind => Some(PathBuf::from(format!("/usr/share/refind/refind_{}.efi", efi.suffix))),
+        }
+    }
+
+    /// Host programs needed to build the EFI system partition image
+    pub fn required_tools(self) -> Vec<&'static str> {
+        let mut tools = vec!["mkfs.fat", "mcopy"];
+        if self == Self::Grub {
+            tools.push("grub-mkstandalone");
+        }
+        tools
+    }
+}
+
+/// UEFI naming for a target architecture
+pub struct EfiArch {
+    /// Suffix of the removable-media loader path, `EFI/BOOT/BOOT<SUFFIX>.EFI`
+    pub suffix: &'static str,
+    pub grub_target: &'static str,
+}
+
+impl EfiArch {
+    pub fn for_architecture(arch: &str) -> Option<Self> {
+        let (suffix, grub_target) = match arch {
+            "x86_64" => ("x64", "x86_64-efi"),
+            "i686" => ("ia32", "i386-efi"),
+            "aarch64" => ("aa64", "arm64-efi"),
+            "armv7h" => ("arm", "arm-efi"),
+            _ => return None,
+        };
+        Some(Self { suffix, grub_target })
+    }
+
+    /// The fallback loader path UEFI firmware boots from removable media
+    pub fn loader_path(&self) -> String {
+        format!("EFI/BOOT/BOOT{}.EFI", self.suffix.to_uppercase())
+    }
+}
+
+/// Whether the architecture boots through a PC BIOS, and so gets an isolinux entry
+pub fn has_bios(arch: &str) -> bool {
+    matches!(arch, "x86_64" | "i686")
+}
+
+/// The serial console for an architecture, so boot messages reach headless
+/// machines and `lda test-iso`
+pub fn serial_console(arch: &str) -> &'static str {
+    match arch {
+        "aarch64" | "armv7h" => "ttyAMA0,115200",
+        _ => "ttyS0,115200",
+    }
+}
+
+/// Where distributions install syslinux's BIOS files
+const SYSLINUX_DIRS: [&str; 4] = [
+    "/usr/lib/syslinux/bios",
+    "/usr/lib/ISOLINUX",
+    "/usr/lib/syslinux/modules/bios",
+    "/usr/share/syslinux",
+];
+
+/// The isolinux loader and the menu modules it needs
+pub const ISOLINUX_FILES: [&str; 5] = ["isolinux.bin", "ldlinux.c32", "libcom32.c32", "libutil.c32", "menu.c32"];
+
+/// A syslinux BIOS file (`isolinux.bin`, `isohdpfx.bin`, ...) on the build host
+pub fn syslinux_file(name: &str) -> Option<PathBuf> {
+    SYSLINUX_DIRS.iter()
+        .map(|dir| Path::new(dir).join(name))
+        .find(|path| path.exists())
+}
+
+/// An ISO 9660 volume label for a distribution: at most 32 upper-case
+/// letters, digits and underscores
+pub fn volume_label(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .take(32)
+        .collect()
+}
+
+/// One boot menu entry, rendered for each loader
+#[derive(Debug, Clone)]
+pub struct BootEntry {
+    pub id: String,
+    pub title: String,
+    pub options: String,
+    pub has_initrd: bool,
+}
+
+/// The default entry and a safe-graphics fallback
+pub fn boot_entries(default_entry: &str, title: &str, options: &str, has_initrd: bool) -> Vec<BootEntry> {
+    vec![
+        BootEntry { id: default_entry.to_string(), title: title.to_string(), options: options.to_string(), has_initrd },
+        BootEntry {
+            id: format!("{}-nomodeset", default_entry),
+            title: format!("{} (safe graphics)", title),
+            options: format!("{} nomodeset", options),
+            has_initrd,
+        },
+    ]
+}
+
+pub fn isolinux_cfg(entries: &[BootEntry], timeout: u32) -> String {
+    let mut cfg = format!("UI menu.c32\nDEFAULT {}\nPROMPT 0\nTIMEOUT {}0\n", entries[0].id, timeout);
+    for entry in entries {
+        cfg.push_str(&format!("\nLABEL {}\n    MENU LABEL {}\n    LINUX {}\n    APPEND {}\n", entry.id, entry.title, KERNEL_PATH, entry.options));
+        if entry.has_initrd {
+            cfg.push_str(&format!("    INITRD {}\n", INITRD_PATH));
+        }
+    }
+    cfg
+}
+
+/// `/boot/grub/grub.cfg` on the ISO
+pub fn grub_cfg(entries: &[BootEntry], timeout: u32) -> String {
+    let mut cfg = format!("set default=\"{}\"\nset timeout={}\n", entries[0].id, timeout);
+    for entry in entries {
+        cfg.push_str(&format!("\nmenuentry \"{}\" --id {} {{\n    linux {} {}\n", entry.title, entry.id, KERNEL_PATH, entry.options));
+        if entry.has_initrd {
+            cfg.push_str(&format!("    initrd {}\n", INITRD_PATH));
+        }
+        cfg.push_str("}\n");
+    }
+    cfg
+}
+
+/// The config embedded in the standalone GRUB image, which hands over to
+/// the ISO's own grub.cfg
+pub fn grub_embedded_cfg(label: &str) -> String {
+    format!("search --no-floppy --set=root --label {}\nset prefix=($root)/boot/grub\nconfigfile $prefix/grub.cfg\n", label)
+}
+
+/// The config embedded in a disk image's standalone GRUB: the menu itself,
+/// reading the kernel from the ESP with FAT volume ID `esp_uuid`
+pub fn grub_disk_cfg(entries: &[BootEntry], timeout: u32, esp_uuid: &str) -> String {
+    format!("search --no-floppy --set=root --fs-uuid {}\n{}", esp_uuid, grub_cfg(entries, timeout))
+}
+
+pub fn systemd_boot_loader_conf(entries: &[BootEntry], timeout: u32) -> String {
+    format!("default {}.conf\ntimeout {}\n", entries[0].id, timeout)
+}
+
+/// `loader/entries/<id>.conf` for one entry
+pub fn systemd_boot_entry(entry: &BootEntry) -> String {
+    let mut conf = format!("title {}\nlinux {}\n", entry.title, KERNEL_PATH);
+    if entry.has_initrd {
+        conf.push_str(&format!("initrd {}\n", INITRD_PATH));
+    }
+    conf.push_str(&format!("options {}\n", entry.options));
+    conf
+}
+
+pub fn refind_conf(entries: &[BootEntry], timeout: u32) -> String {
+    let mut conf = format!("timeout {}\nscanfor manual\n", timeout);
+    for entry in entries {
+        conf.push_str(&format!("\nmenuentry \"{}\" {{\n    loader {}\n", entry.title, KERNEL_PATH));
+        if entry.has_initrd {
+            conf.push_str(&format!("    initrd {}\n", INITRD_PATH));
+        }
+        conf.push_str(&format!("    options \"{}\"\n}}\n", entry.options));
+    }
+    conf
+}
+
+/// The newest kernel in a root filesystem's `/boot` and the initramfs built
+/// for it, if there is one. Kernels are named `vmlinuz-<suffix>` by every
+/// base system; initramfs naming differs.
+pub fn find_boot_files(boot_dir: &Path) -> Result<(PathBuf, Option<PathBuf>)> {
+    let mut names: Vec<String> = fs::read_dir(boot_dir)
+        .with_context(|| format!("Failed to read {}", boot_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let kernel = names.iter().rev()
+        .find(|name| name.starts_with("vmlinuz"))
+        .with_context(|| format!("No kernel (vmlinuz*) found in {}", boot_dir.display()))?;
+    let suffix = kernel.trim_start_matches("vmlinuz").trim_start_matches('-');
+    let candidates = if suffix.is_empty() {
+        vec!["initrd.img".to_string(), "initramfs.img".to_string()]
+    } else {
+        vec![
+            format!("initramfs-{}.img", suffix),
+            format!("initrd.img-{}", suffix),
+            format!("initramfs-{}", suffix),
+        ]
+    };
+    let initrd = candidates.iter()
+        .find(|candidate| names.contains(candidate))
+        .map(|name| boot_dir.join(name));
+    Ok((boot_dir.join(kernel), initrd))
+}
+
+/// Size in KiB of the files under `dir`
+pub fn tree_size_kib(dir: &Path) -> Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() { tree_size_kib(&entry.path())? } else { metadata.len().div_ceil(1024) };
+    }
+    Ok(total)
+}
+
+/// Steps that pack the staged ESP tree in `esp_dir` into a FAT image. The
+/// image gets a quarter of headroom for FAT overhead, and at least 4 MiB.
+pub fn esp_image_steps(esp_dir: &Path, image: &Path, volume_id: u32) -> Result<Vec<CommandStep>> {
+    let size_kib = (tree_size_kib(esp_dir)? * 5 / 4 + 1024).max(4096);
+    let image = image.display().to_string();
+    let volume_id = format!("{:08X}", volume_id);
+    let mut steps = vec![CommandStep::new("mkfs.fat", ["-C", "-n", "EFIBOOT", "-i", &volume_id, &image, &size_kib.to_string()])];
+
+    let mut top_level: Vec<PathBuf> = fs::read_dir(esp_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    top_level.sort();
+    // -m keeps the mtimes, which the builder clamps to SOURCE_DATE_EPOCH
+    let mut mcopy = vec!["-s".to_string(), "-m".to_string(), "-i".to_string(), image.clone()];
+    mcopy.extend(top_level.iter().map(|path| path.display().to_string()));
+    mcopy.push("::/".to_string());
+    steps.push(CommandStep::new("mcopy", mcopy));
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loader_configs() {
+        let entries = boot_entries("linux", "MyLinux", "root=live:LABEL=MYLINUX", true);
+        let grub = grub_cfg(&entries, 5);
+        assert!(grub.starts_with("set default=\"linux\"\nset timeout=5\n"));
+        assert!(grub.contains("menuentry \"MyLinux (safe graphics)\" --id linux-nomodeset {\n    linux /boot/vmlinuz root=live:LABEL=MYLINUX nomodeset\n    initrd /boot/initrd.img\n}"));
+
+        assert_eq!(systemd_boot_loader_conf(&entries, 5), "default linux.conf\ntimeout 5\n");
+        assert_eq!(systemd_boot_entry(&entries[0]),
+            "title MyLinux\nlinux /boot/vmlinuz\ninitrd /boot/initrd.img\noptions root=live:LABEL=MYLINUX\n");
+        assert!(isolinux_cfg(&entries, 3).contains("TIMEOUT 30\n"));
+
+        assert_eq!(volume_label("My Linux-2"), "MY_LINUX_2");
+        assert_eq!(EfiArch::for_architecture("x86_64").unwrap().loader_path(), "EFI/BOOT/BOOTX64.EFI");
+        assert_eq!(EfiLoader::for_bootloader(&Bootloader::Syslinux), EfiLoader::Grub);
+    }
+
+    #[test]
+    fn test_find_boot_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let boot = dir.path();
+        for name in ["vmlinuz-6.1.0-18-amd64", "vmlinuz-6.1.0-20-amd64", "initrd.img-6.1.0-20-amd64", "config-6.1.0-20-amd64"] {
+            fs::write(boot.join(name), "").unwrap();
+        }
+        let (kernel, initrd) = find_boot_files(boot).unwrap();
+        assert_eq!(kernel, boot.join("vmlinuz-6.1.0-20-amd64"));
+        assert_eq!(initrd, Some(boot.join("initrd.img-6.1.0-20-amd64")));
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("vmlinuz-linux"), "").unwrap();
+        fs::write(dir.path().join("initramfs-linux-fallback.img"), "").unwrap();
+        fs::write(dir.path().join("initramfs-linux.img"), "").unwrap();
+        let (_, initrd) = find_boot_files(dir.path()).unwrap();
+        assert_eq!(initrd, Some(dir.path().join("initramfs-linux.img")));
+        assert!(find_boot_files(tempfile::tempdir().unwrap().path()).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use crate::executor::CommandStep;
+
+/// What a booted system prints on the serial console once it's up
+pub const DEFAULT_MARKER: &str = "login:";
+/// Emulation without KVM is slow; a desktop image can take minutes to boot
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(600);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Console output that means the boot has failed, matched case-insensitively
+const FAILURE_MARKERS: [&str; 5] = [
+    "kernel panic",
+    "emergency mode",
+    "emergency shell",
+    "(initramfs)",
+    "falling back to interactive prompt",
+];
+
+/// UEFI firmware images, by QEMU system architecture
+const X86_64_FIRMWARE: [&str; 5] = [
+    "/usr/share/OVMF/OVMF_CODE.fd",
+    "/usr/share/OVMF/OVMF_CODE_4M.fd",
+    "/usr/share/edk2/x64/OVMF_CODE.fd",
+    "/usr/share/edk2/ovmf/OVMF_CODE.fd",
+    "/usr/share/qemu/ovmf-x86_64-code.bin",
+];
+const AARCH64_FIRMWARE: [&str; 4] = [
+    "/usr/share/AAVMF/AAVMF_CODE.fd",
+    "/usr/share/edk2/aarch64/QEMU_CODE.fd",
+    "/usr/share/edk2/aarch64/QEMU_EFI-pflash.raw",
+    "/usr/share/qemu/aavmf-aarch64-code.bin",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IsoTestOutcome {
+    /// The success marker appeared
+    Booted,
+    /// A failure marker appeared
+    Failed(String),
+    TimedOut,
+    /// QEMU stopped before the marker appeared
+    Exited(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct IsoTestResult {
+    pub outcome: IsoTestOutcome,
+    pub elapsed: Duration,
+    /// The serial console output, saved next to the ISO
+    pub log_path: PathBuf,
+}
+
+/// Boots an ISO headless in QEMU with software emulation (TCG, so no KVM is
+/// needed) and watches the serial console until the system is up
+#[derive(Debug, Clone)]
+pub struct IsoTest {
+    iso: PathBuf,
+    architecture: String,
+    marker: String,
+    timeout: Duration,
+    uefi: bool,
+    memory_mb: u32,
+}
+
+impl IsoTest {
+    /// A test of `iso`, for the architecture named in the builder's
+    /// `<name>-<version>-<arch>.iso` file name or else the host's
+    pub fn new(iso: PathBuf) -> Self {
+        let stem = iso.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        let architecture = ["x86_64", "i686", "aarch64"].iter()
+            .find(|arch| stem.ends_with(&format!("-{}", arch)))
+            .map_or(std::env::consts::ARCH, |arch| *arch)
+            .to_string();
+        Self { iso, architecture, marker: DEFAULT_MARKER.to_string(), timeout: DEFAULT_TIMEOUT, uefi: false, memory_mb: 2048 }
+    }
+
+    pub fn architecture(mut self, architecture: &str) -> Self {
+        self.architecture = architecture.to_string();
+        self
+    }
+
+    /// Console text that means the system booted
+    pub fn marker(mut self, marker: &str) -> Self {
+        self.marker = marker.to_string();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Boot through UEFI firmware instead of the BIOS; always the case off x86
+    pub fn uefi(mut self, uefi: bool) -> Self {
+        self.uefi = uefi;
+        self
+    }
+
+    /// `<iso name>.console.log`, next to the ISO
+    pub fn log_path(&self) -> PathBuf {
+        self.iso.with_extension("console.log")
+    }
+
+    pub fn qemu_command(&self) -> Result<CommandStep> {
+        let iso = self.iso.display().to_string();
+        let mut args: Vec<String> = Vec::new();
+        let (program, firmware): (&str, &[&str]) = match self.architecture.as_str() {
+            "x86_64" => ("qemu-system-x86_64", &X86_64_FIRMWARE),
+            "i686" => ("qemu-system-i386", &[]),
+            "aarch64" => ("qemu-system-aarch64", &AARCH64_FIRMWARE),
+            other => anyhow::bail!("No QEMU smoke test for {} ISOs", other),
+        };
+        let uefi = self.uefi || program == "qemu-system-aarch64";
+
+        if program == "qemu-system-aarch64" {
+            args.extend(["-machine", "virt,accel=tcg", "-cpu", "max"].map(String::from));
+            // The virt machine has no IDE, so the ISO is a SCSI CD-ROM
+            args.extend(["-device", "virtio-scsi-pci", "-device", "scsi-cd,drive=cd0"].map(String::from));
+            args.push("-drive".to_string());
+            args.push(format!("if=none,id=cd0,media=cdrom,readonly=on,format=raw,file={}", iso));
+        } else {
+            args.extend(["-machine", "q35,accel=tcg", "-cpu", "max"].map(String::from));
+            args.extend(["-cdrom".to_string(), iso, "-boot".to_string(), "d".to_string()]);
+        }
+        if uefi {
+            let code = firmware.iter()
+                .find(|path| Path::new(path).exists())
+                .with_context(|| format!("No UEFI firmware found for {}; install OVMF/AAVMF (edk2)", self.architecture))?;
+            args.push("-drive".to_string());
+            args.push(format!("if=pflash,format=raw,readonly=on,file={}", code));
+        }
+
+        args.extend(["-m".to_string(), self.memory_mb.to_string(), "-smp".to_string(), "2".to_string()]);
+        args.extend(["-display", "none", "-monitor", "none", "-no-reboot"].map(String::from));
+        args.push("-serial".to_string());
+        args.push(format!("file:{}", self.log_path().display()));
+        Ok(CommandStep::new(program, args))
+    }
+
+    /// Boot the ISO and wait for the success marker, a failure marker, QEMU
+    /// exiting or the timeout, whichever comes first
+    pub async fn run(&self) -> Result<IsoTestResult> {
+        if !self.iso.is_file() {
+            anyhow::bail!("ISO not found: {}", self.iso.display());
+        }
+        let log_path = self.log_path();
+        let _ = fs::remove_file(&log_path);
+
+        let step = self.qemu_command()?;
+        let mut child = tokio::process::Command::from(step.to_command())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}; is QEMU installed?", step.program))?;
+
+        // Drain stderr as QEMU writes it so a full pipe can't stall it,
+        // keeping the last line to explain an early exit
+        let stderr = child.stderr.take().map(|pipe| tokio::spawn(async move {
+            let mut lines = BufReader::new(pipe).lines();
+            let mut last = None;
+            while let Ok(Some(line)) = lines.next_line().await {
+                last = Some(line);
+            }
+            last
+        }));
+
+        let start = Instant::now();
+        let outcome = loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let console = fs::read(&log_path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned()).unwrap_or_default();
+            if let Some(outcome) = check_console(&console, &self.marker) {
+                break outcome;
+            }
+            if let Some(status) = child.try_wait()? {
+                let last_line = match stderr {
+                    Some(reader) => reader.await.ok().flatten(),
+                    None => None,
+                };
+                break IsoTestOutcome::Exited(last_line.unwrap_or_else(|| status.to_string()));
+            }
+            if start.elapsed() > self.timeout {
+                break IsoTestOutcome::TimedOut;
+            }
+        };
+
+        let _ = child.kill().await;
+        Ok(IsoTestResult { outcome, elapsed: start.elapsed(), log_path })
+    }
+}
+
+/// The outcome the console output so far decides, if any
+pub fn check_console(console: &str, marker: &str) -> Option<IsoTestOutcome> {
+    if console.contains(marker) {
+        return Some(IsoTestOutcome::Booted);
+    }
+    let lower = console.to_lowercase();
+    FAILURE_MARKERS.iter()
+        .find(|failure| lower.contains(*failure))
+        .map(|failure| {
+            // Report the console line the failure was on
+            let line = console.lines()
+                .find(|line| line.to_lowercase().contains(failure))
+                .unwrap_or(failure);
+            IsoTestOutcome::Failed(line.trim().to_string())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_console() {
+        assert_eq!(check_console("[  OK  ] Reached target Multi-User System.\n\nmylinux login: ", DEFAULT_MARKER),
+            Some(IsoTestOutcome::Booted));
+        assert_eq!(check_console("Reached target Multi-User System", "Multi-User System"), Some(IsoTestOutcome::Booted));
+        assert_eq!(check_console("[    3.1] Kernel panic - not syncing: VFS: Unable to mount root fs\n", DEFAULT_MARKER),
+            Some(IsoTestOutcome::Failed("[    3.1] Kernel panic - not syncing: VFS: Unable to mount root fs".to_string())));
+        assert!(matches!(check_console("You are in emergency mode. After logging in", DEFAULT_MARKER), Some(IsoTestOutcome::Failed(_))));
+        assert_eq!(check_console("Loading initial ramdisk ...", DEFAULT_MARKER), None);
+    }
+
+    #[test]
+    fn test_qemu_command() {
+        let test = IsoTest::new(PathBuf::from("/out/MyLinux-1.0-i686.iso"));
+        assert_eq!(test.log_path(), PathBuf::from("/out/MyLinux-1.0-i686.console.log"));
+        let command = test.qemu_command().unwrap().to_string();
+        assert!(command.starts_with("qemu-system-i386 -machine q35,accel=tcg -cpu max -cdrom /out/MyLinux-1.0-i686.iso -boot d"));
+        assert!(command.ends_with("-no-reboot -serial file:/out/MyLinux-1.0-i686.console.log"));
+        assert!(test.architecture("riscv64").qemu_command().is_err());
+    }
+}
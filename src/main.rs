@@ -3,6 +3,12 @@ mod config_manager;
 mod config_wizard;
 mod distro;
 mod distro_builder;
+mod build_checkpoint;
+mod build_lock;
+mod builder_backend;
+mod disk_image;
+mod iso_boot;
+mod iso_test;
 mod executor;
 mod logger;
 mod history;
@@ -14,13 +20,29 @@ mod system_config;
 mod system_logger;
 mod wsm;
 mod security;
+mod service_manager;
 mod plugins;
 mod agent;
+mod agent_store;
+mod agent_daemon;
+mod agent_plan;
+mod agent_learning;
+mod agent_intent;
+mod agent_schedule;
+mod agent_approval;
 mod self_update;
 mod distributed_cache;
 mod signing_verification;
 mod compatibility_layer;
 mod package_sources;
+mod package_search;
+mod inventory;
+mod transaction;
+mod runner;
+mod simulation;
+mod snapshot;
+mod policy;
+mod failure;
 
 use clap::{Parser, Subcommand, CommandFactory};
 use clap_complete::{generate, Generator, Shell};
@@ -109,27 +131,43 @@ enum Commands {
         #[clap(short, long)]
         extended: bool,
     },
-    /// Get package manager command for installing a package
+    /// Plan or install one or more packages
     Install {
-        /// Package name to install
-        package: String,
+        /// Packages to install; prefix with aur:, flatpak: or snap: to pick a source
+        #[clap(required = true)]
+        packages: Vec<String>,
         /// Execute the command directly (requires confirmation)
         #[clap(short, long)]
         execute: bool,
+        /// Preview the exact changes using the package manager's own simulation
+        #[clap(long, conflicts_with = "execute")]
+        simulate: bool,
+        /// Print the simulation as JSON
+        #[clap(long, requires = "simulate")]
+        json: bool,
     },
     /// Get package manager command for searching packages
     Search {
         /// Search query
         query: String,
-        /// Execute the command directly
+        /// Run the search and show parsed results as a table
         #[clap(short, long)]
         execute: bool,
+        /// Run the search and print parsed results as JSON
+        #[clap(long)]
+        json: bool,
     },
     /// Get system update command
     Update {
         /// Execute the command directly (requires confirmation)
         #[clap(short, long)]
         execute: bool,
+        /// Preview the exact changes using the package manager's own simulation
+        #[clap(long, conflicts_with = "execute")]
+        simulate: bool,
+        /// Print the simulation as JSON
+        #[clap(long, requires = "simulate")]
+        json: bool,
     },
     /// Display comprehensive system information as JSON
     Info {
@@ -141,13 +179,20 @@ enum Commands {
     ListSupported,
     /// Check system compatibility and provide recommendations
     Doctor,
-    /// Remove a package
+    /// Plan or remove one or more packages
     Remove {
-        /// Package name to remove
-        package: String,
+        /// Packages to remove; prefix with aur:, flatpak: or snap: to pick a source
+        #[clap(required = true)]
+        packages: Vec<String>,
         /// Execute the command directly (requires confirmation)
         #[clap(short, long)]
         execute: bool,
+        /// Preview the exact changes using the package manager's own simulation
+        #[clap(long, conflicts_with = "execute")]
+        simulate: bool,
+        /// Print the simulation as JSON
+        #[clap(long, requires = "simulate")]
+        json: bool,
     },
     /// List installed packages or package information
     List {
@@ -157,6 +202,12 @@ enum Commands {
         /// Filter packages by name pattern
         #[clap(short, long)]
         filter: Option<String>,
+        /// Print the inventory as JSON
+        #[clap(long)]
+        json: bool,
+        /// Print the inventory as CSV
+        #[clap(long, conflicts_with = "json")]
+        csv: bool,
     },
     /// Show package information
     PackageInfo {
@@ -208,6 +259,50 @@ enum Commands {
         /// Use default minimal configuration
         #[clap(long)]
         minimal: bool,
+        /// Continue from the work directory's checkpoint, skipping steps whose inputs haven't changed
+        #[clap(long)]
+        resume: bool,
+        /// Start at this step (name or number), reusing the work directory for earlier steps
+        #[clap(long, value_name = "STEP")]
+        from_step: Option<String>,
+        /// Stop after this step (name or number)
+        #[clap(long, value_name = "STEP")]
+        until_step: Option<String>,
+        /// Boot the finished ISO in QEMU and fail the build if it doesn't come up
+        #[clap(long)]
+        test: bool,
+        /// Console text that means the test boot succeeded [default: login:]
+        #[clap(long, value_name = "TEXT", requires = "test")]
+        test_marker: Option<String>,
+        /// Seconds to wait for the test boot [default: 600]
+        #[clap(long, value_name = "SECS", requires = "test")]
+        test_timeout: Option<u64>,
+        /// Test-boot through UEFI firmware instead of the BIOS
+        #[clap(long, requires = "test")]
+        test_uefi: bool,
+        /// Fail if the installed packages differ from the lockfile, or there is none
+        #[clap(long)]
+        locked: bool,
+        /// Ignore the lockfile and resolve packages from a new repository snapshot
+        #[clap(long, conflicts_with = "locked")]
+        update_lock: bool,
+    },
+    /// Boot an ISO headless in QEMU and check it reaches a login prompt
+    TestIso {
+        /// ISO image to boot
+        iso: PathBuf,
+        /// Architecture to emulate [default: from the ISO name, or the host's]
+        #[clap(long)]
+        arch: Option<String>,
+        /// Console text that means the system booted [default: login:]
+        #[clap(long, value_name = "TEXT")]
+        marker: Option<String>,
+        /// Seconds to wait before giving up [default: 600]
+        #[clap(long, value_name = "SECS")]
+        timeout: Option<u64>,
+        /// Boot through UEFI firmware instead of the BIOS
+        #[clap(long)]
+        uefi: bool,
     },
     /// Generate a distro configuration template
     GenerateConfig {
@@ -388,6 +483,12 @@ enum Commands {
         /// Add a task to the agent queue
         #[clap(long)]
         add_task: Option<String>,
+        /// Repeat the added task on a cron expression (`0 2 * * *`), `@daily` or `@every 6h`
+        #[clap(long, value_name = "SPEC", requires = "add_task")]
+        schedule: Option<String>,
+        /// What to do with scheduled runs missed while no agent was running (skip, catch-up)
+        #[clap(long, value_name = "POLICY", default_value = "skip", requires = "schedule")]
+        missed: String,
         /// Show agent status and current tasks
         #[clap(long)]
         status: bool,
@@ -400,6 +501,36 @@ enum Commands {
         /// Enable dry-run mode (tasks won't be executed)
         #[clap(long)]
         dry_run: bool,
+        /// Queue and run the tasks in a TOML plan file
+        #[clap(long, value_name = "FILE")]
+        plan: Option<PathBuf>,
+        /// Restore the snapshot taken before a task ran
+        #[clap(long, value_name = "TASK_ID")]
+        rollback: Option<String>,
+        /// Approve a task waiting for approval
+        #[clap(long, value_name = "TASK_ID", conflicts_with = "reject")]
+        approve: Option<String>,
+        /// Reject a task waiting for approval, removing it from the queue
+        #[clap(long, value_name = "TASK_ID", requires = "reason")]
+        reject: Option<String>,
+        /// Why a task was approved or rejected, kept in the approval record
+        #[clap(long, value_name = "TEXT")]
+        reason: Option<String>,
+        /// Show which policy rule decides whether a command may run
+        #[clap(long, value_name = "COMMAND")]
+        explain: Option<String>,
+        /// Run as a long-lived daemon with a JSON-RPC control socket
+        #[clap(long)]
+        daemon: bool,
+        /// Control socket path for --daemon
+        #[clap(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+        /// Print a systemd unit that runs the agent daemon
+        #[clap(long)]
+        systemd_unit: bool,
+        /// Generate a systemd user unit instead of a system unit
+        #[clap(long, requires = "systemd_unit")]
+        user_unit: bool,
     },
     /// Package signing and verification
     Verify {
@@ -509,6 +640,159 @@ fn print_completions<G: Generator>(generator: G, cmd: &mut clap::Command) {
     generate(generator, cmd, cmd.get_name().to_string(), &mut io::stdout());
 }
 
+/// Split package arguments on whitespace so `lda install "git vim"` keeps working
+fn split_package_args(args: &[String]) -> Vec<String> {
+    let mut packages: Vec<String> = Vec::new();
+    for package in args.iter().flat_map(|arg| arg.split_whitespace()) {
+        if !packages.iter().any(|p| p == package) {
+            packages.push(package.to_string());
+        }
+    }
+    packages
+}
+
+/// Show the plan for a package operation and, unless it is a dry run, confirm
+/// once, execute every step and record the result as one history entry
+fn run_package_operation(
+    operation: &package_manager::PackageOperation,
+    distro: &DistroInfo,
+    logger: &Logger,
+) -> Result<transaction::OperationPlan> {
+    let registry = package_manager::PackageManagerRegistry::new();
+    let plan = transaction::OperationPlan::for_distro(operation, distro, &registry)?;
+    logger.output(plan.render());
+
+    if plan.steps.is_empty() {
+        logger.info("Nothing to do");
+        return Ok(plan);
+    }
+    if operation.dry_run {
+        logger.info("");
+        logger.info("💡 Use --execute to run this plan");
+        return Ok(plan);
+    }
+
+    match dialoguer::Confirm::new().with_prompt("Proceed with this plan?").interact() {
+        Ok(true) => {}
+        Ok(false) => {
+            logger.info("Operation cancelled");
+            return Ok(plan);
+        }
+        Err(e) => anyhow::bail!("Failed to get user confirmation: {}", e),
+    }
+
+    let outcomes = plan.execute();
+    if let Err(e) = plan.record_history(&outcomes, &distro.name) {
+        logger.verbose(format!("Failed to record history: {}", e));
+    }
+
+    for outcome in &outcomes {
+        let packages = outcome.packages.join(" ");
+        match &outcome.error {
+            None => logger.success(format!("✓ {}: {}", outcome.origin, packages)),
+            Some(error) => logger.error(format!("✗ {}: {} ({})", outcome.origin, packages, error)),
+        }
+    }
+
+    let failed = outcomes.iter().filter(|outcome| !outcome.success).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} step(s) failed: {}", failed, outcomes.len(), transaction::summarize(&outcomes));
+    }
+    Ok(plan)
+}
+
+/// Print what each step of a plan would change according to the backends'
+/// native simulation, without asking for confirmation or running anything
+fn run_simulation(
+    operation: &package_manager::PackageOperation,
+    distro: &DistroInfo,
+    logger: &Logger,
+    json: bool,
+) -> Result<()> {
+    let registry = package_manager::PackageManagerRegistry::new();
+    let plan = transaction::OperationPlan::for_distro(operation, distro, &registry)?;
+    let simulations = plan.simulate(&registry);
+
+    if json {
+        logger.json(serde_json::to_string_pretty(&simulations)?);
+        return Ok(());
+    }
+
+    logger.output(plan.render());
+    if simulations.is_empty() {
+        logger.info("Nothing to do");
+        return Ok(());
+    }
+    logger.output("");
+    logger.output("Simulated changes:");
+    for simulation in &simulations {
+        logger.output(simulation.render());
+    }
+    Ok(())
+}
+
+/// Print how the agent policy judges each part of a command line
+fn explain_policy(policy: &policy::Policy, command: &str, logger: &Logger) {
+    match &policy.source {
+        Some(path) => logger.output(format!("Policy: {} (then built-in rules)", path.display())),
+        None => logger.output("Policy: built-in rules"),
+    }
+
+    let decision = policy.evaluate(command, &policy::Caller::current());
+    for (i, verdict) in decision.verdicts.iter().enumerate() {
+        let invocation = &verdict.invocation;
+        let mut words = vec![invocation.program.clone()];
+        words.extend(invocation.args.iter().cloned());
+        logger.output(format!("  {}. {}{}", i + 1, if invocation.privileged { "sudo " } else { "" }, words.join(" ")));
+
+        let rule = match &verdict.rule {
+            Some((name, true)) => format!("rule {} (built-in)", name),
+            Some((name, false)) => format!("rule {}", name),
+            None => "no rule matched".to_string(),
+        };
+        let action = if verdict.allowed { format!("allow, safety {}", verdict.level) } else { "deny".to_string() };
+        let reason = verdict.reason.as_ref().map(|reason| format!(" - {}", reason)).unwrap_or_default();
+        logger.output(format!("     → {}: {}{}", rule, action, reason));
+    }
+
+    if decision.allowed() {
+        logger.success(format!("Allowed with safety level {} ({})", decision.level(), decision.summary()));
+    } else {
+        logger.error(format!("Denied: {}", decision.summary()));
+    }
+}
+
+/// Boot an ISO in QEMU, failing unless it reaches the success marker
+async fn run_iso_test(
+    iso_test: iso_test::IsoTest,
+    marker: Option<&str>,
+    timeout_secs: Option<u64>,
+    uefi: bool,
+    logger: &Logger,
+) -> Result<()> {
+    let mut iso_test = iso_test.uefi(uefi);
+    if let Some(marker) = marker {
+        iso_test = iso_test.marker(marker);
+    }
+    if let Some(secs) = timeout_secs {
+        iso_test = iso_test.timeout(std::time::Duration::from_secs(secs));
+    }
+
+    logger.info(format!("Booting in QEMU: {}", iso_test.qemu_command()?));
+    let result = iso_test.run().await?;
+    let elapsed = result.elapsed.as_secs_f64();
+    let log = result.log_path.display();
+    match result.outcome {
+        iso_test::IsoTestOutcome::Booted => {
+            logger.success(format!("ISO booted in {:.0}s (console log: {})", elapsed, log));
+            Ok(())
+        }
+        iso_test::IsoTestOutcome::Failed(line) => anyhow::bail!("ISO failed to boot after {:.0}s: {} (console log: {})", elapsed, line, log),
+        iso_test::IsoTestOutcome::TimedOut => anyhow::bail!("ISO didn't boot within {:.0}s (console log: {})", elapsed, log),
+        iso_test::IsoTestOutcome::Exited(reason) => anyhow::bail!("QEMU exited before the ISO booted: {} (console log: {})", reason, log),
+    }
+}
+
 async fn handle_self_update(
     logger: &Logger, 
     force: bool, 
@@ -602,7 +886,8 @@ async fn main() -> Result<()> {
     
     // Handle distro builder commands that don't need distro detection
     match &cli.command {
-        Commands::BuildDistro { name, config, work_dir, output_dir, minimal } => {
+        Commands::BuildDistro { name, config, work_dir, output_dir, minimal, resume, from_step, until_step, test, test_marker, test_timeout, test_uefi, locked, update_lock } => {
+            let config_path = config.clone().filter(|_| !*minimal);
             let config = if *minimal {
                 logger.info("Using default minimal configuration.");
                 DistroConfig::default()
@@ -625,12 +910,51 @@ async fn main() -> Result<()> {
             let work_dir = work_dir.clone().unwrap_or_else(|| "./work_dir".into());
             let output_dir = output_dir.clone().unwrap_or_else(|| "./output".into());
 
+            let from_step: Option<build_checkpoint::BuildStep> = from_step.as_deref().map(str::parse).transpose()?;
+            let until_step: Option<build_checkpoint::BuildStep> = until_step.as_deref().map(str::parse).transpose()?;
+
             // Create builder
-            let builder = DistroBuilder::new(config, work_dir, output_dir);
-            let rt = tokio::runtime::Runtime::new()?;
-            let iso_path = rt.block_on(builder.build())?;
+            if *test && !config.output_formats.contains(&disk_image::OutputFormat::Iso) {
+                anyhow::bail!("--test boots the ISO, but output_formats doesn't include Iso");
+            }
+            if *test && let Some(step) = until_step.filter(|step| *step < build_checkpoint::BuildStep::Images) {
+                anyhow::bail!("--test boots the ISO, but --until-step {} stops before it is built", step);
+            }
+            let architecture = config.architecture.clone();
+            // The lockfile lives next to the config it was resolved for
+            let lock_path = config_path.map(|path| path.with_file_name(format!("{}.lock", config.name)));
+            let mut builder = DistroBuilder::new(config, work_dir, output_dir)
+                .resume(*resume)
+                .steps(from_step, until_step);
+            if let Some(lock_path) = lock_path {
+                builder = builder.lock_file(lock_path);
+            }
+            let builder = builder.update_lock(*update_lock).locked(*locked);
+            let outputs = builder.build().await?;
+            let paths = outputs.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
 
-            logger.success(format!("🎉 Distro build complete! ISO created at: {}", iso_path.display()));
+            match until_step {
+                Some(step) if step < build_checkpoint::BuildStep::Images => {
+                    logger.success(format!("Build stopped after step {}; work directory: {}", step, paths));
+                }
+                _ => {
+                    logger.success(format!("🎉 Distro build complete! Created: {}", paths));
+                    if *test {
+                        let iso = outputs.into_iter().find(|path| path.extension().is_some_and(|ext| ext == "iso"))
+                            .ok_or_else(|| anyhow::anyhow!("--test boots the ISO, but the build produced none"))?;
+                        let iso_test = iso_test::IsoTest::new(iso).architecture(&architecture);
+                        run_iso_test(iso_test, test_marker.as_deref(), *test_timeout, *test_uefi, &logger).await?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Commands::TestIso { iso, arch, marker, timeout, uefi } => {
+            let mut iso_test = iso_test::IsoTest::new(iso.clone());
+            if let Some(arch) = arch {
+                iso_test = iso_test.architecture(arch);
+            }
+            run_iso_test(iso_test, marker.as_deref(), *timeout, *uefi, &logger).await?;
             return Ok(());
         }
         Commands::GenerateConfig { output, template } => {
@@ -741,63 +1065,67 @@ logger.verbose(format!("ID Like: {id_like}"));
                 }
             }
         }
-        Commands::Install { package, execute } => {
-            match distro.get_package_install_command(&package) {
-                Some(cmd) => {
-                    if execute {
-                        let _ = CommandExecutor::execute_command(&cmd, true)?;
-                    } else {
-                        logger.output(format!("To install '{package}', run: {cmd}"));
-                    }
-                }
-                None => {
-                    // Package not found in native repos, check alternative sources
-                    logger.warn(format!("Package '{}' not found in native repositories", package));
-                    
-                    match package_sources::PackageSourceManager::new(cli.verbose, cli.quiet) {
-                        Ok(source_manager) => {
-                            let rt = tokio::runtime::Runtime::new().unwrap();
-                            let suggestions = rt.block_on(source_manager.suggest_alternatives(&package));
-                            
-                            for suggestion in suggestions {
+        Commands::Install { packages, execute, simulate, json } => {
+            let operation = package_manager::PackageOperation::new(
+                package_manager::OperationType::Install,
+                split_package_args(&packages),
+                !execute,
+            );
+            if simulate {
+                return run_simulation(&operation, &distro, &logger, json);
+            }
+            let plan = run_package_operation(&operation, &distro, &logger)?;
+
+            // Point at alternative sources for anything the plan could not place
+            if !plan.unresolved.is_empty() {
+                match package_sources::PackageSourceManager::new(cli.verbose, cli.quiet) {
+                    Ok(source_manager) => {
+                        for package in &plan.unresolved {
+                            for suggestion in source_manager.suggest_alternatives(package).await {
                                 logger.info(&suggestion);
                             }
-                            
-                            // Try to get the best source and show command
-                            if let Some(best_source) = rt.block_on(source_manager.get_best_source(&package)) {
-                                logger.info("");
-                                logger.info("💡 Recommended installation:");
+                            if let Some(best_source) = source_manager.get_best_source(package).await {
+                                logger.info(format!("💡 Recommended installation for '{}':", package));
                                 logger.output(format!("   {}", best_source.install_command));
-                                
-                                if execute {
-                                    match dialoguer::Confirm::new()
-                                        .with_prompt("Would you like to install from the recommended source?")
-                                        .interact() {
-                                        Ok(true) => {
-                                            let _ = CommandExecutor::execute_command(&best_source.install_command, true)?;
-                                        }
-                                        Ok(false) => logger.info("Installation cancelled"),
-                                        Err(_) => logger.error("Failed to get user confirmation"),
-                                    }
-                                }
                             }
                         }
-                        Err(e) => logger.error(format!("Failed to initialize package source manager: {}", e)),
                     }
+                    Err(e) => logger.error(format!("Failed to initialize package source manager: {}", e)),
                 }
             }
         }
-        Commands::Search { query, execute } => {
-            // Search in native repositories first
-            match distro.get_package_search_command(&query) {
-                Some(cmd) => {
-                    if execute {
-                        let safe_to_run = CommandExecutor::is_safe_to_execute(&cmd);
-                        let _ = CommandExecutor::execute_command(&cmd, !safe_to_run)?;
-                    } else {
-                        logger.output(format!("To search in native repositories for '{query}', run: {cmd}"));
-                    }
+        Commands::Search { query, execute, json } => {
+            if execute || json {
+                // Native repositories first, then alternative sources
+                let mut hits = Vec::new();
+                let registry = package_manager::PackageManagerRegistry::new();
+                match distro.backend(&registry) {
+                    Some(backend) => match backend.search(&query) {
+                        Ok(native_hits) => hits.extend(native_hits),
+                        Err(e) => logger.error(format!("Native search failed: {}", e)),
+                    },
+                    None => logger.warn("Unable to determine native package search command for this distribution"),
+                }
+
+                match package_sources::PackageSourceManager::new(cli.verbose, cli.quiet) {
+                    Ok(source_manager) => hits.extend(source_manager.search_hits(&query).await),
+                    Err(e) => logger.verbose(format!("Could not search alternative sources: {}", e)),
                 }
+
+                if json {
+                    logger.json(serde_json::to_string_pretty(&hits)?);
+                } else if hits.is_empty() {
+                    logger.info(format!("No packages found matching '{query}'"));
+                } else {
+                    logger.output(package_search::render_table(&hits));
+                    logger.info("");
+                    logger.info(format!("{} result(s), * = installed", hits.len()));
+                }
+                return Ok(());
+            }
+
+            match distro.get_package_search_command(&query) {
+                Some(cmd) => logger.output(format!("To search in native repositories for '{query}', run: {cmd}")),
                 None => logger.warn("Unable to determine native package search command for this distribution"),
             }
             
@@ -814,22 +1142,38 @@ logger.verbose(format!("ID Like: {id_like}"));
                             logger.info(&result);
                         }
 
-                        if !execute {
-                            logger.info("");
-                            logger.info("💡 Use --execute to run the native search command, or use 'lda install <package>' to install from alternative sources");
-                        }
+                        logger.info("");
+                        logger.info("💡 Use --execute for a merged results table, --json for machine-readable output, or 'lda install <package>' to install from alternative sources");
                     }
                 }
                 Err(e) => logger.verbose(format!("Could not search alternative sources: {}", e)),
             }
         }
-        Commands::Update { execute } => {
+        Commands::Update { execute, simulate, json } => {
+            if simulate {
+                let registry = package_manager::PackageManagerRegistry::new();
+                let Some(backend) = distro.backend(&registry) else {
+                    anyhow::bail!("Unable to determine the package manager for this distribution");
+                };
+                let simulation = transaction::StepSimulation::run(
+                    transaction::PackageOrigin::Native,
+                    backend,
+                    &package_manager::OperationType::Update,
+                    &[],
+                );
+                if json {
+                    logger.json(serde_json::to_string_pretty(&[&simulation])?);
+                } else {
+                    logger.output(simulation.render());
+                }
+                return Ok(());
+            }
             match distro.get_system_update_command() {
                 Some(cmd) => {
                     if execute {
-                        let _ = CommandExecutor::execute_command(&cmd, true)?;
+                        let _ = CommandExecutor::execute_steps(&cmd, true)?;
                     } else {
-                        logger.output(format!("To update the system, run: {cmd}"));
+                        logger.output(format!("To update the system, run: {}", executor::render_steps(&cmd)));
                     }
                 }
                 None => logger.error("Unable to determine system update command for this distribution"),
@@ -853,6 +1197,10 @@ logger.verbose(format!("ID Like: {id_like}"));
             logger.info("• Gentoo: portage");
             logger.info("• NixOS: nix");
             logger.info("• Alpine: apk");
+            logger.info("• Void: xbps");
+            logger.info("");
+            let registry = package_manager::PackageManagerRegistry::new();
+            logger.info(format!("Registered package backends: {}", registry.names().join(", ")));
         }
         Commands::Doctor => {
             logger.info("System Compatibility Check:");
@@ -881,32 +1229,71 @@ logger.verbose(format!("ID Like: {id_like}"));
                 logger.info("• All package management commands should work correctly");
             }
         }
-        Commands::Remove { package, execute } => {
-            match distro.get_package_remove_command(&package) {
-                Some(cmd) => {
-                    if execute {
-                        let _ = CommandExecutor::execute_command(&cmd, true)?;
+        Commands::Remove { packages, execute, simulate, json } => {
+            let operation = package_manager::PackageOperation::new(
+                package_manager::OperationType::Remove,
+                split_package_args(&packages),
+                !execute,
+            );
+            if simulate {
+                return run_simulation(&operation, &distro, &logger, json);
+            }
+            run_package_operation(&operation, &distro, &logger)?;
+        }
+        Commands::List { detailed, filter, json, csv } => {
+            let registry = package_manager::PackageManagerRegistry::new();
+            let inventory = match distro.backend(&registry) {
+                Some(backend) => backend.installed_packages(),
+                None => Err(anyhow::anyhow!("Unable to determine package manager for this distribution")),
+            };
+
+            match inventory {
+                Ok(mut packages) => {
+                    // Keep the full inventory cached for other commands
+                    if let Ok(mut cache_manager) = cache::CacheManager::new()
+                        && let Err(e) = cache_manager.store("installed_packages", cache::CacheData::InstalledPackages(packages.clone()))
+                    {
+                        logger.verbose(format!("Failed to cache package inventory: {}", e));
+                    }
+
+                    if let Some(pattern) = &filter {
+                        packages.retain(|p| p.name.contains(pattern.as_str()));
+                    }
+
+                    if json {
+                        logger.json(serde_json::to_string_pretty(&packages)?);
+                    } else if csv {
+                        print!("{}", inventory::to_csv(&packages));
                     } else {
-                        logger.output(format!("To remove '{package}', run: {cmd}"));
+                        logger.output(inventory::render_table(&packages, detailed));
+                        logger.info(format!("\n{} package(s) installed", packages.len()));
                     }
                 }
-                None => logger.error("Unable to determine package remove command for this distribution"),
-            }
-        }
-        Commands::List { detailed, filter } => {
-            match distro.get_package_list_command(detailed, filter.as_deref()) {
-                Some(cmd) => {
-                    let safe_to_run = CommandExecutor::is_safe_to_execute(&cmd);
-                    let _ = CommandExecutor::execute_command(&cmd, !safe_to_run)?;
+                Err(e) if json || csv => {
+                    logger.error(format!("Failed to read installed packages: {}", e));
+                }
+                Err(e) => {
+                    logger.verbose(format!("Package inventory unavailable, falling back to list command: {}", e));
+                    match distro.get_package_list_command(detailed) {
+                        Some(cmd) => {
+                            let output = CommandExecutor::capture_output(&cmd)?;
+                            for line in String::from_utf8_lossy(&output.stdout).lines() {
+                                if filter.as_deref().is_none_or(|pattern| line.contains(pattern)) {
+                                    logger.output(line);
+                                }
+                            }
+                        }
+                        None => logger.error("Unable to determine package list command for this distribution"),
+                    }
                 }
-                None => logger.error("Unable to determine package list command for this distribution"),
             }
         }
         Commands::PackageInfo { package } => {
+            package_manager::validate_package_name(&package)?;
             match distro.get_package_info_command(&package) {
                 Some(cmd) => {
-                    let safe_to_run = CommandExecutor::is_safe_to_execute(&cmd);
-                    let _ = CommandExecutor::execute_command(&cmd, !safe_to_run)?;
+                    let safe_to_run = CommandExecutor::is_safe_to_execute(std::slice::from_ref(&cmd));
+                    let _ = CommandExecutor::execute_steps(&[cmd], !safe_to_run)?;
                 }
                 None => logger.error("Unable to determine package info command for this distribution"),
             }
@@ -1056,6 +1443,10 @@ match String::from_utf8(value.to_vec()) {
             // This case is handled early in the function
             unreachable!()
         }
+        Commands::TestIso { .. } => {
+            // This case is handled early in the function
+            unreachable!()
+        }
         Commands::GenerateConfig { .. } => {
             // This case is handled early in the function
             unreachable!()
@@ -1552,34 +1943,69 @@ match String::from_utf8(value.to_vec()) {
                 }
             }
         }
-        Commands::Agent { start, add_task, status, stats, clear_tasks, dry_run: _ } => {
-            let mut agent = agent::IntelligentAgent::new(cli.verbose, cli.quiet);
+        Commands::Agent { start, add_task, schedule, missed, status, stats, clear_tasks, dry_run, plan, rollback, approve, reject, reason, explain, daemon, socket, systemd_unit, user_unit } => {
+            if systemd_unit {
+                let executable = std::env::current_exe()?;
+                logger.output(agent_daemon::systemd_unit(&executable, user_unit));
+                return Ok(());
+            }
+            if let Some(command) = explain {
+                let policy = policy::Policy::load(&system_config::SystemConfig::load()?.agent)?;
+                explain_policy(&policy, &command, &logger);
+                return Ok(());
+            }
+            if daemon {
+                let socket_path = match socket {
+                    Some(socket) => socket,
+                    None => agent_daemon::default_socket_path()?,
+                };
+                let daemon = agent_daemon::AgentDaemon::new(socket_path, agent_store::AgentStore::default_dir()?, cli.verbose, cli.quiet);
+                return daemon.run().await;
+            }
+
+            let mut agent = agent::IntelligentAgent::new(cli.verbose, cli.quiet)?;
             
-            if start {
+            if let Some(plan_path) = plan {
+                let tasks = agent_plan::TaskPlan::load(&plan_path)?.into_tasks(&agent)?;
+                if dry_run {
+                    agent.validate_tasks(&tasks)?;
+                    let order = agent.preview_plan(&tasks)?;
+                    logger.info(format!("📋 Plan {} ({} tasks, in execution order):", plan_path.display(), tasks.len()));
+                    for (i, task) in order.iter().enumerate() {
+                        let schedule = task.schedule.as_ref().map(|schedule| format!(", Schedule: {}", schedule.spec)).unwrap_or_default();
+                        logger.info(format!("  {}. {} — {} (Priority: {:?}, Safety: {}{})",
+                            i + 1, task.description, task.command, task.priority, task.safety_level, schedule));
+                    }
+                    logger.success("Plan is valid; nothing was queued (dry run)");
+                } else {
+                    agent.add_tasks(tasks)?;
+                    agent.run_agent_loop().await?;
+                }
+            } else if let Some(task_id) = rollback {
+                match agent.rollback_task(&task_id)? {
+                    snapshot::RestoreOutcome::Restored => logger.success(format!("Task {} rolled back", task_id)),
+                    snapshot::RestoreOutcome::RebootRequired => {
+                        logger.warn(format!("Snapshot for task {} is staged; reboot to finish the rollback", task_id));
+                    }
+                }
+            } else if let Some(task_id) = approve {
+                let description = agent.decide_approval(&task_id, true, reason)?;
+                logger.success(format!("Approved {} ({}); it runs on the next agent pass", description, task_id));
+            } else if let Some(task_id) = reject {
+                let description = agent.decide_approval(&task_id, false, reason)?;
+                logger.success(format!("Rejected {} ({})", description, task_id));
+            } else if start {
                 logger.info("🤖 Starting Intelligent Agent...");
                 match agent.run_agent_loop().await {
                     Ok(()) => logger.success("Agent loop completed successfully"),
                     Err(e) => logger.error(format!("Agent loop failed: {}", e)),
                 }
             } else if let Some(task_description) = add_task {
-                // Parse task type from description
-                let task_type = if task_description.contains("install") || task_description.contains("remove") {
-                    agent::TaskType::PackageManagement
-                } else if task_description.contains("security") || task_description.contains("audit") {
-                    agent::TaskType::SecurityAudit
-                } else if task_description.contains("config") {
-                    agent::TaskType::SystemConfiguration
-                } else if task_description.contains("monitor") {
-                    agent::TaskType::Monitoring
-                } else {
-                    agent::TaskType::PackageManagement
-                };
-                
-                let task = agent.create_task_from_command(
-                    "manual",
-                    &[task_description],
-                    task_type
-                );
+                let intent = agent_intent::Intent::parse(&task_description)?;
+                let mut task = agent.create_task_from_intent(&task_description, intent)?;
+                if let Some(spec) = &schedule {
+                    task.schedule = Some(agent_schedule::TaskSchedule::new(spec, missed.parse()?)?);
+                }
                 
                 match agent.add_task(task) {
                     Ok(()) => logger.success("Task added to agent queue"),
@@ -1589,8 +2015,14 @@ match String::from_utf8(value.to_vec()) {
                 let state = agent.get_state();
                 logger.info("🤖 Agent Status:");
                 logger.info(format!("Current tasks: {}", state.current_tasks.len()));
+                logger.info(format!("Scheduled tasks: {}", state.scheduled_tasks.len()));
+                logger.info(format!("Running tasks: {}", state.in_progress.len()));
+                if state.paused {
+                    logger.warn("Queue is paused; no new tasks will be started");
+                }
                 logger.info(format!("Completed tasks: {}", state.completed_tasks.len()));
                 logger.info(format!("Failed tasks: {}", state.failed_tasks.len()));
+                logger.info(format!("Skipped tasks: {}", state.skipped_tasks.len()));
                 logger.info(format!("Safety violations: {}", state.safety_violations));
                 logger.info(format!("Last update: {:?}", state.last_update));
                 
@@ -1601,6 +2033,49 @@ match String::from_utf8(value.to_vec()) {
                             task.description, task.priority, task.safety_level));
                     }
                 }
+                let awaiting: Vec<_> = state.current_tasks.iter()
+                    .chain(&state.scheduled_tasks)
+                    .filter_map(|task| Some((task, task.approval.as_ref().filter(|approval| approval.is_pending())?)))
+                    .collect();
+                if !awaiting.is_empty() {
+                    logger.info("\nAwaiting Approval (agent --approve <id> / --reject <id> --reason <text>):");
+                    for (task, approval) in awaiting {
+                        logger.info(format!("  • {} ({}) submitted by {}", task.description, task.id, approval.submitted_by));
+                        for line in approval.plan.lines() {
+                            logger.info(format!("      {}", line));
+                        }
+                    }
+                }
+                if !state.in_progress.is_empty() {
+                    logger.info("\nRunning Tasks:");
+                    for task in &state.in_progress {
+                        logger.info(format!("  • {} ({})", task.description, task.id));
+                    }
+                }
+                if !state.scheduled_tasks.is_empty() {
+                    let time = |at: std::time::SystemTime| chrono::DateTime::<chrono::Local>::from(at).format("%Y-%m-%d %H:%M").to_string();
+                    logger.info("\nScheduled Tasks:");
+                    for task in &state.scheduled_tasks {
+                        let Some(schedule) = &task.schedule else { continue };
+                        let last = match (schedule.last_run, &schedule.last_task) {
+                            (Some(at), Some(last_task)) => {
+                                let outcome = if state.completed_tasks.iter().any(|r| &r.task_id == last_task) {
+                                    "succeeded"
+                                } else if state.failed_tasks.iter().any(|r| &r.task_id == last_task) {
+                                    "failed"
+                                } else if state.skipped_tasks.iter().any(|r| &r.task_id == last_task) {
+                                    "skipped"
+                                } else {
+                                    "pending"
+                                };
+                                format!("{} ({})", time(at), outcome)
+                            }
+                            _ => "never".to_string(),
+                        };
+                        logger.info(format!("  • {} [{}] ({}) last run: {}, next run: {}",
+                            task.description, schedule.spec, task.id, last, time(schedule.next_run)));
+                    }
+                }
             } else if stats {
                 let state = agent.get_state();
                 let total_tasks = state.completed_tasks.len() + state.failed_tasks.len();
@@ -1623,16 +2098,35 @@ match String::from_utf8(value.to_vec()) {
                 logger.info(format!("Success rate: {:.1}%", success_rate));
                 logger.info(format!("Average task duration: {:.1}s", avg_duration));
                 logger.info(format!("Safety violations: {}", state.safety_violations));
-                logger.info(format!("Learning data points: {}", state.learning_data.len()));
+                logger.info(format!("Commands learned: {}", state.command_history.len()));
+
+                let mut failures: std::collections::BTreeMap<failure::FailureClass, (usize, u32)> = std::collections::BTreeMap::new();
+                for class in state.failed_tasks.iter().filter_map(|r| r.failure) {
+                    failures.entry(class).or_default().0 += 1;
+                }
+                for (class, retries) in &state.retries {
+                    failures.entry(*class).or_default().1 += retries;
+                }
+                if !failures.is_empty() {
+                    logger.info("\nFailures by cause:");
+                    for (class, (failed, retried)) in failures {
+                        let kind = if class.is_transient() { "transient" } else { "permanent" };
+                        logger.info(format!("  • {} ({}): {} failed, {} retried", class, kind, failed, retried));
+                    }
+                }
                 
-                if !state.learning_data.is_empty() {
-                    logger.info("\nLearning Data:");
-                    for (key, value) in &state.learning_data {
-                        logger.info(format!("  • {}: {:.3}", key, value));
+                if !state.command_history.is_empty() {
+                    logger.info("\nCommand History:");
+                    let mut history: Vec<_> = state.command_history.iter().collect();
+                    history.sort_by(|a, b| a.0.cmp(b.0));
+                    for (signature, stats) in history {
+                        logger.info(format!("  • {}: {} run(s), {:.0}% success, ~{:.1}s{}", signature, stats.runs,
+                            stats.success_ratio() * 100.0, stats.avg_duration.as_secs_f64(),
+                            stats.last_failure.as_ref().map(|failure| format!(", last failure: {}", failure)).unwrap_or_default()));
                     }
                 }
             } else if clear_tasks {
-                agent.clear_all_tasks();
+                agent.clear_all_tasks()?;
                 logger.success("All tasks cleared from agent queue");
             } else {
                 logger.info("🤖 Intelligent Agent System");
@@ -1640,8 +2134,14 @@ match String::from_utf8(value.to_vec()) {
                 logger.info("Use --status to show current agent status");
                 logger.info("Use --stats to show learning statistics");
                 logger.info("Use --add-task \"command\" to add a task");
+                logger.info("Use --plan plan.toml to queue and run a file of tasks");
+                logger.info("Use --rollback <task_id> to undo a task from its snapshot");
+                logger.info("Use --approve <task_id> or --reject <task_id> --reason \"...\" to decide on risky tasks");
+                logger.info("Use --explain \"command\" to see which policy rule applies to a command");
                 logger.info("Use --clear-tasks to clear all pending tasks");
                 logger.info("Use --dry-run to enable dry-run mode");
+                logger.info("Use --daemon to keep running and accept tasks on a control socket");
+                logger.info("Use --systemd-unit to generate a service unit for the daemon");
             }
         }
         Commands::Verify { 
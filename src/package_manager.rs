@@ -1,16 +1,197 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use anyhow::Result;
+use std::path::Path;
+use std::process::Output;
+use crate::executor::{CommandExecutor, CommandStep};
+use crate::inventory::{self, InstalledPackage};
+use crate::package_search::{self, PackageSearchHit};
+use crate::simulation::{self, SimulationReport};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PackageManager {
-    pub name: String,
-    pub install_cmd: String,
-    pub remove_cmd: String,
-    pub search_cmd: String,
-    pub update_cmd: String,
-    pub list_cmd: String,
-    pub info_cmd: String,
-    pub requires_sudo: bool,
+/// A package manager that knows how to express every package operation.
+///
+/// Each supported manager implements this trait once and is registered in
+/// [`PackageManagerRegistry`], so every CLI command resolves its commands
+/// through the same backend. Commands are returned as argv-based
+/// [`CommandStep`]s and are never passed through a shell.
+pub trait PackageBackend: Send + Sync {
+    /// Registry key, matching `DistroInfo::package_manager`
+    fn name(&self) -> &'static str;
+
+    /// Whether modifying operations need to be run through sudo
+    fn requires_sudo(&self) -> bool {
+        true
+    }
+
+    // Install, remove and update steps run unattended once the user has
+    // confirmed them, so they must not prompt
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep>;
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep>;
+    fn update_command(&self) -> Vec<CommandStep>;
+    fn search_command(&self, query: &str) -> CommandStep;
+    fn list_command(&self, detailed: bool) -> CommandStep;
+    fn info_command(&self, package: &str) -> CommandStep;
+    fn is_installed_command(&self, package: &str) -> CommandStep;
+
+    /// Command that succeeds when a package exists in the configured repositories
+    fn is_available_command(&self, _package: &str) -> Option<CommandStep> {
+        None
+    }
+
+    /// Check whether a package is currently installed
+    fn is_installed(&self, package: &str) -> bool {
+        step_succeeds(&self.is_installed_command(package))
+    }
+
+    /// Check whether a package can be installed from this backend. Backends
+    /// without a cheap lookup are assumed to provide every package.
+    fn is_available(&self, package: &str) -> bool {
+        self.is_available_command(package).is_none_or(|step| step_succeeds(&step))
+    }
+
+    /// Parse the output of `search_command` into structured hits
+    fn parse_search_output(&self, _output: &str) -> Vec<PackageSearchHit> {
+        Vec::new()
+    }
+
+    /// Run a search and return the parsed results
+    fn search(&self, query: &str) -> Result<Vec<PackageSearchHit>> {
+        let output = CommandExecutor::capture_output(&self.search_command(query))?;
+        // Most managers exit non-zero when nothing matches, so only stdout matters here
+        Ok(self.parse_search_output(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Command that previews an install, remove or update with the manager's
+    /// own solver without changing the system
+    fn simulate_command(&self, _operation: &OperationType, _packages: &[String]) -> Option<CommandStep> {
+        None
+    }
+
+    /// Parse the output of `simulate_command`
+    fn parse_simulation(&self, _operation: &OperationType, _output: &str) -> SimulationReport {
+        SimulationReport::default()
+    }
+
+    /// Whether the simulation ran to completion
+    fn simulation_succeeded(&self, output: &Output) -> bool {
+        output.status.success()
+    }
+
+    /// Run the native simulation and return the parsed delta
+    fn simulate(&self, operation: &OperationType, packages: &[String]) -> Result<SimulationReport> {
+        let Some(step) = self.simulate_command(operation, packages) else {
+            anyhow::bail!("Simulation is not supported for {}", self.name());
+        };
+        let output = CommandExecutor::capture_output(&step)?;
+        if !self.simulation_succeeded(&output) {
+            anyhow::bail!("Simulation failed ({}): {}", step, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(self.parse_simulation(operation, &String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Read the installed package inventory from the package manager database
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> {
+        anyhow::bail!("Installed package inventory is not supported for {}", self.name())
+    }
+
+    /// Latest entry in the manager's own transaction history, for managers
+    /// that can undo whole transactions
+    fn last_transaction(&self) -> Option<String> {
+        None
+    }
+
+    /// Commands that undo every transaction recorded after `transaction`
+    fn undo_transactions_command(&self, _transaction: &str) -> Option<Vec<CommandStep>> {
+        None
+    }
+
+    /// Commands that install exact package versions, downgrading as needed.
+    /// `None` when the manager can't go back to an older version.
+    fn install_versions_command(&self, _packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        None
+    }
+
+    /// Mark a step as needing root when this backend requires sudo
+    fn privileged(&self, step: CommandStep) -> CommandStep {
+        if self.requires_sudo() {
+            step.with_root()
+        } else {
+            step
+        }
+    }
+}
+
+/// Run a step and report whether it exited successfully
+fn step_succeeds(step: &CommandStep) -> bool {
+    step.to_command()
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Build a step from fixed leading arguments followed by package names
+fn with_packages(program: &str, leading: &[&str], packages: &[String]) -> CommandStep {
+    CommandStep::new(program, leading.iter().map(|arg| arg.to_string()).chain(packages.iter().cloned()))
+}
+
+/// Build a step from fixed leading arguments followed by `name<sep>version` pairs
+fn with_versions(program: &str, leading: &[&str], packages: &[(String, String)], separator: &str) -> CommandStep {
+    let pinned: Vec<String> = packages.iter().map(|(name, version)| format!("{name}{separator}{version}")).collect();
+    with_packages(program, leading, &pinned)
+}
+
+/// Find a package file for an exact version in pacman's package cache
+fn pacman_cached_package(cache_dir: &Path, name: &str, version: &str) -> Option<std::path::PathBuf> {
+    let prefix = format!("{name}-{version}-");
+    std::fs::read_dir(cache_dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .and_then(|file| file.to_str())
+                .is_some_and(|file| {
+                    // The architecture must be all that separates the version from the extension
+                    file.strip_prefix(&prefix)
+                        .and_then(|rest| rest.split_once(".pkg.tar"))
+                        .is_some_and(|(arch, _)| !arch.contains('-'))
+                        && !file.ends_with(".sig")
+                })
+        })
+}
+
+/// The first transaction id in `dnf history list` output
+fn parse_dnf_history(output: &str) -> Option<String> {
+    output.lines()
+        .filter_map(|line| line.split('|').next())
+        .map(str::trim)
+        .find(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+/// Pick the simulation arguments for an operation. Read-only operations have
+/// nothing to simulate.
+fn simulation_args<'a>(operation: &OperationType, install: &'a [&'a str], remove: &'a [&'a str], update: &'a [&'a str]) -> Option<&'a [&'a str]> {
+    match operation {
+        OperationType::Install => Some(install),
+        OperationType::Remove => Some(remove),
+        OperationType::Update => Some(update),
+        OperationType::Search | OperationType::List | OperationType::Info => None,
+    }
+}
+
+/// Reject package names that could be mistaken for options or that contain
+/// characters no package manager uses in names
+pub fn validate_package_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("Package name must not be empty");
+    }
+    if name.starts_with('-') {
+        anyhow::bail!("Invalid package name '{}': names must not start with '-'", name);
+    }
+    if let Some(c) = name.chars().find(|c| !(c.is_ascii_alphanumeric() || "._+-@:/=~".contains(*c))) {
+        anyhow::bail!("Invalid package name '{}': unexpected character '{}'", name, c.escape_default());
+    }
+    Ok(())
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +202,17 @@ pub struct PackageOperation {
     pub dry_run: bool,
 }
 
+impl PackageOperation {
+    pub fn new(operation_type: OperationType, packages: Vec<String>, dry_run: bool) -> Self {
+        Self {
+            operation_type,
+            packages,
+            options: HashMap::new(),
+            dry_run,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OperationType {
     Install,
@@ -31,8 +223,246 @@ pub enum OperationType {
     Info,
 }
 
+pub struct Pacman;
+pub struct Apt;
+pub struct Dnf;
+pub struct Zypper;
+pub struct Portage;
+pub struct Nix;
+pub struct Apk;
+pub struct Xbps;
+pub struct AurHelper(&'static str);
+pub struct Flatpak;
+pub struct Snap;
+
+impl PackageBackend for Pacman {
+    fn name(&self) -> &'static str { "pacman" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("pacman", &["-S", "--noconfirm"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("pacman", &["-R", "--noconfirm"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![self.privileged(CommandStep::new("pacman", ["-Syu", "--noconfirm"]))] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("pacman", ["-Ss", query]) }
+    fn list_command(&self, detailed: bool) -> CommandStep { CommandStep::new("pacman", [if detailed { "-Qi" } else { "-Q" }]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("pacman", ["-Qi", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("pacman", ["-Q", package]) }
+    fn is_available_command(&self, package: &str) -> Option<CommandStep> { Some(CommandStep::new("pacman", ["-Si", package])) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_pacman(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::read_pacman_db(Path::new("/var/lib/pacman/local")) }
+    // Older versions can only come from the package cache
+    fn install_versions_command(&self, packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        let cache = Path::new("/var/cache/pacman/pkg");
+        let files: Option<Vec<String>> = packages.iter()
+            .map(|(name, version)| pacman_cached_package(cache, name, version).map(|path| path.display().to_string()))
+            .collect();
+        Some(vec![self.privileged(with_packages("pacman", &["-U", "--noconfirm"], &files?))])
+    }
+    fn simulate_command(&self, operation: &OperationType, packages: &[String]) -> Option<CommandStep> {
+        simulation_args(
+            operation,
+            &["-S", "--print", "--print-format", "%n %v %s"],
+            &["-R", "--print", "--print-format", "%n %v"],
+            &["-Su", "--print", "--print-format", "%n %v %s"],
+        ).map(|args| with_packages("pacman", args, packages))
+    }
+    fn parse_simulation(&self, operation: &OperationType, output: &str) -> SimulationReport {
+        let installed = self.installed_packages().unwrap_or_default()
+            .into_iter()
+            .map(|package| (package.name, package.version))
+            .collect();
+        simulation::parse_pacman(output, matches!(operation, OperationType::Remove), &installed)
+    }
+}
+
+impl PackageBackend for Apt {
+    fn name(&self) -> &'static str { "apt" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("apt", &["install", "-y"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("apt", &["remove", "-y"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> {
+        vec![self.privileged(CommandStep::new("apt", ["update"])), self.privileged(CommandStep::new("apt", ["upgrade", "-y"]))]
+    }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("apt", ["search", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("dpkg-query", ["-l"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("apt", ["show", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("dpkg", ["-s", package]) }
+    fn is_available_command(&self, package: &str) -> Option<CommandStep> { Some(CommandStep::new("apt-cache", ["show", package])) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_apt(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::read_dpkg_status(Path::new("/var/lib/dpkg/status"), Path::new("/var/lib/apt/extended_states")) }
+    fn install_versions_command(&self, packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        Some(vec![self.privileged(with_versions("apt-get", &["install", "-y", "--allow-downgrades"], packages, "="))])
+    }
+    fn simulate_command(&self, operation: &OperationType, packages: &[String]) -> Option<CommandStep> {
+        simulation_args(operation, &["-s", "install"], &["-s", "remove"], &["-s", "upgrade"])
+            .map(|args| with_packages("apt-get", args, packages))
+    }
+    fn parse_simulation(&self, _operation: &OperationType, output: &str) -> SimulationReport { simulation::parse_apt(output) }
+}
+
+impl PackageBackend for Dnf {
+    fn name(&self) -> &'static str { "dnf" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("dnf", &["install", "-y"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("dnf", &["remove", "-y"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![self.privileged(CommandStep::new("dnf", ["upgrade", "-y"]))] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("dnf", ["search", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("dnf", ["list", "installed"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("dnf", ["info", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("rpm", ["-q", package]) }
+    fn is_available_command(&self, package: &str) -> Option<CommandStep> { Some(CommandStep::new("dnf", ["-q", "info", package])) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_dnf(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::query_rpm() }
+    fn simulate_command(&self, operation: &OperationType, packages: &[String]) -> Option<CommandStep> {
+        simulation_args(operation, &["install", "--assumeno"], &["remove", "--assumeno"], &["upgrade", "--assumeno"])
+            .map(|args| self.privileged(with_packages("dnf", args, packages)))
+    }
+    fn parse_simulation(&self, _operation: &OperationType, output: &str) -> SimulationReport { simulation::parse_dnf(output) }
+    fn last_transaction(&self) -> Option<String> {
+        let output = CommandExecutor::capture_output(&CommandStep::new("dnf", ["history", "list"])).ok()?;
+        parse_dnf_history(&String::from_utf8_lossy(&output.stdout))
+    }
+    fn undo_transactions_command(&self, transaction: &str) -> Option<Vec<CommandStep>> {
+        Some(vec![self.privileged(CommandStep::new("dnf", ["history", "rollback", "-y", transaction]))])
+    }
+    fn install_versions_command(&self, packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        Some(vec![self.privileged(with_versions("dnf", &["install", "-y"], packages, "-"))])
+    }
+    // --assumeno always ends with "Operation aborted" and exit status 1
+    fn simulation_succeeded(&self, output: &Output) -> bool {
+        output.status.success()
+            || (output.status.code() == Some(1) && String::from_utf8_lossy(&output.stderr).contains("Operation aborted"))
+    }
+}
+
+impl PackageBackend for Zypper {
+    fn name(&self) -> &'static str { "zypper" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("zypper", &["--non-interactive", "install"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("zypper", &["--non-interactive", "remove"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![self.privileged(CommandStep::new("zypper", ["--non-interactive", "update"]))] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("zypper", ["search", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("zypper", ["se", "--installed-only"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("zypper", ["info", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("rpm", ["-q", package]) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_zypper(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::query_rpm() }
+    fn simulate_command(&self, operation: &OperationType, packages: &[String]) -> Option<CommandStep> {
+        simulation_args(
+            operation,
+            &["--non-interactive", "install", "--dry-run"],
+            &["--non-interactive", "remove", "--dry-run"],
+            &["--non-interactive", "update", "--dry-run"],
+        ).map(|args| self.privileged(with_packages("zypper", args, packages)))
+    }
+    fn parse_simulation(&self, _operation: &OperationType, output: &str) -> SimulationReport { simulation::parse_zypper(output) }
+    fn install_versions_command(&self, packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        Some(vec![self.privileged(with_versions("zypper", &["--non-interactive", "install", "--oldpackage"], packages, "="))])
+    }
+}
+
+impl PackageBackend for Portage {
+    fn name(&self) -> &'static str { "portage" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("emerge", &[], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("emerge", &["--unmerge"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> {
+        vec![self.privileged(CommandStep::new("emerge", ["--sync"])), self.privileged(CommandStep::new("emerge", ["-uDN", "@world"]))]
+    }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("emerge", ["--search", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("equery", ["list", "*"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("equery", ["list", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("equery", ["-q", "list", package]) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_portage(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::read_portage_db(Path::new("/var/db/pkg"), Path::new("/var/lib/portage/world")) }
+}
+
+impl PackageBackend for Nix {
+    fn name(&self) -> &'static str { "nix" }
+    fn requires_sudo(&self) -> bool { false }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> {
+        let attrs = packages.iter().map(|p| format!("nixpkgs.{p}"));
+        vec![CommandStep::new("nix-env", ["-iA".to_string()].into_iter().chain(attrs))]
+    }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![with_packages("nix-env", &["-e"], packages)] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![CommandStep::new("nixos-rebuild", ["switch", "--upgrade"]).with_root()] }
+    // nix-env matches package names as regular expressions, replacing the old `| grep` pipeline
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("nix-env", ["-qaP".to_string(), format!(".*{query}.*")]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("nix-env", ["-q"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("nix-env", ["-qaP", "--description", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("nix-env", ["-q", package]) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_nix(output) }
+}
+
+impl PackageBackend for Apk {
+    fn name(&self) -> &'static str { "apk" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("apk", &["add"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("apk", &["del"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> {
+        vec![self.privileged(CommandStep::new("apk", ["update"])), self.privileged(CommandStep::new("apk", ["upgrade"]))]
+    }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("apk", ["search", "-v", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("apk", ["list", "--installed"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("apk", ["info", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("apk", ["info", "-e", package]) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_apk(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::read_apk_db(Path::new("/lib/apk/db/installed"), Path::new("/etc/apk/world")) }
+    fn simulate_command(&self, operation: &OperationType, packages: &[String]) -> Option<CommandStep> {
+        simulation_args(operation, &["add", "--simulate"], &["del", "--simulate"], &["upgrade", "--simulate"])
+            .map(|args| self.privileged(with_packages("apk", args, packages)))
+    }
+    fn parse_simulation(&self, _operation: &OperationType, output: &str) -> SimulationReport { simulation::parse_apk(output) }
+    fn install_versions_command(&self, packages: &[(String, String)]) -> Option<Vec<CommandStep>> {
+        Some(vec![self.privileged(with_versions("apk", &["add"], packages, "="))])
+    }
+}
+
+impl PackageBackend for Xbps {
+    fn name(&self) -> &'static str { "xbps" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("xbps-install", &["-y"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("xbps-remove", &["-y"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![self.privileged(CommandStep::new("xbps-install", ["-Suy"]))] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("xbps-query", ["-Rs", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("xbps-query", ["-l"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("xbps-query", ["-RS", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("xbps-query", [package]) }
+    fn is_available_command(&self, package: &str) -> Option<CommandStep> { Some(CommandStep::new("xbps-query", ["-R", package])) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_xbps(output) }
+    fn installed_packages(&self) -> Result<Vec<InstalledPackage>> { inventory::query_xbps() }
+}
+
+impl PackageBackend for AurHelper {
+    fn name(&self) -> &'static str { self.0 }
+    // AUR helpers escalate privileges themselves and refuse to run as root
+    fn requires_sudo(&self) -> bool { false }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![with_packages(self.0, &["-S", "--noconfirm"], packages)] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![with_packages(self.0, &["-R", "--noconfirm"], packages)] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![CommandStep::new(self.0, ["-Syu", "--noconfirm"])] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new(self.0, ["-Ss", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new(self.0, ["-Qm"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new(self.0, ["-Si", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("pacman", ["-Q", package]) }
+    fn parse_search_output(&self, output: &str) -> Vec<PackageSearchHit> { package_search::parse_pacman(output) }
+}
+
+impl PackageBackend for Flatpak {
+    fn name(&self) -> &'static str { "flatpak" }
+    fn requires_sudo(&self) -> bool { false }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![with_packages("flatpak", &["install", "-y"], packages)] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![with_packages("flatpak", &["uninstall", "-y"], packages)] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![CommandStep::new("flatpak", ["update", "-y"])] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("flatpak", ["search", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("flatpak", ["list"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("flatpak", ["info", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("flatpak", ["info", package]) }
+}
+
+impl PackageBackend for Snap {
+    fn name(&self) -> &'static str { "snap" }
+    fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("snap", &["install"], packages))] }
+    fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![self.privileged(with_packages("snap", &["remove"], packages))] }
+    fn update_command(&self) -> Vec<CommandStep> { vec![self.privileged(CommandStep::new("snap", ["refresh"]))] }
+    fn search_command(&self, query: &str) -> CommandStep { CommandStep::new("snap", ["find", query]) }
+    fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new("snap", ["list"]) }
+    fn info_command(&self, package: &str) -> CommandStep { CommandStep::new("snap", ["info", package]) }
+    fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new("snap", ["list", package]) }
+}
+
 pub struct PackageManagerRegistry {
-    managers: HashMap<String, PackageManager>,
+    managers: HashMap<String, Box<dyn PackageBackend>>,
 }
 
 impl PackageManagerRegistry {
@@ -45,91 +475,39 @@ impl PackageManagerRegistry {
     }
 
     fn initialize_default_managers(&mut self) {
-        // Pacman (Arch Linux)
-        self.managers.insert("pacman".to_string(), PackageManager {
-            name: "pacman".to_string(),
-            install_cmd: "sudo pacman -S --noconfirm".to_string(),
-            remove_cmd: "sudo pacman -R".to_string(),
-            search_cmd: "pacman -Ss".to_string(),
-            update_cmd: "sudo pacman -Syu".to_string(),
-            list_cmd: "pacman -Q".to_string(),
-            info_cmd: "pacman -Si".to_string(),
-            requires_sudo: true,
-        });
-
-        // APT (Debian/Ubuntu)
-        self.managers.insert("apt".to_string(), PackageManager {
-            name: "apt".to_string(),
-            install_cmd: "sudo apt update && sudo apt install -y".to_string(),
-            remove_cmd: "sudo apt remove".to_string(),
-            search_cmd: "apt search".to_string(),
-            update_cmd: "sudo apt update && sudo apt upgrade -y".to_string(),
-            list_cmd: "apt list --installed".to_string(),
-            info_cmd: "apt show".to_string(),
-            requires_sudo: true,
-        });
-
-        // DNF (Fedora/RHEL)
-        self.managers.insert("dnf".to_string(), PackageManager {
-            name: "dnf".to_string(),
-            install_cmd: "sudo dnf install -y".to_string(),
-            remove_cmd: "sudo dnf remove".to_string(),
-            search_cmd: "dnf search".to_string(),
-            update_cmd: "sudo dnf update -y".to_string(),
-            list_cmd: "dnf list installed".to_string(),
-            info_cmd: "dnf info".to_string(),
-            requires_sudo: true,
-        });
-
-        // Zypper (openSUSE)
-        self.managers.insert("zypper".to_string(), PackageManager {
-            name: "zypper".to_string(),
-            install_cmd: "sudo zypper install -y".to_string(),
-            remove_cmd: "sudo zypper remove".to_string(),
-            search_cmd: "zypper search".to_string(),
-            update_cmd: "sudo zypper update -y".to_string(),
-            list_cmd: "zypper search --installed".to_string(),
-            info_cmd: "zypper info".to_string(),
-            requires_sudo: true,
-        });
-
-        // Portage (Gentoo)
-        self.managers.insert("portage".to_string(), PackageManager {
-            name: "portage".to_string(),
-            install_cmd: "sudo emerge".to_string(),
-            remove_cmd: "sudo emerge --unmerge".to_string(),
-            search_cmd: "emerge --search".to_string(),
-            update_cmd: "sudo emerge --sync && sudo emerge -uDN @world".to_string(),
-            list_cmd: "qlist -I".to_string(),
-            info_cmd: "emerge --info".to_string(),
-            requires_sudo: true,
-        });
-
-        // Nix (NixOS)
-        self.managers.insert("nix".to_string(), PackageManager {
-            name: "nix".to_string(),
-            install_cmd: "nix-env -i".to_string(),
-            remove_cmd: "nix-env -e".to_string(),
-            search_cmd: "nix search".to_string(),
-            update_cmd: "nix-channel --update && nix-env -u".to_string(),
-            list_cmd: "nix-env -q".to_string(),
-            info_cmd: "nix-env -qa --description".to_string(),
-            requires_sudo: false,
-        });
-
-        // APK (Alpine Linux)
-        self.managers.insert("apk".to_string(), PackageManager {
-            name: "apk".to_string(),
-            install_cmd: "sudo apk add".to_string(),
-            remove_cmd: "sudo apk del".to_string(),
-            search_cmd: "apk search".to_string(),
-            update_cmd: "sudo apk update && sudo apk upgrade".to_string(),
-            list_cmd: "apk list --installed".to_string(),
-            info_cmd: "apk info".to_string(),
-            requires_sudo: true,
-        });
+        // Native package managers
+        self.register(Box::new(Pacman));
+        self.register(Box::new(Apt));
+        self.register(Box::new(Dnf));
+        self.register(Box::new(Zypper));
+        self.register(Box::new(Portage));
+        self.register(Box::new(Nix));
+        self.register(Box::new(Apk));
+        self.register(Box::new(Xbps));
+
+        // Alternative sources
+        self.register(Box::new(AurHelper("paru")));
+        self.register(Box::new(AurHelper("yay")));
+        self.register(Box::new(Flatpak));
+        self.register(Box::new(Snap));
     }
 
+    /// Register a backend, replacing any existing backend with the same name
+    pub fn register(&mut self, backend: Box<dyn PackageBackend>) {
+        self.managers.insert(backend.name().to_string(), backend);
+    }
+
+    /// Look up a backend by package manager name
+    pub fn get(&self, name: &str) -> Option<&dyn PackageBackend> {
+        self.managers.get(name).map(|backend| backend.as_ref())
+    }
+
+    /// Names of all registered backends, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.managers.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        names
+    }
 }
 
 impl Default for PackageManagerRegistry {
@@ -138,3 +516,103 @@ impl Default for PackageManagerRegistry {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_contains_native_backends() {
+        let registry = PackageManagerRegistry::new();
+        for name in ["pacman", "apt", "dnf", "zypper", "portage", "nix", "apk", "xbps"] {
+            assert!(registry.get(name).is_some(), "missing backend {name}");
+        }
+        assert!(registry.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_every_backend_supports_every_operation() {
+        let registry = PackageManagerRegistry::new();
+        let packages = vec!["vim".to_string(), "git".to_string()];
+        let mentions = |step: &CommandStep, word: &str| step.args.iter().any(|arg| arg.contains(word));
+        for name in registry.names() {
+            let backend = registry.get(name).unwrap();
+            assert!(backend.install_command(&packages).iter().any(|s| mentions(s, "vim") && mentions(s, "git")), "{name} install");
+            assert!(backend.remove_command(&packages).iter().any(|s| mentions(s, "vim")), "{name} remove");
+            assert!(mentions(&backend.search_command("vim"), "vim"), "{name} search");
+            assert!(mentions(&backend.info_command("vim"), "vim"), "{name} info");
+            assert!(mentions(&backend.is_installed_command("vim"), "vim"), "{name} is-installed");
+            assert!(!backend.update_command().is_empty(), "{name} update");
+            assert!(!backend.list_command(false).program.is_empty(), "{name} list");
+        }
+    }
+
+    #[test]
+    fn test_sudo_prefix() {
+        let registry = PackageManagerRegistry::new();
+        let packages = vec!["vim".to_string()];
+        let render = |name: &str| crate::executor::render_steps(&registry.get(name).unwrap().install_command(&packages));
+        assert_eq!(render("apk"), "sudo apk add vim");
+        assert_eq!(render("nix"), "nix-env -iA nixpkgs.vim");
+        assert_eq!(render("paru"), "paru -S --noconfirm vim");
+        assert_eq!(render("pacman"), "sudo pacman -S --noconfirm vim");
+        assert_eq!(render("dnf"), "sudo dnf install -y vim");
+        assert_eq!(render("zypper"), "sudo zypper --non-interactive install vim");
+        assert_eq!(render("xbps"), "sudo xbps-install -y vim");
+        let remove = |name: &str| crate::executor::render_steps(&registry.get(name).unwrap().remove_command(&packages));
+        assert_eq!(remove("apt"), "sudo apt remove -y vim");
+        assert_eq!(remove("flatpak"), "flatpak uninstall -y vim");
+        assert_eq!(crate::executor::render_steps(&registry.get("pacman").unwrap().update_command()), "sudo pacman -Syu --noconfirm");
+        assert_eq!(crate::executor::render_steps(&registry.get("apt").unwrap().update_command()), "sudo apt update && sudo apt upgrade -y");
+    }
+
+    #[test]
+    fn test_simulate_commands() {
+        let registry = PackageManagerRegistry::new();
+        let packages = vec!["vim".to_string()];
+        let simulate = |name: &str, operation: OperationType| {
+            registry.get(name).unwrap().simulate_command(&operation, &packages).map(|step| step.to_string())
+        };
+        assert_eq!(simulate("pacman", OperationType::Install).as_deref(), Some("pacman -S --print --print-format '%n %v %s' vim"));
+        assert_eq!(simulate("apt", OperationType::Remove).as_deref(), Some("apt-get -s remove vim"));
+        assert_eq!(simulate("dnf", OperationType::Install).as_deref(), Some("sudo dnf install --assumeno vim"));
+        assert_eq!(simulate("zypper", OperationType::Install).as_deref(), Some("sudo zypper --non-interactive install --dry-run vim"));
+        assert_eq!(simulate("apk", OperationType::Remove).as_deref(), Some("sudo apk del --simulate vim"));
+        assert_eq!(simulate("apt", OperationType::Search), None);
+        assert_eq!(simulate("flatpak", OperationType::Install), None);
+    }
+
+    #[test]
+    fn test_package_undo_commands() {
+        let registry = PackageManagerRegistry::new();
+        let pinned = vec![("vim".to_string(), "2:9.0-1".to_string())];
+        let render = |name: &str| registry.get(name).unwrap().install_versions_command(&pinned).map(|steps| crate::executor::render_steps(&steps));
+        assert_eq!(render("apt").as_deref(), Some("sudo apt-get install -y --allow-downgrades vim=2:9.0-1"));
+        assert_eq!(render("dnf").as_deref(), Some("sudo dnf install -y vim-2:9.0-1"));
+        assert_eq!(render("snap"), None);
+
+        let cache = tempfile::tempdir().unwrap();
+        for file in ["vim-9.0-1-x86_64.pkg.tar.zst", "vim-9.0-1-x86_64.pkg.tar.zst.sig", "vim-runtime-9.0-1-x86_64.pkg.tar.zst"] {
+            std::fs::write(cache.path().join(file), "").unwrap();
+        }
+        assert_eq!(pacman_cached_package(cache.path(), "vim", "9.0-1"), Some(cache.path().join("vim-9.0-1-x86_64.pkg.tar.zst")));
+        assert_eq!(pacman_cached_package(cache.path(), "vim", "8.2-1"), None);
+
+        let history = "ID     | Command line             | Date and time    | Action(s)      | Altered\n\
+            -------------------------------------------------------------------------------\n\
+                42 | install htop             | 2024-05-01 10:00 | Install        |    1\n\
+                41 | upgrade                  | 2024-04-30 09:00 | Upgrade        |   12\n";
+        assert_eq!(parse_dnf_history(history).as_deref(), Some("42"));
+        assert_eq!(crate::executor::render_steps(&registry.get("dnf").unwrap().undo_transactions_command("42").unwrap()),
+            "sudo dnf history rollback -y 42");
+    }
+
+    #[test]
+    fn test_validate_package_name() {
+        for name in ["vim", "python3.12", "g++", "libc6:amd64", "app-editors/vim", "=dev-lang/rust-1.75.0", "org.gimp.GIMP"] {
+            assert!(validate_package_name(name).is_ok(), "{name}");
+        }
+        for name in ["", "foo;rm -rf ~", "--force", "vim && reboot", "$(id)", "a b", "pkg`id`"] {
+            assert!(validate_package_name(name).is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use serde::{Deserialize, Serialize};
+
+/// A single package returned by a search, independent of the backend that found it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageSearchHit {
+    pub name: String,
+    pub version: Option<String>,
+    pub repo: Option<String>,
+    pub description: Option<String>,
+    pub installed: bool,
+}
+
+impl PackageSearchHit {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            version: None,
+            repo: None,
+            description: None,
+            installed: false,
+        }
+    }
+}
+
+/// Parse `pacman -Ss` (and `paru`/`yay -Ss`) output:
+///
+/// ```text
+/// extra/vim 9.1.0-1 [installed]
+///     Vi Improved, a highly configurable text editor
+/// ```
+pub fn parse_pacman(output: &str) -> Vec<PackageSearchHit> {
+    let mut hits: Vec<PackageSearchHit> = Vec::new();
+
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            if let Some(hit) = hits.last_mut()
+                && hit.description.is_none()
+            {
+                hit.description = Some(line.trim().to_string());
+            }
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let Some((repo, name)) = fields.next().and_then(|f| f.split_once('/')) else {
+            continue;
+        };
+
+        let mut hit = PackageSearchHit::new(name);
+        hit.repo = Some(repo.to_string());
+        hit.version = fields.next().map(str::to_string);
+        hit.installed = line.to_lowercase().contains("[installed");
+        hits.push(hit);
+    }
+
+    hits
+}
+
+/// Parse `apt search` output:
+///
+/// ```text
+/// vim/jammy-updates,now 2:8.2.3995-1ubuntu2.13 amd64 [installed]
+///   Vi IMproved - enhanced vi editor
+/// ```
+pub fn parse_apt(output: &str) -> Vec<PackageSearchHit> {
+    let mut hits: Vec<PackageSearchHit> = Vec::new();
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            if let Some(hit) = hits.last_mut()
+                && hit.description.is_none()
+            {
+                hit.description = Some(line.trim().to_string());
+            }
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let Some((name, repo)) = fields.next().and_then(|f| f.split_once('/')) else {
+            // "Sorting...", "Full Text Search..." and similar progress lines
+            continue;
+        };
+
+        let mut hit = PackageSearchHit::new(name);
+        hit.repo = Some(repo.split(',').next().unwrap_or(repo).to_string());
+        hit.version = fields.next().map(str::to_string);
+        hit.installed = line.contains("[installed");
+        hits.push(hit);
+    }
+
+    hits
+}
+
+/// Parse `dnf search` output:
+///
+/// ```text
+/// ======== Name Exactly Matched: vim ========
+/// vim-enhanced.x86_64 : A version of the VIM editor
+/// ```
+pub fn parse_dnf(output: &str) -> Vec<PackageSearchHit> {
+    let mut hits = Vec::new();
+
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with('=') || line.starts_with("Last metadata") || line.starts_with("Matched fields") {
+            continue;
+        }
+
+        // dnf4 separates name and summary with " : ", dnf5 with a tab
+        let Some((package, summary)) = line.split_once(" : ").or_else(|| line.split_once('\t')) else {
+            continue;
+        };
+
+        let package = package.trim();
+        let name = match package.rsplit_once('.') {
+            Some((name, _arch)) => name,
+            None => package,
+        };
+
+        let mut hit = PackageSearchHit::new(name);
+        hit.description = Some(summary.trim().to_string());
+        hits.push(hit);
+    }
+
+    hits
+}
+
+/// Parse `zypper search` table output:
+///
+/// ```text
+/// S  | Name     | Summary     | Type
+/// ---+----------+-------------+--------
+/// i+ | vim      | Vi IMproved | package
+/// ```
+pub fn parse_zypper(output: &str) -> Vec<PackageSearchHit> {
+    let mut hits = Vec::new();
+    let mut in_table = false;
+
+    for line in output.lines() {
+        if line.starts_with("---") {
+            in_table = true;
+            continue;
+        }
+        if !in_table {
+            continue;
+        }
+
+        let columns: Vec<&str> = line.split('|').map(str::trim).collect();
+        if columns.len() < 3 || columns[1].is_empty() {
+            continue;
+        }
+        if columns.get(3).is_some_and(|kind| !kind.is_empty() && *kind != "package") {
+            continue;
+        }
+
+        let mut hit = PackageSearchHit::new(columns[1]);
+        hit.description = Some(columns[2].to_string()).filter(|d| !d.is_empty());
+        hit.installed = columns[0].starts_with('i');
+        hits.push(hit);
+    }
+
+    hits
+}
+
+/// Parse `apk search -v` output (`name-1.2.3-r0 - description`)
+pub fn parse_apk(output: &str) -> Vec<PackageSearchHit> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let (package, description) = match line.split_once(" - ") {
+                Some((package, description)) => (package.trim(), Some(description.trim().to_string())),
+                None => (line.trim(), None),
+            };
+
+            // apk versions always end in "<version>-r<pkgrel>"
+            let mut parts = package.rsplitn(3, '-');
+            let pkgrel = parts.next()?;
+            let version = parts.next()?;
+            let name = parts.next()?;
+
+            let mut hit = PackageSearchHit::new(name);
+            hit.version = Some(format!("{version}-{pkgrel}"));
+            hit.description = description;
+            Some(hit)
+        })
+        .collect()
+}
+
+/// Parse `xbps-query -Rs` output (`[*] vim-9.0_1  Vi Improved`)
+pub fn parse_xbps(output: &str) -> Vec<PackageSearchHit> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let (marker, rest) = line.split_once(' ')?;
+            let rest = rest.trim_start();
+            let (package, description) = match rest.split_once(char::is_whitespace) {
+                Some((package, description)) => (package, Some(description.trim().to_string())),
+                None => (rest, None),
+            };
+            let (name, version) = package.rsplit_once('-')?;
+
+            let mut hit = PackageSearchHit::new(name);
+            hit.version = Some(version.to_string());
+            hit.description = description;
+            hit.installed = marker == "[*]";
+            Some(hit)
+        })
+        .collect()
+}
+
+/// Parse `emerge --search` output, which prints one indented block per package
+pub fn parse_portage(output: &str) -> Vec<PackageSearchHit> {
+    let mut hits: Vec<PackageSearchHit> = Vec::new();
+
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if let Some(atom) = trimmed.strip_prefix('*') {
+            let atom = atom.trim();
+            if atom.contains('/') {
+                let atom = atom.trim_end_matches("[ Masked ]").trim();
+                let (category, name) = atom.split_once('/').unwrap_or(("", atom));
+                let mut hit = PackageSearchHit::new(name);
+                hit.repo = Some(category.to_string());
+                hits.push(hit);
+            }
+            continue;
+        }
+
+        let Some(hit) = hits.last_mut() else {
+            continue;
+        };
+
+        if let Some(version) = trimmed.strip_prefix("Latest version available:") {
+            hit.version = Some(version.trim().to_string());
+        } else if let Some(installed) = trimmed.strip_prefix("Latest version installed:") {
+            hit.installed = !installed.contains("Not Installed");
+        } else if let Some(description) = trimmed.strip_prefix("Description:") {
+            hit.description = Some(description.trim().to_string());
+        }
+    }
+
+    hits
+}
+
+/// Parse `nix-env -qaP` output (`nixpkgs.vim  vim-9.0.1`)
+pub fn parse_nix(output: &str) -> Vec<PackageSearchHit> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let attr = fields.next()?;
+            let package = fields.next()?;
+
+            let (repo, name) = attr.split_once('.').unwrap_or(("nixpkgs", attr));
+            let mut hit = PackageSearchHit::new(name);
+            hit.repo = Some(repo.to_string());
+            // Like nix itself, the version starts at the first dash followed by a digit
+            hit.version = package
+                .match_indices('-')
+                .map(|(index, _)| &package[index + 1..])
+                .find(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+                .map(str::to_string);
+            Some(hit)
+        })
+        .collect()
+}
+
+/// Render search hits as an aligned plain-text table
+pub fn render_table(hits: &[PackageSearchHit]) -> String {
+    let name_width = hits.iter().map(|h| h.name.len()).max().unwrap_or(0).max(4);
+    let version_width = hits
+        .iter()
+        .map(|h| h.version.as_deref().unwrap_or("-").len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    let repo_width = hits
+        .iter()
+        .map(|h| h.repo.as_deref().unwrap_or("-").len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+
+    let mut output = format!(
+        "  {:<name_width$}  {:<version_width$}  {:<repo_width$}  DESCRIPTION\n",
+        "NAME", "VERSION", "REPO"
+    );
+    for hit in hits {
+        output.push_str(&format!(
+            "{} {:<name_width$}  {:<version_width$}  {:<repo_width$}  {}\n",
+            if hit.installed { "*" } else { " " },
+            hit.name,
+            hit.version.as_deref().unwrap_or("-"),
+            hit.repo.as_deref().unwrap_or("-"),
+            hit.description.as_deref().unwrap_or(""),
+        ));
+    }
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pacman() {
+        let output = "extra/vim 9.1.0016-1 [installed]\n    Vi Improved, a highly configurable text editor\nextra/gvim 9.1.0016-1\n    Vi Improved, GTK3 GUI\n";
+        let hits = parse_pacman(output);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].name, "vim");
+        assert_eq!(hits[0].repo.as_deref(), Some("extra"));
+        assert_eq!(hits[0].version.as_deref(), Some("9.1.0016-1"));
+        assert!(hits[0].installed);
+        assert_eq!(hits[1].description.as_deref(), Some("Vi Improved, GTK3 GUI"));
+        assert!(!hits[1].installed);
+    }
+
+    #[test]
+    fn test_parse_apt() {
+        let output = "Sorting...\nFull Text Search...\nvim/jammy-updates,now 2:8.2.3995-1ubuntu2.13 amd64 [installed]\n  Vi IMproved - enhanced vi editor\n\nvim-tiny/jammy 2:8.2.3995-1ubuntu2 amd64\n  Vi IMproved - compact version\n";
+        let hits = parse_apt(output);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].name, "vim");
+        assert_eq!(hits[0].repo.as_deref(), Some("jammy-updates"));
+        assert_eq!(hits[0].version.as_deref(), Some("2:8.2.3995-1ubuntu2.13"));
+        assert!(hits[0].installed);
+        assert_eq!(hits[1].description.as_deref(), Some("Vi IMproved - compact version"));
+    }
+
+    #[test]
+    fn test_parse_dnf_zypper_apk() {
+        let dnf = "Last metadata expiration check: 0:01:02 ago.\n===== Name Exactly Matched: vim =====\nvim-enhanced.x86_64 : A version of the VIM editor\n";
+        let hits = parse_dnf(dnf);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].name, "vim-enhanced");
+
+        let zypper = "Loading repository data...\n\nS  | Name     | Summary     | Type\n---+----------+-------------+--------\ni+ | vim      | Vi IMproved | package\n   | vim-data | Data files  | package\n";
+        let hits = parse_zypper(zypper);
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].installed);
+        assert!(!hits[1].installed);
+
+        let apk = "vim-9.0.2073-r0 - Improved vi-style text editor\nvim-doc-9.0.2073-r0 - Improved vi-style text editor (documentation)\n";
+        let hits = parse_apk(apk);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[1].name, "vim-doc");
+        assert_eq!(hits[1].version.as_deref(), Some("9.0.2073-r0"));
+    }
+
+    #[test]
+    fn test_parse_xbps() {
+        let output = "[*] vim-9.1.0764_1         Vi IMproved
+[-] vim-huge-9.1.0764_1    Vi IMproved - huge variant
+[-] vim-colorschemes-1.0_2
+";
+        let hits = parse_xbps(output);
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].name, "vim");
+        assert_eq!(hits[0].version.as_deref(), Some("9.1.0764_1"));
+        assert!(hits[0].installed);
+        assert_eq!(hits[1].name, "vim-huge");
+        assert_eq!(hits[1].description.as_deref(), Some("Vi IMproved - huge variant"));
+        assert!(!hits[1].installed);
+        assert_eq!(hits[2].description, None);
+    }
+
+    #[test]
+    fn test_parse_portage() {
+        let output = "\
+[ Results for search key : vim ]
+Searching...
+
+*  app-editors/vim
+      Latest version available: 9.1.0697
+      Latest version installed: 9.1.0697
+      Size of files: 17,411 KiB
+      Homepage:      https://vim.org/ https://github.com/vim/vim
+      Description:   Vim, an improved vi-style text editor
+      License:       vim
+
+*  app-editors/gvim [ Masked ]
+      Latest version available: 9.1.0697
+      Latest version installed: [ Not Installed ]
+      Description:   GUI version of the Vim text editor
+
+[ Applications found : 2 ]
+";
+        let hits = parse_portage(output);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].name, "vim");
+        assert_eq!(hits[0].repo.as_deref(), Some("app-editors"));
+        assert_eq!(hits[0].version.as_deref(), Some("9.1.0697"));
+        assert_eq!(hits[0].description.as_deref(), Some("Vim, an improved vi-style text editor"));
+        assert!(hits[0].installed);
+        assert_eq!(hits[1].name, "gvim");
+        assert!(!hits[1].installed);
+    }
+
+    #[test]
+    fn test_parse_nix() {
+        let output = "nixpkgs.vim                 vim-9.1.0707
+nixpkgs.vimPlugins.vim-nix  vimplugin-vim-nix-2024-05-25
+nixos.neovim                neovim-unwrapped
+";
+        let hits = parse_nix(output);
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].name, "vim");
+        assert_eq!(hits[0].repo.as_deref(), Some("nixpkgs"));
+        assert_eq!(hits[0].version.as_deref(), Some("9.1.0707"));
+        assert_eq!(hits[1].name, "vimPlugins.vim-nix");
+        assert_eq!(hits[1].version.as_deref(), Some("2024-05-25"));
+        assert_eq!(hits[2].repo.as_deref(), Some("nixos"));
+        assert_eq!(hits[2].version, None);
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::process::Command;
 use crate::config_manager::Config;
+use crate::package_search::PackageSearchHit;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PackageSource {
@@ -21,6 +22,26 @@ pub struct PackageSourceInfo {
     pub version: Option<String>,
 }
 
+impl From<PackageSourceInfo> for PackageSearchHit {
+    fn from(info: PackageSourceInfo) -> Self {
+        let repo = match info.source {
+            PackageSource::Native => "native",
+            PackageSource::AUR => "aur",
+            PackageSource::Flatpak => "flatpak",
+            PackageSource::Snap => "snap",
+            PackageSource::AppImage => "appimage",
+        };
+
+        PackageSearchHit {
+            name: info.package_name,
+            version: info.version,
+            repo: Some(repo.to_string()),
+            description: info.description,
+            installed: false,
+        }
+    }
+}
+
 pub struct PackageSourceManager {
     config: Config,
 }
@@ -81,6 +102,15 @@ impl PackageSourceManager {
         results
     }
 
+    /// Search alternative sources and return the results as structured hits
+    pub async fn search_hits(&self, query: &str) -> Vec<PackageSearchHit> {
+        self.search_package(query)
+            .await
+            .into_iter()
+            .map(PackageSearchHit::from)
+            .collect()
+    }
+
     /// Get the best available source for a package based on configuration preferences
     pub async fn get_best_source(&self, package_name: &str) -> Option<PackageSourceInfo> {
         let sources = self.search_package(package_name).await;
@@ -0,0 +1,788 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::Chars;
+use crate::agent::SafetyLevel;
+use crate::system_config::{AgentConfig, SystemConfig};
+
+/// Where administrators put their own rules
+pub const POLICY_PATH: &str = "/etc/linux-distro-agent/policy.toml";
+
+/// Rules that apply after any rules in the policy file, unless the file
+/// sets `include_defaults = false`
+const DEFAULT_RULES: &str = r#"
+[[rule]]
+name = "remove-root"
+action = "deny"
+programs = ["rm"]
+paths = ["/", "/*"]
+reason = "Removes the root filesystem or a top-level directory"
+
+[[rule]]
+name = "format-filesystem"
+action = "deny"
+programs = ["mkfs", "mkfs.*", "mke2fs", "mkswap", "wipefs"]
+reason = "Formats a filesystem"
+
+[[rule]]
+name = "find-delete-critical"
+action = "deny"
+programs = ["find"]
+args = ["-delete"]
+paths = ["/", "/boot", "/boot/**", "/etc", "/etc/**", "/usr", "/usr/**"]
+reason = "Deletes files under a critical system path"
+
+[[rule]]
+name = "raw-disk-write"
+action = "deny"
+programs = ["dd"]
+args = ["of=/dev/*"]
+reason = "Writes directly to a block device"
+
+[[rule]]
+name = "critical-path-removal"
+action = "deny"
+programs = ["rm", "shred", "truncate", "dd"]
+paths = ["/boot", "/boot/**", "/etc", "/etc/**", "/usr", "/usr/**"]
+reason = "Destroys files under a critical system path"
+
+[[rule]]
+name = "destructive"
+approval = "Dangerous"
+programs = ["rm", "dd", "shred", "fdisk", "sfdisk", "gdisk", "parted"]
+reason = "Deletes data or changes partitions"
+
+[[rule]]
+name = "find-delete"
+approval = "Dangerous"
+programs = ["find"]
+args = ["-delete"]
+reason = "Deletes every file it finds"
+
+[[rule]]
+name = "unlisted-caller"
+approval = "Risky"
+allowed_caller = false
+reason = "Caller is not in agent.allowed_users or agent.allowed_groups"
+
+[[rule]]
+name = "privileged"
+approval = "Risky"
+privileged = true
+reason = "Runs with elevated privileges"
+
+[[rule]]
+name = "ownership-and-permissions"
+approval = "Risky"
+programs = ["chmod", "chown", "chgrp", "setfacl", "su"]
+reason = "Changes ownership or permissions"
+
+[[rule]]
+name = "agent-system-tasks"
+approval = "Risky"
+programs = ["linux-distro-agent", "lda"]
+args = ["build-distro", "security"]
+reason = "Builds a system image or audits the whole system"
+
+[[rule]]
+name = "package-changes"
+approval = "Moderate"
+programs = ["apt", "apt-get", "dnf", "yum", "zypper", "pacman", "apk", "xbps-install", "xbps-remove", "emerge", "paru", "yay", "flatpak", "snap", "nix-env"]
+args = ["install", "remove", "purge", "upgrade", "dist-upgrade", "full-upgrade", "autoremove", "add", "del", "refresh", "uninstall", "-S*", "-R*", "-U*", "-i*", "-e"]
+reason = "Changes installed packages"
+"#;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    #[default]
+    Allow,
+    Deny,
+}
+
+/// One policy rule. Every condition that is set must match; unset
+/// conditions match anything.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub action: RuleAction,
+    /// Safety level, and so approval, that allowed commands are given
+    pub approval: Option<SafetyLevel>,
+    /// Globs matched against the program name
+    #[serde(default)]
+    pub programs: Vec<String>,
+    /// Globs of which at least one argument must match
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Path globs (`*` within a directory, `**` across) of which at least
+    /// one path argument must match
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Match only commands run through sudo/doas (true) or not (false)
+    pub privileged: Option<bool>,
+    #[serde(default)]
+    pub users: Vec<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Match only callers that are (true) or aren't (false) listed in
+    /// `agent.allowed_users` / `agent.allowed_groups`
+    pub allowed_caller: Option<bool>,
+    pub reason: Option<String>,
+    #[serde(skip)]
+    builtin: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default = "default_true")]
+    include_defaults: bool,
+    critical_paths: Option<Vec<String>>,
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Ordered allow/deny rules deciding whether, and at what safety level,
+/// the agent may run a command. The first matching rule decides.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+    /// Paths whose files are snapshotted before a task changes them
+    pub critical_paths: Vec<String>,
+    /// File the rules came from, if any
+    pub source: Option<PathBuf>,
+    allowed_users: Vec<String>,
+    allowed_groups: Vec<String>,
+}
+
+/// A single program invocation parsed out of a command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invocation {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Run through sudo or doas
+    pub privileged: bool,
+    /// Part of the command is only known when the shell runs it: a command
+    /// substitution, a script read from a file or stdin, arguments from
+    /// `xargs`, or text the parser couldn't make sense of
+    pub dynamic: bool,
+}
+
+/// Who is asking the agent to run something
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub user: String,
+    pub groups: Vec<String>,
+}
+
+/// How the policy judged one invocation
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub invocation: Invocation,
+    /// Deciding rule and whether it is built in; `None` when nothing matched
+    pub rule: Option<(String, bool)>,
+    pub allowed: bool,
+    pub level: SafetyLevel,
+    pub reason: Option<String>,
+}
+
+/// How the policy judged a whole command line
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub verdicts: Vec<Verdict>,
+}
+
+impl Decision {
+    pub fn allowed(&self) -> bool {
+        self.verdicts.iter().all(|verdict| verdict.allowed)
+    }
+
+    /// The highest safety level of any invocation
+    pub fn level(&self) -> SafetyLevel {
+        self.verdicts.iter().map(|verdict| verdict.level.clone()).max().unwrap_or(SafetyLevel::Safe)
+    }
+
+    /// The verdict that decided the outcome: the first denial, otherwise the
+    /// invocation with the highest level
+    pub fn deciding(&self) -> Option<&Verdict> {
+        self.verdicts.iter().find(|verdict| !verdict.allowed)
+            .or_else(|| self.verdicts.iter().rev().max_by_key(|verdict| verdict.level.clone()))
+    }
+
+    /// One-line explanation, suitable for error messages
+    pub fn summary(&self) -> String {
+        match self.deciding() {
+            Some(verdict) => {
+                let rule = verdict.rule.as_ref().map(|(name, _)| format!("rule {name}")).unwrap_or_else(|| "no rule".to_string());
+                match &verdict.reason {
+                    Some(reason) => format!("{}: {} ({})", verdict.invocation.program, reason, rule),
+                    None => format!("{} ({})", verdict.invocation.program, rule),
+                }
+            }
+            None => "empty command".to_string(),
+        }
+    }
+}
+
+impl Default for Policy {
+    /// The built-in rules and the default agent configuration
+    fn default() -> Self {
+        let agent = SystemConfig::default().agent;
+        Self::from_parts(None, PolicyFile { include_defaults: true, critical_paths: None, rules: Vec::new() }, &agent)
+            .expect("built-in policy rules are valid")
+    }
+}
+
+impl Policy {
+    /// Load `/etc/linux-distro-agent/policy.toml`, falling back to the
+    /// built-in rules when it doesn't exist
+    pub fn load(agent: &AgentConfig) -> Result<Self> {
+        let path = Path::new(POLICY_PATH);
+        if path.exists() {
+            Self::load_from(path, agent)
+        } else {
+            Self::from_parts(None, PolicyFile { include_defaults: true, critical_paths: None, rules: Vec::new() }, agent)
+        }
+    }
+
+    pub fn load_from(path: &Path, agent: &AgentConfig) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read policy file: {path:?}"))?;
+        let file: PolicyFile = toml::from_str(&content)
+            .with_context(|| format!("Invalid policy file: {path:?}"))?;
+        Self::from_parts(Some(path.to_path_buf()), file, agent)
+    }
+
+    fn from_parts(source: Option<PathBuf>, file: PolicyFile, agent: &AgentConfig) -> Result<Self> {
+        let mut rules = file.rules;
+        if file.include_defaults {
+            let defaults: PolicyFile = toml::from_str(DEFAULT_RULES)?;
+            rules.extend(defaults.rules.into_iter().map(|rule| Rule { builtin: true, ..rule }));
+        }
+        Ok(Self {
+            rules,
+            critical_paths: file.critical_paths.unwrap_or_else(|| {
+                ["/boot", "/etc", "/usr", "/var"].iter().map(|path| path.to_string()).collect()
+            }),
+            source,
+            allowed_users: agent.allowed_users.clone(),
+            allowed_groups: agent.allowed_groups.clone(),
+        })
+    }
+
+    /// Judge every invocation in a shell command line
+    pub fn evaluate(&self, command: &str, caller: &Caller) -> Decision {
+        let verdicts = parse_command_line(command)
+            .into_iter()
+            .map(|invocation| {
+                let mut verdict = match self.rules.iter().find(|rule| self.matches(rule, &invocation, caller)) {
+                    Some(rule) => Verdict {
+                        allowed: rule.action == RuleAction::Allow,
+                        level: rule.approval.clone().unwrap_or(SafetyLevel::Safe),
+                        reason: rule.reason.clone(),
+                        rule: Some((rule.name.clone(), rule.builtin)),
+                        invocation,
+                    },
+                    None => Verdict { invocation, rule: None, allowed: true, level: SafetyLevel::Safe, reason: None },
+                };
+                // What the rules can't see is never safe
+                if verdict.invocation.dynamic && verdict.allowed && verdict.level < SafetyLevel::Risky {
+                    verdict.level = SafetyLevel::Risky;
+                    verdict.reason = Some("Runs commands that are only known when the shell runs it".to_string());
+                    verdict.rule = Some(("dynamic-command".to_string(), true));
+                }
+                verdict
+            })
+            .collect();
+        Decision { verdicts }
+    }
+
+    fn matches(&self, rule: &Rule, invocation: &Invocation, caller: &Caller) -> bool {
+        let program_matches = rule.programs.is_empty()
+            || rule.programs.iter().any(|pattern| glob_match(pattern, &invocation.program, false));
+        let args_match = rule.args.is_empty()
+            || invocation.args.iter().any(|arg| rule.args.iter().any(|pattern| glob_match(pattern, arg, false)));
+        let paths_match = rule.paths.is_empty()
+            || path_arguments(invocation).any(|path| rule.paths.iter().any(|pattern| glob_match(pattern, &path, true)));
+        let privileged_matches = rule.privileged.is_none_or(|privileged| privileged == invocation.privileged);
+        let user_matches = rule.users.is_empty() || rule.users.contains(&caller.user);
+        let group_matches = rule.groups.is_empty() || caller.groups.iter().any(|group| rule.groups.contains(group));
+        let caller_matches = rule.allowed_caller.is_none_or(|allowed| allowed == self.is_allowed_caller(caller));
+
+        program_matches && args_match && paths_match && privileged_matches
+            && user_matches && group_matches && caller_matches
+    }
+
+    /// Whether the caller is listed in `agent.allowed_users` or `allowed_groups`
+    pub fn is_allowed_caller(&self, caller: &Caller) -> bool {
+        self.allowed_users.contains(&caller.user)
+            || caller.groups.iter().any(|group| self.allowed_groups.contains(group))
+    }
+}
+
+impl Caller {
+    /// The user running this process
+    pub fn current() -> Self {
+        Self {
+            user: id(&["-un"]).or_else(|| std::env::var("USER").ok()).unwrap_or_default(),
+            groups: groups(id(&["-Gn"])),
+        }
+    }
+
+    /// The user with this uid, such as the peer of a Unix socket
+    pub fn for_uid(uid: u32) -> Self {
+        let uid = uid.to_string();
+        Self {
+            user: id(&["-un", &uid]).unwrap_or_else(|| uid.clone()),
+            groups: groups(id(&["-Gn", &uid])),
+        }
+    }
+}
+
+/// Output of `id` with these arguments
+fn id(args: &[&str]) -> Option<String> {
+    Command::new("id")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn groups(names: Option<String>) -> Vec<String> {
+    names.map(|names| names.split_whitespace().map(str::to_string).collect()).unwrap_or_default()
+}
+
+/// Arguments that name absolute paths, including `key=/path` forms
+fn path_arguments(invocation: &Invocation) -> impl Iterator<Item = String> {
+    invocation.args.iter().filter_map(|arg| {
+        if arg.starts_with('/') {
+            Some(normalize_path(arg))
+        } else {
+            arg.split_once("=/").map(|(_, rest)| normalize_path(&arg[arg.len() - rest.len() - 1..]))
+        }
+    })
+}
+
+/// An absolute path with repeated slashes, `.` and `..` resolved without
+/// touching the filesystem, so `//`, `/./` and `/etc/..` all read as `/`
+fn normalize_path(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+/// Words of one simple command, before wrappers are unwrapped
+#[derive(Default)]
+struct Words {
+    argv: Vec<String>,
+    dynamic: bool,
+}
+
+/// Split a shell command line into invocations, honouring quotes and the
+/// `;`, `&&`, `||`, `|` and `&` separators. Wrappers such as `sudo`, `env`
+/// and `nohup` are unwrapped so rules see the program that really runs, and
+/// scripts passed to `sh -c` or `eval`, commands run by `xargs` and
+/// `find -exec`, and command substitutions are parsed as invocations too.
+pub fn parse_command_line(line: &str) -> Vec<Invocation> {
+    let mut commands: Vec<Words> = vec![Words::default()];
+    let mut substituted = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars().peekable();
+
+    let finish_word = |word: &mut String, in_word: &mut bool, commands: &mut Vec<Words>| {
+        if *in_word {
+            commands.last_mut().expect("always one command").argv.push(std::mem::take(word));
+            *in_word = false;
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        // $(...), `...`, <(...) and >(...) run a command of their own
+        let substitution = match (quote, c) {
+            (None | Some('"'), '$') | (None, '<' | '>') if chars.peek() == Some(&'(') => {
+                chars.next();
+                Some(read_substitution(&mut chars, ')'))
+            }
+            (None | Some('"'), '`') => Some(read_substitution(&mut chars, '`')),
+            _ => None,
+        };
+        if let Some(body) = substitution {
+            let command = commands.last_mut().expect("always one command");
+            command.dynamic = true;
+            match body {
+                Some(body) => {
+                    word.push_str(&format!("$({body})"));
+                    substituted.extend(parse_command_line(&body));
+                }
+                None => word.push('$'),
+            }
+            in_word = true;
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                    in_word = true;
+                }
+            }
+            (None, ';' | '&' | '|' | '\n') => {
+                finish_word(&mut word, &mut in_word, &mut commands);
+                if chars.peek() == Some(&c) {
+                    chars.next();
+                }
+                commands.push(Words::default());
+            }
+            (None, '>' | '<') => {
+                // Redirection targets become arguments of their own
+                finish_word(&mut word, &mut in_word, &mut commands);
+                while chars.peek().is_some_and(|next| *next == '>' || *next == '&') {
+                    chars.next();
+                }
+            }
+            (None, c) if c.is_whitespace() || c == '(' || c == ')' => {
+                finish_word(&mut word, &mut in_word, &mut commands);
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    finish_word(&mut word, &mut in_word, &mut commands);
+    // An unterminated quote means the shell would see something else
+    if quote.is_some() {
+        commands.last_mut().expect("always one command").dynamic = true;
+    }
+
+    commands.into_iter()
+        .flat_map(|command| unwrap_invocation(command.argv, command.dynamic, false))
+        .chain(substituted)
+        .collect()
+}
+
+/// The body of a substitution up to its closing `)` or backtick, or `None`
+/// if it is never closed
+fn read_substitution(chars: &mut Peekable<Chars>, close: char) -> Option<String> {
+    let mut body = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if close == '`' => body.extend(chars.next()),
+            '(' if close == ')' => {
+                depth += 1;
+                body.push(c);
+            }
+            c if c == close && depth == 0 => return Some(body),
+            ')' => {
+                depth -= 1;
+                body.push(c);
+            }
+            c => body.push(c),
+        }
+    }
+    None
+}
+
+/// Strip environment assignments and wrapper programs from an argv, and
+/// parse what shells, `eval`, `xargs` and `find` run in turn
+fn unwrap_invocation(argv: Vec<String>, dynamic: bool, privileged: bool) -> Vec<Invocation> {
+    let mut words = argv.into_iter().peekable();
+    let mut privileged = privileged;
+
+    loop {
+        let Some(word) = words.peek().cloned() else { return Vec::new() };
+        let program = Path::new(&word).file_name().and_then(|name| name.to_str()).unwrap_or(&word).to_string();
+
+        if is_assignment(&word) {
+            words.next();
+        } else if program == "sudo" || program == "doas" {
+            privileged = true;
+            words.next();
+            while let Some(option) = words.peek().filter(|option| option.starts_with('-')).cloned() {
+                words.next();
+                // Options that take a value
+                if matches!(option.as_str(), "-u" | "-g" | "-C" | "-p" | "-h" | "-r" | "-t" | "-U") {
+                    words.next();
+                }
+            }
+        } else if matches!(program.as_str(), "env" | "nohup" | "nice" | "exec" | "time" | "command") {
+            words.next();
+            while words.peek().is_some_and(|option| option.starts_with('-') || is_assignment(option)) {
+                words.next();
+            }
+        } else if matches!(program.as_str(), "sh" | "bash" | "dash" | "zsh" | "ksh" | "mksh" | "ash") {
+            words.next();
+            return unwrap_shell(program, words.collect(), dynamic, privileged);
+        } else if program == "eval" {
+            words.next();
+            return nested(&words.collect::<Vec<_>>().join(" "), dynamic, privileged);
+        } else if program == "xargs" {
+            words.next();
+            while let Some(option) = words.peek().filter(|option| option.starts_with('-')).cloned() {
+                words.next();
+                if matches!(option.as_str(), "-I" | "-n" | "-P" | "-L" | "-s" | "-d" | "-E" | "-a") {
+                    words.next();
+                }
+            }
+            // The arguments come from stdin
+            let command: Vec<String> = words.collect();
+            if command.is_empty() {
+                return vec![Invocation { program, args: Vec::new(), privileged, dynamic }];
+            }
+            return unwrap_invocation(command, true, privileged);
+        } else {
+            words.next();
+            let dynamic = dynamic || program.contains(['$', '`']);
+            let invocation = Invocation { program, args: words.collect(), privileged, dynamic };
+            let mut invocations = find_exec_invocations(&invocation);
+            invocations.insert(0, invocation);
+            return invocations;
+        }
+    }
+}
+
+/// The invocations in a script, run with the privileges of whatever ran it
+fn nested(script: &str, dynamic: bool, privileged: bool) -> Vec<Invocation> {
+    parse_command_line(script).into_iter()
+        .map(|invocation| Invocation {
+            privileged: invocation.privileged || privileged,
+            dynamic: invocation.dynamic || dynamic,
+            ..invocation
+        })
+        .collect()
+}
+
+/// A shell's `-c` script, or the shell itself when it reads a script from
+/// a file or stdin that the policy can't see
+fn unwrap_shell(program: String, args: Vec<String>, dynamic: bool, privileged: bool) -> Vec<Invocation> {
+    let mut args_iter = args.iter();
+    let mut command_string = false;
+    while let Some(arg) = args_iter.clone().next().filter(|arg| arg.starts_with(['-', '+'])) {
+        args_iter.next();
+        if arg == "--" {
+            break;
+        }
+        if matches!(arg.as_str(), "-o" | "+o" | "--rcfile" | "--init-file") {
+            args_iter.next();
+        } else if !arg.starts_with("--") && arg.contains('c') {
+            command_string = true;
+        }
+    }
+    match args_iter.next() {
+        Some(script) if command_string => nested(script, dynamic, privileged),
+        _ => vec![Invocation { program, args, privileged, dynamic: true }],
+    }
+}
+
+/// The commands `find -exec`, `-execdir`, `-ok` and `-okdir` run, with `{}`
+/// standing for the starting points
+fn find_exec_invocations(find: &Invocation) -> Vec<Invocation> {
+    if find.program != "find" {
+        return Vec::new();
+    }
+    let roots: Vec<String> = find.args.iter()
+        .take_while(|arg| !arg.starts_with(['-', '(', '!']))
+        .cloned()
+        .collect();
+    let roots = if roots.is_empty() { vec![".".to_string()] } else { roots };
+
+    let mut invocations = Vec::new();
+    let mut args = find.args.iter();
+    while let Some(arg) = args.next() {
+        if matches!(arg.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir") {
+            let command: Vec<String> = args.by_ref()
+                .take_while(|arg| *arg != ";" && *arg != "+")
+                .flat_map(|arg| if arg == "{}" { roots.clone() } else { vec![arg.clone()] })
+                .collect();
+            invocations.extend(unwrap_invocation(command, find.dynamic, find.privileged));
+        }
+    }
+    invocations
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+    })
+}
+
+/// Glob match supporting `*` and `?`. In path mode `*` stops at `/` and
+/// `**` crosses directories.
+pub fn glob_match(pattern: &str, text: &str, path_mode: bool) -> bool {
+    fn matches(pattern: &[char], text: &[char], path_mode: bool) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => {
+                let (crosses, rest) = match rest.split_first() {
+                    Some(('*', after)) => (true, after),
+                    _ => (!path_mode, rest),
+                };
+                (0..=text.len())
+                    .take_while(|&i| crosses || !text[..i].contains(&'/'))
+                    .any(|i| matches(rest, &text[i..], path_mode))
+            }
+            Some(('?', rest)) => text.split_first().is_some_and(|(c, text)| (!path_mode || *c != '/') && matches(rest, text, path_mode)),
+            Some((p, rest)) => text.split_first().is_some_and(|(c, text)| c == p && matches(rest, text, path_mode)),
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text, path_mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caller(user: &str, groups: &[&str]) -> Caller {
+        Caller { user: user.to_string(), groups: groups.iter().map(|g| g.to_string()).collect() }
+    }
+
+    fn judge(policy: &Policy, command: &str) -> (bool, SafetyLevel, Option<String>) {
+        let decision = policy.evaluate(command, &caller("alice", &["wheel"]));
+        let rule = decision.deciding().and_then(|verdict| verdict.rule.clone()).map(|(name, _)| name);
+        (decision.allowed(), decision.level(), rule)
+    }
+
+    #[test]
+    fn test_parse_command_line() {
+        let invocations = parse_command_line("FOO=1 sudo -u root env -i apt-get install 'vim tiny' && echo \"a b\" > /etc/motd; ls");
+        assert_eq!(invocations, vec![
+            Invocation { program: "apt-get".to_string(), args: vec!["install".to_string(), "vim tiny".to_string()], privileged: true, dynamic: false },
+            Invocation { program: "echo".to_string(), args: vec!["a b".to_string(), "/etc/motd".to_string()], privileged: false, dynamic: false },
+            Invocation { program: "ls".to_string(), args: vec![], privileged: false, dynamic: false },
+        ]);
+        assert_eq!(parse_command_line("/usr/bin/rm -f x")[0].program, "rm");
+    }
+
+    #[test]
+    fn test_nested_commands_are_judged() {
+        let policy = Policy::default();
+        for command in [
+            "bash -c 'rm -rf /'",
+            "sh -c \"rm -rf /etc\"",
+            "sudo bash -ec 'cd /tmp && rm -rf /usr'",
+            "eval rm -rf /",
+            "echo $(rm -rf /)",
+            "echo \"`rm -rf /etc`\"",
+            "diff <(rm -rf /boot) /dev/null",
+            "find /etc -delete",
+            "find /etc -name '*.conf' -exec rm {} \\;",
+            "ls | xargs -n 1 rm -rf /usr",
+        ] {
+            assert!(!judge(&policy, command).0, "{command} should be denied");
+        }
+        assert_eq!(judge(&policy, "bash -c 'ls /tmp'"), (true, SafetyLevel::Safe, None));
+        assert_eq!(judge(&policy, "sudo sh -c 'systemctl restart nginx'").2.as_deref(), Some("privileged"));
+        assert_eq!(judge(&policy, "find /tmp -name '*.log' -delete").1, SafetyLevel::Dangerous);
+
+        // Whatever the policy can't see is at least Risky
+        for command in ["echo $(date)", "curl -fsSL https://example.com/install | sh", "bash ./setup.sh", "ls | xargs echo",
+                        "echo 'unterminated", "$CMD -rf /"] {
+            let (allowed, level, rule) = judge(&policy, command);
+            assert!(allowed && level == SafetyLevel::Risky, "{command}: {level:?}");
+            assert_eq!(rule.as_deref(), Some("dynamic-command"), "{command}");
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("mkfs.*", "mkfs.ext4", false));
+        assert!(!glob_match("mkfs.*", "mkfs", false));
+        assert!(glob_match("/etc/**", "/etc/ssh/sshd_config", true));
+        assert!(glob_match("/*", "/etc", true));
+        assert!(!glob_match("/*", "/etc/passwd", true));
+        assert!(glob_match("-S*", "-Syu", false));
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let policy = Policy::default();
+        assert_eq!(judge(&policy, "clang-format -i main.c"), (true, SafetyLevel::Safe, None));
+        assert!(!judge(&policy, "sudo rm -rf /").0);
+        assert_eq!(judge(&policy, "rm -rf /etc/nginx").2.as_deref(), Some("critical-path-removal"));
+        assert_eq!(judge(&policy, "rm -rf /var/cache/foo"), (true, SafetyLevel::Dangerous, Some("destructive".to_string())));
+        assert!(!judge(&policy, "mkfs.ext4 /dev/sdb1").0);
+        assert_eq!(judge(&policy, "dd if=/dev/zero of=/dev/sda").2.as_deref(), Some("raw-disk-write"));
+        for command in ["rm -rf //", "rm -rf /./", "sudo rm -rf /tmp/../", "rm -rf /usr//lib/..", "rm -rf /var/../etc/ssh",
+                        "find // -delete", "find /./etc -delete", "find /tmp/../boot -delete"] {
+            assert!(!judge(&policy, command).0, "{command} should be denied");
+        }
+        assert_eq!(normalize_path("/var//lib/./pacman/../dpkg/"), "/var/lib/dpkg");
+        assert_eq!(normalize_path("/../.."), "/");
+        assert_eq!(judge(&policy, "apt-get update && apt-get -y upgrade"), (true, SafetyLevel::Moderate, Some("package-changes".to_string())));
+        assert_eq!(judge(&policy, "sudo systemctl restart nginx"), (true, SafetyLevel::Risky, Some("privileged".to_string())));
+
+        // Callers outside the configured users and groups get extra scrutiny
+        let decision = policy.evaluate("ls /tmp", &caller("mallory", &["users"]));
+        assert_eq!(decision.level(), SafetyLevel::Risky);
+    }
+
+    #[test]
+    fn test_policy_file_rules_come_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("policy.toml");
+        std::fs::write(&path, r#"
+            critical_paths = ["/etc", "/srv"]
+
+            [[rule]]
+            name = "no-reboots"
+            action = "deny"
+            programs = ["reboot", "shutdown"]
+            reason = "Reboots are scheduled by hand"
+
+            [[rule]]
+            name = "ops-may-restart"
+            programs = ["systemctl"]
+            args = ["restart"]
+            privileged = true
+            groups = ["ops"]
+            approval = "Moderate"
+        "#).unwrap();
+        let policy = Policy::load_from(&path, &SystemConfig::default().agent).unwrap();
+
+        assert_eq!(policy.critical_paths, ["/etc", "/srv"]);
+        assert_eq!(judge(&policy, "sudo reboot").2.as_deref(), Some("no-reboots"));
+        let ops = policy.evaluate("sudo systemctl restart nginx", &caller("bob", &["ops", "wheel"]));
+        assert_eq!(ops.level(), SafetyLevel::Moderate);
+        // Built-in rules still apply after the file's rules
+        assert_eq!(judge(&policy, "sudo systemctl restart nginx").1, SafetyLevel::Risky);
+
+        std::fs::write(&path, "[[rule]]\nname = \"x\"\nverb = \"deny\"\n").unwrap();
+        assert!(Policy::load_from(&path, &SystemConfig::default().agent).is_err());
+    }
+}
@@ -2,7 +2,8 @@ use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::time::Duration;
-use tokio::process::Command as AsyncCommand;
+use std::process::Command;
+use crate::runner::StreamingRunner;
 use crate::system_config::RemoteConfig;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,10 +111,8 @@ impl RemoteController {
         host: &RemoteHost,
         task: &RemoteTask,
     ) -> Result<RemoteResult> {
-        let start_time = std::time::Instant::now();
-        
         // Build SSH command
-        let mut ssh_cmd = AsyncCommand::new("ssh");
+        let mut ssh_cmd = Command::new("ssh");
         
         // Add SSH options
         ssh_cmd.arg("-o").arg("StrictHostKeyChecking=no");
@@ -139,24 +138,23 @@ impl RemoteController {
 
         ssh_cmd.arg(&command);
 
-        // Set timeout if specified
-        if let Some(_timeout) = task.timeout {
-            ssh_cmd.kill_on_drop(true);
-        }
-
-        // Execute command
-        let output = ssh_cmd.output().await
+        // Execute command; parallel runs are captured only so their output
+        // doesn't interleave
+        let output = StreamingRunner::new()
+            .label(format!("{}: {}", host.hostname, task.command))
+            .timeout(task.timeout)
+            .quiet(task.parallel)
+            .run_async(ssh_cmd)
+            .await
             .context("Failed to execute SSH command")?;
 
-        let duration = start_time.elapsed();
-
         Ok(RemoteResult {
             host: host.hostname.clone(),
-            success: output.status.success(),
-            exit_code: output.status.code(),
-            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-            duration,
+            success: output.success,
+            exit_code: output.exit_code,
+            stderr: if output.timed_out { output.error_summary() } else { output.stderr },
+            stdout: output.stdout,
+            duration: output.duration,
         })
     }
 
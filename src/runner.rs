@@ -0,0 +1,400 @@
+use anyhow::{Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a timed-out command gets to exit after SIGTERM before its whole
+/// process group is killed
+const KILL_GRACE: Duration = Duration::from_secs(2);
+
+/// Everything a streamed command produced
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    pub success: bool,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration: Duration,
+}
+
+impl RunOutput {
+    /// The last `max_lines` lines of combined output, for history entries and error messages
+    pub fn tail(&self, max_lines: usize) -> String {
+        let lines: Vec<&str> = self.stdout.lines().chain(self.stderr.lines()).collect();
+        lines[lines.len().saturating_sub(max_lines)..].join("\n")
+    }
+
+    /// Short reason for a failed run
+    pub fn error_summary(&self) -> String {
+        if self.timed_out {
+            return format!("timed out after {:.0}s", self.duration.as_secs_f64());
+        }
+        let stderr: Vec<&str> = self.stderr.lines().collect();
+        if stderr.is_empty() {
+            return format!("exit code {}", self.exit_code.map_or("none".to_string(), |code| code.to_string()));
+        }
+        stderr[stderr.len().saturating_sub(20)..].join("\n")
+    }
+}
+
+/// What a command reads on stdin
+#[derive(Debug, Clone, Default)]
+pub enum Input {
+    /// Nothing, so a prompt reads end-of-file
+    #[default]
+    Closed,
+    /// The terminal, so the user can answer prompts and sudo's password request
+    Terminal,
+    /// This text, then end-of-file
+    #[cfg(test)]
+    Text(String),
+}
+
+enum Line {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Runs a process while teeing its stdout/stderr line by line to the
+/// terminal (below a spinner), an optional log file and a capture buffer.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingRunner {
+    label: Option<String>,
+    timeout: Option<Duration>,
+    log_file: Option<PathBuf>,
+    quiet: bool,
+    input: Input,
+}
+
+impl StreamingRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Message shown next to the spinner
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Kill the command if it runs longer than this
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Append all output to this file
+    pub fn log_file(mut self, path: Option<PathBuf>) -> Self {
+        self.log_file = path;
+        self
+    }
+
+    /// Capture and log output without echoing it to the terminal
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// What the command reads on stdin
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Run a command to completion (or timeout)
+    pub fn run(&self, mut command: Command) -> Result<RunOutput> {
+        let description = describe(&command);
+        let start = Instant::now();
+
+        let mut log = match &self.log_file {
+            Some(path) => Some(open_log(path)?),
+            None => None,
+        };
+        if let Some(log) = log.as_mut() {
+            writeln!(log, "==> [{}] $ {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"), description)?;
+        }
+
+        let stdin = match self.input {
+            Input::Closed => Stdio::null(),
+            Input::Terminal => Stdio::inherit(),
+            #[cfg(test)]
+            Input::Text(_) => Stdio::piped(),
+        };
+        // Its own process group, so a timeout reaches whatever it started
+        // (the package manager behind sudo or `sh -c`). Commands reading the
+        // terminal stay in ours: a background group is stopped as soon as
+        // sudo asks for a password.
+        let own_group = !matches!(self.input, Input::Terminal);
+        if own_group {
+            command.process_group(0);
+        }
+        let mut child = command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute command: {description}"))?;
+        #[cfg(test)]
+        if let (Input::Text(text), Some(mut stdin)) = (&self.input, child.stdin.take()) {
+            let text = text.clone();
+            // Written from a thread so a child that doesn't read can't block us
+            thread::spawn(move || stdin.write_all(text.as_bytes()));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let stdout_sender = sender.clone();
+        let stdout = child.stdout.take().context("child stdout was not captured")?;
+        let stderr = child.stderr.take().context("child stderr was not captured")?;
+        thread::spawn(move || forward_lines(stdout, |line| stdout_sender.send(Line::Stdout(line)).is_ok()));
+        thread::spawn(move || forward_lines(stderr, |line| sender.send(Line::Stderr(line)).is_ok()));
+
+        let spinner = self.spinner(&description);
+        let mut captured_stdout = String::new();
+        let mut captured_stderr = String::new();
+        let mut status = None;
+        let mut timed_out = false;
+        let mut terminated_at: Option<Instant> = None;
+
+        loop {
+            match receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(line) => {
+                    let (text, buffer, prefix) = match &line {
+                        Line::Stdout(text) => (text, &mut captured_stdout, ""),
+                        Line::Stderr(text) => (text, &mut captured_stderr, "stderr: "),
+                    };
+                    buffer.push_str(text);
+                    buffer.push('\n');
+                    if let Some(log) = log.as_mut() {
+                        writeln!(log, "{prefix}{text}")?;
+                    }
+                    if !self.quiet {
+                        match &spinner {
+                            Some(spinner) => spinner.println(text),
+                            None if matches!(line, Line::Stderr(_)) => eprintln!("{text}"),
+                            None => println!("{text}"),
+                        }
+                    }
+                    continue;
+                }
+                // Both readers have finished, so the pipes are closed
+                Err(RecvTimeoutError::Disconnected) if status.is_some() || terminated_at.is_some() => break,
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(50)),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            // A terminated child is left unreaped until the end, so its pid
+            // can't be reused while the group is still being signalled
+            if status.is_none() && terminated_at.is_none() {
+                status = child.try_wait()?;
+            }
+
+            if status.is_none()
+                && terminated_at.is_none()
+                && let Some(timeout) = self.timeout
+                && start.elapsed() > timeout
+            {
+                timed_out = true;
+                signal(&mut child, own_group, libc::SIGTERM);
+                terminated_at = Some(Instant::now());
+            }
+
+            if terminated_at.is_some_and(|at| at.elapsed() > KILL_GRACE) {
+                break;
+            }
+        }
+
+        if terminated_at.is_some() {
+            signal(&mut child, own_group, libc::SIGKILL);
+            status = Some(child.wait()?);
+        }
+
+        if let Some(spinner) = spinner {
+            spinner.finish_and_clear();
+        }
+
+        let duration = start.elapsed();
+        let exit_code = status.and_then(|status| status.code());
+        let success = !timed_out && status.is_some_and(|status| status.success());
+
+        if let Some(log) = log.as_mut() {
+            let outcome = if timed_out {
+                format!("timed out after {:.1}s", duration.as_secs_f64())
+            } else {
+                format!("exit code {}", exit_code.map_or("none".to_string(), |code| code.to_string()))
+            };
+            writeln!(log, "<== {outcome}\n")?;
+        }
+
+        Ok(RunOutput {
+            success,
+            exit_code,
+            timed_out,
+            stdout: captured_stdout,
+            stderr: captured_stderr,
+            duration,
+        })
+    }
+
+    /// Run a command from async code without blocking the runtime
+    pub async fn run_async(&self, command: Command) -> Result<RunOutput> {
+        let runner = self.clone();
+        tokio::task::spawn_blocking(move || runner.run(command))
+            .await
+            .context("Command runner task panicked")?
+    }
+
+    fn spinner(&self, description: &str) -> Option<ProgressBar> {
+        if self.quiet {
+            return None;
+        }
+
+        let spinner = ProgressBar::new_spinner();
+        if spinner.is_hidden() {
+            // Not a terminal: plain line output only
+            return None;
+        }
+        spinner.set_style(
+            ProgressStyle::with_template("{spinner:.cyan} {msg} [{elapsed}]")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        spinner.set_message(self.label.clone().unwrap_or_else(|| description.to_string()));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        Some(spinner)
+    }
+}
+
+/// Default log file for interactive commands
+pub fn default_log_path() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|dir| dir.join("linux-distro-agent").join("logs").join("commands.log"))
+}
+
+fn open_log(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open log file: {}", path.display()))
+}
+
+/// Send `signal` to the child's process group, or to the child alone when
+/// it shares ours
+fn signal(child: &mut Child, own_group: bool, signal: libc::c_int) {
+    if own_group {
+        // SAFETY: plain syscall; the group is ours and its leader unreaped
+        unsafe { libc::killpg(child.id() as libc::pid_t, signal) };
+    } else if signal == libc::SIGKILL {
+        let _ = child.kill();
+    } else {
+        // SAFETY: as above, for the unreaped child alone
+        unsafe { libc::kill(child.id() as libc::pid_t, signal) };
+    }
+}
+
+fn forward_lines(stream: impl Read, mut send: impl FnMut(String) -> bool) {
+    let mut reader = BufReader::new(stream);
+    let mut buffer = Vec::new();
+    // Read raw bytes so invalid UTF-8 doesn't end the stream early
+    while let Ok(read) = reader.read_until(b'\n', &mut buffer) {
+        if read == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&buffer).trim_end_matches(['\n', '\r']).to_string();
+        buffer.clear();
+        if !send(line) {
+            break;
+        }
+    }
+}
+
+fn describe(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_captures_both_streams_and_logs() {
+        let log = std::env::temp_dir().join(format!("lda-runner-{}.log", std::process::id()));
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2; exit 3"]);
+
+        let output = StreamingRunner::new().quiet(true).log_file(Some(log.clone())).run(command).unwrap();
+        assert!(!output.success);
+        assert_eq!(output.exit_code, Some(3));
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert_eq!(output.tail(1), "err");
+        assert_eq!(output.error_summary(), "err");
+
+        // stdout and stderr are read concurrently, so only check each line is present
+        let logged = fs::read_to_string(&log).unwrap();
+        assert!(logged.contains("\nout\n"));
+        assert!(logged.contains("stderr: err\n"));
+        assert!(logged.contains("<== exit code 3"));
+        let _ = fs::remove_file(log);
+    }
+
+    #[test]
+    fn test_prompts_read_input() {
+        let prompt = || {
+            let mut command = Command::new("sh");
+            command.args(["-c", "printf 'Proceed? [y/N] '; read answer; [ \"$answer\" = y ]"]);
+            command
+        };
+
+        let answered = StreamingRunner::new().quiet(true).input(Input::Text("y\n".to_string())).run(prompt()).unwrap();
+        assert!(answered.success);
+        // With stdin closed the prompt reads end-of-file and fails instead of hanging
+        let closed = StreamingRunner::new().quiet(true).timeout(Some(Duration::from_secs(10))).run(prompt()).unwrap();
+        assert!(!closed.success);
+        assert!(!closed.timed_out);
+    }
+
+    #[test]
+    fn test_timeout_kills_child() {
+        let mut command = Command::new("sleep");
+        command.arg("30");
+
+        let output = StreamingRunner::new().quiet(true).timeout(Some(Duration::from_millis(200))).run(command).unwrap();
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert!(output.duration < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_timeout_kills_process_group() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & echo $!; wait"]);
+
+        let output = StreamingRunner::new().quiet(true).timeout(Some(Duration::from_millis(300))).run(command).unwrap();
+        assert!(output.timed_out);
+        let grandchild = output.stdout.trim();
+        assert!(!grandchild.is_empty());
+
+        // Gone, or a zombie waiting for init to reap it
+        let running = || fs::read_to_string(format!("/proc/{grandchild}/stat"))
+            .is_ok_and(|stat| stat.rsplit_once(") ").is_some_and(|(_, rest)| !rest.starts_with('Z')));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while running() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!running(), "sleep {grandchild} outlived the timeout");
+    }
+}
@@ -342,13 +342,21 @@ impl SecurityAuditor {
             "ntalk",
         ];
 
-        // This is a simplified check - in reality you'd query the package manager
+        // Only report packages that the package manager database says are installed
+        let installed = match crate::inventory::detect_installed_packages() {
+            Ok(packages) => packages,
+            Err(_) => return Ok(()),
+        };
+
         for package in problematic_packages {
-            // Simulate package check (would use actual package manager queries)
+            let Some(found) = installed.iter().find(|p| p.name == package) else {
+                continue;
+            };
+
             self.findings.push(SecurityFinding {
                 id: format!("PKG_{}", package.to_uppercase()),
-                title: format!("Potentially insecure package {} may be installed", package),
-                description: format!("Package {} is known to have security issues", package),
+                title: format!("Potentially insecure package {} is installed", package),
+                description: format!("Package {} ({}) is known to have security issues", package, found.version),
                 severity: SecurityLevel::Low,
                 category: SecurityCategory::Vulnerabilities,
                 recommendation: format!("Consider removing package {}", package),
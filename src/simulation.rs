@@ -0,0 +1,376 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::inventory;
+
+/// A package the simulated transaction would touch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageChange {
+    pub name: String,
+    /// Version after the transaction (the removed version for removals)
+    pub version: Option<String>,
+    /// Installed version being replaced, for upgrades
+    pub old_version: Option<String>,
+}
+
+impl PackageChange {
+    fn new(name: &str, version: Option<&str>) -> Self {
+        Self {
+            name: name.to_string(),
+            version: version.map(str::to_string),
+            old_version: None,
+        }
+    }
+}
+
+/// The delta a package operation would apply, as reported by the backend's
+/// native simulation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub install: Vec<PackageChange>,
+    pub upgrade: Vec<PackageChange>,
+    pub remove: Vec<PackageChange>,
+    /// Total bytes to download, when the backend reports it
+    pub download_size: Option<u64>,
+}
+
+impl SimulationReport {
+    pub fn is_empty(&self) -> bool {
+        self.install.is_empty() && self.upgrade.is_empty() && self.remove.is_empty()
+    }
+
+    /// Human-readable listing of the delta
+    pub fn render(&self) -> String {
+        if self.is_empty() {
+            return "No changes".to_string();
+        }
+
+        let mut lines = Vec::new();
+        let sections = [("install", "+", &self.install), ("upgrade", "~", &self.upgrade), ("remove", "-", &self.remove)];
+        for (verb, marker, changes) in sections {
+            if changes.is_empty() {
+                continue;
+            }
+            lines.push(format!("Would {} {} package(s):", verb, changes.len()));
+            for change in changes {
+                let version = match (&change.old_version, &change.version) {
+                    (Some(old), Some(new)) => format!(" {old} -> {new}"),
+                    (None, Some(version)) => format!(" {version}"),
+                    _ => String::new(),
+                };
+                lines.push(format!("  {} {}{}", marker, change.name, version));
+            }
+        }
+        if !self.install.is_empty() || !self.upgrade.is_empty() {
+            lines.push(format!(
+                "Download size: {}",
+                self.download_size.map_or("unknown".to_string(), inventory::format_size)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Move installs of packages that are already present into the upgrade list
+    fn classify_upgrades(&mut self, installed: &HashMap<String, String>) {
+        let (upgrades, installs) = std::mem::take(&mut self.install)
+            .into_iter()
+            .partition(|change| installed.contains_key(&change.name));
+        self.install = installs;
+        self.upgrade = upgrades;
+        for change in &mut self.upgrade {
+            change.old_version = installed.get(&change.name).cloned();
+        }
+    }
+}
+
+/// Parse a size such as `1.9 M`, `20 k`, `1,234 kB` or `2 MiB` into bytes.
+/// Single-letter and `*iB` units are binary, `kB`/`MB`/`GB` are decimal.
+fn parse_size(number: &str, unit: &str) -> Option<u64> {
+    let value: f64 = number.replace(',', "").parse().ok()?;
+    let multiplier = match unit.trim_end_matches(['.', ',']) {
+        "B" | "" => 1.0,
+        "k" | "K" | "KiB" => 1024.0,
+        "M" | "MiB" => 1024.0 * 1024.0,
+        "G" | "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "kB" | "KB" => 1000.0,
+        "MB" => 1000.0 * 1000.0,
+        "GB" => 1000.0 * 1000.0 * 1000.0,
+        _ => return None,
+    };
+    Some((value * multiplier).round() as u64)
+}
+
+/// Parse the size that follows `marker` on any line of the output
+fn find_size(output: &str, marker: &str) -> Option<u64> {
+    output.lines().find_map(|line| {
+        let (_, rest) = line.split_once(marker)?;
+        let mut fields = rest.split_whitespace();
+        parse_size(fields.next()?, fields.next().unwrap_or(""))
+    })
+}
+
+/// Parse `pacman -S --print --print-format "%n %v %s"` output (sizes are in
+/// bytes and omitted for removals). Packages in `installed` are upgrades.
+///
+/// ```text
+/// vim 9.1.0016-1 1951232
+/// vim-runtime 9.1.0016-1 7340032
+/// ```
+pub fn parse_pacman(output: &str, removing: bool, installed: &HashMap<String, String>) -> SimulationReport {
+    let mut report = SimulationReport::default();
+    let mut download_size = 0;
+
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Skip ":: ..." notices and warnings
+        if fields.len() < 2 || fields[0].ends_with(':') || fields[0] == "::" {
+            continue;
+        }
+
+        let change = PackageChange::new(fields[0], Some(fields[1]));
+        if removing {
+            report.remove.push(change);
+        } else {
+            download_size += fields.get(2).and_then(|size| size.parse::<u64>().ok()).unwrap_or(0);
+            report.install.push(change);
+        }
+    }
+
+    if !removing {
+        report.classify_upgrades(installed);
+        report.download_size = Some(download_size);
+    }
+    report
+}
+
+/// Parse `apt-get -s` output:
+///
+/// ```text
+/// Need to get 1,663 kB of archives.
+/// Inst vim-runtime (2:9.0.1378-2 Debian:12.5/stable [all])
+/// Inst vim [2:9.0.1378-1] (2:9.0.1378-2 Debian:12.5/stable [amd64])
+/// Remv nano [7.2-1]
+/// ```
+pub fn parse_apt(output: &str) -> SimulationReport {
+    let mut report = SimulationReport {
+        download_size: find_size(output, "Need to get "),
+        ..Default::default()
+    };
+
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        let (Some(action), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let rest: Vec<&str> = fields.collect();
+        let old_version = rest.first()
+            .filter(|field| field.starts_with('['))
+            .map(|field| field.trim_matches(['[', ']']).to_string());
+        let new_version = rest.iter()
+            .find(|field| field.starts_with('('))
+            .map(|field| field.trim_start_matches('(').to_string());
+
+        match action {
+            "Inst" => {
+                let mut change = PackageChange::new(name, new_version.as_deref());
+                if old_version.is_some() {
+                    change.old_version = old_version;
+                    report.upgrade.push(change);
+                } else {
+                    report.install.push(change);
+                }
+            }
+            "Remv" | "Purg" => report.remove.push(PackageChange::new(name, old_version.as_deref())),
+            _ => {}
+        }
+    }
+
+    report
+}
+
+/// Parse the transaction table printed by `dnf --assumeno` (dnf4 and dnf5):
+///
+/// ```text
+/// Installing:
+///  vim-enhanced     x86_64   2:9.0.2153-1.fc39   updates   1.9 M
+/// Upgrading:
+///  vim-common       x86_64   2:9.0.2153-1.fc39   updates   7.0 M
+/// Total download size: 8.9 M
+/// ```
+pub fn parse_dnf(output: &str) -> SimulationReport {
+    let mut report = SimulationReport {
+        download_size: find_size(output, "Total download size: ").or_else(|| find_size(output, "Need to download ")),
+        ..Default::default()
+    };
+
+    let mut section: Option<&mut Vec<PackageChange>> = None;
+    for line in output.lines() {
+        // Section headers ("Installing dependencies:", "Removing:") end with a colon
+        if !line.starts_with(char::is_whitespace) && line.trim_end().ends_with(':') {
+            let header = line.trim_end();
+            section = if header.starts_with("Installing") || header.starts_with("Reinstalling") {
+                Some(&mut report.install)
+            } else if header.starts_with("Upgrading") || header.starts_with("Downgrading") {
+                Some(&mut report.upgrade)
+            } else if header.starts_with("Removing") {
+                Some(&mut report.remove)
+            } else {
+                None
+            };
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) {
+            section = None;
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let Some(changes) = section.as_mut()
+            && fields.len() >= 4
+            && fields[0] != "replacing"
+        {
+            changes.push(PackageChange::new(fields[0], Some(fields[2])));
+        }
+    }
+
+    report
+}
+
+/// Parse `zypper --dry-run` output. Zypper only lists names unless run with
+/// `--details`:
+///
+/// ```text
+/// The following 2 NEW packages are going to be installed:
+///   vim vim-data-common
+///
+/// Overall download size: 2.1 MiB. Already cached: 0 B. ...
+/// ```
+pub fn parse_zypper(output: &str) -> SimulationReport {
+    let mut report = SimulationReport {
+        download_size: find_size(output, "Overall download size: "),
+        ..Default::default()
+    };
+
+    let mut section: Option<&mut Vec<PackageChange>> = None;
+    for line in output.lines() {
+        if line.starts_with("The following") {
+            section = if line.contains("going to be installed") || line.contains("going to be reinstalled") {
+                Some(&mut report.install)
+            } else if line.contains("going to be upgraded") || line.contains("going to be downgraded") {
+                Some(&mut report.upgrade)
+            } else if line.contains("going to be REMOVED") {
+                Some(&mut report.remove)
+            } else {
+                None
+            };
+            continue;
+        }
+        if !line.starts_with(char::is_whitespace) || line.trim().is_empty() {
+            section = None;
+            continue;
+        }
+        if let Some(changes) = section.as_mut() {
+            changes.extend(line.split_whitespace().map(|name| PackageChange::new(name, None)));
+        }
+    }
+
+    report
+}
+
+/// Parse `apk --simulate` output. apk does not report a download size:
+///
+/// ```text
+/// (1/2) Installing vim-common (9.0.2127-r0)
+/// (2/2) Upgrading vim (9.0.2073-r0 -> 9.0.2127-r0)
+/// (1/1) Purging nano (7.2-r1)
+/// ```
+pub fn parse_apk(output: &str) -> SimulationReport {
+    let mut report = SimulationReport::default();
+
+    for line in output.lines() {
+        let Some((_, rest)) = line.split_once(") ").filter(|_| line.starts_with('(')) else {
+            continue;
+        };
+        let mut fields = rest.splitn(3, ' ');
+        let (Some(action), Some(name)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let versions = fields.next().unwrap_or("").trim_matches(['(', ')']);
+
+        match action {
+            "Installing" | "Reinstalling" => report.install.push(PackageChange::new(name, Some(versions))),
+            "Upgrading" | "Downgrading" | "Replacing" => {
+                let mut change = match versions.split_once(" -> ") {
+                    Some((old, new)) => {
+                        let mut change = PackageChange::new(name, Some(new));
+                        change.old_version = Some(old.to_string());
+                        change
+                    }
+                    None => PackageChange::new(name, Some(versions)),
+                };
+                if change.version.as_deref() == Some("") {
+                    change.version = None;
+                }
+                report.upgrade.push(change);
+            }
+            "Purging" | "Deleting" => report.remove.push(PackageChange::new(name, Some(versions))),
+            _ => {}
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pacman_splits_upgrades() {
+        let output = "vim 9.1.0016-1 1951232\nvim-runtime 9.1.0016-1 7340032\n";
+        let installed = HashMap::from([("vim-runtime".to_string(), "9.0.2-1".to_string())]);
+        let report = parse_pacman(output, false, &installed);
+        assert_eq!(report.install, vec![PackageChange::new("vim", Some("9.1.0016-1"))]);
+        assert_eq!(report.upgrade[0].old_version.as_deref(), Some("9.0.2-1"));
+        assert_eq!(report.download_size, Some(1951232 + 7340032));
+
+        let report = parse_pacman("nano 7.2-1\n", true, &HashMap::new());
+        assert_eq!(report.remove.len(), 1);
+        assert_eq!(report.download_size, None);
+    }
+
+    #[test]
+    fn test_parse_apt() {
+        let output = "NOTE: This is only a simulation!\nNeed to get 1,663 kB of archives.\nRemv nano [7.2-1]\nInst vim-runtime (2:9.0.1378-2 Debian:12.5/stable [all])\nInst vim [2:9.0.1378-1] (2:9.0.1378-2 Debian:12.5/stable [amd64])\nConf vim (2:9.0.1378-2 Debian:12.5/stable [amd64])\n";
+        let report = parse_apt(output);
+        assert_eq!(report.install, vec![PackageChange::new("vim-runtime", Some("2:9.0.1378-2"))]);
+        assert_eq!(report.upgrade[0].old_version.as_deref(), Some("2:9.0.1378-1"));
+        assert_eq!(report.remove, vec![PackageChange::new("nano", Some("7.2-1"))]);
+        assert_eq!(report.download_size, Some(1_663_000));
+    }
+
+    #[test]
+    fn test_parse_dnf() {
+        let output = "Dependencies resolved.\n================\n Package   Arch   Version   Repository   Size\n================\nInstalling:\n vim-enhanced  x86_64  2:9.0.2153-1.fc39  updates  1.9 M\nInstalling dependencies:\n gpm-libs  x86_64  1.20.7-44.fc39  fedora  20 k\nUpgrading:\n vim-common  x86_64  2:9.0.2153-1.fc39  updates  7.0 M\n replacing  vim-common.x86_64 2:9.0.2120-1.fc39\n\nTransaction Summary\n================\nInstall  2 Packages\n\nTotal download size: 8.9 M\nOperation aborted.\n";
+        let report = parse_dnf(output);
+        assert_eq!(report.install.len(), 2);
+        assert_eq!(report.upgrade, vec![PackageChange::new("vim-common", Some("2:9.0.2153-1.fc39"))]);
+        assert_eq!(report.download_size, Some((8.9f64 * 1024.0 * 1024.0).round() as u64));
+    }
+
+    #[test]
+    fn test_parse_zypper_and_apk() {
+        let zypper = "Loading repository data...\n\nThe following 2 NEW packages are going to be installed:\n  vim vim-data-common\n\nThe following package is going to be REMOVED:\n  nano\n\n2 new packages to install.\nOverall download size: 2.1 MiB. Already cached: 0 B.\n";
+        let report = parse_zypper(zypper);
+        assert_eq!(report.install.len(), 2);
+        assert_eq!(report.remove, vec![PackageChange::new("nano", None)]);
+        assert!(report.download_size.is_some());
+
+        let apk = "(1/3) Installing vim-common (9.0.2127-r0)\n(2/3) Upgrading vim (9.0.2073-r0 -> 9.0.2127-r0)\n(3/3) Purging nano (7.2-r1)\nOK: 120 MiB in 40 packages\n";
+        let report = parse_apk(apk);
+        assert_eq!(report.install.len(), 1);
+        assert_eq!(report.upgrade[0].old_version.as_deref(), Some("9.0.2073-r0"));
+        assert_eq!(report.upgrade[0].version.as_deref(), Some("9.0.2127-r0"));
+        assert_eq!(report.remove.len(), 1);
+        assert_eq!(report.download_size, None);
+    }
+}
@@ -0,0 +1,575 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use crate::distro::DistroInfo;
+use crate::executor::{CommandExecutor, CommandStep};
+use crate::package_manager::{PackageBackend, PackageManagerRegistry};
+
+/// System state saved before a risky agent task runs, so the task can be
+/// undone with `lda agent --rollback <task_id>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Provider that took the snapshot
+    pub provider: String,
+    /// Provider-specific handle: snapshot number, subvolume, LV or archive path
+    pub id: String,
+    pub created_at: SystemTime,
+    /// Files saved by providers that only capture part of the system
+    #[serde(default)]
+    pub paths: Vec<PathBuf>,
+    /// Package state to return to when the snapshot doesn't include the
+    /// package database
+    pub packages: Option<PackageCheckpoint>,
+}
+
+/// What restoring a snapshot achieved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestoreOutcome {
+    /// The system is back in its pre-task state
+    Restored,
+    /// The snapshot is staged and takes effect on the next boot
+    RebootRequired,
+}
+
+/// A way of saving and restoring system state.
+///
+/// Providers are tried in the order [`SnapshotManager`] lists them and the
+/// first one available on this system is used.
+pub trait SnapshotProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn is_available(&self) -> bool;
+
+    /// Whether a snapshot covers the whole root filesystem, package
+    /// database included
+    fn covers_system(&self) -> bool {
+        true
+    }
+
+    /// Whether `restore` takes effect without a reboot
+    fn restores_live(&self) -> bool {
+        false
+    }
+
+    /// Take a snapshot and return its id. `paths` are the files the task is
+    /// expected to touch.
+    fn create(&self, label: &str, paths: &[PathBuf]) -> Result<String>;
+    fn restore(&self, id: &str) -> Result<RestoreOutcome>;
+}
+
+/// Run a step and return its stdout, failing with its stderr
+fn run(step: CommandStep) -> Result<String> {
+    let output = CommandExecutor::capture_output(&step)?;
+    if !output.status.success() {
+        anyhow::bail!("{} failed: {}", step, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn command_exists(program: &str) -> bool {
+    Command::new("which")
+        .arg(program)
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+fn description(label: &str) -> String {
+    format!("linux-distro-agent: before {label}")
+}
+
+/// Snapper on the `root` config; changes are reverted in place
+pub struct Snapper;
+
+impl SnapshotProvider for Snapper {
+    fn name(&self) -> &'static str { "snapper" }
+
+    fn is_available(&self) -> bool {
+        command_exists("snapper") && run(CommandStep::new("snapper", ["-c", "root", "list"]).with_root()).is_ok()
+    }
+
+    fn restores_live(&self) -> bool { true }
+
+    fn create(&self, label: &str, _paths: &[PathBuf]) -> Result<String> {
+        let description = description(label);
+        let number = run(CommandStep::new("snapper", [
+            "-c", "root", "create", "--type", "single", "--cleanup-algorithm", "number",
+            "--print-number", "--description", description.as_str(),
+        ]).with_root())?;
+        Ok(number.trim().to_string())
+    }
+
+    fn restore(&self, id: &str) -> Result<RestoreOutcome> {
+        run(CommandStep::new("snapper", ["-c".to_string(), "root".to_string(), "undochange".to_string(), format!("{id}..0")]).with_root())?;
+        Ok(RestoreOutcome::Restored)
+    }
+}
+
+/// Timeshift, in whichever mode (rsync or btrfs) it is configured for
+pub struct Timeshift;
+
+impl SnapshotProvider for Timeshift {
+    fn name(&self) -> &'static str { "timeshift" }
+
+    fn is_available(&self) -> bool {
+        command_exists("timeshift")
+    }
+
+    fn create(&self, label: &str, _paths: &[PathBuf]) -> Result<String> {
+        let description = description(label);
+        let output = run(CommandStep::new("timeshift", ["--create", "--comments", description.as_str(), "--scripted"]).with_root())?;
+        parse_timeshift_tag(&output).context("timeshift did not report the snapshot it created")
+    }
+
+    fn restore(&self, id: &str) -> Result<RestoreOutcome> {
+        run(CommandStep::new("timeshift", ["--restore", "--snapshot", id, "--scripted", "--yes"]).with_root())?;
+        Ok(RestoreOutcome::RebootRequired)
+    }
+}
+
+/// The snapshot name from timeshift's "Tagged snapshot '<name>': ondemand" line
+fn parse_timeshift_tag(output: &str) -> Option<String> {
+    output.lines()
+        .find_map(|line| line.split_once("Tagged snapshot '"))
+        .and_then(|(_, rest)| rest.split_once('\''))
+        .map(|(name, _)| name.to_string())
+}
+
+/// Read-only btrfs snapshots of `/`. Restoring makes a writable copy the
+/// default subvolume, so the system boots into it.
+pub struct Btrfs {
+    snapshot_dir: PathBuf,
+}
+
+impl Btrfs {
+    pub fn new() -> Self {
+        Self { snapshot_dir: PathBuf::from("/.snapshots/linux-distro-agent") }
+    }
+}
+
+impl SnapshotProvider for Btrfs {
+    fn name(&self) -> &'static str { "btrfs" }
+
+    fn is_available(&self) -> bool {
+        command_exists("btrfs")
+            && run(CommandStep::new("stat", ["-f", "-c", "%T", "/"])).is_ok_and(|fs_type| fs_type.trim() == "btrfs")
+    }
+
+    fn create(&self, label: &str, _paths: &[PathBuf]) -> Result<String> {
+        let snapshot_dir = self.snapshot_dir.display().to_string();
+        run(CommandStep::new("mkdir", ["-p", snapshot_dir.as_str()]).with_root())?;
+        let target = self.snapshot_dir.join(label).display().to_string();
+        run(CommandStep::new("btrfs", ["subvolume", "snapshot", "-r", "/", target.as_str()]).with_root())?;
+        Ok(target)
+    }
+
+    fn restore(&self, id: &str) -> Result<RestoreOutcome> {
+        let writable = format!("{id}-restored");
+        run(CommandStep::new("btrfs", ["subvolume", "snapshot", id, writable.as_str()]).with_root())?;
+        let info = run(CommandStep::new("btrfs", ["subvolume", "show", writable.as_str()]).with_root())?;
+        let subvolume = parse_subvolume_id(&info).context("btrfs did not report the restored subvolume id")?;
+        run(CommandStep::new("btrfs", ["subvolume", "set-default", subvolume.as_str(), "/"]).with_root())?;
+        Ok(RestoreOutcome::RebootRequired)
+    }
+}
+
+fn parse_subvolume_id(output: &str) -> Option<String> {
+    output.lines()
+        .find_map(|line| line.trim().strip_prefix("Subvolume ID:"))
+        .map(|id| id.trim().to_string())
+}
+
+/// Thin LVM snapshots of the root logical volume, merged back on the next
+/// activation
+pub struct LvmThin;
+
+impl LvmThin {
+    /// Volume group and logical volume holding `/`, when it is thin-provisioned
+    fn root_volume() -> Option<(String, String)> {
+        let source = run(CommandStep::new("findmnt", ["-n", "-o", "SOURCE", "/"])).ok()?;
+        let output = run(CommandStep::new("lvs", ["--noheadings", "-o", "vg_name,lv_name,pool_lv", source.trim()]).with_root()).ok()?;
+        parse_thin_volume(&output)
+    }
+}
+
+impl SnapshotProvider for LvmThin {
+    fn name(&self) -> &'static str { "lvm-thin" }
+
+    fn is_available(&self) -> bool {
+        command_exists("lvcreate") && Self::root_volume().is_some()
+    }
+
+    fn create(&self, label: &str, _paths: &[PathBuf]) -> Result<String> {
+        let (group, volume) = Self::root_volume().context("The root filesystem is not on a thin logical volume")?;
+        let name = format!("lda-{label}");
+        run(CommandStep::new("lvcreate", ["-s".to_string(), "-n".to_string(), name.clone(), format!("{group}/{volume}")]).with_root())?;
+        Ok(format!("{group}/{name}"))
+    }
+
+    fn restore(&self, id: &str) -> Result<RestoreOutcome> {
+        run(CommandStep::new("lvconvert", ["--merge", id]).with_root())?;
+        Ok(RestoreOutcome::RebootRequired)
+    }
+}
+
+/// `vg lv pool` from `lvs` output, only when the volume sits in a thin pool
+fn parse_thin_volume(output: &str) -> Option<(String, String)> {
+    let fields: Vec<&str> = output.split_whitespace().collect();
+    match fields.as_slice() {
+        [group, volume, _pool] => Some((group.to_string(), volume.to_string())),
+        _ => None,
+    }
+}
+
+/// Fallback that archives only the files a task names under the critical
+/// system paths. Named files that don't exist yet are listed next to the
+/// archive and deleted on restore.
+pub struct TarArchive {
+    archive_dir: PathBuf,
+    root: PathBuf,
+}
+
+impl TarArchive {
+    pub fn new(archive_dir: PathBuf) -> Self {
+        Self { archive_dir, root: PathBuf::from("/") }
+    }
+
+    /// Files under the real root need root to read, write back and delete
+    fn step(&self, program: &str, args: Vec<String>) -> CommandStep {
+        let step = CommandStep::new(program, args);
+        if self.root == Path::new("/") { step.with_root() } else { step }
+    }
+
+    /// The list of files the task may create, kept beside its archive
+    fn created_list(archive: &Path) -> PathBuf {
+        archive.with_extension("created")
+    }
+}
+
+impl SnapshotProvider for TarArchive {
+    fn name(&self) -> &'static str { "tar" }
+
+    fn is_available(&self) -> bool { true }
+
+    fn covers_system(&self) -> bool { false }
+
+    fn restores_live(&self) -> bool { true }
+
+    fn create(&self, label: &str, paths: &[PathBuf]) -> Result<String> {
+        fs::create_dir_all(&self.archive_dir)
+            .with_context(|| format!("Failed to create snapshot directory: {:?}", self.archive_dir))?;
+        let archive = self.archive_dir.join(format!("{label}.tar"));
+        let (existing, missing): (Vec<&PathBuf>, Vec<&PathBuf>) = paths.iter()
+            .filter(|path| path.starts_with(&self.root))
+            .partition(|path| path.symlink_metadata().is_ok());
+
+        if !existing.is_empty() {
+            let mut args = vec!["-C".to_string(), self.root.display().to_string(), "-cpf".to_string(), archive.display().to_string()];
+            args.extend(existing.iter().filter_map(|path| path.strip_prefix(&self.root).ok()).map(|path| path.display().to_string()));
+            run(self.step("tar", args))?;
+        }
+        if !missing.is_empty() {
+            let list: String = missing.iter().map(|path| format!("{}\n", path.display())).collect();
+            let created = Self::created_list(&archive);
+            fs::write(&created, list).with_context(|| format!("Failed to write {:?}", created))?;
+        }
+        Ok(archive.display().to_string())
+    }
+
+    fn restore(&self, id: &str) -> Result<RestoreOutcome> {
+        let archive = Path::new(id);
+        if let Ok(list) = fs::read_to_string(Self::created_list(archive)) {
+            let created: Vec<String> = list.lines()
+                .filter(|path| Path::new(path).symlink_metadata().is_ok())
+                .map(str::to_string)
+                .collect();
+            if !created.is_empty() {
+                let mut args = vec!["-rf".to_string(), "--".to_string()];
+                args.extend(created);
+                run(self.step("rm", args))?;
+            }
+        }
+        if archive.exists() {
+            run(self.step("tar", vec!["-C".to_string(), self.root.display().to_string(), "-xpf".to_string(), id.to_string()]))?;
+        }
+        Ok(RestoreOutcome::Restored)
+    }
+}
+
+/// Package manager state recorded before a task, for undoing its package
+/// transaction when the snapshot itself doesn't cover it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageCheckpoint {
+    pub backend: String,
+    /// Last transaction in the manager's history, for managers that keep one
+    pub transaction: Option<String>,
+    /// Installed package versions
+    pub versions: BTreeMap<String, String>,
+}
+
+impl PackageCheckpoint {
+    /// Record the native package manager's state, or `None` when it can't be read
+    pub fn capture() -> Option<Self> {
+        let registry = PackageManagerRegistry::new();
+        let distro = DistroInfo::detect().ok()?;
+        Self::capture_with(distro.backend(&registry)?).ok()
+    }
+
+    fn capture_with(backend: &dyn PackageBackend) -> Result<Self> {
+        Ok(Self {
+            backend: backend.name().to_string(),
+            transaction: backend.last_transaction(),
+            versions: installed_versions(backend)?,
+        })
+    }
+
+    /// Commands that return the packages to this state. Managers with a
+    /// transaction history undo through it; the rest remove new packages and
+    /// reinstall the previous versions of changed ones.
+    pub fn undo_steps(&self, backend: &dyn PackageBackend) -> Result<Vec<CommandStep>> {
+        if let Some(transaction) = &self.transaction
+            && let Some(steps) = backend.undo_transactions_command(transaction)
+        {
+            let unchanged = backend.last_transaction().as_ref() == Some(transaction);
+            return Ok(if unchanged { Vec::new() } else { steps });
+        }
+
+        let (added, changed) = package_changes(&self.versions, &installed_versions(backend)?);
+        let mut steps = Vec::new();
+        if !added.is_empty() {
+            steps.extend(backend.remove_command(&added));
+        }
+        if !changed.is_empty() {
+            let reinstall = backend.install_versions_command(&changed).with_context(|| {
+                let names: Vec<&str> = changed.iter().map(|(name, _)| name.as_str()).collect();
+                format!("{} can't reinstall the previous versions of: {}", backend.name(), names.join(", "))
+            })?;
+            steps.extend(reinstall);
+        }
+        Ok(steps)
+    }
+
+    /// Undo every package change made since the checkpoint
+    pub fn undo(&self) -> Result<()> {
+        let registry = PackageManagerRegistry::new();
+        let backend = registry.get(&self.backend)
+            .with_context(|| format!("Unknown package manager: {}", self.backend))?;
+        let steps = self.undo_steps(backend)?;
+        if steps.is_empty() {
+            return Ok(());
+        }
+
+        let output = CommandExecutor::run_steps(&steps)?;
+        if !output.success {
+            anyhow::bail!("Undoing the package transaction failed: {}", output.error_summary());
+        }
+        Ok(())
+    }
+}
+
+fn installed_versions(backend: &dyn PackageBackend) -> Result<BTreeMap<String, String>> {
+    Ok(backend.installed_packages()?
+        .into_iter()
+        .map(|package| (package.name, package.version))
+        .collect())
+}
+
+/// Packages installed since `before`, and the packages removed or changed
+/// since, paired with the version to go back to
+fn package_changes(before: &BTreeMap<String, String>, now: &BTreeMap<String, String>) -> (Vec<String>, Vec<(String, String)>) {
+    let added = now.keys().filter(|name| !before.contains_key(*name)).cloned().collect();
+    let changed = before.iter()
+        .filter(|(name, version)| now.get(*name) != Some(*version))
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+    (added, changed)
+}
+
+/// Picks a snapshot provider for this system and restores snapshots with
+/// the provider that took them
+pub struct SnapshotManager {
+    providers: Vec<Box<dyn SnapshotProvider>>,
+    selected: OnceLock<usize>,
+    capture_packages: fn() -> Option<PackageCheckpoint>,
+}
+
+impl SnapshotManager {
+    pub fn new(archive_dir: PathBuf) -> Self {
+        Self {
+            providers: vec![
+                Box::new(Snapper),
+                Box::new(Timeshift),
+                Box::new(Btrfs::new()),
+                Box::new(LvmThin),
+                Box::new(TarArchive::new(archive_dir)),
+            ],
+            selected: OnceLock::new(),
+            capture_packages: PackageCheckpoint::capture,
+        }
+    }
+
+    /// Where the tar fallback keeps its archives
+    pub fn default_archive_dir() -> PathBuf {
+        dirs::data_local_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("linux-distro-agent")
+            .join("snapshots")
+    }
+
+    /// The first provider that works here; the tar fallback always does
+    pub fn provider(&self) -> &dyn SnapshotProvider {
+        let index = *self.selected.get_or_init(|| {
+            self.providers.iter()
+                .position(|provider| provider.is_available())
+                .unwrap_or(self.providers.len() - 1)
+        });
+        self.providers[index].as_ref()
+    }
+
+    fn find(&self, name: &str) -> Result<&dyn SnapshotProvider> {
+        self.providers.iter()
+            .find(|provider| provider.name() == name)
+            .map(|provider| provider.as_ref())
+            .with_context(|| format!("Unknown snapshot provider: {}", name))
+    }
+
+    /// Snapshot with the selected provider, falling back to the tar archive
+    /// when it fails
+    pub fn create(&self, label: &str, paths: &[PathBuf]) -> Result<Snapshot> {
+        let selected = self.provider();
+        let fallback = self.providers[self.providers.len() - 1].as_ref();
+        let (provider, id) = match selected.create(label, paths) {
+            Ok(id) => (selected, id),
+            Err(e) if selected.name() != fallback.name() => {
+                let id = fallback.create(label, paths)
+                    .with_context(|| format!("{} snapshot failed ({}), and so did the {} fallback", selected.name(), e, fallback.name()))?;
+                (fallback, id)
+            }
+            Err(e) => return Err(e),
+        };
+        let packages = if provider.covers_system() { None } else { (self.capture_packages)() };
+        // A partial snapshot of no files and no package state can't undo anything
+        if !provider.covers_system() && paths.is_empty() && packages.is_none() {
+            anyhow::bail!("The {} snapshot saves nothing for this task: it names no files under the critical paths and the package state couldn't be read", provider.name());
+        }
+        Ok(Snapshot {
+            provider: provider.name().to_string(),
+            id,
+            created_at: SystemTime::now(),
+            paths: paths.to_vec(),
+            packages,
+        })
+    }
+
+    /// Whether restoring this snapshot takes effect immediately
+    pub fn restores_live(&self, snapshot: &Snapshot) -> bool {
+        self.find(&snapshot.provider).is_ok_and(|provider| provider.restores_live())
+    }
+
+    /// Undo the package transaction, then put the saved files back
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<RestoreOutcome> {
+        let provider = self.find(&snapshot.provider)?;
+        if let Some(packages) = &snapshot.packages {
+            packages.undo()?;
+        }
+        provider.restore(&snapshot.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider_output() {
+        let timeshift = "Creating new snapshot...(RSYNC)\nSaving to device: /dev/sda2\nTagged snapshot '2024-05-01_10-00-00': ondemand\n";
+        assert_eq!(parse_timeshift_tag(timeshift).as_deref(), Some("2024-05-01_10-00-00"));
+
+        let btrfs = "/.snapshots/x\n\tName: \t\t\tx\n\tSubvolume ID: \t\t261\n\tGeneration: \t\t12\n";
+        assert_eq!(parse_subvolume_id(btrfs).as_deref(), Some("261"));
+
+        assert_eq!(parse_thin_volume("  vg0 root pool0\n"), Some(("vg0".to_string(), "root".to_string())));
+        assert_eq!(parse_thin_volume("  vg0 root\n"), None);
+    }
+
+    #[test]
+    fn test_package_changes() {
+        let before = BTreeMap::from([
+            ("bash".to_string(), "5.1".to_string()),
+            ("vim".to_string(), "9.0".to_string()),
+            ("nano".to_string(), "7.2".to_string()),
+        ]);
+        let now = BTreeMap::from([
+            ("bash".to_string(), "5.1".to_string()),
+            ("vim".to_string(), "9.1".to_string()),
+            ("htop".to_string(), "3.3".to_string()),
+        ]);
+        let (added, changed) = package_changes(&before, &now);
+        assert_eq!(added, ["htop"]);
+        assert_eq!(changed, [("nano".to_string(), "7.2".to_string()), ("vim".to_string(), "9.0".to_string())]);
+    }
+
+    /// A provider whose snapshots always fail
+    struct Broken;
+
+    impl SnapshotProvider for Broken {
+        fn name(&self) -> &'static str { "broken" }
+        fn is_available(&self) -> bool { true }
+        fn create(&self, _label: &str, _paths: &[PathBuf]) -> Result<String> { anyhow::bail!("no space left") }
+        fn restore(&self, _id: &str) -> Result<RestoreOutcome> { anyhow::bail!("no snapshot") }
+    }
+
+    #[test]
+    fn test_failed_snapshot_falls_back_to_tar() {
+        let root = tempfile::tempdir().unwrap();
+        let archives = tempfile::tempdir().unwrap();
+        let manager = SnapshotManager {
+            providers: vec![
+                Box::new(Broken),
+                Box::new(TarArchive { archive_dir: archives.path().to_path_buf(), root: root.path().to_path_buf() }),
+            ],
+            selected: OnceLock::new(),
+            capture_packages: || None,
+        };
+        assert_eq!(manager.provider().name(), "broken");
+        let config = root.path().join("etc/app.conf");
+        let snapshot = manager.create("task_1", std::slice::from_ref(&config)).unwrap();
+        assert_eq!(snapshot.provider, "tar");
+        assert!(manager.restores_live(&snapshot));
+
+        // Nothing to restore is no snapshot at all
+        let error = manager.create("task_2", &[]).unwrap_err().to_string();
+        assert!(error.contains("saves nothing"), "{error}");
+    }
+
+    #[test]
+    fn test_tar_snapshot_restores_files() {
+        let root = tempfile::tempdir().unwrap();
+        let archives = tempfile::tempdir().unwrap();
+        let config = root.path().join("etc/app.conf");
+        fs::create_dir_all(config.parent().unwrap()).unwrap();
+        fs::write(&config, "original").unwrap();
+
+        let provider = TarArchive { archive_dir: archives.path().to_path_buf(), root: root.path().to_path_buf() };
+        let id = provider.create("task_1", std::slice::from_ref(&config)).unwrap();
+        fs::write(&config, "changed").unwrap();
+
+        assert_eq!(provider.restore(&id).unwrap(), RestoreOutcome::Restored);
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+
+        // Files the task creates are deleted, along with their new directories
+        let created = root.path().join("etc/app.d/extra.conf");
+        let created_dir = root.path().join("etc/app.d");
+        let id = provider.create("task_2", &[config.clone(), created_dir.clone()]).unwrap();
+        fs::create_dir_all(&created_dir).unwrap();
+        fs::write(&created, "new").unwrap();
+        fs::write(&config, "changed").unwrap();
+        assert_eq!(provider.restore(&id).unwrap(), RestoreOutcome::Restored);
+        assert!(!created_dir.exists());
+        assert_eq!(fs::read_to_string(&config).unwrap(), "original");
+    }
+}
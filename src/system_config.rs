@@ -2,6 +2,7 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use crate::agent::SafetyLevel;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemConfig {
@@ -19,6 +20,19 @@ pub struct AgentConfig {
     pub allowed_groups: Vec<String>,
     pub enable_history: bool,
     pub cache_duration: u64,
+    /// Order agent tasks by how reliable and quick their commands have been
+    #[serde(default)]
+    pub adaptive_planning: bool,
+    /// Tasks at or above this safety level wait for `agent --approve`
+    #[serde(default = "default_approval_threshold")]
+    pub approval_threshold: SafetyLevel,
+    /// Refuse approvals from the user who submitted the task
+    #[serde(default)]
+    pub separate_approver: bool,
+}
+
+fn default_approval_threshold() -> SafetyLevel {
+    SafetyLevel::Risky
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +80,9 @@ impl Default for SystemConfig {
                 allowed_groups: vec!["wheel".to_string(), "sudo".to_string()],
                 enable_history: true,
                 cache_duration: 300,
+                adaptive_planning: false,
+                approval_threshold: default_approval_threshold(),
+                separate_approver: false,
             },
             security: SecurityConfig {
                 enable_audit_log: true,
@@ -0,0 +1,411 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::config_manager::Config;
+use crate::distro::DistroInfo;
+use crate::executor::{self, CommandExecutor, CommandStep};
+use crate::history::HistoryManager;
+use crate::package_manager::{self, OperationType, PackageBackend, PackageManagerRegistry, PackageOperation};
+use crate::simulation::SimulationReport;
+
+/// Lines of command output kept per step in the history entry
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Where the packages of a plan step are installed from or removed through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageOrigin {
+    Native,
+    Aur,
+    Flatpak,
+    Snap,
+}
+
+impl PackageOrigin {
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix {
+            "native" => Some(Self::Native),
+            "aur" => Some(Self::Aur),
+            "flatpak" => Some(Self::Flatpak),
+            "snap" => Some(Self::Snap),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PackageOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Native => write!(f, "native"),
+            Self::Aur => write!(f, "aur"),
+            Self::Flatpak => write!(f, "flatpak"),
+            Self::Snap => write!(f, "snap"),
+        }
+    }
+}
+
+/// Split an optional `source:` prefix (e.g. `flatpak:org.gimp.GIMP`) off a package argument.
+/// Unknown prefixes are kept as part of the name so `pkg:amd64` style names still work.
+pub fn parse_package_spec(spec: &str) -> (Option<PackageOrigin>, &str) {
+    match spec.split_once(':') {
+        Some((prefix, name)) if !name.is_empty() => match PackageOrigin::from_prefix(prefix) {
+            Some(origin) => (Some(origin), name),
+            None => (None, spec),
+        },
+        _ => (None, spec),
+    }
+}
+
+/// One backend invocation covering every package from the same source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub origin: PackageOrigin,
+    pub backend: String,
+    pub packages: Vec<String>,
+    pub commands: Vec<CommandStep>,
+}
+
+/// Result of running a single plan step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepOutcome {
+    pub origin: PackageOrigin,
+    pub packages: Vec<String>,
+    pub success: bool,
+    pub error: Option<String>,
+    /// Last lines of the captured command output
+    pub output: String,
+}
+
+/// The backend's native simulation of a single plan step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepSimulation {
+    pub origin: PackageOrigin,
+    pub backend: String,
+    pub packages: Vec<String>,
+    pub report: Option<SimulationReport>,
+    pub error: Option<String>,
+}
+
+impl StepSimulation {
+    pub fn run(origin: PackageOrigin, backend: &dyn PackageBackend, operation: &OperationType, packages: &[String]) -> Self {
+        let (report, error) = match backend.simulate(operation, packages) {
+            Ok(report) => (Some(report), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Self {
+            origin,
+            backend: backend.name().to_string(),
+            packages: packages.to_vec(),
+            report,
+            error,
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let body = match (&self.report, &self.error) {
+            (Some(report), _) => report.render(),
+            (None, Some(error)) => format!("Simulation unavailable: {error}"),
+            (None, None) => "Simulation unavailable".to_string(),
+        };
+        let indented = body.lines().map(|line| format!("    {line}")).collect::<Vec<_>>().join("\n");
+        format!("  [{} via {}]\n{}", self.origin, self.backend, indented)
+    }
+}
+
+/// The full set of commands needed to carry out a [`PackageOperation`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationPlan {
+    pub operation_type: OperationType,
+    pub steps: Vec<PlanStep>,
+    /// Packages that need no action (already installed, or not installed for removals)
+    pub skipped: Vec<String>,
+    /// Packages that no enabled source can handle
+    pub unresolved: Vec<String>,
+}
+
+impl OperationPlan {
+    /// Build a plan for this system, using the native backend first and then
+    /// whichever alternative sources are enabled in the user configuration
+    pub fn for_distro(operation: &PackageOperation, distro: &DistroInfo, registry: &PackageManagerRegistry) -> Result<Self> {
+        let config = Config::load().unwrap_or_default();
+        let mut sources: Vec<(PackageOrigin, &dyn PackageBackend)> = Vec::new();
+
+        if let Some(native) = distro.backend(registry) {
+            sources.push((PackageOrigin::Native, native));
+        }
+        if config.enable_aur
+            && distro.package_manager.as_deref() == Some("pacman")
+            && let Some(helper) = registry.get(&config.preferred_aur_helper).or_else(|| registry.get("paru"))
+        {
+            sources.push((PackageOrigin::Aur, helper));
+        }
+        if config.enable_flatpak && let Some(flatpak) = registry.get("flatpak") {
+            sources.push((PackageOrigin::Flatpak, flatpak));
+        }
+        if config.enable_snap && let Some(snap) = registry.get("snap") {
+            sources.push((PackageOrigin::Snap, snap));
+        }
+
+        Self::build(operation, &sources, |name| distro.resolve_package_name(name))
+    }
+
+    /// Build a plan from an ordered list of sources. `resolve_native` maps a
+    /// canonical package name to the native package name.
+    pub fn build(
+        operation: &PackageOperation,
+        sources: &[(PackageOrigin, &dyn PackageBackend)],
+        resolve_native: impl Fn(&str) -> String,
+    ) -> Result<Self> {
+        let installing = match operation.operation_type {
+            OperationType::Install => true,
+            OperationType::Remove => false,
+            ref other => anyhow::bail!("{:?} operations cannot be planned", other),
+        };
+
+        let mut groups: BTreeMap<PackageOrigin, (&dyn PackageBackend, Vec<String>)> = BTreeMap::new();
+        let mut skipped = Vec::new();
+        let mut unresolved = Vec::new();
+
+        for spec in &operation.packages {
+            let (requested_origin, name) = parse_package_spec(spec);
+            package_manager::validate_package_name(name)?;
+            let candidates: Vec<(PackageOrigin, &dyn PackageBackend, String)> = sources
+                .iter()
+                .filter(|(origin, _)| requested_origin.is_none_or(|requested| requested == *origin))
+                .map(|(origin, backend)| {
+                    let package = if *origin == PackageOrigin::Native { resolve_native(name) } else { name.to_string() };
+                    (*origin, *backend, package)
+                })
+                .collect();
+
+            if candidates.is_empty() {
+                unresolved.push(spec.clone());
+                continue;
+            }
+
+            let installed_from = candidates.iter().find(|(_, backend, package)| backend.is_installed(package));
+            let chosen = if installing {
+                if installed_from.is_some() {
+                    skipped.push(spec.clone());
+                    continue;
+                }
+                // An explicit source is trusted as-is, otherwise take the first source that has the package
+                if requested_origin.is_some() {
+                    candidates.first()
+                } else {
+                    candidates.iter().find(|(_, backend, package)| backend.is_available(package))
+                }
+            } else {
+                match installed_from {
+                    Some(candidate) => Some(candidate),
+                    None => {
+                        skipped.push(spec.clone());
+                        continue;
+                    }
+                }
+            };
+
+            match chosen {
+                Some((origin, backend, package)) => {
+                    groups.entry(*origin).or_insert_with(|| (*backend, Vec::new())).1.push(package.clone());
+                }
+                None => unresolved.push(spec.clone()),
+            }
+        }
+
+        let steps = groups
+            .into_iter()
+            .map(|(origin, (backend, packages))| PlanStep {
+                origin,
+                backend: backend.name().to_string(),
+                commands: if installing { backend.install_command(&packages) } else { backend.remove_command(&packages) },
+                packages,
+            })
+            .collect();
+
+        Ok(Self {
+            operation_type: operation.operation_type.clone(),
+            steps,
+            skipped,
+            unresolved,
+        })
+    }
+
+    /// Human-readable summary of what the plan will do
+    pub fn render(&self) -> String {
+        let verb = self.verb();
+        let package_count: usize = self.steps.iter().map(|step| step.packages.len()).sum();
+        let mut output = format!("Plan: {} {} package(s) in {} step(s)\n", verb, package_count, self.steps.len());
+
+        for step in &self.steps {
+            output.push_str(&format!("  [{} via {}] {}\n", step.origin, step.backend, step.packages.join(" ")));
+            output.push_str(&format!("      {}\n", executor::render_steps(&step.commands)));
+        }
+        if !self.skipped.is_empty() {
+            let reason = if verb == "install" { "already installed" } else { "not installed" };
+            output.push_str(&format!("  Skipping ({}): {}\n", reason, self.skipped.join(" ")));
+        }
+        if !self.unresolved.is_empty() {
+            output.push_str(&format!("  No source available: {}\n", self.unresolved.join(" ")));
+        }
+
+        output.trim_end().to_string()
+    }
+
+    /// Run every step in order, continuing past failures so each source gets a chance
+    pub fn execute(&self) -> Vec<StepOutcome> {
+        self.steps
+            .iter()
+            .map(|step| {
+                let (success, error, output) = match CommandExecutor::run_steps(&step.commands) {
+                    Ok(output) if output.success => (true, None, output.tail(OUTPUT_TAIL_LINES)),
+                    Ok(output) => (false, Some(output.error_summary()), output.tail(OUTPUT_TAIL_LINES)),
+                    Err(e) => (false, Some(e.to_string()), String::new()),
+                };
+                StepOutcome {
+                    origin: step.origin,
+                    packages: step.packages.clone(),
+                    success,
+                    error,
+                    output,
+                }
+            })
+            .collect()
+    }
+
+    /// Ask each step's backend what it would change, without changing anything
+    pub fn simulate(&self, registry: &PackageManagerRegistry) -> Vec<StepSimulation> {
+        self.steps
+            .iter()
+            .map(|step| match registry.get(&step.backend) {
+                Some(backend) => StepSimulation::run(step.origin, backend, &self.operation_type, &step.packages),
+                None => StepSimulation {
+                    origin: step.origin,
+                    backend: step.backend.clone(),
+                    packages: step.packages.clone(),
+                    report: None,
+                    error: Some(format!("Unknown backend {}", step.backend)),
+                },
+            })
+            .collect()
+    }
+
+    /// Record the whole operation as a single history entry
+    pub fn record_history(&self, outcomes: &[StepOutcome], distro: &str) -> Result<()> {
+        let command = self.steps.iter().map(|step| executor::render_steps(&step.commands)).collect::<Vec<_>>().join(" ; ");
+        let packages = outcomes.iter().flat_map(|outcome| outcome.packages.iter().cloned()).collect::<Vec<_>>().join(" ");
+        let success = outcomes.iter().all(|outcome| outcome.success);
+
+        let mut output = summarize(outcomes);
+        for outcome in outcomes.iter().filter(|outcome| !outcome.output.is_empty()) {
+            output.push_str(&format!("\n--- {} ---\n{}", outcome.origin, outcome.output));
+        }
+
+        let mut history_manager = HistoryManager::new()?;
+        history_manager.track_command(&command, self.verb(), Some(packages), success, Some(output), distro)
+    }
+
+    fn verb(&self) -> &'static str {
+        match self.operation_type {
+            OperationType::Remove => "remove",
+            _ => "install",
+        }
+    }
+}
+
+/// One line per step, e.g. `native: vim git ok; flatpak: org.gimp.GIMP failed (...)`
+pub fn summarize(outcomes: &[StepOutcome]) -> String {
+    outcomes
+        .iter()
+        .map(|outcome| match (&outcome.error, outcome.success) {
+            (_, true) => format!("{}: {} ok", outcome.origin, outcome.packages.join(" ")),
+            (Some(error), false) => format!("{}: {} failed ({})", outcome.origin, outcome.packages.join(" "), error),
+            (None, false) => format!("{}: {} failed", outcome.origin, outcome.packages.join(" ")),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backend with fixed installed/available package lists
+    struct FakeBackend {
+        name: &'static str,
+        installed: &'static [&'static str],
+        available: &'static [&'static str],
+    }
+
+    impl PackageBackend for FakeBackend {
+        fn name(&self) -> &'static str { self.name }
+        fn requires_sudo(&self) -> bool { false }
+        fn install_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![CommandStep::new(self.name, ["add"].into_iter().chain(packages.iter().map(String::as_str)))] }
+        fn remove_command(&self, packages: &[String]) -> Vec<CommandStep> { vec![CommandStep::new(self.name, ["del"].into_iter().chain(packages.iter().map(String::as_str)))] }
+        fn update_command(&self) -> Vec<CommandStep> { vec![CommandStep::new(self.name, ["upgrade"])] }
+        fn search_command(&self, query: &str) -> CommandStep { CommandStep::new(self.name, ["search", query]) }
+        fn list_command(&self, _detailed: bool) -> CommandStep { CommandStep::new(self.name, ["list"]) }
+        fn info_command(&self, package: &str) -> CommandStep { CommandStep::new(self.name, ["info", package]) }
+        fn is_installed_command(&self, package: &str) -> CommandStep { CommandStep::new(self.name, ["query", package]) }
+        fn is_installed(&self, package: &str) -> bool { self.installed.contains(&package) }
+        fn is_available(&self, package: &str) -> bool { self.available.contains(&package) }
+    }
+
+    fn operation(operation_type: OperationType, packages: &[&str]) -> PackageOperation {
+        PackageOperation::new(operation_type, packages.iter().map(|p| p.to_string()).collect(), false)
+    }
+
+    #[test]
+    fn test_parse_package_spec() {
+        assert_eq!(parse_package_spec("flatpak:org.gimp.GIMP"), (Some(PackageOrigin::Flatpak), "org.gimp.GIMP"));
+        assert_eq!(parse_package_spec("vim"), (None, "vim"));
+        assert_eq!(parse_package_spec("libc6:amd64"), (None, "libc6:amd64"));
+    }
+
+    #[test]
+    fn test_install_plan_groups_by_source() {
+        let native = FakeBackend { name: "pacman", installed: &["curl"], available: &["vim", "git", "curl"] };
+        let aur = FakeBackend { name: "paru", installed: &[], available: &["vim", "git", "curl", "spotify"] };
+        let flatpak = FakeBackend { name: "flatpak", installed: &[], available: &[] };
+        let sources: Vec<(PackageOrigin, &dyn PackageBackend)> =
+            vec![(PackageOrigin::Native, &native), (PackageOrigin::Aur, &aur), (PackageOrigin::Flatpak, &flatpak)];
+
+        let op = operation(OperationType::Install, &["vim", "spotify", "curl", "git", "flatpak:org.gimp.GIMP", "nothing"]);
+        let plan = OperationPlan::build(&op, &sources, str::to_string).unwrap();
+
+        assert_eq!(plan.steps.len(), 3);
+        assert_eq!(plan.steps[0].origin, PackageOrigin::Native);
+        assert_eq!(plan.steps[0].packages, vec!["vim", "git"]);
+        assert_eq!(executor::render_steps(&plan.steps[0].commands), "pacman add vim git");
+        assert_eq!(plan.steps[1].packages, vec!["spotify"]);
+        assert_eq!(plan.steps[2].packages, vec!["org.gimp.GIMP"]);
+        assert_eq!(plan.skipped, vec!["curl"]);
+        assert_eq!(plan.unresolved, vec!["nothing"]);
+    }
+
+    #[test]
+    fn test_remove_plan_uses_installed_source() {
+        let native = FakeBackend { name: "apt", installed: &["vim"], available: &[] };
+        let snap = FakeBackend { name: "snap", installed: &["code"], available: &[] };
+        let sources: Vec<(PackageOrigin, &dyn PackageBackend)> = vec![(PackageOrigin::Native, &native), (PackageOrigin::Snap, &snap)];
+
+        let op = operation(OperationType::Remove, &["vim", "code", "emacs"]);
+        let plan = OperationPlan::build(&op, &sources, str::to_string).unwrap();
+
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(executor::render_steps(&plan.steps[1].commands), "snap del code");
+        assert_eq!(plan.skipped, vec!["emacs"]);
+        assert!(OperationPlan::build(&operation(OperationType::Update, &[]), &sources, str::to_string).is_err());
+        assert!(OperationPlan::build(&operation(OperationType::Remove, &["vim;reboot"]), &sources, str::to_string).is_err());
+    }
+
+    #[test]
+    fn test_summarize_reports_partial_failure() {
+        let outcomes = vec![
+            StepOutcome { origin: PackageOrigin::Native, packages: vec!["vim".to_string()], success: true, error: None, output: String::new() },
+            StepOutcome { origin: PackageOrigin::Flatpak, packages: vec!["org.gimp.GIMP".to_string()], success: false, error: Some("exit 1".to_string()), output: String::new() },
+        ];
+        assert_eq!(summarize(&outcomes), "native: vim ok; flatpak: org.gimp.GIMP failed (exit 1)");
+    }
+}